@@ -1,22 +1,18 @@
 use std::{path::PathBuf, str::FromStr};
 
 use zcash_artifacts::{
-    ArtifactResolver, ArtifactSource, BuildConfig, ResolvedArtifact, ResolverConfig,
-    git::GitPolicy, registry::ZCASHD,
+    ArtifactResolver, ArtifactSource, ResolvedArtifact, ResolverConfig, git::GitPolicy,
+    registry::ZCASHD,
 };
 
 fn main() {
     println!("Hello friend");
 
-    let cfg = ResolverConfig {
-        cache_root: tempfile::tempdir().unwrap().keep(),
-        build_config: BuildConfig {
-            allow_build: true,
-            default_jobs: Some(2),
-            default_policy: GitPolicy::RequireClean,
-            default_expected_output: PathBuf::from("src/zcashd"),
-        },
-    };
+    let cfg = ResolverConfig::builder()
+        .cache_root(tempfile::tempdir().unwrap().keep())
+        .allow_build(true)
+        .jobs(2)
+        .finish();
     let provider = ArtifactResolver::new(cfg);
 
     let src = ArtifactSource::Build {
@@ -24,11 +20,12 @@ fn main() {
         refspec: None,
         policy: GitPolicy::RequireClean,
         expected_output: None,
+        target: None,
         service: ZCASHD,
     };
 
     let zcashd_path = match provider.resolve(&src).unwrap() {
-        ResolvedArtifact::Executable { path } => path,
+        ResolvedArtifact::Executable { path, .. } => path,
         _ => panic!(),
     };
 
@@ -0,0 +1,179 @@
+//! Extracting release archives and locating binaries inside them.
+
+use std::path::{Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+
+use crate::{
+    error::{FsError, Result, UnpackError},
+    registry::ToolSpec,
+};
+
+/// Finds the service binary under `root`, an extracted archive.
+///
+/// When the spec declares [`ToolSpec::archive_layout`] hints they are tried in
+/// order and the first (lexicographically smallest) match wins. Otherwise the
+/// shallowest file named like one of the spec's binary names is used.
+pub fn locate_binary(root: &Path, spec: &ToolSpec, platform: &str) -> Result<PathBuf> {
+    let mut files = Vec::new();
+    walk(root, root, &mut files)?;
+    files.sort();
+    let archive = root.display().to_string();
+    let rel = pick_binary(&files, spec, &spec.archive_layout, platform, &archive)?;
+    Ok(root.join(rel))
+}
+
+/// [`locate_binary`] over the sorted relative paths of an archive's files, with
+/// `layout` in place of the spec's hints.
+pub(crate) fn pick_binary<'a>(
+    files: &'a [PathBuf],
+    spec: &ToolSpec,
+    layout: &[String],
+    platform: &str,
+    archive: &str,
+) -> Result<&'a PathBuf> {
+    let not_found = |tried: String| UnpackError::BinaryNotFound {
+        archive: archive.to_string(),
+        tried,
+    };
+
+    if !layout.is_empty() {
+        let opts = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        for hint in layout {
+            let pattern = Pattern::new(hint).map_err(|e| UnpackError::BadLayoutHint {
+                pattern: hint.clone(),
+                reason: e.to_string(),
+            })?;
+            if let Some(rel) = files
+                .iter()
+                .find(|rel| pattern.matches_with(&to_slash(rel), opts))
+            {
+                return Ok(rel);
+            }
+        }
+        return Err(not_found(layout.join(", ")).into());
+    }
+
+    let names = spec.binary_names_for(platform);
+    files
+        .iter()
+        .filter(|rel| {
+            rel.file_name()
+                .is_some_and(|n| names.iter().any(|name| n == name.as_str()))
+        })
+        .min_by_key(|rel| rel.components().count())
+        .ok_or_else(|| not_found(names.join(", ")).into())
+}
+
+/// Extracts the archive at `path` into `dest`, creating it if needed.
+///
+/// The format is picked from `name`, the asset's file name: `.tar.gz`/`.tgz`
+/// and `.zip` are supported, and with the `deb` feature `.deb` packages, of
+/// which the files of `data.tar` are extracted. Entries that would land outside
+/// `dest` are skipped.
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub(crate) fn extract(path: &Path, name: &str, dest: &Path) -> Result<()> {
+    let tool = |e: Box<dyn std::error::Error + Send + Sync>| UnpackError::Tool {
+        archive: name.to_string(),
+        source: e,
+    };
+    let file = std::fs::File::open(path).map_err(|e| FsError::Io {
+        context: format!("open {}", path.display()),
+        source: e,
+    })?;
+    std::fs::create_dir_all(dest).map_err(|e| FsError::Io {
+        context: format!("mkdir {}", dest.display()),
+        source: e,
+    })?;
+    #[cfg(feature = "deb")]
+    if name.ends_with(".deb") {
+        return extract_deb(file, name, dest);
+    }
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        tar::Archive::new(flate2::read::GzDecoder::new(file))
+            .unpack(dest)
+            .map_err(|e| tool(Box::new(e)))?;
+    } else if name.ends_with(".zip") {
+        zip::ZipArchive::new(file)
+            .and_then(|mut zip| zip.extract(dest))
+            .map_err(|e| tool(Box::new(e)))?;
+    } else {
+        return Err(UnpackError::UnsupportedFormat {
+            archive: name.to_string(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Extracts the `data.tar` member of the `.deb` package `file`, which may be
+/// uncompressed or compressed with gzip, xz or zstd.
+#[cfg(feature = "deb")]
+fn extract_deb(file: std::fs::File, name: &str, dest: &Path) -> Result<()> {
+    use std::io::Read;
+
+    let tool = |e: Box<dyn std::error::Error + Send + Sync>| UnpackError::Tool {
+        archive: name.to_string(),
+        source: e,
+    };
+    let mut members = ar::Archive::new(file);
+    while let Some(member) = members.next_entry() {
+        let member = member.map_err(|e| tool(Box::new(e)))?;
+        let id = String::from_utf8_lossy(member.header().identifier()).into_owned();
+        let Some(compression) = id.trim_end_matches('/').strip_prefix("data.tar") else {
+            continue;
+        };
+        let data: Box<dyn Read + '_> = match compression {
+            "" => Box::new(member),
+            ".gz" => Box::new(flate2::read::GzDecoder::new(member)),
+            ".xz" => Box::new(lzma_rust2::XzReader::new(member, true)),
+            ".zst" => Box::new(
+                ruzstd::decoding::StreamingDecoder::new(member).map_err(|e| tool(Box::new(e)))?,
+            ),
+            _ => {
+                return Err(UnpackError::UnsupportedFormat {
+                    archive: format!("{name} ({id})"),
+                }
+                .into());
+            }
+        };
+        return tar::Archive::new(data)
+            .unpack(dest)
+            .map_err(|e| tool(Box::new(e)).into());
+    }
+    Err(UnpackError::Entry {
+        archive: name.to_string(),
+        entry: "data.tar".into(),
+        source: "no such member".into(),
+    }
+    .into())
+}
+
+/// Collects regular files under `dir` as paths relative to `root`.
+fn walk(root: &Path, dir: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+    let io = |e| FsError::Io {
+        context: format!("read_dir {}", dir.display()),
+        source: e,
+    };
+    for entry in std::fs::read_dir(dir).map_err(io)? {
+        let entry = entry.map_err(io)?;
+        let ty = entry.file_type().map_err(io)?;
+        let path = entry.path();
+        if ty.is_dir() {
+            walk(root, &path, out)?;
+        } else if ty.is_file() {
+            out.push(path.strip_prefix(root).unwrap_or(&path).to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+fn to_slash(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -0,0 +1,351 @@
+//! Attestations attached to images: SLSA provenance and SBOMs.
+//!
+//! With [`AttestationPolicy::fetch`] on, pulling an image also fetches the
+//! [in-toto](https://in-toto.io) statements attached to it, from either place
+//! they are published:
+//!
+//! - BuildKit's attestation manifests (`docker buildx build --provenance
+//!   --sbom`), entries of the image's manifest list with the
+//!   `vnd.docker.reference.type: attestation-manifest` annotation;
+//! - the registry's referrers API (OCI 1.1), e.g. `cosign attest` or
+//!   `oras attach` artifacts, as plain statements or DSSE envelopes.
+//!
+//! Only statements whose subject is the image's manifest are kept. They are
+//! checked against the policy, then stored with the cached artifact (the image
+//! entry, or the binary extracted from it):
+//!
+//! ```text
+//! meta/attestations/provenance-<digest prefix>.json
+//! meta/attestations/sbom-<digest prefix>.json
+//! meta/attestations/attestation-<digest prefix>.json  # other predicate types
+//! ```
+//!
+//! and listed in META's `attestations`. Envelope signatures are not verified;
+//! a policy on the builder identity is only as good as the registry's access
+//! control. Cache hits are checked against the stored statements; entries
+//! cached without fetching them are pulled again.
+//!
+//! Images pulled through a container runtime have their attestations fetched
+//! from the registry API, with the resolver's credentials; those resolved to
+//! [`OciImage`](crate::ResolvedArtifact::OciImage)s have no cache entry to
+//! store them in, so they are only checked.
+
+use std::{collections::BTreeMap, path::Path};
+
+use base64::Engine;
+use serde::Deserialize;
+
+use crate::{
+    ResolveContext, cache,
+    error::{FsError, OciError, Result},
+    oci::{self, Client, ImagePlatform, Reference},
+};
+
+/// What to do about attestations of pulled images; see the [module docs](self).
+#[derive(Debug, Clone, Default)]
+pub struct AttestationPolicy {
+    /// Fetch attestations and store them with the cached artifact. Off by
+    /// default; the other fields require it.
+    pub fetch: bool,
+    /// Fail for images without SLSA provenance.
+    pub require_provenance: bool,
+    /// Builder IDs the SLSA provenance must name, e.g.
+    /// `https://github.com/actions/runner/github-hosted`; implies
+    /// `require_provenance`. Empty accepts any builder.
+    pub builders: Vec<String>,
+}
+
+/// BuildKit's annotations on attestation manifests in a manifest list.
+const REFERENCE_TYPE: &str = "vnd.docker.reference.type";
+const REFERENCE_DIGEST: &str = "vnd.docker.reference.digest";
+
+const STATEMENT_TYPE: &str = "application/vnd.in-toto+json";
+const DSSE_TYPE: &str = "application/vnd.dsse.envelope.v1+json";
+
+/// A manifest list, or the referrers API's answer, with what we look at.
+#[derive(Debug, Deserialize)]
+struct Index {
+    #[serde(default)]
+    manifests: Vec<Entry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Entry {
+    digest: String,
+    #[serde(default)]
+    platform: Option<ImagePlatform>,
+    #[serde(default)]
+    annotations: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    #[serde(default)]
+    layers: Vec<Layer>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Layer {
+    media_type: String,
+    digest: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Statement {
+    #[serde(default)]
+    subject: Vec<Subject>,
+    predicate_type: String,
+    #[serde(default)]
+    predicate: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct Subject {
+    #[serde(default)]
+    digest: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Envelope {
+    payload_type: String,
+    payload: String,
+}
+
+/// An in-toto statement about the image.
+struct Attestation {
+    statement: Statement,
+    /// The statement as published, unwrapped from its envelope.
+    body: Vec<u8>,
+    /// Digest of the blob it came in.
+    digest: String,
+}
+
+impl Attestation {
+    fn is_provenance(&self) -> bool {
+        self.statement
+            .predicate_type
+            .starts_with("https://slsa.dev/provenance/")
+    }
+
+    fn kind(&self) -> &'static str {
+        let predicate_type = self.statement.predicate_type.as_str();
+        if self.is_provenance() {
+            "provenance"
+        } else if predicate_type.starts_with("https://spdx.dev/Document")
+            || predicate_type.starts_with("https://cyclonedx.org/bom")
+        {
+            "sbom"
+        } else {
+            "attestation"
+        }
+    }
+
+    /// The builder ID of SLSA provenance: `builder.id` before v1,
+    /// `runDetails.builder.id` since.
+    fn builder(&self) -> Option<&str> {
+        let predicate = &self.statement.predicate;
+        predicate
+            .pointer("/runDetails/builder/id")
+            .or_else(|| predicate.pointer("/builder/id"))
+            .and_then(|id| id.as_str())
+    }
+
+    fn file_name(&self) -> String {
+        let hex = self.digest.split_once(':').map_or("", |(_, hex)| hex);
+        format!("{}-{}.json", self.kind(), &hex[..hex.len().min(16)])
+    }
+}
+
+/// Fetches and checks the attestations of the image `reference` pins, and
+/// stores them under `meta_dir`, if given. Returns the stored files' names for
+/// META; `None` if the policy doesn't fetch attestations.
+pub(crate) fn verify(
+    ctx: &ResolveContext<'_>,
+    reference: &Reference,
+    meta_dir: Option<&Path>,
+) -> Result<Option<Vec<String>>> {
+    let policy = &ctx.config.oci.attestations;
+    if !policy.fetch {
+        return Ok(None);
+    }
+    let attestations = fetch(ctx, reference)?;
+    check(policy, reference, &attestations)?;
+    let Some(meta_dir) = meta_dir else {
+        return Ok(Some(Vec::new()));
+    };
+    let dir = meta_dir.join("attestations");
+    // Left over from an earlier pull of the entry.
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).map_err(|e| FsError::Io {
+        context: format!("mkdir {}", dir.display()),
+        source: e,
+    })?;
+    let mut names = Vec::new();
+    for attestation in &attestations {
+        let name = attestation.file_name();
+        cache::write_atomic(&dir.join(&name), &attestation.body)?;
+        names.push(name);
+    }
+    names.sort();
+    names.dedup();
+    Ok(Some(names))
+}
+
+/// Whether the cached entry with META in `meta_dir` passes the policy: it must
+/// have been pulled with attestations, and its stored ones must pass.
+pub(crate) fn cached_ok(ctx: &ResolveContext<'_>, meta_dir: &Path) -> bool {
+    let policy = &ctx.config.oci.attestations;
+    if !policy.fetch {
+        return true;
+    }
+    let Some(meta) = std::fs::read(meta_dir.join("META.json"))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<cache::Meta>(&bytes).ok())
+    else {
+        return false;
+    };
+    let Some(names) = meta.attestations else {
+        return false;
+    };
+    let dir = meta_dir.join("attestations");
+    let stored: Option<Vec<Attestation>> = names
+        .iter()
+        .map(|name| {
+            let body = std::fs::read(dir.join(name)).ok()?;
+            Some(Attestation {
+                statement: serde_json::from_slice(&body).ok()?,
+                body,
+                digest: String::new(),
+            })
+        })
+        .collect();
+    let reference = Reference::parse(meta.image.as_deref().unwrap_or_default());
+    match (stored, reference) {
+        (Some(stored), Ok(reference)) => check(policy, &reference, &stored).is_ok(),
+        _ => false,
+    }
+}
+
+fn check(
+    policy: &AttestationPolicy,
+    reference: &Reference,
+    attestations: &[Attestation],
+) -> Result<()> {
+    let rejected = |reason: String| OciError::AttestationRejected {
+        reference: reference.to_string(),
+        reason,
+    };
+    let provenance: Vec<_> = attestations.iter().filter(|a| a.is_provenance()).collect();
+    if (policy.require_provenance || !policy.builders.is_empty()) && provenance.is_empty() {
+        return Err(rejected("no SLSA provenance is attached".into()).into());
+    }
+    if policy.builders.is_empty() {
+        return Ok(());
+    }
+    for attestation in provenance {
+        match attestation.builder() {
+            Some(builder) if policy.builders.iter().any(|b| b == builder) => {}
+            Some(builder) => {
+                return Err(
+                    rejected(format!("built by `{builder}`, not an allowed builder")).into(),
+                );
+            }
+            None => return Err(rejected("provenance names no builder".into()).into()),
+        }
+    }
+    Ok(())
+}
+
+/// The statements about the image `reference` pins, for the platform being
+/// resolved for.
+fn fetch(ctx: &ResolveContext<'_>, reference: &Reference) -> Result<Vec<Attestation>> {
+    let provider = ctx.config.credential_provider();
+    let mut client = Client::new(reference, provider.as_ref(), ctx);
+    let accept = [oci::MANIFEST_TYPES, oci::INDEX_TYPES].concat();
+    let top = client.manifest(reference.manifest_ref(), &accept)?;
+
+    // (digest of a manifest holding statements)
+    let mut holders = Vec::new();
+    let image = if oci::INDEX_TYPES.contains(&oci::media_type(&top)) {
+        let index: Index = serde_json::from_slice(&top.body)
+            .map_err(|e| client.error(format!("bad manifest list: {e}")))?;
+        let wanted = oci::wanted_platform(ctx);
+        let Some(image) = index.manifests.iter().find(|m| {
+            !m.annotations.contains_key(REFERENCE_TYPE)
+                && m.platform.as_ref().is_some_and(|p| p.canonical() == wanted)
+        }) else {
+            return Ok(Vec::new());
+        };
+        holders.extend(
+            index
+                .manifests
+                .iter()
+                .filter(|m| {
+                    m.annotations.get(REFERENCE_TYPE).map(String::as_str)
+                        == Some("attestation-manifest")
+                        && m.annotations.get(REFERENCE_DIGEST) == Some(&image.digest)
+                })
+                .map(|m| m.digest.clone()),
+        );
+        image.digest.clone()
+    } else {
+        top.digest.clone()
+    };
+    if let Some(referrers) = client.referrers(&image)? {
+        let referrers: Index = serde_json::from_slice(&referrers)
+            .map_err(|e| client.error(format!("bad referrers index: {e}")))?;
+        holders.extend(referrers.manifests.into_iter().map(|m| m.digest));
+    }
+
+    let mut attestations = Vec::new();
+    for holder in holders {
+        let manifest = client.manifest(&holder, oci::MANIFEST_TYPES)?;
+        let manifest: Manifest = serde_json::from_slice(&manifest.body)
+            .map_err(|e| client.error(format!("bad attestation manifest: {e}")))?;
+        for layer in manifest.layers {
+            let body = match layer.media_type.as_str() {
+                STATEMENT_TYPE => client.blob(&layer.digest)?,
+                DSSE_TYPE => match unwrap_envelope(&client.blob(&layer.digest)?) {
+                    Some(body) => body,
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let statement: Statement = serde_json::from_slice(&body).map_err(|e| {
+                client.error(format!("bad in-toto statement {}: {e}", layer.digest))
+            })?;
+            if about(&statement, &image) {
+                attestations.push(Attestation {
+                    statement,
+                    body,
+                    digest: layer.digest,
+                });
+            }
+        }
+    }
+    Ok(attestations)
+}
+
+/// The in-toto statement a DSSE envelope carries, if it carries one.
+fn unwrap_envelope(bytes: &[u8]) -> Option<Vec<u8>> {
+    let envelope: Envelope = serde_json::from_slice(bytes).ok()?;
+    (envelope.payload_type == STATEMENT_TYPE).then_some(())?;
+    base64::engine::general_purpose::STANDARD
+        .decode(envelope.payload)
+        .ok()
+}
+
+/// Whether `statement` is about the manifest `digest`.
+fn about(statement: &Statement, digest: &str) -> bool {
+    let Some((algorithm, hex)) = digest.split_once(':') else {
+        return false;
+    };
+    statement
+        .subject
+        .iter()
+        .any(|subject| subject.digest.get(algorithm).is_some_and(|d| d == hex))
+}
@@ -0,0 +1,1177 @@
+//! Executable header sniffing (ELF, Mach-O, PE).
+//!
+//! Cached and downloaded binaries are checked against the platform they were
+//! resolved for before they are handed out, which catches truncated downloads,
+//! HTML error pages saved as binaries and wrong-architecture assets early.
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{FsError, PlatformError, Result, VerifyError},
+    macho,
+    platform::{Libc, Platform},
+};
+
+/// The container format of an executable.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Elf,
+    MachO,
+    /// A universal Mach-O holding one slice per architecture.
+    MachOUniversal,
+    Pe,
+    /// A `#!` script, which runs wherever its interpreter does.
+    Script,
+}
+
+/// What an executable's header says about where it runs.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryInfo {
+    pub format: Format,
+    /// Canonical OS name, when the format pins one down. ELF binaries only do
+    /// when branded for FreeBSD.
+    pub os: Option<&'static str>,
+    /// Architectures in their OS-independent spelling (`aarch64`); several for
+    /// universal binaries and none for scripts. Unknown machines are kept as hex.
+    pub archs: Vec<String>,
+}
+
+/// Reads the header of `path`; `Ok(None)` means no known executable format.
+pub fn sniff(path: &Path) -> Result<Option<BinaryInfo>> {
+    let io = |e| FsError::Io {
+        context: format!("read {}", path.display()),
+        source: e,
+    };
+    // Enough for every header we parse, including universal binaries' slice tables.
+    let mut head = Vec::with_capacity(4096);
+    File::open(path)
+        .map_err(io)?
+        .take(4096)
+        .read_to_end(&mut head)
+        .map_err(io)?;
+    Ok(sniff_bytes(&head))
+}
+
+fn sniff_bytes(head: &[u8]) -> Option<BinaryInfo> {
+    let u16_at = |at: usize, le: bool| -> Option<u16> {
+        let b: [u8; 2] = head.get(at..at + 2)?.try_into().ok()?;
+        Some(if le {
+            u16::from_le_bytes(b)
+        } else {
+            u16::from_be_bytes(b)
+        })
+    };
+    let u32_at = |at: usize, le: bool| -> Option<u32> {
+        let b: [u8; 4] = head.get(at..at + 4)?.try_into().ok()?;
+        Some(if le {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        })
+    };
+    let info = |format, os, archs| Some(BinaryInfo { format, os, archs });
+
+    if head.starts_with(b"#!") {
+        return info(Format::Script, None, Vec::new());
+    }
+    if head.starts_with(b"\x7fELF") {
+        let le = *head.get(5)? == 1;
+        let os = (*head.get(7)? == 9).then_some("freebsd");
+        let arch = match u16_at(18, le)? {
+            0x3e => "x86_64".to_string(),
+            0xb7 => "aarch64".to_string(),
+            0x28 => "arm".to_string(),
+            0x03 => "x86".to_string(),
+            0xf3 => "riscv".to_string(),
+            other => format!("{other:#x}"),
+        };
+        return info(Format::Elf, os, vec![arch]);
+    }
+    if let Some(slices) = macho::fat_slices(head) {
+        let archs = slices.iter().map(|s| macho_arch(s.cpu)).collect();
+        return info(Format::MachOUniversal, Some("macos"), archs);
+    }
+    if head.starts_with(&[0xcf, 0xfa, 0xed, 0xfe]) || head.starts_with(&[0xce, 0xfa, 0xed, 0xfe]) {
+        let archs = vec![macho_arch(u32_at(4, true)?)];
+        return info(Format::MachO, Some("macos"), archs);
+    }
+    if head.starts_with(b"MZ") {
+        let pe = u32_at(0x3c, true)? as usize;
+        if head.get(pe..pe + 4)? != b"PE\0\0" {
+            return None;
+        }
+        let arch = match u16_at(pe + 4, true)? {
+            0x8664 => "x86_64".to_string(),
+            0xaa64 => "aarch64".to_string(),
+            0x14c => "x86".to_string(),
+            other => format!("{other:#x}"),
+        };
+        return info(Format::Pe, Some("windows"), vec![arch]);
+    }
+    None
+}
+
+fn macho_arch(cpu: u32) -> String {
+    macho::arch_name(cpu).map_or_else(|| format!("{cpu:#x}"), str::to_string)
+}
+
+impl BinaryInfo {
+    /// Whether a binary with this header can run on `platform`.
+    ///
+    /// x86_64 Mach-O binaries count as runnable on `macos-arm64` when Rosetta
+    /// is installed here.
+    pub fn runs_on(&self, platform: &Platform) -> bool {
+        self.format == Format::Script || (self.os_matches(platform) && self.arch_matches(platform))
+    }
+
+    fn os_matches(&self, platform: &Platform) -> bool {
+        match (self.format, platform.os()) {
+            (Format::Script, _) => true,
+            (Format::Elf, "linux") => self.os.is_none(),
+            (Format::Elf, "freebsd") => true,
+            (Format::MachO | Format::MachOUniversal, os) => os == "macos",
+            (Format::Pe, os) => os == "windows",
+            _ => false,
+        }
+    }
+
+    fn arch_matches(&self, platform: &Platform) -> bool {
+        let wanted = platform.generic_arch();
+        self.archs.iter().any(|arch| {
+            arch == wanted
+                // ELF only says "32-bit ARM"; the platform names the revision.
+                || (arch == "arm" && wanted.starts_with("arm"))
+                || (arch == "x86_64"
+                    && wanted == "aarch64"
+                    && platform.os() == "macos"
+                    && crate::platform::rosetta_installed())
+        })
+    }
+}
+
+/// Fails unless `path` is a recognizable executable that runs on `platform`.
+pub(crate) fn check(path: &Path, platform: &str) -> Result<()> {
+    let bad = |reason: String| VerifyError::BadHeader {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let Some(info) = sniff(path)? else {
+        return Err(bad("not an ELF, Mach-O, PE or script file".into()).into());
+    };
+    // Platforms we can't parse (and so can't judge) pass.
+    let Some(target) = Platform::parse(platform) else {
+        return Ok(());
+    };
+    if info.format != Format::Script && info.os_matches(&target) && !info.arch_matches(&target) {
+        let hint = if target.os() == "macos" && info.archs.iter().any(|a| a == "x86_64") {
+            format!(
+                "use the {} build, or install Rosetta 2 (`softwareupdate --install-rosetta`)",
+                target.arch()
+            )
+        } else {
+            format!("use the {} build of this binary", target.arch())
+        };
+        return Err(PlatformError::ArchMismatch {
+            path: path.to_path_buf(),
+            found: info.archs.join("+"),
+            platform: platform.to_string(),
+            hint,
+        }
+        .into());
+    }
+    if !info.runs_on(&target) {
+        let os = info.os.unwrap_or(match info.format {
+            Format::Elf => "linux",
+            _ => "unknown",
+        });
+        return Err(bad(format!(
+            "{:?} binary for {os}-{} does not run on {platform}",
+            info.format,
+            info.archs.join("+")
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Whether `path` is a cache entry worth returning: executable (after repairing
+/// its exec bit if need be), and with a header that runs on `platform`.
+pub(crate) fn usable(path: &Path, platform: &str) -> Result<bool> {
+    Ok(crate::cache::repair_executable(path)? && check(path, platform).is_ok())
+}
+
+/// How to start an executable on this host.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Launch {
+    pub program: PathBuf,
+    /// Emulator to run `program` under (`qemu-aarch64 <program> ...`), if any.
+    pub interpreter: Option<PathBuf>,
+    /// Environment the interpreter needs, e.g. `QEMU_LD_PREFIX` for the target's
+    /// shared libraries.
+    pub env: Vec<(String, String)>,
+}
+
+impl Launch {
+    /// A [`Command`](std::process::Command) that starts the program, to which
+    /// the caller appends its own arguments.
+    pub fn command(&self) -> std::process::Command {
+        let mut command = match &self.interpreter {
+            Some(interpreter) => {
+                let mut command = std::process::Command::new(interpreter);
+                command.arg(&self.program);
+                command
+            }
+            None => std::process::Command::new(&self.program),
+        };
+        command.envs(self.env.iter().map(|(k, v)| (k, v)));
+        command
+    }
+}
+
+/// Works out how to run `path` here, falling back to qemu-user for Linux
+/// binaries of another architecture.
+///
+/// Binaries the kernel already hands to an emulator through `binfmt_misc` run
+/// directly. Otherwise a `qemu-<arch>` (or `qemu-<arch>-static`) on `PATH` becomes
+/// the interpreter. Without either, foreign binaries fail with
+/// [`PlatformError::ArchMismatch`]. Files of no known format are assumed to run.
+pub fn launch(path: &Path) -> Result<Launch> {
+    let host = Platform::host();
+    let mut launch = Launch {
+        program: path.to_path_buf(),
+        interpreter: None,
+        env: Vec::new(),
+    };
+    let Some(info) = sniff(path)? else {
+        return Ok(launch);
+    };
+    if info.runs_on(&host) {
+        return Ok(launch);
+    }
+    let qemu = (info.format == Format::Elf && host.os() == "linux" && info.os_matches(&host))
+        .then(|| info.archs.first().and_then(|arch| qemu_arch(arch)))
+        .flatten();
+    if let Some((qemu_arch, gnu_triple)) = qemu {
+        if binfmt_misc_handles(qemu_arch) {
+            return Ok(launch);
+        }
+        let names = [
+            format!("qemu-{qemu_arch}"),
+            format!("qemu-{qemu_arch}-static"),
+        ];
+        if let Some(interpreter) = names.iter().find_map(|name| find_on_path(name)) {
+            // Where Debian-style cross toolchains install the target's libraries.
+            let sysroot = Path::new("/usr").join(gnu_triple);
+            if sysroot.is_dir() {
+                launch.env.push((
+                    "QEMU_LD_PREFIX".into(),
+                    sysroot.to_string_lossy().into_owned(),
+                ));
+            }
+            launch.interpreter = Some(interpreter);
+            return Ok(launch);
+        }
+    }
+    check(path, &host.to_string())?;
+    Ok(launch)
+}
+
+/// qemu-user's name for an ELF architecture, and the GNU triple of its sysroot.
+fn qemu_arch(arch: &str) -> Option<(&'static str, &'static str)> {
+    Some(match arch {
+        "x86_64" => ("x86_64", "x86_64-linux-gnu"),
+        "aarch64" => ("aarch64", "aarch64-linux-gnu"),
+        "arm" => ("arm", "arm-linux-gnueabihf"),
+        "x86" => ("i386", "i686-linux-gnu"),
+        "riscv" => ("riscv64", "riscv64-linux-gnu"),
+        _ => return None,
+    })
+}
+
+/// Whether an enabled `binfmt_misc` entry runs `qemu_arch` binaries.
+fn binfmt_misc_handles(qemu_arch: &str) -> bool {
+    std::fs::read_to_string(format!("/proc/sys/fs/binfmt_misc/qemu-{qemu_arch}"))
+        .is_ok_and(|entry| entry.lines().next() == Some("enabled"))
+}
+
+pub(crate) fn find_on_path(name: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| crate::cache::looks_executable(path))
+}
+
+/// The newest `GLIBC_x.y` symbol version an ELF binary links against.
+///
+/// Read from the `.gnu.version_r` section, so `Ok(None)` covers non-ELF files,
+/// static and musl binaries, and binaries whose section headers were removed.
+pub fn required_glibc(path: &Path) -> Result<Option<(u32, u32)>> {
+    const SHT_GNU_VERNEED: u32 = 0x6fff_fffe;
+
+    let Some(mut elf) = ElfSections::open(path)? else {
+        return Ok(None);
+    };
+    let Some((needs, strings)) = elf.with_strings(SHT_GNU_VERNEED)? else {
+        return Ok(None);
+    };
+    let (e, count) = (elf.endian, elf.find(SHT_GNU_VERNEED).map_or(0, |s| s.info));
+
+    // Verneed entries, each followed by a chain of Vernaux entries naming versions.
+    let mut newest = None;
+    let mut need = 0usize;
+    for _ in 0..count {
+        let (Some(aux_count), Some(aux), Some(next)) = (
+            e.u16(&needs, need + 2),
+            e.u32(&needs, need + 8),
+            e.u32(&needs, need + 12),
+        ) else {
+            break;
+        };
+        let mut entry = need + aux as usize;
+        for _ in 0..aux_count {
+            let version = e
+                .u32(&needs, entry + 8)
+                .and_then(|at| c_str(&strings, at as u64))
+                .and_then(|name| name.strip_prefix("GLIBC_"))
+                .and_then(crate::platform::parse_version);
+            newest = newest.max(version);
+            match e.u32(&needs, entry + 12) {
+                Some(0) | None => break,
+                Some(step) => entry += step as usize,
+            }
+        }
+        if next == 0 {
+            break;
+        }
+        need += next as usize;
+    }
+    Ok(newest)
+}
+
+/// Shared libraries an ELF binary names in its `DT_NEEDED` entries, in order.
+///
+/// Empty for non-ELF files and static binaries.
+pub fn needed_libraries(path: &Path) -> Result<Vec<String>> {
+    Ok(dynamic_section(path)?.map(|d| d.needed).unwrap_or_default())
+}
+
+/// Shared libraries the ELF binary at `path` needs, directly or through other
+/// libraries, that the dynamic loader would not find on this host.
+///
+/// An approximation of `ldd` that never runs the binary: libraries are looked up
+/// in the binary's `RPATH`/`RUNPATH`, `LD_LIBRARY_PATH`, the directories listed
+/// in `/etc/ld.so.conf` (`/etc/ld-elf.so.conf` on FreeBSD) and the usual system
+/// directories. Always empty off Linux and FreeBSD, and for binaries that aren't
+/// ELF.
+pub fn missing_libraries(path: &Path) -> Result<Vec<String>> {
+    if !cfg!(any(target_os = "linux", target_os = "freebsd")) {
+        return Ok(Vec::new());
+    }
+    let Some(arch) = sniff(path)?
+        .filter(|info| info.format == Format::Elf)
+        .map(|info| info.archs)
+    else {
+        return Ok(Vec::new());
+    };
+    let system = system_library_dirs();
+    let mut missing = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    let mut queue = vec![path.to_path_buf()];
+    while let Some(object) = queue.pop() {
+        let Some(dynamic) = dynamic_section(&object)? else {
+            continue;
+        };
+        let origin = object.parent().unwrap_or(Path::new("/"));
+        let expand = |dir: &str| {
+            let dir = dir
+                .replace("$ORIGIN", &origin.to_string_lossy())
+                .replace("${ORIGIN}", &origin.to_string_lossy());
+            PathBuf::from(dir)
+        };
+        let env_dirs = std::env::var("LD_LIBRARY_PATH").unwrap_or_default();
+        let dirs: Vec<_> = dynamic
+            .search_path
+            .iter()
+            .map(|d| expand(d))
+            .chain(
+                env_dirs
+                    .split(':')
+                    .filter(|d| !d.is_empty())
+                    .map(Into::into),
+            )
+            .chain(system.iter().cloned())
+            .collect();
+        for library in dynamic.needed {
+            if !seen.insert(library.clone()) {
+                continue;
+            }
+            let candidates: Vec<_> = if library.contains('/') {
+                vec![expand(&library)]
+            } else {
+                dirs.iter().map(|dir| dir.join(&library)).collect()
+            };
+            // The loader skips libraries built for another architecture.
+            let found = candidates.into_iter().find(|candidate| {
+                sniff(candidate)
+                    .ok()
+                    .flatten()
+                    .is_some_and(|info| info.format == Format::Elf && info.archs == arch)
+            });
+            match found {
+                Some(found) => queue.push(found),
+                None => missing.push(library),
+            }
+        }
+    }
+    Ok(missing)
+}
+
+/// Fails if the ELF binary at `path` needs a newer glibc than the host has.
+///
+/// `what` names the binary for the error, e.g. `zcashd v5.9 asset`. Hosts whose
+/// libc can't be determined pass.
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub(crate) fn check_glibc(path: &Path, what: &str) -> Result<()> {
+    let Some((major, minor)) = required_glibc(path)? else {
+        return Ok(());
+    };
+    let host = match crate::platform::host_libc() {
+        Some(Libc::Glibc {
+            major: h_major,
+            minor: h_minor,
+        }) if (h_major, h_minor) < (major, minor) => format!("{h_major}.{h_minor}"),
+        Some(Libc::Musl) => "musl".to_string(),
+        _ => return Ok(()),
+    };
+    Err(PlatformError::GlibcTooOld {
+        what: what.to_string(),
+        required: format!("GLIBC_{major}.{minor}"),
+        host,
+    }
+    .into())
+}
+
+#[derive(Clone, Copy)]
+struct Section {
+    kind: u32,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+}
+
+/// An ELF file's section table, for reading individual sections on demand.
+struct ElfSections {
+    file: File,
+    path: PathBuf,
+    endian: Elf,
+    wide: bool,
+    sections: Vec<Section>,
+}
+
+impl ElfSections {
+    /// `Ok(None)` for files that aren't ELF or have no section table.
+    fn open(path: &Path) -> Result<Option<Self>> {
+        let mut elf = ElfSections {
+            file: File::open(path).map_err(|e| FsError::Io {
+                context: format!("read {}", path.display()),
+                source: e,
+            })?,
+            path: path.to_path_buf(),
+            endian: Elf { le: true },
+            wide: false,
+            sections: Vec::new(),
+        };
+        let ident = elf.read_at(0, 64)?;
+        if !ident.starts_with(b"\x7fELF") || ident.len() < 64 {
+            return Ok(None);
+        }
+        elf.wide = ident[4] == 2;
+        elf.endian = Elf { le: ident[5] == 1 };
+        let e = elf.endian;
+        let (shoff, shentsize, shnum) = if elf.wide {
+            (
+                e.u64(&ident, 0x28),
+                e.u16(&ident, 0x3a),
+                e.u16(&ident, 0x3c),
+            )
+        } else {
+            (
+                e.u32(&ident, 0x20).map(u64::from),
+                e.u16(&ident, 0x2e),
+                e.u16(&ident, 0x30),
+            )
+        };
+        let (Some(shoff), Some(shentsize), Some(shnum)) = (shoff, shentsize, shnum) else {
+            return Ok(None);
+        };
+        let table = elf.read_at(shoff, u64::from(shentsize) * u64::from(shnum))?;
+        let wide = elf.wide;
+        elf.sections = (0..usize::from(shnum))
+            .map_while(|index| {
+                let at = index * usize::from(shentsize);
+                Some(if wide {
+                    Section {
+                        kind: e.u32(&table, at + 4)?,
+                        offset: e.u64(&table, at + 24)?,
+                        size: e.u64(&table, at + 32)?,
+                        link: e.u32(&table, at + 40)?,
+                        info: e.u32(&table, at + 44)?,
+                    }
+                } else {
+                    Section {
+                        kind: e.u32(&table, at + 4)?,
+                        offset: e.u32(&table, at + 16)?.into(),
+                        size: e.u32(&table, at + 20)?.into(),
+                        link: e.u32(&table, at + 24)?,
+                        info: e.u32(&table, at + 28)?,
+                    }
+                })
+            })
+            .collect();
+        Ok(Some(elf))
+    }
+
+    fn find(&self, kind: u32) -> Option<Section> {
+        self.sections.iter().find(|s| s.kind == kind).copied()
+    }
+
+    /// The first section of `kind` and the string table it links to.
+    fn with_strings(&mut self, kind: u32) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let Some(section) = self.find(kind) else {
+            return Ok(None);
+        };
+        let Some(&strtab) = self.sections.get(section.link as usize) else {
+            return Ok(None);
+        };
+        let data = self.read_at(section.offset, section.size)?;
+        let strings = self.read_at(strtab.offset, strtab.size)?;
+        Ok(Some((data, strings)))
+    }
+
+    fn read_at(&mut self, offset: u64, len: u64) -> Result<Vec<u8>> {
+        // Offsets come from the file itself; don't trust `len` for the allocation.
+        let mut buf = Vec::with_capacity(len.min(1 << 20) as usize);
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| (&mut self.file).take(len).read_to_end(&mut buf))
+            .map_err(|e| FsError::Io {
+                context: format!("read {}", self.path.display()),
+                source: e,
+            })?;
+        Ok(buf)
+    }
+}
+
+struct Dynamic {
+    needed: Vec<String>,
+    /// `RUNPATH`, or `RPATH` when there is none, split into directories.
+    search_path: Vec<String>,
+}
+
+fn dynamic_section(path: &Path) -> Result<Option<Dynamic>> {
+    const SHT_DYNAMIC: u32 = 6;
+    const DT_NEEDED: u64 = 1;
+    const DT_RPATH: u64 = 15;
+    const DT_RUNPATH: u64 = 29;
+
+    let Some(mut elf) = ElfSections::open(path)? else {
+        return Ok(None);
+    };
+    let Some((entries, strings)) = elf.with_strings(SHT_DYNAMIC)? else {
+        return Ok(None);
+    };
+    let (e, width) = (elf.endian, if elf.wide { 8 } else { 4 });
+    let word = |at: usize| {
+        if elf.wide {
+            e.u64(&entries, at)
+        } else {
+            e.u32(&entries, at).map(u64::from)
+        }
+    };
+
+    let (mut needed, mut rpath, mut runpath) = (Vec::new(), None, None);
+    for at in (0..entries.len()).step_by(2 * width) {
+        let (Some(tag), Some(value)) = (word(at), word(at + width)) else {
+            break;
+        };
+        let string = || c_str(&strings, value).map(str::to_string);
+        match tag {
+            0 => break,
+            DT_NEEDED => needed.extend(string().filter(|name| !name.is_empty())),
+            DT_RPATH => rpath = string(),
+            DT_RUNPATH => runpath = string(),
+            _ => {}
+        }
+    }
+    let search_path = runpath
+        .or(rpath)
+        .map(|p| {
+            p.split(':')
+                .filter(|d| !d.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(Some(Dynamic {
+        needed,
+        search_path,
+    }))
+}
+
+/// The NUL-terminated string at `at` in a string table.
+fn c_str(strings: &[u8], at: u64) -> Option<&str> {
+    let rest = strings.get(usize::try_from(at).ok()?..)?;
+    std::str::from_utf8(&rest[..rest.iter().position(|&b| b == 0)?]).ok()
+}
+
+/// Directories the dynamic loader searches by default on this host.
+fn system_library_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    // FreeBSD's loader reads its own file, with the same syntax minus `include`.
+    let conf = if cfg!(target_os = "freebsd") {
+        "/etc/ld-elf.so.conf"
+    } else {
+        "/etc/ld.so.conf"
+    };
+    read_ld_so_conf(Path::new(conf), &mut dirs, 0);
+    for base in ["/lib", "/usr/lib", "/lib64", "/usr/lib64", "/usr/local/lib"] {
+        let base = Path::new(base);
+        dirs.push(base.to_path_buf());
+        // Debian-style multiarch directories, e.g. `/usr/lib/x86_64-linux-gnu`.
+        if let Ok(entries) = std::fs::read_dir(base) {
+            dirs.extend(
+                entries
+                    .flatten()
+                    .filter(|e| e.file_name().to_string_lossy().contains("-linux-"))
+                    .map(|e| e.path()),
+            );
+        }
+    }
+    dirs
+}
+
+/// Appends the directories listed in an `ld.so.conf`-style file, following
+/// `include` lines (whose patterns may only glob the file name).
+fn read_ld_so_conf(path: &Path, dirs: &mut Vec<PathBuf>, depth: u32) {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return;
+    };
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let Some(pattern) = line.strip_prefix("include").map(str::trim) else {
+            if line.starts_with('/') {
+                dirs.push(line.into());
+            }
+            continue;
+        };
+        if depth > 4 {
+            continue;
+        }
+        let pattern = Path::new(pattern);
+        let (Some(dir), Some(name)) = (pattern.parent(), pattern.file_name()) else {
+            continue;
+        };
+        let name = name.to_string_lossy();
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        let mut files: Vec<_> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| {
+                p.file_name().is_some_and(|n| {
+                    let n = n.to_string_lossy();
+                    match name.split_once('*') {
+                        Some((prefix, suffix)) => {
+                            n.len() >= prefix.len() + suffix.len()
+                                && n.starts_with(prefix)
+                                && n.ends_with(suffix)
+                        }
+                        None => n == name,
+                    }
+                })
+            })
+            .collect();
+        files.sort();
+        for file in files {
+            read_ld_so_conf(&file, dirs, depth + 1);
+        }
+    }
+}
+
+/// Endian-aware field reads for ELF structures.
+#[derive(Clone, Copy)]
+struct Elf {
+    le: bool,
+}
+
+impl Elf {
+    fn u16(self, bytes: &[u8], at: usize) -> Option<u16> {
+        let b = bytes.get(at..at.checked_add(2)?)?.try_into().ok()?;
+        Some(if self.le {
+            u16::from_le_bytes(b)
+        } else {
+            u16::from_be_bytes(b)
+        })
+    }
+
+    fn u32(self, bytes: &[u8], at: usize) -> Option<u32> {
+        let b = bytes.get(at..at.checked_add(4)?)?.try_into().ok()?;
+        Some(if self.le {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        })
+    }
+
+    fn u64(self, bytes: &[u8], at: usize) -> Option<u64> {
+        let b = bytes.get(at..at.checked_add(8)?)?.try_into().ok()?;
+        Some(if self.le {
+            u64::from_le_bytes(b)
+        } else {
+            u64::from_be_bytes(b)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The first 64 bytes of a little-endian ELF for `machine`.
+    fn elf_header(class: u8, machine: u16, osabi: u8) -> Vec<u8> {
+        let mut head = vec![0; 64];
+        head[..4].copy_from_slice(b"\x7fELF");
+        head[4] = class;
+        head[5] = 1;
+        head[6] = 1;
+        head[7] = osabi;
+        head[18..20].copy_from_slice(&machine.to_le_bytes());
+        head
+    }
+
+    /// A little-endian ELF64 x86_64 file with `sections` after the null one,
+    /// each its type, contents, `sh_link` and `sh_info`.
+    fn elf_file(sections: &[(u32, Vec<u8>, u32, u32)]) -> Vec<u8> {
+        let mut file = elf_header(2, 0x3e, 0);
+        let mut table = vec![0; 64];
+        for (kind, data, link, info) in sections {
+            let mut header = vec![0; 64];
+            header[4..8].copy_from_slice(&kind.to_le_bytes());
+            header[24..32].copy_from_slice(&(file.len() as u64).to_le_bytes());
+            header[32..40].copy_from_slice(&(data.len() as u64).to_le_bytes());
+            header[40..44].copy_from_slice(&link.to_le_bytes());
+            header[44..48].copy_from_slice(&info.to_le_bytes());
+            table.extend(header);
+            file.extend(data);
+        }
+        let shoff = file.len() as u64;
+        file[0x28..0x30].copy_from_slice(&shoff.to_le_bytes());
+        file[0x3a..0x3c].copy_from_slice(&64_u16.to_le_bytes());
+        file[0x3c..0x3e].copy_from_slice(&((sections.len() + 1) as u16).to_le_bytes());
+        file.extend(table);
+        file
+    }
+
+    /// Writes `bytes` to a new file in `dir`.
+    fn write(dir: &tempfile::TempDir, name: &str, bytes: &[u8]) -> PathBuf {
+        let path = dir.path().join(name);
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    /// A universal binary header with one 32-bit slice entry per `cpus`.
+    fn fat_header(cpus: &[u32]) -> Vec<u8> {
+        let mut head = 0xcafe_babe_u32.to_be_bytes().to_vec();
+        head.extend((cpus.len() as u32).to_be_bytes());
+        for (i, cpu) in cpus.iter().enumerate() {
+            let offset = 0x4000 * (i as u32 + 1);
+            for field in [*cpu, 0, offset, 0x1000, 14] {
+                head.extend(field.to_be_bytes());
+            }
+        }
+        head
+    }
+
+    /// A PE file whose `PE\0\0` signature is at `pe`, for `machine`.
+    fn pe_header(pe: u32, machine: u16) -> Vec<u8> {
+        let mut head = vec![0; pe as usize + 6];
+        head[..2].copy_from_slice(b"MZ");
+        head[0x3c..0x40].copy_from_slice(&pe.to_le_bytes());
+        head[pe as usize..pe as usize + 4].copy_from_slice(b"PE\0\0");
+        head[pe as usize + 4..].copy_from_slice(&machine.to_le_bytes());
+        head
+    }
+
+    fn archs(info: Option<BinaryInfo>) -> Option<(Format, Option<&'static str>, Vec<String>)> {
+        info.map(|info| (info.format, info.os, info.archs))
+    }
+
+    #[test]
+    fn sniffs_elf_headers() {
+        assert_eq!(
+            archs(sniff_bytes(&elf_header(2, 0x3e, 0))),
+            Some((Format::Elf, None, vec!["x86_64".into()]))
+        );
+        assert_eq!(
+            archs(sniff_bytes(&elf_header(2, 0xb7, 9))),
+            Some((Format::Elf, Some("freebsd"), vec!["aarch64".into()]))
+        );
+        assert_eq!(
+            archs(sniff_bytes(&elf_header(1, 0x1234, 0))),
+            Some((Format::Elf, None, vec!["0x1234".into()]))
+        );
+        // Big-endian machine field.
+        let mut big = elf_header(2, 0, 0);
+        big[5] = 2;
+        big[18..20].copy_from_slice(&0x28_u16.to_be_bytes());
+        assert_eq!(archs(sniff_bytes(&big)).unwrap().2, ["arm"]);
+    }
+
+    #[test]
+    fn sniffs_mach_o_headers() {
+        let mut thin = vec![0xcf, 0xfa, 0xed, 0xfe];
+        thin.extend(macho::CPU_TYPE_ARM64.to_le_bytes());
+        assert_eq!(
+            archs(sniff_bytes(&thin)),
+            Some((Format::MachO, Some("macos"), vec!["aarch64".into()]))
+        );
+        let fat = fat_header(&[macho::CPU_TYPE_X86_64, macho::CPU_TYPE_ARM64]);
+        assert_eq!(
+            archs(sniff_bytes(&fat)),
+            Some((
+                Format::MachOUniversal,
+                Some("macos"),
+                vec!["x86_64".into(), "aarch64".into()]
+            ))
+        );
+        let unknown = fat_header(&[7]);
+        assert_eq!(archs(sniff_bytes(&unknown)).unwrap().2, ["0x7"]);
+    }
+
+    #[test]
+    fn sniffs_pe_headers() {
+        assert_eq!(
+            archs(sniff_bytes(&pe_header(0x80, 0x8664))),
+            Some((Format::Pe, Some("windows"), vec!["x86_64".into()]))
+        );
+        assert_eq!(
+            archs(sniff_bytes(&pe_header(0x40, 0xaa64))).unwrap().2,
+            ["aarch64"]
+        );
+    }
+
+    #[test]
+    fn scripts_and_other_files() {
+        assert_eq!(
+            archs(sniff_bytes(b"#!/bin/sh\n")),
+            Some((Format::Script, None, Vec::new()))
+        );
+        for head in [
+            &b""[..],
+            b"<!DOCTYPE html><title>404 Not Found</title>",
+            b"PK\x03\x04",
+            // Java class files share the universal magic.
+            b"\xca\xfe\xba\xbe\x00\x00\x00\x34",
+        ] {
+            assert_eq!(sniff_bytes(head), None, "{head:?}");
+        }
+    }
+
+    #[test]
+    fn truncated_and_malformed_headers_are_unknown() {
+        let elf = elf_header(2, 0x3e, 0);
+        for len in [4, 5, 7, 19] {
+            assert_eq!(sniff_bytes(&elf[..len]), None, "ELF cut at {len}");
+        }
+        let thin = [0xcf, 0xfa, 0xed, 0xfe, 0x0c];
+        assert_eq!(sniff_bytes(&thin), None);
+        let fat = fat_header(&[macho::CPU_TYPE_X86_64, macho::CPU_TYPE_ARM64]);
+        assert_eq!(sniff_bytes(&fat[..30]), None);
+        let mut many = fat_header(&[]);
+        many[4..8].copy_from_slice(&17_u32.to_be_bytes());
+        assert_eq!(sniff_bytes(&many), None);
+
+        let pe = pe_header(0x80, 0x8664);
+        assert_eq!(sniff_bytes(&pe[..0x3e]), None, "no e_lfanew");
+        assert_eq!(sniff_bytes(&pe[..0x83]), None, "cut signature");
+        assert_eq!(sniff_bytes(&pe[..0x85]), None, "cut machine");
+        let mut far = pe.clone();
+        far[0x3c..0x40].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(sniff_bytes(&far), None, "offset past the end");
+        let mut dos = pe;
+        dos[0x80..0x84].copy_from_slice(b"NE\0\0");
+        assert_eq!(sniff_bytes(&dos), None, "not PE");
+    }
+
+    #[test]
+    fn check_rejects_unknown_and_foreign_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let page = dir.path().join("page");
+        std::fs::write(&page, "<html>rate limited</html>").unwrap();
+        let err = check(&page, "linux-x86_64").unwrap_err();
+        assert_eq!(err.kind(), crate::ErrorKind::Verification, "{err}");
+
+        let arm = dir.path().join("arm");
+        std::fs::write(&arm, elf_header(2, 0xb7, 0)).unwrap();
+        check(&arm, "linux-aarch64").unwrap();
+        let err = check(&arm, "linux-x86_64").unwrap_err();
+        assert!(err.to_string().contains("aarch64"), "{err}");
+        assert!(check(&arm, "macos-arm64").is_err());
+    }
+
+    const SHT_STRTAB: u32 = 3;
+    const SHT_GNU_VERNEED: u32 = 0x6fff_fffe;
+
+    /// `.dynstr` contents, and the offsets of the version names in it.
+    fn version_strings() -> (Vec<u8>, [u32; 3]) {
+        let strings = b"\0libc.so.6\0GLIBC_2.17\0GLIBC_2.34\0GLIBC_PRIVATE\0".to_vec();
+        (strings, [11, 22, 33])
+    }
+
+    /// A `.gnu.version_r` naming libc with one Vernaux entry per `names`.
+    fn verneed(names: &[u32]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(1_u16.to_le_bytes());
+        data.extend((names.len() as u16).to_le_bytes());
+        data.extend(1_u32.to_le_bytes()); // vn_file
+        data.extend(16_u32.to_le_bytes()); // vn_aux
+        data.extend(0_u32.to_le_bytes()); // vn_next
+        for (i, name) in names.iter().enumerate() {
+            data.extend(0_u32.to_le_bytes()); // vna_hash
+            data.extend(0_u32.to_le_bytes()); // vna_flags, vna_other
+            data.extend(name.to_le_bytes());
+            let next: u32 = if i + 1 < names.len() { 16 } else { 0 };
+            data.extend(next.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn reads_the_newest_glibc_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let (strings, [v2_17, v2_34, private]) = version_strings();
+        let bin = write(
+            &dir,
+            "bin",
+            &elf_file(&[
+                (SHT_STRTAB, strings, 0, 0),
+                (SHT_GNU_VERNEED, verneed(&[v2_34, private, v2_17]), 1, 1),
+            ]),
+        );
+        assert_eq!(required_glibc(&bin).unwrap(), Some((2, 34)));
+    }
+
+    #[test]
+    fn files_without_version_needs_have_no_glibc_requirement() {
+        let dir = tempfile::tempdir().unwrap();
+        let (strings, _) = version_strings();
+        for (name, bytes) in [
+            ("script", b"#!/bin/sh\n".to_vec()),
+            ("header", elf_header(2, 0x3e, 0)),
+            ("short", elf_header(2, 0x3e, 0)[..40].to_vec()),
+            ("static", elf_file(&[(SHT_STRTAB, strings, 0, 0)])),
+        ] {
+            let path = write(&dir, name, &bytes);
+            assert_eq!(required_glibc(&path).unwrap(), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn malformed_version_needs_are_errors_or_ignored_not_panics() {
+        let dir = tempfile::tempdir().unwrap();
+        let (strings, [_, v2_34, _]) = version_strings();
+        let good = verneed(&[v2_34]);
+
+        let mut far_aux = good.clone();
+        far_aux[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut far_name = good.clone();
+        far_name[24..28].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut many = good.clone();
+        many[2..4].copy_from_slice(&u16::MAX.to_le_bytes());
+        let mut looping = good.clone();
+        looping[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        let cases = [
+            ("cut", good[..10].to_vec(), 1, 1),
+            ("far aux", far_aux, 1, 1),
+            ("far name", far_name, 1, 1),
+            ("many aux", many, 1, 1),
+            ("far next", looping, 1, u32::MAX),
+            ("bad link", good.clone(), 9, 1),
+            ("no strings", good.clone(), 0, 1),
+        ];
+        for (name, data, link, count) in cases {
+            let bin = elf_file(&[
+                (SHT_STRTAB, strings.clone(), 0, 0),
+                (SHT_GNU_VERNEED, data, link, count),
+            ]);
+            let path = write(&dir, name, &bin);
+            assert!(
+                matches!(required_glibc(&path), Ok(None | Some((2, 34)))),
+                "{name}"
+            );
+        }
+
+        // Section offsets and sizes past the end of the file.
+        let mut bin = elf_file(&[(SHT_STRTAB, strings, 0, 0), (SHT_GNU_VERNEED, good, 1, 1)]);
+        let table = bin.len() - 3 * 64;
+        let verneed_header = table + 2 * 64;
+        bin[verneed_header + 24..verneed_header + 32]
+            .copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+        bin[verneed_header + 32..verneed_header + 40].copy_from_slice(&u64::MAX.to_le_bytes());
+        let path = write(&dir, "far section", &bin);
+        assert!(matches!(required_glibc(&path), Ok(None) | Err(_)));
+        let mut bin = std::fs::read(&path).unwrap();
+        bin[0x28..0x30].copy_from_slice(&u64::MAX.to_le_bytes());
+        bin[0x3c..0x3e].copy_from_slice(&u16::MAX.to_le_bytes());
+        let path = write(&dir, "far table", &bin);
+        assert!(matches!(required_glibc(&path), Ok(None) | Err(_)));
+    }
+
+    const SHT_DYNAMIC: u32 = 6;
+
+    /// A 64-bit `.dynamic` section holding `entries`, then `DT_NULL`.
+    fn dynamic(entries: &[(u64, u64)]) -> Vec<u8> {
+        entries
+            .iter()
+            .chain([&(0, 0)])
+            .flat_map(|(tag, value)| [tag.to_le_bytes(), value.to_le_bytes()])
+            .flatten()
+            .collect()
+    }
+
+    /// An x86_64 ELF needing `needed` with `runpath`, from a fresh `.dynstr`.
+    fn linked(needed: &[&str], runpath: Option<&str>) -> Vec<u8> {
+        let mut strings = vec![0];
+        let mut entries = Vec::new();
+        let names = needed
+            .iter()
+            .map(|name| (1, name))
+            .chain(runpath.as_ref().map(|p| (29, p)));
+        for (tag, name) in names {
+            entries.push((tag, strings.len() as u64));
+            strings.extend(name.as_bytes());
+            strings.push(0);
+        }
+        elf_file(&[
+            (SHT_STRTAB, strings, 0, 0),
+            (SHT_DYNAMIC, dynamic(&entries), 1, 0),
+        ])
+    }
+
+    #[test]
+    fn reads_needed_libraries_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let bin = write(
+            &dir,
+            "bin",
+            &linked(&["libz.so.1", "libc.so.6"], Some("$ORIGIN/lib")),
+        );
+        assert_eq!(needed_libraries(&bin).unwrap(), ["libz.so.1", "libc.so.6"]);
+        let header = write(&dir, "static", &elf_header(2, 0x3e, 0));
+        assert!(needed_libraries(&header).unwrap().is_empty());
+        let script = write(&dir, "script", b"#!/bin/sh\n");
+        assert!(needed_libraries(&script).unwrap().is_empty());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    #[test]
+    fn finds_libraries_through_runpath_and_reports_the_rest() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("lib")).unwrap();
+        let bin = write(
+            &dir,
+            "bin",
+            &linked(
+                &[
+                    "libzcash-found.so",
+                    "libzcash-gone.so",
+                    "libzcash-foreign.so",
+                ],
+                Some("$ORIGIN/lib"),
+            ),
+        );
+        // Found libraries are followed for their own needs, through their own
+        // RUNPATH; a library built for another architecture doesn't count.
+        write(
+            &dir,
+            "lib/libzcash-found.so",
+            &linked(&["libzcash-deep.so"], Some("$ORIGIN")),
+        );
+        write(&dir, "lib/libzcash-foreign.so", &elf_header(2, 0xb7, 0));
+        assert_eq!(
+            missing_libraries(&bin).unwrap(),
+            [
+                "libzcash-gone.so",
+                "libzcash-foreign.so",
+                "libzcash-deep.so"
+            ]
+        );
+
+        write(&dir, "lib/libzcash-gone.so", &elf_header(2, 0x3e, 0));
+        write(&dir, "lib/libzcash-foreign.so", &elf_header(2, 0x3e, 0));
+        write(&dir, "lib/libzcash-deep.so", &elf_header(2, 0x3e, 0));
+        assert!(missing_libraries(&bin).unwrap().is_empty());
+
+        let script = write(&dir, "script", b"#!/bin/sh\n");
+        assert!(missing_libraries(&script).unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_dynamic_sections_are_errors_or_ignored_not_panics() {
+        let dir = tempfile::tempdir().unwrap();
+        let strings = b"\0libz.so.1\0".to_vec();
+        let good = dynamic(&[(1, 1)]);
+        let mut odd = good.clone();
+        odd.truncate(good.len() - 3);
+        let cases = [
+            ("odd length", odd, 1),
+            ("half entry", good[..12].to_vec(), 1),
+            ("far string", dynamic(&[(1, u64::MAX), (29, 1 << 40)]), 1),
+            (
+                "unterminated",
+                dynamic(&[(1, strings.len() as u64 - 1), (1, 1)]),
+                1,
+            ),
+            ("no terminator", good[..16].to_vec(), 1),
+            ("bad link", good.clone(), 9),
+            ("self link", good.clone(), 2),
+        ];
+        for (name, data, link) in cases {
+            let bin = elf_file(&[
+                (SHT_STRTAB, strings.clone(), 0, 0),
+                (SHT_DYNAMIC, data, link, 0),
+            ]);
+            let path = write(&dir, name, &bin);
+            let needed = needed_libraries(&path);
+            assert!(
+                needed
+                    .as_ref()
+                    .is_ok_and(|n| n.iter().all(|l| l == "libz.so.1")),
+                "{name}: {needed:?}"
+            );
+            assert!(missing_libraries(&path).is_ok(), "{name}");
+        }
+
+        let mut bin = elf_file(&[(SHT_STRTAB, strings, 0, 0), (SHT_DYNAMIC, good, 1, 0)]);
+        let dynamic_header = bin.len() - 64;
+        bin[dynamic_header + 24..dynamic_header + 32]
+            .copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+        let path = write(&dir, "far section", &bin);
+        assert!(matches!(
+            needed_libraries(&path).as_deref(),
+            Ok([]) | Err(_)
+        ));
+        assert!(matches!(
+            missing_libraries(&path).as_deref(),
+            Ok([]) | Err(_)
+        ));
+    }
+}
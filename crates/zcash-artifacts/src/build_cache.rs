@@ -0,0 +1,283 @@
+//! A registry repository as a team-wide cache of local builds.
+//!
+//! With [`OciConfig::build_cache`](crate::oci::OciConfig::build_cache) set, a
+//! `Build` source that misses the local cache is looked up there, by
+//! [`BuildCacheLayer`](crate::pipeline::BuildCacheLayer), before anything is
+//! built; even with [`BuildConfig::allow_build`](crate::BuildConfig::allow_build)
+//! off. With [`OciConfig::push_builds`](crate::oci::OciConfig::push_builds) on
+//! (say, in CI), every fresh build is pushed there.
+//!
+//! Entries are [ORAS](https://oras.land) artifacts, tagged with their cache key:
+//!
+//! ```text
+//! <repository>:<service>-<commit>-<platform or target>-v<schema>
+//!   artifactType: application/vnd.zcash-artifacts.build.v1
+//!   layers:       the executable and its companions, then the build's
+//!                 provenance.json (see `crate::slsa`) and META.json,
+//!                 each titled with its file name
+//! ```
+//!
+//! so `oras pull <repository>:<tag>` gets the files as well. Builds of dirty
+//! worktrees are neither looked up nor pushed, as their key covers local edits.
+//!
+//! Pulled entries are checked and finalized like builds. META keeps the
+//! provenance of the machine that built it, and records the artifact pulled in
+//! `image` and `image_digest`. Binaries from the repository run with the trust
+//! of whoever can push to it.
+
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    ResolveContext,
+    cache::{self, CacheKey, CachePaths},
+    error::{FsError, OciError, Result},
+    oci::{Client, Reference, hex, sha256_digest},
+    oras::{self, Manifest, TITLE},
+    registry::ToolSpec,
+};
+
+const ARTIFACT_TYPE: &str = "application/vnd.zcash-artifacts.build.v1";
+const META_TYPE: &str = "application/vnd.zcash-artifacts.meta.v1+json";
+const PROVENANCE_TYPE: &str = "application/vnd.in-toto+json";
+const FILE_TYPE: &str = "application/octet-stream";
+const MANIFEST_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+/// The `{}` config of artifacts that have none.
+const EMPTY_TYPE: &str = "application/vnd.oci.empty.v1+json";
+
+/// The configured repository, tagged for `key`; `None` without a build cache
+/// or for dirty builds.
+fn reference(ctx: &ResolveContext<'_>, key: &CacheKey) -> Result<Option<Reference>> {
+    let Some(repository) = &ctx.config.oci.build_cache else {
+        return Ok(None);
+    };
+    if key.worktree_hash.is_some() {
+        return Ok(None);
+    }
+    let parsed = Reference::parse(repository)?;
+    if parsed.tag.is_some() || parsed.digest.is_some() {
+        return Err(OciError::InvalidReference {
+            reference: repository.clone(),
+            reason: "a build cache is a repository, without tag or digest".into(),
+        }
+        .into());
+    }
+    Ok(Some(Reference {
+        tag: Some(format!(
+            "{}-{}-{}-v{}",
+            key.service, key.revision, key.platform, key.schema
+        )),
+        ..parsed
+    }))
+}
+
+/// Pulls the build for `key` into `paths`, if the repository has it, and
+/// returns the cached executable.
+///
+/// The caller holds the entry's lock.
+pub(crate) fn pull(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    key: &CacheKey,
+    paths: &CachePaths,
+    bin_name: &str,
+    platform: &str,
+) -> Result<Option<PathBuf>> {
+    let Some(reference) = reference(ctx, key)? else {
+        return Ok(None);
+    };
+    let provider = ctx.config.credential_provider();
+    let mut client = Client::new(&reference, provider.as_ref(), ctx);
+    let tag = reference.tag.as_deref().expect("tagged above");
+    let Some(fetched) = client.find_manifest(tag, &[MANIFEST_TYPE])? else {
+        return Ok(None);
+    };
+    let bad = |reason: String| OciError::Pull {
+        reference: reference.to_string(),
+        source: reason.into(),
+    };
+    let manifest: Manifest =
+        serde_json::from_slice(&fetched.body).map_err(|e| bad(format!("bad manifest: {e}")))?;
+    if manifest.artifact_type.as_deref() != Some(ARTIFACT_TYPE) {
+        return Err(bad(format!("not a build cache entry ({ARTIFACT_TYPE})")).into());
+    }
+
+    let work = paths.staging()?;
+    let result = pull_into(ctx, &mut client, &manifest, &work).and_then(|mut meta| {
+        let binary = work.join(bin_name);
+        if !binary.is_file() {
+            return Err(bad(format!("no {bin_name} in it")).into());
+        }
+        // Checked like a build's output; `finalize` sets the exec bits.
+        crate::binfmt::check(&binary, platform)?;
+        let companions: Vec<_> = manifest
+            .layers
+            .iter()
+            .filter(|layer| layer.media_type == FILE_TYPE)
+            .filter_map(|layer| layer.title())
+            .filter(|name| *name != bin_name)
+            .map(|name| (name.to_string(), work.join(name)))
+            .collect();
+        meta.image = Some(reference.to_string());
+        meta.image_digest = Some(fetched.digest.clone());
+        crate::oci::record_transport(ctx, &mut meta, &client);
+        // The builder's provenance, if it pushed one; in place before the
+        // binary, whose presence marks the entry complete.
+        if let Some(name) = manifest
+            .layers
+            .iter()
+            .find(|layer| layer.media_type == PROVENANCE_TYPE)
+            .and_then(|layer| layer.title())
+        {
+            let statement = std::fs::read(work.join(name)).map_err(|e| FsError::Io {
+                context: format!("read {}", work.join(name).display()),
+                source: e,
+            })?;
+            cache::write_atomic(&paths.meta.join(crate::slsa::STATEMENT), &statement)?;
+        }
+        cache::finalize(
+            ctx,
+            paths,
+            bin_name,
+            &binary,
+            &companions,
+            spec.version_probe
+                .as_deref()
+                .filter(|_| platform == crate::platform::host()),
+            false,
+            meta,
+        )
+    });
+    let _ = std::fs::remove_dir_all(&work);
+    result.map(Some)
+}
+
+/// Downloads the artifact's files into `work` and returns its META.
+fn pull_into(
+    ctx: &ResolveContext<'_>,
+    client: &mut Client<'_>,
+    manifest: &Manifest,
+    work: &Path,
+) -> Result<cache::Meta> {
+    oras::download(ctx, client, &manifest.layers, work)?;
+    let Some(name) = manifest
+        .layers
+        .iter()
+        .find(|layer| layer.media_type == META_TYPE)
+        .and_then(|layer| layer.title())
+    else {
+        return Err(client.error("no META").into());
+    };
+    let path = work.join(name);
+    let bytes = std::fs::read(&path).map_err(|e| FsError::Io {
+        context: format!("read {}", path.display()),
+        source: e,
+    })?;
+    serde_json::from_slice(&bytes).map_err(|e| client.error(format!("bad META: {e}")).into())
+}
+
+/// Pushes the finalized entry at `paths` for `key`, if a build cache is
+/// configured and [`push_builds`](crate::oci::OciConfig::push_builds) is on.
+pub(crate) fn push(
+    ctx: &ResolveContext<'_>,
+    key: &CacheKey,
+    paths: &CachePaths,
+    bin_name: &str,
+) -> Result<()> {
+    if !ctx.config.oci.push_builds {
+        return Ok(());
+    }
+    let Some(reference) = reference(ctx, key)? else {
+        return Ok(());
+    };
+    let provider = ctx.config.credential_provider();
+    let mut client = Client::for_push(&reference, provider.as_ref(), ctx);
+
+    // The binary first, then its companions, then provenance and META.
+    let mut files = vec![(bin_name.to_string(), paths.out.join(bin_name), FILE_TYPE)];
+    let mut companions = Vec::new();
+    for entry in std::fs::read_dir(&paths.out).map_err(|e| FsError::Io {
+        context: format!("read_dir {}", paths.out.display()),
+        source: e,
+    })? {
+        let entry = entry.map_err(|e| FsError::Io {
+            context: format!("read_dir {}", paths.out.display()),
+            source: e,
+        })?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name != bin_name && !name.starts_with('.') && entry.path().is_file() {
+            companions.push((name, entry.path(), FILE_TYPE));
+        }
+    }
+    companions.sort();
+    files.extend(companions);
+    let provenance = paths.meta.join(crate::slsa::STATEMENT);
+    if provenance.is_file() {
+        files.push((crate::slsa::STATEMENT.into(), provenance, PROVENANCE_TYPE));
+    }
+    files.push(("META.json".into(), paths.meta.join("META.json"), META_TYPE));
+
+    let mut layers = Vec::new();
+    for (name, path, media_type) in &files {
+        let (digest, size) = file_digest(path)?;
+        client.push_blob(&digest, &|| {
+            std::fs::File::open(path).map(Into::into).map_err(|e| {
+                FsError::Io {
+                    context: format!("open {}", path.display()),
+                    source: e,
+                }
+                .into()
+            })
+        })?;
+        layers.push(serde_json::json!({
+            "mediaType": media_type,
+            "digest": digest,
+            "size": size,
+            "annotations": { TITLE: name },
+        }));
+    }
+    let empty = b"{}";
+    let empty_digest = sha256_digest(empty);
+    client.push_blob(&empty_digest, &|| Ok(empty.to_vec().into()))?;
+
+    let manifest = serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": MANIFEST_TYPE,
+        "artifactType": ARTIFACT_TYPE,
+        "config": { "mediaType": EMPTY_TYPE, "digest": empty_digest, "size": empty.len() },
+        "layers": layers,
+        "annotations": { "org.opencontainers.image.revision": key.revision },
+    });
+    let tag = reference.tag.as_deref().expect("tagged above");
+    client.push_manifest(
+        tag,
+        MANIFEST_TYPE,
+        &serde_json::to_vec(&manifest).expect("manifest serializes"),
+    )?;
+    Ok(())
+}
+
+/// The sha256 digest and size of the file at `path`.
+fn file_digest(path: &Path) -> Result<(String, u64)> {
+    let io = |e| FsError::Io {
+        context: format!("read {}", path.display()),
+        source: e,
+    };
+    let mut file = std::fs::File::open(path).map_err(io)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let n = file.read(&mut buf).map_err(io)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    Ok((format!("sha256:{}", hex(&hasher.finalize())), size))
+}
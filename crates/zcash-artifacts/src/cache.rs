@@ -1,4 +1,4 @@
-//! # Cache behavior & layout (for the local-build path)
+//! # Cache behavior & layout
 //!
 //! `zcash-artifacts` maintains a **content-addressed cache** for artifacts it
 //! produces. The cache makes repeated resolutions fast and deterministic, avoids
@@ -10,22 +10,34 @@
 //! **build logs**, and a small `META.json` with provenance (commit, dirty status,
 //! worktree hash, platform triple, jobs, version string, timestamps).
 //!
+//! Downloaded releases (`http` feature) are cached the same way once their
+//! checksum verified: the executable, its companions and `META.json`, with the
+//! release version and URL in place of the git fields.
+//!
 //! ## Where is it?
-//! The root directory is `Config::cache_root`. By default, callers set this to a
-//! per-user cache dir (e.g., `~/.cache/zcash-artifacts`). Under it, this crate
-//! creates a stable hierarchy keyed by the artifact identity.
+//! The root directory is `ResolverConfig::cache_root`. [`ResolverConfig::builder`](crate::ResolverConfig::builder)
+//! defaults it to [`default_root`], the per-user cache dir (e.g.
+//! `~/.cache/zcash-artifacts`). Under it, this crate creates a stable hierarchy
+//! keyed by the artifact identity.
 //!
 //! ### Directory layout
 //! ```text
 //! <cache_root>/
+//!   logs/                                    # the resolver's own log; see `observe::LogFile`
+//!     resolver.log
+//!     resolver.log.1                         # older, rotated out at 4 MiB
 //!   zcashd/                                  # service name
 //!     <key>/                                 # unique key for this build (see below)
+//!       .staging-<pid>/                      # downloads and files not yet verified; see below
 //!       out/                                 # final runnable binaries returned to the caller
 //!         zcashd
 //!       logs/                                # stdout/stderr captured during build
 //!         build-2025-09-29T14-21-03.log
 //!       meta/                                # provenance
 //!         META.json
+//!         META.json.sig                      # with a signing key; see `signing`
+//!         provenance.json                    # builds' SLSA provenance; see `slsa`
+//!         worktree.patch                     # dirty builds' uncommitted changes
 //! ```
 //!
 //! ## How is the cache key computed?
@@ -37,15 +49,33 @@
 //! - **worktree hash** *(optional)*: when the worktree is dirty and policy allows
 //!   dirty builds, we compute a deterministic hash of tracked files (and, if
 //!   requested, untracked files). This keeps each local edit isolated.
-//! - **platform triple**: e.g. `"linux-x86_64"`, `"linux-aarch64"`, `"macos-arm64"`
-//! - **builder schema version**: an internal integer you can bump if you change
-//!   cache layout or the build recipe in a way that invalidates old entries.
+//! - **platform triple**: e.g. `"linux-x86_64"`, `"macos-arm64"`, `"freebsd-x86_64"`,
+//!   always in the canonical spelling documented in [`crate::platform`]. That
+//!   spelling is stable, so upgrading this crate doesn't orphan existing entries;
+//!   entries written with older spellings (`darwin-aarch64`) are renamed to it the
+//!   first time a resolver uses the cache.
+//!   Cross builds (`target` on a `Build` source) use the Rust target triple
+//!   instead, e.g. `"aarch64-unknown-linux-gnu"`, keeping them apart from native ones.
+//! - **builder schema version**: a per-service integer
+//!   ([`ToolSpec::builder_schema`](crate::registry::ToolSpec::builder_schema)) you can
+//!   bump if you change cache layout or that service's build recipe in a way that
+//!   invalidates old entries. Bumping zebrad's schema leaves zcashd entries alone.
+//!
+//! For releases, the **commit** is replaced by the release version (and there is
+//! no worktree hash), prefixed `guix-` for Guix builds, `brew-<formula>-` for
+//! Homebrew bottles and `deb-<suite>-` for Debian packages; bare `Url` sources
+//! live under `url/`, keyed by the first 16 hex digits of their sha256. Pulled images live under `oci/`, keyed the same
+//! way by their manifest digest; see [`crate::oci`]. Nix builds live under `nix/`,
+//! keyed by their store path's hash.
 //!
 //! Conceptually:
 //! ```text
 //! key = "zcashd|" + <commit> + ( "+" + <worktree_hash> if dirty ) + "|" + <platform> + "|v" + <schema>
 //! ```
 //!
+//! On disk, the key directory is named `<commit>[+<worktree_hash>]-<platform>-v<schema>`
+//! (the service is already the parent directory).
+//!
 //! Using a per-key directory means concurrent runs that target *different keys*
 //! never contend, and rebuilding the same commit just becomes a cache hit.
 //!
@@ -60,20 +90,23 @@
 //!   “thundering herd” of redundant work.
 //!
 //! ## When do we reuse vs. rebuild?
-//! - **Reuse (cache hit)** when `out/zcashd` exists for the computed key and looks
-//!   executable (regular file, exec bit set). The build script is **not** run.
+//! - **Reuse (cache hit)** when `out/zcashd` exists for the computed key, looks
+//!   executable (regular file, exec bit set) and its header matches the platform.
+//!   The build script is **not** run.
 //! - **Rebuild** when any of these change:
 //!   - `refspec` resolves to a different commit,
 //!   - dirty/clean policy flips (or worktree contents changed, altering the hash),
 //!   - the platform triple changes (different OS/arch),
 //!   - you bump the **builder schema version**,
-//!   - the cached `out/zcashd` is missing or fails the executable sanity check.
+//!   - the cached `out/zcashd` is missing or fails the executable sanity checks.
 //!
 //! ## Executable sanity
 //! On Unix platforms we:
 //! - ensure the file is a regular file,
-//! - ensure the exec bit is set (and set it if we own the file),
-//! - optionally sniff the file header (ELF/Mach-O) to catch obviously corrupt outputs.
+//! - ensure the exec bit is set, setting it if we own the file; an entry owned by
+//!   another user (shared cache) that lacks it is treated as a miss,
+//! - sniff the file header (ELF/Mach-O/PE, see [`crate::binfmt`]) and check its OS
+//!   and architecture against the platform, to catch corrupt or foreign outputs.
 //!
 //! ## Provenance (`meta/META.json`)
 //! A tiny JSON file written next to the artifact records the most important facts
@@ -90,6 +123,7 @@
 //!   "worktree_hash": null,
 //!   "jobs":    8,
 //!   "host":    "linux-x86_64",
+//!   "platform": "linux-x86_64",
 //!   "built_at": "2025-09-29T14:21:03Z",
 //!   "builder_schema": 1,
 //!   "version_string": "Zcashd version v5.9.0 (…)"
@@ -102,9 +136,15 @@
 //! ## Security posture
 //! - The cache **never executes scripts from inside the cache**. Scripts are run
 //!   only from your repository (e.g., `./zcutil/build.sh`) during a build.
-//! - Downloaded content is **not** part of the local-build path in the MVP, but
-//!   if upstream’s build pulls dependencies, that happens within the upstream
+//! - Downloaded content only enters the cache after its sha256 matched; if
+//!   upstream’s build pulls dependencies, that happens within the upstream
 //!   script, not the cache itself.
+//! - Downloads are fetched, unpacked and checked (checksum, signatures, header
+//!   sniff) in the entry's `.staging-<pid>/`, where the files are also staged
+//!   and version-probed. Only once every check passed are they moved into
+//!   `out/`, so nothing there was unverified. A failed resolution removes its
+//!   staging directory; one left by a process that died is removed by the
+//!   next resolution of the key.
 //! - The cache layout segregates artifacts by commit & platform; copying an
 //!   artifact between machines should only be done when the platform matches.
 //!
@@ -114,40 +154,39 @@
 //! - nuke everything: delete `<cache_root>/zcash-artifacts/zcashd/`,
 //! - keep a retention policy in your tooling (e.g., “keep last N keys”). Future
 //!   versions may add helpers, but manual removal is safe: keys are immutable.
+//! - after removing pulled images under `oci/`, call `oci::prune_layers` (`oci`
+//!   feature) to free the layers no remaining image uses.
 //!
 //! ## Example (end-to-end, local build with cache)
 //! ```no_run
-//! use std::path::PathBuf;
-//! # use zcash_artifacts::{Provider, Config};
 //! # #[cfg(feature = "local-build")]
-//! # use zcash_artifacts::{ArtifactSource, BuildDefaults, GitPolicy};
-//!
-//! // Configure the library (no env vars).
-//! let cfg = Config {
-//!     cache_root: dirs::cache_dir().unwrap().join("zcash-artifacts"),
-//!     platform_override: None,
-//!     # #[cfg(feature = "local-build")]
-//!     build_defaults: BuildDefaults {
-//!         allow_build: true,
-//!         default_jobs: None, // auto: CPU cores
-//!         default_policy: GitPolicy::RequireClean,
-//!         default_expected_output: PathBuf::from("src/zcashd"),
-//!     },
+//! # {
+//! use zcash_artifacts::{
+//!     ArtifactResolver, ArtifactSource, BuildIsolation, ResolverConfig, git::GitPolicy,
+//!     registry::ZCASHD,
 //! };
-//! let provider = Provider::new(cfg);
+//!
+//! // Configure the library (no env vars; see `ResolverConfig::from_env`).
+//! let cfg = ResolverConfig::builder()
+//!     .cache_root("/home/me/.cache/zcash-artifacts") // default: `cache::default_root()`
+//!     .allow_build(true) // jobs default to the CPU cores
+//!     .isolation(BuildIsolation::None) // or sandbox the build
+//!     .finish(); // resolves for the host; credentials from <cache_root>/credentials.toml
+//! let resolver = ArtifactResolver::new(cfg);
 //!
 //! // Ask to build from a local clone; subsequent calls hit the cache.
-//! # #[cfg(feature = "local-build")]
-//! let resolved = provider.resolve(&ArtifactSource::Build {
+//! let resolved = resolver.resolve(&ArtifactSource::Build {
+//!     service: ZCASHD,
 //!     repo: "/home/me/src/zcashd".into(),
 //!     refspec: None,                       // HEAD
 //!     policy: GitPolicy::RequireClean,     // or AllowDirty { hash_untracked: true }
 //!     expected_output: None,               // default "src/zcashd"
-//!     jobs: Some(8),
+//!     target: None,                        // build for the host
 //! }).expect("build or cache hit");
 //!
 //! // Use the executable path with your launcher:
-//! // zcash_services::launch_zcashd(... resolved.path ...);
+//! // zcash_services::launch_zcashd(... resolved.primary_path() ...);
+//! # }
 //! ```
 //!
 //! ## More succinctly
@@ -155,3 +194,1009 @@
 //! - If the key exists, you get a **cache hit** (no build).
 //! - Writes are **atomic**; concurrent builds of the same key are serialized.
 //! - `META.json` provides the provenance you’ll want in CI and bug reports.
+
+// Entries are only written by downloads and builds.
+#![cfg_attr(not(any(feature = "http", feature = "local-build")), allow(dead_code))]
+
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{FsError, InputError, Result};
+
+/// Identity of a cache entry; see the module docs for how it's derived.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CacheKey {
+    /// Service name, or `url` for downloads not tied to a service.
+    pub service: String,
+    /// Commit SHA for builds, release version for releases, checksum prefix for URLs.
+    pub revision: String,
+    pub worktree_hash: Option<String>,
+    pub platform: String,
+    pub schema: u32,
+}
+
+impl CacheKey {
+    /// Directory name of this key under `<cache_root>/<service>/`.
+    pub fn dir_name(&self) -> String {
+        match &self.worktree_hash {
+            Some(h) => format!("{}+{h}-{}-v{}", self.revision, self.platform, self.schema),
+            None => format!("{}-{}-v{}", self.revision, self.platform, self.schema),
+        }
+    }
+}
+
+impl std::fmt::Display for CacheKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}|{}", self.service, self.revision)?;
+        if let Some(h) = &self.worktree_hash {
+            write!(f, "+{h}")?;
+        }
+        write!(f, "|{}|v{}", self.platform, self.schema)
+    }
+}
+
+/// The directory name an entry written with a legacy platform spelling (e.g.
+/// `v2.0.0-darwin-aarch64-v1`) has under the canonical one, if it differs.
+fn canonical_dir_name(name: &str) -> Option<String> {
+    let (rest, schema) = name.rsplit_once("-v")?;
+    schema.parse::<u32>().ok()?;
+    let parts: Vec<&str> = rest.split('-').collect();
+    // `<os>-<arch>` or its musl flavor, after at least one revision component.
+    let (platform_len, musl) = match parts.last() {
+        Some(&"musl") => (3, true),
+        _ => (2, false),
+    };
+    let split = parts.len().checked_sub(platform_len).filter(|&n| n > 0)?;
+    let old = parts[split..split + 2].join("-");
+    let mut new = crate::platform::canonical_alias(&old)?;
+    if new == old {
+        return None;
+    }
+    if musl {
+        new = crate::platform::musl_flavor(&new);
+    }
+    Some(format!("{}-{new}-v{schema}", parts[..split].join("-")))
+}
+
+/// The per-user cache directory of this crate: `$XDG_CACHE_HOME/zcash-artifacts`
+/// (`~/.cache/zcash-artifacts` without it) on Linux and the BSDs,
+/// `~/Library/Caches/zcash-artifacts` on macOS and
+/// `%LOCALAPPDATA%\zcash-artifacts` on Windows. Falls back to the system temp
+/// directory when there is no home directory to speak of.
+pub fn default_root() -> PathBuf {
+    let env_dir = |var: &str| {
+        std::env::var_os(var)
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+    };
+    let base = if cfg!(windows) {
+        env_dir("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library/Caches"))
+    } else {
+        env_dir("XDG_CACHE_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".cache")))
+    };
+    base.unwrap_or_else(std::env::temp_dir)
+        .join("zcash-artifacts")
+}
+
+/// Renames entries under `cache_root` written with legacy platform spellings to
+/// their canonical keys, once per root and process.
+///
+/// Each entry is moved under its lock, and its META rewritten to name the
+/// canonical key and platform so hits don't reject it for naming another key.
+/// That invalidates META's signature, which is removed: with
+/// [signing](crate::signing) configured, the entry is then replaced on its
+/// next resolution rather than trusted unsigned.
+///
+/// Best effort: entries that are locked, whose canonical key already exists,
+/// or that can't be renamed, are left where they are and simply no longer hit.
+pub(crate) fn migrate_legacy_entries(cache_root: &Path) {
+    static DONE: std::sync::Mutex<Vec<PathBuf>> = std::sync::Mutex::new(Vec::new());
+    {
+        let mut done = DONE.lock().unwrap_or_else(|e| e.into_inner());
+        if done.iter().any(|root| root == cache_root) {
+            return;
+        }
+        done.push(cache_root.to_path_buf());
+    }
+    let Ok(services) = fs::read_dir(cache_root) else {
+        return;
+    };
+    for service in services.flatten() {
+        let Ok(entries) = fs::read_dir(service.path()) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let Some(new) = canonical_dir_name(&name.to_string_lossy()) else {
+                continue;
+            };
+            // The lock file moves with the entry, so it stays held until the
+            // rename and META rewrite are done.
+            let Ok(lock) = File::create(entry.path().join(".lock")) else {
+                continue;
+            };
+            if lock.try_lock().is_err() {
+                continue;
+            }
+            let target = service.path().join(new);
+            if target.exists() || fs::rename(entry.path(), &target).is_err() {
+                continue;
+            }
+            rekey_meta(&target.join("meta"));
+        }
+    }
+}
+
+/// Rewrites a migrated entry's META to name the canonical platform, in its
+/// `key` and `platform`, and removes its now stale signature. A META that
+/// can't be rewritten is removed too.
+fn rekey_meta(meta_dir: &Path) {
+    let path = meta_dir.join("META.json");
+    let _ = fs::remove_file(meta_dir.join("META.json.sig"));
+    let Ok(bytes) = fs::read(&path) else {
+        return;
+    };
+    let Ok(mut meta) = serde_json::from_slice::<serde_json::Value>(&bytes) else {
+        let _ = fs::remove_file(&path);
+        return;
+    };
+    if let Some(platform) = meta.get_mut("platform")
+        && let Some(old) = platform.as_str()
+    {
+        *platform = crate::platform::normalize(old).into();
+    }
+    // `service|revision[+worktree]|platform|vN`
+    if let Some(key) = meta.get_mut("key")
+        && let Some(old) = key.as_str()
+    {
+        let mut parts: Vec<String> = old.split('|').map(str::to_string).collect();
+        if let [_, _, platform, _] = parts.as_mut_slice() {
+            *platform = crate::platform::normalize(platform);
+        }
+        *key = parts.join("|").into();
+    }
+    let json = serde_json::to_vec_pretty(&meta).expect("META serializes");
+    if write_atomic(&path, &json).is_err() {
+        let _ = fs::remove_file(&path);
+    }
+}
+
+/// How often a cancellable resolution waiting for an entry's lock tries it.
+const LOCK_POLL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// The directories making up one cache entry.
+#[derive(Debug, Clone)]
+pub(crate) struct CachePaths {
+    /// The key the entry is for; recorded in META.
+    pub key: CacheKey,
+    pub root: PathBuf,
+    pub out: PathBuf,
+    pub logs: PathBuf,
+    pub meta: PathBuf,
+}
+
+impl CachePaths {
+    /// The paths of `key`'s entry under `cache_root`.
+    ///
+    /// Versions and services can come from config files, manifests and
+    /// package indexes, so a key component that would name a directory other
+    /// than its own (empty, `.`, `..`, or containing a path separator or NUL)
+    /// is rejected rather than joined onto `cache_root`.
+    pub fn new(cache_root: &Path, key: &CacheKey) -> Result<Self> {
+        let components = [
+            Some(&key.service),
+            Some(&key.revision),
+            key.worktree_hash.as_ref(),
+            Some(&key.platform),
+        ];
+        for component in components.into_iter().flatten() {
+            let bad = match component.as_str() {
+                "" => Some("empty component".to_string()),
+                "." | ".." => Some(format!("component `{component}` names another directory")),
+                _ => component
+                    .chars()
+                    .find(|&c| matches!(c, '/' | '\\' | '\0') || (cfg!(windows) && c == ':'))
+                    .map(|c| format!("component {component:?} contains {c:?}")),
+            };
+            if let Some(reason) = bad {
+                return Err(InputError::InvalidCacheKey {
+                    key: key.to_string(),
+                    reason,
+                }
+                .into());
+            }
+        }
+        let root = cache_root.join(&key.service).join(key.dir_name());
+        Ok(Self {
+            out: root.join("out"),
+            logs: root.join("logs"),
+            meta: root.join("meta"),
+            root,
+            key: key.clone(),
+        })
+    }
+
+    /// Where the entry's downloads are unpacked and checked, and its files
+    /// staged, before anything is moved into `out/`; one per process.
+    pub fn staging_dir(&self) -> PathBuf {
+        self.root.join(format!(".staging-{}", std::process::id()))
+    }
+
+    /// Creates an empty [staging directory](Self::staging_dir), removing
+    /// those left behind by processes that died; call with the
+    /// [lock](Self::lock) held.
+    #[cfg(feature = "http")]
+    pub fn staging(&self) -> Result<PathBuf> {
+        if let Ok(entries) = fs::read_dir(&self.root) {
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                // `.work-` directories are what older versions staged in.
+                if name.starts_with(".staging-") || name.starts_with(".work-") {
+                    let _ = fs::remove_dir_all(entry.path());
+                }
+            }
+        }
+        let dir = self.staging_dir();
+        fs::create_dir_all(&dir).map_err(|e| FsError::Io {
+            context: format!("mkdir {}", dir.display()),
+            source: e,
+        })?;
+        Ok(dir)
+    }
+
+    pub fn create_dirs(&self) -> Result<()> {
+        for dir in [&self.out, &self.logs, &self.meta] {
+            fs::create_dir_all(dir).map_err(|e| FsError::Io {
+                context: format!("mkdir {}", dir.display()),
+                source: e,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Takes the per-key lock, blocking until any other holder releases it,
+    /// or until the resolution is cancelled. A wait is reported to `ctx`'s
+    /// observers as [`Event::LockWaited`](crate::observe::Event::LockWaited).
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "cache_lock", level = "debug", skip_all, fields(entry = %self.root.display()))
+    )]
+    pub fn lock(&self, ctx: &crate::ResolveContext<'_>) -> Result<File> {
+        use std::fs::TryLockError;
+
+        let path = self.root.join(".lock");
+        let io = |action: &str, e| FsError::Io {
+            context: format!("{action} {}", path.display()),
+            source: e,
+        };
+        let file = File::create(&path).map_err(|e| io("create", e))?;
+        match file.try_lock() {
+            Ok(()) => return Ok(file),
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(e)) => return Err(io("lock", e).into()),
+        }
+        let started = std::time::Instant::now();
+        match ctx.cancel {
+            None => file.lock().map_err(|e| io("lock", e))?,
+            Some(cancel) => loop {
+                cancel.check()?;
+                std::thread::sleep(LOCK_POLL);
+                match file.try_lock() {
+                    Ok(()) => break,
+                    Err(TryLockError::WouldBlock) => {}
+                    Err(TryLockError::Error(e)) => return Err(io("lock", e).into()),
+                }
+            },
+        }
+        ctx.emit(crate::observe::Event::LockWaited {
+            entry: &self.root,
+            waited: started.elapsed(),
+        });
+        Ok(file)
+    }
+}
+
+/// Provenance written to `meta/META.json`.
+///
+/// Fields that only make sense for some sources are omitted when unset.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Meta {
+    pub service: String,
+    /// The source kind, e.g. `local-repo` or `release`.
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refspec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[serde(default)]
+    pub dirty: bool,
+    #[serde(default)]
+    pub worktree_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<usize>,
+    /// Sandbox the build ran in, e.g. `bubblewrap`; see [`crate::BuildIsolation`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isolation: Option<String>,
+    /// Remote builder the build ran on, e.g. `ssh://builder`; see [`crate::executor`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executor: Option<String>,
+    /// Rust target triple of a cross build.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Requested release version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release: Option<String>,
+    /// Where the asset was downloaded from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// `SHA256SUMS` of the independent rebuilds a Guix release was checked
+    /// against; see `crate::guix` (`http` feature).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rebuilds: Vec<String>,
+    /// Normalized reference of a pulled image, or of the
+    /// [build cache](crate::build_cache) artifact a build was pulled from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Flake output a Nix entry was built from, `<flake ref>#<attr>`; see
+    /// `crate::nix` (`nix` feature).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flake: Option<String>,
+    /// Nix store path the binary was taken from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub store_path: Option<PathBuf>,
+    /// Manifest digest of a pulled image or artifact; for multi-arch images,
+    /// that of the platform's manifest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_digest: Option<String>,
+    /// Digest of the manifest list a multi-arch image was selected from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index_digest: Option<String>,
+    /// Attestations of a pulled image stored in `meta/attestations/`; `None`
+    /// if they weren't fetched. See `crate::attestation` (`oci` feature).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestations: Option<Vec<String>>,
+    /// Pulled over plain HTTP from one of the `insecure_registries` (`oci` feature).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub insecure: bool,
+    /// Registry mirror the entry was pulled through; `None` for the registry
+    /// itself, also after falling back to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirror: Option<String>,
+    /// Platform of the machine that produced the entry.
+    pub host: String,
+    /// Platform the artifact is for; differs from `host` under a platform override,
+    /// for cross builds, or when an x86_64 asset was used under Rosetta.
+    #[serde(default)]
+    pub platform: String,
+    /// When the entry was finalized (built or downloaded).
+    pub built_at: String,
+    /// The [cache key](self) the entry was made for, e.g.
+    /// `zcashd|v6.0.0|linux-x86_64|v1`. Signed META must name the key it is
+    /// found under; see `crate::signing` (`signing` feature).
+    #[serde(default)]
+    pub key: String,
+    pub builder_schema: u32,
+    pub version_string: Option<String>,
+    /// macOS signing authority, when the codesign policy inspected the binary.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_identity: Option<String>,
+    /// [Pinned signer](crate::trust) the artifact was checked against, e.g.
+    /// `gpg:<fingerprint>` (`trust-store` feature).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signer: Option<String>,
+    /// [Transparency log](crate::transparency) entry of the downloaded asset's
+    /// digest (`transparency` feature).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_entry: Option<String>,
+    /// Things that went differently than asked, e.g. a Rosetta fallback.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// BLAKE3 of the cached executable.
+    pub digest: String,
+    /// BLAKE3 of each companion next to the executable, by file name.
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub companions: std::collections::BTreeMap<String, String>,
+    /// BLAKE3 of the universal binary the cached executable was thinned from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub universal_digest: Option<String>,
+    pub size: u64,
+}
+
+impl Meta {
+    /// Writes `META.json` for the entry in `paths`, recording its key, and signs
+    /// it if the resolver is configured to; see `crate::signing` (`signing` feature).
+    pub(crate) fn write(
+        &mut self,
+        ctx: &crate::ResolveContext<'_>,
+        paths: &CachePaths,
+    ) -> Result<()> {
+        self.key = paths.key.to_string();
+        let json = serde_json::to_vec_pretty(self).expect("META serializes");
+        write_atomic(&paths.meta.join("META.json"), &json)?;
+        #[cfg(feature = "signing")]
+        crate::signing::sign(ctx, &paths.meta, &json)?;
+        #[cfg(not(feature = "signing"))]
+        let _ = ctx;
+        Ok(())
+    }
+}
+
+/// The entry holding `executable`, relative to `cache_root` (e.g.
+/// `zcashd/v6.0.0-linux-x86_64-v1`), and its META; `None` for executables
+/// outside the cache.
+pub(crate) fn entry_of(cache_root: &Path, executable: &Path) -> Option<(String, Meta)> {
+    let root = executable.parent()?.parent()?;
+    let key = root.strip_prefix(cache_root).ok()?;
+    let meta = fs::read(root.join("meta").join("META.json")).ok()?;
+    let meta = serde_json::from_slice(&meta).ok()?;
+    let key = key
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    Some((key, meta))
+}
+
+/// Moves a produced executable (and its companions) into `paths.out` and writes META.
+///
+/// Everything is staged in the entry's [staging directory](CachePaths::staging)
+/// first: copied, made executable, hashed and probed there, and put through
+/// the resolution's checks (`expected_version`, `check_libraries`,
+/// `health_check`; see [`crate::ResolveOptions`]). Only then are the
+/// companions renamed into `out/`, META written, and the binary renamed into
+/// place last, so a visible `out/<bin_name>` always implies a complete entry
+/// that passed them.
+/// If promoting fails, what was promoted and META are removed again. `meta`'s
+/// digest, size, timestamp and version string are filled in here.
+///
+/// With `clear_quarantine`, macOS's `com.apple.quarantine` attribute is removed
+/// from every executable so Gatekeeper doesn't refuse to spawn it.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "cache_finalize",
+        level = "debug",
+        skip_all,
+        fields(entry = %paths.root.display(), bin = bin_name, bytes = tracing::field::Empty),
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn finalize(
+    ctx: &crate::ResolveContext<'_>,
+    paths: &CachePaths,
+    bin_name: &str,
+    binary: &Path,
+    companions: &[(String, PathBuf)],
+    probe: Option<&dyn crate::VersionProbe>,
+    clear_quarantine: bool,
+    meta: Meta,
+) -> Result<PathBuf> {
+    let dir = paths.staging_dir();
+    let staging = dir.join("out");
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir_all(&staging).map_err(|e| FsError::Io {
+        context: format!("mkdir {}", staging.display()),
+        source: e,
+    })?;
+    let result = stage_and_promote(
+        ctx,
+        paths,
+        &staging,
+        bin_name,
+        binary,
+        companions,
+        probe,
+        clear_quarantine,
+        meta,
+    );
+    let _ = fs::remove_dir_all(&staging);
+    // Empty unless the caller downloaded into it; the caller removes it then.
+    let _ = fs::remove_dir(&dir);
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+fn stage_and_promote(
+    ctx: &crate::ResolveContext<'_>,
+    paths: &CachePaths,
+    staging: &Path,
+    bin_name: &str,
+    binary: &Path,
+    companions: &[(String, PathBuf)],
+    probe: Option<&dyn crate::VersionProbe>,
+    clear_quarantine: bool,
+    mut meta: Meta,
+) -> Result<PathBuf> {
+    let stage = |name: &str, src: &Path| -> Result<PathBuf> {
+        let dst = staging.join(name);
+        copy_atomic(src, &dst)?;
+        chmod_exec(&dst)?;
+        if clear_quarantine {
+            remove_quarantine(&dst)?;
+        }
+        Ok(dst)
+    };
+    let companions = companions
+        .iter()
+        .map(|(name, src)| Ok((name, stage(name, src)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let staged = stage(bin_name, binary)?;
+
+    for (name, path) in &companions {
+        meta.companions
+            .insert(name.to_string(), digest_file(path)?.0);
+    }
+    let (digest, size) = digest_file(&staged)?;
+    crate::trace::record!("bytes" = size);
+    meta.digest = digest;
+    meta.size = size;
+    meta.built_at = timestamp();
+    meta.version_string = probe.and_then(|p| p.probe(&staged));
+    let service = (!meta.service.is_empty())
+        .then(|| crate::registry::ServiceId::new_owned(meta.service.clone()));
+    ctx.run_checks(service.as_ref(), &staged, &[])?;
+
+    let mut promoted = Vec::with_capacity(companions.len());
+    let out_bin = paths.out.join(bin_name);
+    let result = (|| {
+        for (name, src) in &companions {
+            let dst = paths.out.join(name);
+            rename(src, &dst)?;
+            promoted.push(dst);
+        }
+        meta.write(ctx, paths)?;
+        rename(&staged, &out_bin)
+    })();
+    if result.is_err() {
+        for path in promoted {
+            let _ = fs::remove_file(path);
+        }
+        let _ = fs::remove_file(paths.meta.join("META.json"));
+        let _ = fs::remove_file(paths.meta.join("META.json.sig"));
+    }
+    result?;
+    ctx.mark_checked(&out_bin);
+    Ok(out_bin)
+}
+
+/// Whether `executable`, in the `out/` of the entry in `paths`, is finished and
+/// runs on `platform`. Every lookup returning an existing entry goes through
+/// this: with signing configured, such an entry must also be signed, and
+/// failing its signature check is an error rather than a miss; see
+/// `crate::signing` (`signing` feature).
+pub(crate) fn usable_entry(
+    ctx: &crate::ResolveContext<'_>,
+    paths: &CachePaths,
+    executable: &Path,
+    platform: &str,
+) -> Result<bool> {
+    if !crate::binfmt::usable(executable, platform)? {
+        return Ok(false);
+    }
+    #[cfg(feature = "signing")]
+    return crate::signing::verify_hit(ctx, paths, executable);
+    #[cfg(not(feature = "signing"))]
+    {
+        let _ = (ctx, paths);
+        Ok(true)
+    }
+}
+
+/// Whether `path` is a regular file we may execute (on Unix, by its exec bits
+/// for our effective uid and groups).
+pub(crate) fn looks_executable(path: &Path) -> bool {
+    let Ok(md) = fs::metadata(path) else {
+        return false;
+    };
+    md.is_file() && may_execute(&md)
+}
+
+/// Whether the exec bit that applies to this process is set in `md`'s mode:
+/// the owner's if we own the file, else the group's if we're in its group,
+/// else everyone's. Root may execute anything with any exec bit set.
+#[cfg(unix)]
+pub(crate) fn may_execute(md: &fs::Metadata) -> bool {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let mode = md.permissions().mode();
+    // SAFETY: geteuid and getegid have no preconditions and cannot fail.
+    let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+    let bit = if uid == 0 {
+        0o111
+    } else if md.uid() == uid {
+        0o100
+    } else if md.gid() == gid || supplementary_groups().contains(&md.gid()) {
+        0o010
+    } else {
+        0o001
+    };
+    mode & bit != 0
+}
+
+#[cfg(not(unix))]
+pub(crate) fn may_execute(_md: &fs::Metadata) -> bool {
+    true
+}
+
+#[cfg(unix)]
+fn supplementary_groups() -> Vec<libc::gid_t> {
+    // SAFETY: a zero-length call only returns the count; the second call writes
+    // at most `groups.len()` entries and returns how many it wrote.
+    unsafe {
+        let count = libc::getgroups(0, std::ptr::null_mut());
+        let mut groups = vec![0; usize::try_from(count).unwrap_or(0)];
+        let written = libc::getgroups(count.max(0), groups.as_mut_ptr());
+        groups.truncate(usize::try_from(written).unwrap_or(0));
+        groups
+    }
+}
+
+/// Like [`looks_executable`], but first sets missing exec bits on files we own.
+///
+/// `Ok(false)` if `path` isn't a regular file. Failing to chmod our own file is
+/// an [`FsError::Chmod`], and a file owned by someone else (e.g. in a cache
+/// shared between users) that we may not execute is
+/// [`InputError::NotExecutable`].
+pub(crate) fn repair_executable(path: &Path) -> Result<bool> {
+    let Ok(md) = fs::metadata(path) else {
+        return Ok(false);
+    };
+    if !md.is_file() {
+        return Ok(false);
+    }
+    if may_execute(&md) {
+        return Ok(true);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        // SAFETY: geteuid has no preconditions and cannot fail.
+        if md.uid() != unsafe { libc::geteuid() } {
+            return Err(InputError::NotExecutable {
+                path: path.to_path_buf(),
+            }
+            .into());
+        }
+        chmod_exec(path)?;
+    }
+    Ok(true)
+}
+
+/// Copies `src` to `dst` via a temp file in `dst`'s directory and a rename.
+pub(crate) fn copy_atomic(src: &Path, dst: &Path) -> Result<()> {
+    let tmp = temp_sibling(dst);
+    fs::copy(src, &tmp).map_err(|e| FsError::Io {
+        context: format!("copy {} -> {}", src.display(), tmp.display()),
+        source: e,
+    })?;
+    rename(&tmp, dst)
+}
+
+/// Writes `contents` to `dst` via a temp file and a rename.
+pub(crate) fn write_atomic(dst: &Path, contents: &[u8]) -> Result<()> {
+    let tmp = temp_sibling(dst);
+    fs::write(&tmp, contents).map_err(|e| FsError::Io {
+        context: format!("write {}", tmp.display()),
+        source: e,
+    })?;
+    rename(&tmp, dst)
+}
+
+fn temp_sibling(dst: &Path) -> PathBuf {
+    let name = dst.file_name().unwrap_or_default().to_string_lossy();
+    dst.with_file_name(format!(".{name}.tmp-{}", std::process::id()))
+}
+
+fn rename(from: &Path, to: &Path) -> Result<()> {
+    fs::rename(from, to).map_err(|e| {
+        let _ = fs::remove_file(from);
+        FsError::Io {
+            context: format!("rename {} -> {}", from.display(), to.display()),
+            source: e,
+        }
+        .into()
+    })
+}
+
+/// Sets the exec bits on `path` (no-op off Unix).
+pub(crate) fn chmod_exec(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let chmod = |e| FsError::Chmod {
+            path: path.to_path_buf(),
+            source: e,
+        };
+        let mut perms = fs::metadata(path).map_err(chmod)?.permissions();
+        perms.set_mode(perms.mode() | 0o755);
+        fs::set_permissions(path, perms).map_err(chmod)?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Bytes available to unprivileged users on the filesystem holding `path`, or
+/// `None` where that can't be determined (off Unix, or if `path` is missing).
+pub(crate) fn available_space(path: &Path) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+        let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+        // SAFETY: `path` is NUL-terminated and `stat` is only read after success.
+        if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        // SAFETY: statvfs succeeded, so it filled `stat` in.
+        let stat = unsafe { stat.assume_init() };
+        #[allow(clippy::unnecessary_cast)] // the field types differ between platforms
+        Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// Fails with [`FsError::InsufficientSpace`] if the filesystem holding `path`
+/// has less than `needed` bytes free. Unknown free space passes.
+pub(crate) fn ensure_space(path: &Path, needed: u64, what: &str) -> Result<()> {
+    match available_space(path) {
+        Some(available) if available < needed => Err(FsError::InsufficientSpace {
+            what: what.to_string(),
+            path: path.to_path_buf(),
+            needed,
+            available,
+        }
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// macOS's quarantine attribute.
+#[cfg(target_os = "macos")]
+const QUARANTINE: &str = "com.apple.quarantine";
+
+/// Warns of the downloaded `binary` and `companions` that [`finalize`] will
+/// remove the `com.apple.quarantine` xattr from (none off macOS), naming them
+/// as they will be in `paths.out`.
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub(crate) fn warn_quarantined(
+    ctx: &crate::ResolveContext<'_>,
+    paths: &CachePaths,
+    bin_name: &str,
+    binary: &Path,
+    companions: &[(String, PathBuf)],
+) {
+    if ctx.config.keep_quarantine {
+        return;
+    }
+    let files = std::iter::once((bin_name, binary)).chain(
+        companions
+            .iter()
+            .map(|(name, path)| (name.as_str(), path.as_path())),
+    );
+    for (name, path) in files {
+        #[cfg(target_os = "macos")]
+        if xattr::get(path, QUARANTINE).ok().flatten().is_some() {
+            ctx.warn(crate::warning::Warning::QuarantineStripped {
+                path: paths.out.join(name),
+            });
+        }
+        #[cfg(not(target_os = "macos"))]
+        let _ = (paths, name, path);
+    }
+}
+
+/// Removes the `com.apple.quarantine` xattr from `path` if set (no-op off macOS).
+pub(crate) fn remove_quarantine(path: &Path) -> Result<()> {
+    #[cfg(target_os = "macos")]
+    {
+        let io = |e| FsError::Io {
+            context: format!("remove {QUARANTINE} from {}", path.display()),
+            source: e,
+        };
+        if xattr::get(path, QUARANTINE).map_err(io)?.is_some() {
+            xattr::remove(path, QUARANTINE).map_err(io)?;
+        }
+    }
+    #[cfg(not(target_os = "macos"))]
+    let _ = path;
+    Ok(())
+}
+
+/// BLAKE3 hex digest and size of the file at `path`.
+pub(crate) fn digest_file(path: &Path) -> Result<(String, u64)> {
+    let io = |e| FsError::Io {
+        context: format!("hash {}", path.display()),
+        source: e,
+    };
+    let mut hasher = blake3::Hasher::new();
+    let mut file = File::open(path).map_err(io)?;
+    let size = std::io::copy(&mut file, &mut hasher).map_err(io)?;
+    Ok((hasher.finalize().to_hex().to_string(), size))
+}
+
+/// Current UTC time as `YYYY-MM-DDTHH:MM:SSZ`.
+pub(crate) fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    let (h, m, s) = (rem / 3600, (rem % 3600) / 60, rem % 60);
+
+    // Civil-from-days (Howard Hinnant), valid for the Unix era.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let mo = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + i64::from(mo <= 2);
+
+    format!("{y:04}-{mo:02}-{d:02}T{h:02}:{m:02}:{s:02}Z")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_platform_spellings_are_renamed() {
+        for (old, new) in [
+            ("v2.0.0-darwin-aarch64-v1", "v2.0.0-macos-arm64-v1"),
+            ("v5.9.0-rc1-linux-amd64-v2", "v5.9.0-rc1-linux-x86_64-v2"),
+            (
+                "0123abcd+feed-macos-aarch64-v1",
+                "0123abcd+feed-macos-arm64-v1",
+            ),
+            ("v2.0.0-linux-amd64-musl-v1", "v2.0.0-linux-x86_64-musl-v1"),
+        ] {
+            assert_eq!(canonical_dir_name(old).as_deref(), Some(new), "{old}");
+        }
+    }
+
+    #[test]
+    fn canonical_and_unrelated_names_are_kept() {
+        for name in [
+            "v2.0.0-macos-arm64-v1",
+            "v2.0.0-linux-x86_64-musl-v1",
+            "0123abcd-aarch64-unknown-linux-gnu-v1",
+            "0123abcd-x86_64-apple-darwin-v1",
+            "linux-amd64-v1",
+            ".lock",
+        ] {
+            assert_eq!(canonical_dir_name(name), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn keys_use_the_given_canonical_platform() {
+        let key = CacheKey {
+            service: "zebrad".into(),
+            revision: "v2.0.0".into(),
+            worktree_hash: None,
+            platform: crate::platform::normalize("darwin-aarch64"),
+            schema: 1,
+        };
+        assert_eq!(key.dir_name(), "v2.0.0-macos-arm64-v1");
+        assert_eq!(canonical_dir_name(&key.dir_name()), None);
+    }
+
+    #[test]
+    fn keys_naming_other_directories_are_rejected() {
+        let key = |service: &str, revision: &str| CacheKey {
+            service: service.into(),
+            revision: revision.into(),
+            worktree_hash: None,
+            platform: "linux-x86_64".into(),
+            schema: 1,
+        };
+        let root = Path::new("/cache");
+        for (service, revision) in [
+            ("zcashd", "../../x"),
+            ("zcashd", "a/b"),
+            ("zcashd", "a\\b"),
+            ("zcashd", "v1\0"),
+            ("zcashd", ""),
+            ("..", "v1"),
+            (".", "v1"),
+            ("", "v1"),
+        ] {
+            let err = CachePaths::new(root, &key(service, revision)).unwrap_err();
+            assert_eq!(err.kind(), crate::ErrorKind::InvalidInput, "{err}");
+        }
+
+        let paths = CachePaths::new(root, &key("zcashd", "deb-noble-1..2+b1")).unwrap();
+        assert_eq!(
+            paths.root,
+            Path::new("/cache/zcashd/deb-noble-1..2+b1-linux-x86_64-v1")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exec_bits_are_checked_for_our_own_ids() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let set = |path: &Path, mode: u32| {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap()
+        };
+        let bin = dir.path().join("bin");
+        fs::write(&bin, "#!/bin/sh\n").unwrap();
+
+        // Only someone else's exec bit: not ours to run, so we set our own.
+        // SAFETY: geteuid has no preconditions and cannot fail.
+        let root = unsafe { libc::geteuid() } == 0;
+        set(&bin, 0o601);
+        assert_eq!(looks_executable(&bin), root);
+        assert!(repair_executable(&bin).unwrap());
+        assert_eq!(mode(&bin) & 0o100 != 0, !root);
+
+        set(&bin, 0o644);
+        assert!(!looks_executable(&bin));
+        assert!(repair_executable(&bin).unwrap());
+        assert_eq!(mode(&bin), 0o755);
+        assert!(looks_executable(&bin));
+
+        assert!(!repair_executable(dir.path()).unwrap());
+        assert!(!repair_executable(&dir.path().join("missing")).unwrap());
+    }
+
+    #[test]
+    fn migrated_entries_name_their_new_key() {
+        let root = tempfile::tempdir().unwrap();
+        let legacy = |name: &str, revision: &str| {
+            let entry = root.path().join("zcashd").join(name);
+            fs::create_dir_all(entry.join("meta")).unwrap();
+            fs::create_dir_all(entry.join("out")).unwrap();
+            let meta = serde_json::json!({
+                "key": format!("zcashd|{revision}|darwin-aarch64|v1"),
+                "platform": "darwin-aarch64",
+                "digest": "abc",
+            });
+            fs::write(entry.join("meta/META.json"), meta.to_string()).unwrap();
+            fs::write(entry.join("meta/META.json.sig"), "{}").unwrap();
+            entry
+        };
+        legacy("v2.0.0-darwin-aarch64-v1", "v2.0.0");
+        // Held by someone resolving it: left alone.
+        let busy = legacy("v1.0.0-darwin-aarch64-v1", "v1.0.0");
+        let lock = File::create(busy.join(".lock")).unwrap();
+        lock.lock().unwrap();
+
+        migrate_legacy_entries(root.path());
+
+        let moved = root.path().join("zcashd/v2.0.0-macos-arm64-v1");
+        assert!(!root.path().join("zcashd/v2.0.0-darwin-aarch64-v1").exists());
+        let meta: serde_json::Value =
+            serde_json::from_slice(&fs::read(moved.join("meta/META.json")).unwrap()).unwrap();
+        assert_eq!(meta["key"], "zcashd|v2.0.0|macos-arm64|v1");
+        assert_eq!(meta["platform"], "macos-arm64");
+        assert_eq!(meta["digest"], "abc");
+        assert!(!moved.join("meta/META.json.sig").exists());
+
+        assert!(busy.join("meta/META.json.sig").exists());
+        assert!(!root.path().join("zcashd/v1.0.0-macos-arm64-v1").exists());
+    }
+}
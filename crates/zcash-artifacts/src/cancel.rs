@@ -0,0 +1,207 @@
+//! Cooperative cancellation of resolutions.
+//!
+//! Give a [`CancelToken`] to a resolution through
+//! [`ResolveOptions::cancel`](crate::ResolveOptions::cancel), and cancel it
+//! from another thread, e.g. a Ctrl-C handler, to make the resolution fail
+//! with [`ArtifactError::Cancelled`] at its next check. A
+//! [`ResolveOptions::timeout`](crate::ResolveOptions::timeout) is checked at
+//! the same points, and fails it with [`ArtifactError::TimedOut`], naming the
+//! [`Phase`] it was in. The checks are made:
+//!
+//! - before each provider layer runs, and before the resolved binary's
+//!   version, libraries and health are checked;
+//! - between the chunks of a download, whose partial file is removed;
+//! - while waiting for another process's lock on a cache entry;
+//! - while a build command runs, which is then sent `SIGTERM` (its whole
+//!   process group, on Unix) and, if it hasn't exited after a grace period,
+//!   `SIGKILL`. Being in a process group of its own, it no longer gets the
+//!   terminal's Ctrl-C; cancel the token from a Ctrl-C handler instead.
+//!
+//! Cache entries are only ever finalized by renaming a complete binary into
+//! place, and their locks are released as the resolution unwinds, so a
+//! cancelled resolution leaves nothing that a later one would pick up as
+//! finished. Git, Nix and container runtime commands aren't interrupted; the
+//! resolution stops after they return.
+
+use std::{
+    fmt,
+    process::{Child, Command, ExitStatus},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::error::{ArtifactError, Result};
+
+/// How long a cancelled build command gets to exit after `SIGTERM`.
+#[cfg_attr(not(feature = "local-build"), allow(dead_code))]
+const GRACE: Duration = Duration::from_secs(5);
+
+/// How often a running command is checked for cancellation.
+#[cfg_attr(not(feature = "local-build"), allow(dead_code))]
+const POLL: Duration = Duration::from_millis(50);
+
+/// What a resolution was doing when it timed out.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    /// Looking the source up: cache lookups, release indexes, registry
+    /// manifests.
+    #[default]
+    Locate,
+    /// Reading the state of a build's git worktree.
+    Git,
+    /// Downloading, unpacking and checking a download against its checksum.
+    Download,
+    /// Running a build.
+    Build,
+    /// Checking the resolved binary's version, libraries or health.
+    Verify,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Locate => "locating the artifact",
+            Phase::Git => "inspecting the git worktree",
+            Phase::Download => "downloading",
+            Phase::Build => "building",
+            Phase::Verify => "verifying the binary",
+        })
+    }
+}
+
+/// A flag shared by its clones; once cancelled, it stays cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    /// The caller's token, for tokens made for a timeout.
+    parent: Option<CancelToken>,
+    deadline: Option<Deadline>,
+}
+
+#[derive(Debug)]
+struct Deadline {
+    at: Instant,
+    after: Duration,
+    phase: Mutex<Phase>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token for one resolution, cancelled with `parent` or once `after`
+    /// has passed.
+    pub(crate) fn with_timeout(parent: Option<&CancelToken>, after: Duration) -> Self {
+        Self(Arc::new(Inner {
+            cancelled: AtomicBool::new(false),
+            parent: parent.cloned(),
+            deadline: Some(Deadline {
+                at: Instant::now() + after,
+                after,
+                phase: Mutex::default(),
+            }),
+        }))
+    }
+
+    /// Cancels the resolutions using this token, or any of its clones.
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+            || self.timed_out().is_some()
+            || self.0.parent.as_ref().is_some_and(Self::is_cancelled)
+    }
+
+    /// Fails with [`ArtifactError::Cancelled`] if the token was cancelled, or
+    /// [`ArtifactError::TimedOut`] if the resolution ran out of time.
+    pub fn check(&self) -> Result<()> {
+        if let Some(deadline) = self.timed_out() {
+            return Err(ArtifactError::TimedOut {
+                phase: *deadline.phase.lock().unwrap_or_else(|e| e.into_inner()),
+                after: deadline.after,
+            });
+        }
+        match self.is_cancelled() {
+            true => Err(ArtifactError::Cancelled),
+            false => Ok(()),
+        }
+    }
+
+    /// Records that the resolution entered `phase`, for its timeout error.
+    pub(crate) fn enter(&self, phase: Phase) {
+        if let Some(deadline) = &self.0.deadline {
+            *deadline.phase.lock().unwrap_or_else(|e| e.into_inner()) = phase;
+        }
+    }
+
+    fn timed_out(&self) -> Option<&Deadline> {
+        self.0
+            .deadline
+            .as_ref()
+            .filter(|deadline| Instant::now() >= deadline.at)
+    }
+}
+
+/// Runs `command` like [`Command::status`] but, given a token, kills it once
+/// the token is cancelled and fails as [`CancelToken::check`] does. On Unix,
+/// the command then gets a process group of its own, so that what it started
+/// is killed along with it. I/O errors are turned into errors by `io`.
+#[cfg_attr(not(feature = "local-build"), allow(dead_code))]
+pub(crate) fn status(
+    command: &mut Command,
+    cancel: Option<&CancelToken>,
+    io: impl Fn(std::io::Error) -> ArtifactError,
+) -> Result<ExitStatus> {
+    let Some(cancel) = cancel else {
+        return command.status().map_err(io);
+    };
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(command, 0);
+    let mut child = command.spawn().map_err(&io)?;
+    loop {
+        if let Some(status) = child.try_wait().map_err(&io)? {
+            return Ok(status);
+        }
+        if let Err(e) = cancel.check() {
+            terminate(&mut child);
+            return Err(e);
+        }
+        std::thread::sleep(POLL);
+    }
+}
+
+/// Asks `child`'s process group to exit, then kills it after [`GRACE`].
+#[cfg_attr(not(feature = "local-build"), allow(dead_code))]
+fn terminate(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        let group = -(child.id() as libc::pid_t);
+        // SAFETY: kill(2) has no memory-safety preconditions.
+        unsafe { libc::kill(group, libc::SIGTERM) };
+        let deadline = std::time::Instant::now() + GRACE;
+        while std::time::Instant::now() < deadline {
+            if let Ok(Some(_)) = child.try_wait() {
+                // The leader is gone; make sure the rest of the group is too.
+                // SAFETY: as above.
+                unsafe { libc::kill(group, libc::SIGKILL) };
+                return;
+            }
+            std::thread::sleep(POLL);
+        }
+        // SAFETY: as above.
+        unsafe { libc::kill(group, libc::SIGKILL) };
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
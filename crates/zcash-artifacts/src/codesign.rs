@@ -0,0 +1,110 @@
+//! macOS code-signature checks for downloaded binaries.
+//!
+//! Signatures are inspected with Apple's `codesign` tool, so checks only run
+//! when both the host and the resolved platform are macOS; elsewhere every
+//! policy passes and nothing is recorded.
+
+use std::{fmt, path::Path, process::Command, sync::Arc};
+
+use crate::error::{Result, VerifyError};
+
+/// What `codesign` reported about a binary.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeSignature {
+    /// Whether `codesign --verify --strict` accepted the signature.
+    pub valid: bool,
+    /// The leaf signing authority, e.g. `Developer ID Application: Example (ABCDE12345)`.
+    /// `None` for unsigned and ad-hoc signed binaries.
+    pub identity: Option<String>,
+    pub team_id: Option<String>,
+}
+
+/// What to do with the signature of a downloaded macOS binary.
+#[derive(Clone, Default)]
+pub enum CodeSignPolicy {
+    /// Don't run `codesign` at all.
+    #[default]
+    Skip,
+    /// Record the signing identity in META, but accept any binary.
+    Record,
+    /// Reject binaries without a valid, non-ad-hoc signature.
+    RequireSigned,
+    /// Reject binaries the predicate returns `false` for.
+    Custom(Arc<dyn Fn(&CodeSignature) -> bool + Send + Sync>),
+}
+
+impl fmt::Debug for CodeSignPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodeSignPolicy::Skip => f.write_str("Skip"),
+            CodeSignPolicy::Record => f.write_str("Record"),
+            CodeSignPolicy::RequireSigned => f.write_str("RequireSigned"),
+            CodeSignPolicy::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+impl CodeSignPolicy {
+    /// Inspects `path` and applies the policy, returning the signature to record.
+    ///
+    /// Returns `Ok(None)` when the policy is [`CodeSignPolicy::Skip`] or the check
+    /// can't run here (not macOS).
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    pub(crate) fn enforce(&self, path: &Path, platform: &str) -> Result<Option<CodeSignature>> {
+        if matches!(self, CodeSignPolicy::Skip)
+            || !cfg!(target_os = "macos")
+            || !platform.starts_with("macos-")
+        {
+            return Ok(None);
+        }
+        let signature = inspect(path);
+        let accepted = match self {
+            CodeSignPolicy::Skip | CodeSignPolicy::Record => true,
+            CodeSignPolicy::RequireSigned => signature.valid && signature.identity.is_some(),
+            CodeSignPolicy::Custom(accept) => accept(&signature),
+        };
+        if !accepted {
+            let reason = match (&signature.identity, signature.valid) {
+                (_, false) => "no valid code signature".to_string(),
+                (None, true) => "ad-hoc signature only".to_string(),
+                (Some(identity), true) => format!("signed by {identity}, rejected by policy"),
+            };
+            return Err(VerifyError::SignatureInvalid {
+                what: path.display().to_string(),
+                source: reason.into(),
+            }
+            .into());
+        }
+        Ok(Some(signature))
+    }
+}
+
+/// Runs `codesign` on `path`; a missing tool reads as an invalid signature.
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+fn inspect(path: &Path) -> CodeSignature {
+    let valid = Command::new("codesign")
+        .args(["--verify", "--strict"])
+        .arg(path)
+        .output()
+        .is_ok_and(|out| out.status.success());
+
+    // `codesign -d` writes its report to stderr, one `Key=Value` per line.
+    let details = Command::new("codesign")
+        .args(["-d", "--verbose=2"])
+        .arg(path)
+        .output()
+        .map(|out| String::from_utf8_lossy(&out.stderr).into_owned())
+        .unwrap_or_default();
+    let field = |key: &str| {
+        details
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            .map(str::to_string)
+    };
+    CodeSignature {
+        valid,
+        identity: field("Authority"),
+        team_id: field("TeamIdentifier").filter(|t| t != "not set"),
+    }
+}
@@ -0,0 +1,239 @@
+//! Compose files for resolved stacks.
+//!
+//! [`ArtifactResolver::compose`] turns a [`ResolvedStack`] into a
+//! [`ComposeFile`], a typed Compose document that can be adjusted (commands,
+//! environment, port mappings) before it is written out:
+//!
+//! - services resolved to images (`oci` feature) run those images, publishing
+//!   the TCP ports their config exposes as well;
+//! - services resolved to binaries run them from
+//!   [`ComposeOptions::base_image`], with the binary and its companions
+//!   mounted read-only into `/usr/local/bin`. They must be Linux binaries for
+//!   the host's architecture, e.g. resolved with a
+//!   [`platform_override`](crate::ResolverConfig::platform_override) on macOS;
+//! - each service publishes its [usual ports](crate::registry::ToolSpec::ports)
+//!   (on ephemeral host ports, so nodes sharing a port number don't clash)
+//!   and depends on its stack dependencies;
+//! - resources, like `zcash-params`, become named volumes, mounted into the
+//!   services needing them at [`ComposeOptions::resource_mounts`].
+//!
+//! The file is written as JSON, which Compose reads as YAML:
+//!
+//! ```no_run
+//! # use std::collections::HashMap;
+//! # use zcash_artifacts::{ArtifactResolver, compose::ComposeOptions, registry::LIGHTWALLETD};
+//! # fn run(resolver: ArtifactResolver, sources: HashMap<zcash_artifacts::registry::ServiceId, zcash_artifacts::ArtifactSource>) -> zcash_artifacts::Result<()> {
+//! let stack = resolver.resolve_stack(&LIGHTWALLETD, &sources)?;
+//! let mut compose = resolver.compose(&stack, &ComposeOptions::default())?;
+//! compose.services.get_mut("zcashd").unwrap().command = vec!["zcashd".into(), "-regtest".into()];
+//! compose.write("compose.yaml".as_ref())?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    ArtifactResolver, ResolvedArtifact,
+    error::{InputError, Result},
+    platform::Platform,
+    registry::{Dependency, ServiceId},
+    stack::{ResolvedStack, StackService},
+};
+
+/// Where binaries are mounted in their containers.
+const BIN_DIR: &str = "/usr/local/bin";
+
+/// How [`ArtifactResolver::compose`] lays out services.
+#[derive(Debug, Clone)]
+pub struct ComposeOptions {
+    /// Image that services resolved to binaries run in; `debian:bookworm-slim`
+    /// by default. Its glibc must be as new as the binaries need.
+    pub base_image: String,
+    /// Container path of each resource's volume, by resource name. By default,
+    /// `zcash-params` is mounted at `/root/.zcash-params`.
+    pub resource_mounts: BTreeMap<String, String>,
+}
+
+impl Default for ComposeOptions {
+    fn default() -> Self {
+        Self {
+            base_image: "debian:bookworm-slim".into(),
+            resource_mounts: BTreeMap::from([(
+                "zcash-params".into(),
+                "/root/.zcash-params".into(),
+            )]),
+        }
+    }
+}
+
+/// A Compose document; see the [module docs](self).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ComposeFile {
+    /// Services by name, the stack's service ids.
+    pub services: BTreeMap<String, ComposeService>,
+    /// Named volumes, one per resource.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub volumes: BTreeMap<String, ComposeVolume>,
+}
+
+/// One service of a [`ComposeFile`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ComposeService {
+    pub image: String,
+    /// Overrides the image's command; empty keeps it.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub command: Vec<String>,
+    /// Published ports, e.g. `18232` (an ephemeral host port) or `18232:18232`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub environment: BTreeMap<String, String>,
+    /// Mounts, e.g. `zcash-params:/root/.zcash-params`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+}
+
+/// A named volume, with Compose's defaults.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ComposeVolume {}
+
+impl ComposeFile {
+    /// The document as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("compose file serializes")
+    }
+
+    /// Writes the document to `path`, e.g. `compose.yaml`.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        crate::cache::write_atomic(path, self.to_json().as_bytes())
+    }
+}
+
+impl ArtifactResolver {
+    /// A Compose file running `stack`; see [`crate::compose`].
+    pub fn compose(&self, stack: &ResolvedStack, options: &ComposeOptions) -> Result<ComposeFile> {
+        let mut file = ComposeFile::default();
+        for service in &stack.services {
+            let mut composed = self.compose_service(service, options)?;
+            for resource in self.resources_of(&service.service) {
+                let Some(mount) = options.resource_mounts.get(resource) else {
+                    return Err(InputError::InvalidSource {
+                        service: service.service.clone(),
+                        reason: format!(
+                            "no mount point for resource `{resource}`; \
+                             set it in `ComposeOptions::resource_mounts`"
+                        ),
+                    }
+                    .into());
+                };
+                composed.volumes.push(format!("{resource}:{mount}"));
+                file.volumes
+                    .insert(resource.to_string(), ComposeVolume::default());
+            }
+            file.services
+                .insert(service.service.as_str().to_string(), composed);
+        }
+        Ok(file)
+    }
+
+    fn compose_service(
+        &self,
+        service: &StackService,
+        options: &ComposeOptions,
+    ) -> Result<ComposeService> {
+        let usual_ports = self
+            .registry()
+            .get(&service.service)
+            .map(|spec| spec.ports.as_slice())
+            .unwrap_or_default();
+        let mut composed = ComposeService {
+            ports: usual_ports.iter().map(u16::to_string).collect(),
+            depends_on: service
+                .depends_on
+                .iter()
+                .map(|dep| dep.as_str().to_string())
+                .collect(),
+            ..Default::default()
+        };
+        let executables: Vec<(String, &Path)> = match &service.artifact {
+            #[cfg(feature = "oci")]
+            ResolvedArtifact::OciImage {
+                reference, config, ..
+            } => {
+                composed.image = reference.clone();
+                for port in config.tcp_ports() {
+                    if !usual_ports.contains(&port) {
+                        composed.ports.push(port.to_string());
+                    }
+                }
+                return Ok(composed);
+            }
+            ResolvedArtifact::Executable { path, .. } => vec![(file_name(path), path.as_path())],
+            ResolvedArtifact::Bundle { executables, .. } => executables
+                .values()
+                .map(|path| (file_name(path), path.as_path()))
+                .collect(),
+        };
+
+        // Containers run Linux binaries of the host's architecture, also on macOS.
+        let host = Platform::host();
+        let platform = format!("linux-{}", host.generic_arch());
+        for (name, path) in &executables {
+            crate::binfmt::check(path, &platform)?;
+            composed
+                .volumes
+                .push(format!("{}:{BIN_DIR}/{name}:ro", absolute(path)?.display()));
+        }
+        let primary = service
+            .artifact
+            .primary_path()
+            .ok_or_else(|| InputError::InvalidSource {
+                service: service.service.clone(),
+                reason: "bundle has no primary executable".into(),
+            })?;
+        composed.image = options.base_image.clone();
+        composed.command = vec![format!("{BIN_DIR}/{}", file_name(primary))];
+        Ok(composed)
+    }
+
+    /// Resources `service` declares; see [`Dependency::Resource`].
+    pub(crate) fn resources_of(&self, service: &ServiceId) -> Vec<&str> {
+        self.registry()
+            .get(service)
+            .map(|spec| {
+                spec.dependencies
+                    .iter()
+                    .filter_map(|dep| match dep {
+                        Dependency::Resource(name) => Some(name.as_str()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Compose resolves relative bind mounts against the file's directory, not ours.
+fn absolute(path: &Path) -> Result<PathBuf> {
+    std::path::absolute(path).map_err(|e| {
+        crate::error::FsError::Io {
+            context: format!("resolve {}", path.display()),
+            source: e,
+        }
+        .into()
+    })
+}
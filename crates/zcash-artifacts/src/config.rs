@@ -0,0 +1,386 @@
+//! Configuration files.
+//!
+//! Like Cargo with `.cargo/config.toml`,
+//! [`ResolverConfigBuilder::discover`](crate::ResolverConfigBuilder::discover)
+//! reads every `zcash-artifacts.toml` from the working directory up to the
+//! filesystem root, on top of the user's file (see [`user_path`]). Files are
+//! applied from the user's inwards, so the one nearest the working directory
+//! wins, setting by setting and pin by pin. Relative paths are relative to the
+//! directory of the file they are in:
+//!
+//! ```toml
+//! [resolver]
+//! cache_dir = "target/zcash-artifacts"
+//! platform = "linux-x86_64"
+//! offline = false
+//! allowed_hosts = ["github.com", "objects.githubusercontent.com"]
+//! binary_overrides = true
+//! max_downloads = 4           # 0 for no limit
+//! max_builds = 1
+//! max_per_host = 2
+//! log = true
+//! allow_build = true          # `local-build` feature
+//! jobs = 8                    # `local-build` feature; 0 for one per CPU
+//!
+//! [pins.zebrad]
+//! release = "2.0.0"
+//!
+//! [pins.zcashd]
+//! build = "../zcash"          # `local-build` feature
+//! refspec = "v6.0.0"
+//!
+//! [pins.lightwalletd]
+//! path = "bin/lightwalletd"
+//!
+//! [pins.zainod]
+//! url = "https://example.org/zainod-0.1.0"   # `http` feature
+//! sha256 = "<sha256>"
+//!
+//! [signers.zcashd]            # `trust-store` feature
+//! gpg = ["<fingerprint>"]
+//! ```
+//!
+//! The `[resolver]` keys set the [`ResolverConfig`](crate::ResolverConfig)
+//! fields of the same names (`cache_dir` sets `cache_root`, `platform` sets
+//! `platform_override`, `offline` and `allowed_hosts` set `network`, and the
+//! `max_` keys set `limits`).
+//! Each pin sets one of `release`, `path`, `url` (with `sha256`), `build`
+//! (with an optional `refspec`) or `image` (with an optional `digest`, `oci`
+//! feature), and replaces the source of every resolution naming its service;
+//! see [`ResolverConfig::pins`](crate::ResolverConfig::pins).
+//! `[signers.<service>]` tables set the service's
+//! [pinned signers](crate::ResolverConfig::signers) (`trust-store` feature),
+//! replacing those of files applied before. Only the user's file and files
+//! applied with [`ResolverConfigBuilder::file`](crate::ResolverConfigBuilder::file)
+//! may do so freely: a project checkout could otherwise pin its own keys. The
+//! `zcash-artifacts.toml` files found from the working directory up may only
+//! narrow pins, keeping some of those already set for a kind of signature or
+//! pinning one that has none; anything else is an error.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    ArtifactSource, ResolverConfig,
+    error::{FsError, InputError, Result},
+    network::NetworkPolicy,
+    registry::ServiceId,
+};
+
+/// Name of the files searched for from the working directory up.
+pub const FILE_NAME: &str = "zcash-artifacts.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    resolver: ResolverSection,
+    #[serde(default)]
+    pins: HashMap<String, Pin>,
+    #[serde(default)]
+    signers: HashMap<String, toml::Table>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ResolverSection {
+    cache_dir: Option<PathBuf>,
+    platform: Option<String>,
+    offline: Option<bool>,
+    allowed_hosts: Option<Vec<String>>,
+    binary_overrides: Option<bool>,
+    max_downloads: Option<usize>,
+    max_builds: Option<usize>,
+    max_per_host: Option<usize>,
+    log: Option<bool>,
+    #[cfg_attr(not(feature = "local-build"), allow(dead_code))]
+    allow_build: Option<bool>,
+    #[cfg_attr(not(feature = "local-build"), allow(dead_code))]
+    jobs: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Pin {
+    release: Option<String>,
+    path: Option<PathBuf>,
+    url: Option<String>,
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    sha256: Option<String>,
+    build: Option<PathBuf>,
+    #[cfg_attr(not(feature = "local-build"), allow(dead_code))]
+    refspec: Option<String>,
+    image: Option<String>,
+    #[cfg_attr(not(feature = "oci"), allow(dead_code))]
+    digest: Option<String>,
+}
+
+impl Pin {
+    #[cfg_attr(not(feature = "local-build"), allow(unused_variables))]
+    fn into_source(
+        self,
+        service: ServiceId,
+        dir: &Path,
+        config: &ResolverConfig,
+    ) -> std::result::Result<ArtifactSource, String> {
+        let kinds = [
+            self.release.is_some(),
+            self.path.is_some(),
+            self.url.is_some(),
+            self.build.is_some(),
+            self.image.is_some(),
+        ];
+        if kinds.iter().filter(|set| **set).count() != 1 {
+            return Err("set exactly one of `release`, `path`, `url`, `build` or `image`".into());
+        }
+        if let Some(version) = self.release {
+            return Ok(ArtifactSource::Release { service, version });
+        }
+        if let Some(path) = self.path {
+            return Ok(ArtifactSource::LocalPath(dir.join(path)));
+        }
+        #[cfg(feature = "http")]
+        if let Some(url) = self.url {
+            let url = url.parse().map_err(|e| format!("bad `url`: {e}"))?;
+            let checksum = self.sha256.ok_or("`url` pins need a `sha256`")?;
+            return Ok(ArtifactSource::Url { url, checksum });
+        }
+        #[cfg(feature = "local-build")]
+        if let Some(repo) = self.build {
+            return Ok(ArtifactSource::Build {
+                service,
+                repo: dir.join(repo),
+                refspec: self.refspec,
+                policy: config.build_config.default_policy,
+                expected_output: None,
+                target: None,
+            });
+        }
+        #[cfg(feature = "oci")]
+        if let Some(reference) = self.image {
+            return Ok(ArtifactSource::OciImage {
+                reference,
+                digest: self.digest,
+                service: Some(service),
+            });
+        }
+        let (kind, feature) = if self.url.is_some() {
+            ("url", "http")
+        } else if self.build.is_some() {
+            ("build", "local-build")
+        } else {
+            ("image", "oci")
+        };
+        Err(format!("`{kind}` pins need the `{feature}` feature"))
+    }
+}
+
+/// The user's configuration file: `zcash-artifacts/config.toml` under
+/// `$XDG_CONFIG_HOME` (or `~/.config`) on Linux and other Unixes,
+/// `~/Library/Application Support` on macOS and `%APPDATA%` on Windows.
+pub fn user_path() -> Option<PathBuf> {
+    let env_dir = |var: &str| {
+        std::env::var_os(var)
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+    };
+    let base = if cfg!(windows) {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library/Application Support"))
+    } else {
+        env_dir("XDG_CONFIG_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".config")))
+    }?;
+    Some(base.join("zcash-artifacts").join("config.toml"))
+}
+
+/// The configuration files that apply in `dir`, in the order they are
+/// applied: the user's, then those from the filesystem root down to `dir`.
+/// Only existing files are listed.
+pub fn discover(dir: &Path) -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = dir
+        .ancestors()
+        .map(|dir| dir.join(FILE_NAME))
+        .filter(|path| path.is_file())
+        .collect();
+    found.extend(user_path().filter(|path| path.is_file()));
+    found.reverse();
+    found
+}
+
+/// Applies the file at `path` to `config`. With `narrow_signers`, for files
+/// found from the working directory up, its signer pins may only narrow those
+/// set before; see the [module docs](self).
+pub(crate) fn apply(config: &mut ResolverConfig, path: &Path, narrow_signers: bool) -> Result<()> {
+    let invalid = |reason: String| InputError::InvalidConfig {
+        origin: path.display().to_string(),
+        reason,
+    };
+    let text = std::fs::read_to_string(path).map_err(|e| FsError::Io {
+        context: format!("read {}", path.display()),
+        source: e,
+    })?;
+    let file: ConfigFile = toml::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let resolver = file.resolver;
+    if let Some(cache_dir) = resolver.cache_dir {
+        config.cache_root = dir.join(cache_dir);
+    }
+    if let Some(platform) = resolver.platform {
+        config.platform_override = Some(platform);
+    }
+    if let Some(hosts) = resolver.allowed_hosts {
+        config.network = NetworkPolicy::Hosts(hosts);
+    }
+    match resolver.offline {
+        Some(true) => config.network = NetworkPolicy::Offline,
+        Some(false) if config.network == NetworkPolicy::Offline => {
+            config.network = NetworkPolicy::Online;
+        }
+        _ => {}
+    }
+    if let Some(enabled) = resolver.binary_overrides {
+        config.binary_overrides = enabled;
+    }
+    if let Some(enabled) = resolver.log {
+        config.log = enabled;
+    }
+    for (max, limit) in [
+        (resolver.max_downloads, &mut config.limits.downloads),
+        (resolver.max_builds, &mut config.limits.builds),
+        (resolver.max_per_host, &mut config.limits.per_host),
+    ] {
+        if let Some(max) = max {
+            *limit = (max > 0).then_some(max);
+        }
+    }
+    #[cfg(feature = "local-build")]
+    {
+        if let Some(allow) = resolver.allow_build {
+            config.build_config.allow_build = allow;
+        }
+        if let Some(jobs) = resolver.jobs {
+            config.build_config.default_jobs = (jobs > 0).then_some(jobs);
+        }
+    }
+
+    for (name, pin) in file.pins {
+        let service = ServiceId::new_owned(name.clone());
+        let source = pin
+            .into_source(service.clone(), dir, config)
+            .map_err(|reason| invalid(format!("pin `{name}`: {reason}")))?;
+        config.pins.insert(service, source);
+    }
+    #[cfg(feature = "trust-store")]
+    for (name, signers) in file.signers {
+        let mut pins: crate::trust::SignerPins = signers
+            .try_into()
+            .map_err(|e| invalid(format!("signers `{name}`: {e}")))?;
+        let service = ServiceId::new_owned(name.clone());
+        if narrow_signers && let Some(before) = config.signers.get(&service) {
+            pins = narrowed(before, pins)
+                .map_err(|kind| invalid(format!("signers `{name}`: {NOT_NARROWER} `{kind}`")))?;
+        }
+        crate::trace::debug!(service = %name, file = %path.display(), "signer pins set");
+        config.signers.insert(service, pins);
+    }
+    #[cfg(not(feature = "trust-store"))]
+    {
+        let _ = narrow_signers;
+        if let Some(name) = file.signers.keys().next() {
+            return Err(invalid(format!(
+                "signers `{name}`: pinning signers needs the `trust-store` feature"
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "trust-store")]
+const NOT_NARROWER: &str = "a `zcash-artifacts.toml` found from the working directory \
+                            may only narrow the signers pinned by the user's file or code, \
+                            not replace them, for";
+
+/// `pins` if they only narrow `before`: each kind either keeps a subset of
+/// `before`'s pins of that kind, or pins one `before` left open. An empty list
+/// keeps `before`'s. Fails with the first kind that would be widened.
+#[cfg(feature = "trust-store")]
+fn narrowed(
+    before: &crate::trust::SignerPins,
+    pins: crate::trust::SignerPins,
+) -> std::result::Result<crate::trust::SignerPins, &'static str> {
+    fn kind<T: Clone + PartialEq>(
+        name: &'static str,
+        before: &[T],
+        pins: Vec<T>,
+    ) -> std::result::Result<Vec<T>, &'static str> {
+        if pins.is_empty() {
+            Ok(before.to_vec())
+        } else if before.is_empty() || pins.iter().all(|pin| before.contains(pin)) {
+            Ok(pins)
+        } else {
+            Err(name)
+        }
+    }
+    Ok(crate::trust::SignerPins {
+        gpg: kind("gpg", &before.gpg, pins.gpg)?,
+        minisign: kind("minisign", &before.minisign, pins.minisign)?,
+        cosign: kind("cosign", &before.cosign, pins.cosign)?,
+    })
+}
+
+#[cfg(all(test, feature = "trust-store"))]
+mod tests {
+    use super::*;
+
+    fn signers(dir: &Path, name: &str, table: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, format!("[signers.zcashd]\n{table}\n")).unwrap();
+        path
+    }
+
+    #[test]
+    fn discovered_files_may_only_narrow_signer_pins() {
+        let dir = tempfile::tempdir().unwrap();
+        let zcashd = ServiceId::new_static("zcashd");
+        let user = signers(dir.path(), "user.toml", r#"minisign = ["A", "B"]"#);
+        let mut config = ResolverConfig::builder().finish();
+        apply(&mut config, &user, false).unwrap();
+
+        // Swapping in the project's own key, or adding one, is refused.
+        for table in [r#"minisign = ["C"]"#, r#"minisign = ["A", "C"]"#] {
+            let project = signers(dir.path(), FILE_NAME, table);
+            let err = apply(&mut config, &project, true).unwrap_err();
+            assert_eq!(err.kind(), crate::ErrorKind::InvalidInput, "{err}");
+            assert!(err.to_string().contains("may only narrow"), "{err}");
+        }
+
+        // Keeping some pins, or pinning a kind left open, narrows.
+        let project = signers(
+            dir.path(),
+            FILE_NAME,
+            r#"minisign = ["B"]
+gpg = ["F00D"]"#,
+        );
+        apply(&mut config, &project, true).unwrap();
+        let pins = &config.signers[&zcashd];
+        assert_eq!(pins.minisign, ["B"]);
+        assert_eq!(pins.gpg, ["F00D"]);
+
+        // An empty list doesn't lift the pins before it.
+        let project = signers(dir.path(), FILE_NAME, "minisign = []");
+        apply(&mut config, &project, true).unwrap();
+        assert_eq!(config.signers[&zcashd].minisign, ["B"]);
+
+        // Files applied from code are trusted like the user's.
+        let file = signers(dir.path(), "ci.toml", r#"minisign = ["C"]"#);
+        apply(&mut config, &file, false).unwrap();
+        assert_eq!(config.signers[&zcashd].minisign, ["C"]);
+    }
+}
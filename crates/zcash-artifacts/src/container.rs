@@ -0,0 +1,325 @@
+//! Container runtimes as [`OciBackend`]s.
+//!
+//! With [`OciBackend::Docker`] or [`OciBackend::Podman`] (or whichever
+//! [`OciBackend::Auto`] finds), images are pulled with the runtime's CLI, so
+//! the registry credentials, mirrors and proxies it is configured with apply;
+//! the resolver's [`CredentialProvider`](crate::credentials::CredentialProvider)
+//! is not consulted. Pulled images stay in the runtime's store:
+//! [`OciMode::Image`](crate::oci::OciMode::Image) resolves to an
+//! [`OciImage`](crate::ResolvedArtifact::OciImage) without a `layout`, and
+//! [`OciMode::Extract`](crate::oci::OciMode::Extract) reads the binary out of
+//! `<runtime> export` of a container that is created, never started, and
+//! removed again.
+//!
+//! The runtime checks the digest a source pins; unlike the registry backend,
+//! it doesn't check that a tag given alongside it still points there.
+
+use std::{
+    io::Read,
+    process::{Child, Command, Stdio},
+    sync::OnceLock,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    ResolveContext, ResolvedArtifact,
+    cancel::Phase,
+    error::{OciError, Result},
+    oci::{self, ImageConfig, ImagePlatform, OciBackend, Reference},
+};
+
+/// A container runtime CLI, `docker` or `podman`; both take the same arguments.
+pub(crate) struct Runtime {
+    program: &'static str,
+}
+
+/// An image the runtime has pulled.
+pub(crate) struct Pulled {
+    /// The source's reference, pinned to `digest`.
+    pub(crate) reference: Reference,
+    /// The digest the runtime recorded for the reference.
+    pub(crate) digest: String,
+    pub(crate) platform: String,
+    /// The runtime's image ID.
+    pub(crate) id: String,
+    pub(crate) config: ImageConfig,
+}
+
+impl Pulled {
+    pub(crate) fn into_resolved(self) -> ResolvedArtifact {
+        ResolvedArtifact::OciImage {
+            reference: self.reference.to_string(),
+            digest: self.digest,
+            platform: self.platform,
+            layout: None,
+            config: self.config,
+        }
+    }
+}
+
+/// What `<runtime> image inspect` reports, as far as we need it.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Inspect {
+    id: String,
+    #[serde(default)]
+    repo_digests: Vec<String>,
+    #[serde(flatten)]
+    platform: ImagePlatform,
+    #[serde(default)]
+    config: Option<ImageConfig>,
+}
+
+/// An image in the runtime's store; see [`Runtime::local_image`].
+pub(crate) struct LocalImage {
+    /// The image ID, with the `sha256:` prefix.
+    pub(crate) id: String,
+    pub(crate) platform: String,
+    pub(crate) config: ImageConfig,
+}
+
+impl Runtime {
+    /// The runtime behind `backend`; `None` for the registry backend.
+    pub(crate) fn for_backend(backend: OciBackend) -> Result<Option<Self>> {
+        let program = match backend {
+            OciBackend::Registry => return Ok(None),
+            OciBackend::Docker => "docker",
+            OciBackend::Podman => "podman",
+            OciBackend::Auto => return Ok(Self::detect()),
+        };
+        if crate::binfmt::find_on_path(program).is_none() {
+            return Err(OciError::RuntimeUnavailable {
+                program: program.to_string(),
+            }
+            .into());
+        }
+        Ok(Some(Self { program }))
+    }
+
+    /// The runtime to build images with: the one behind `backend`, or whichever
+    /// [`OciBackend::Auto`] finds for the registry backend, which can't build.
+    pub(crate) fn for_build(backend: OciBackend) -> Result<Self> {
+        let backend = match backend {
+            OciBackend::Registry => OciBackend::Auto,
+            backend => backend,
+        };
+        Self::for_backend(backend)?.ok_or_else(|| OciError::NoRuntime.into())
+    }
+
+    /// The first runtime that is on `PATH` and reachable: a `docker` CLI
+    /// without a daemon (or a `podman` without its machine) doesn't count.
+    fn detect() -> Option<Self> {
+        static DETECTED: OnceLock<Option<&'static str>> = OnceLock::new();
+        let program = DETECTED.get_or_init(|| {
+            ["docker", "podman"].into_iter().find(|program| {
+                crate::binfmt::find_on_path(program).is_some()
+                    && Command::new(program)
+                        .arg("info")
+                        .stdin(Stdio::null())
+                        .stdout(Stdio::null())
+                        .stderr(Stdio::null())
+                        .status()
+                        .is_ok_and(|status| status.success())
+            })
+        });
+        program.map(|program| Self { program })
+    }
+
+    pub(crate) fn program(&self) -> &'static str {
+        self.program
+    }
+
+    /// Runs the runtime with `args` and returns its stdout.
+    pub(crate) fn run(&self, args: &[&str]) -> Result<Vec<u8>> {
+        let output = Command::new(self.program)
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| self.failed(args, e.to_string()))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return Err(self.failed(args, stderr).into());
+        }
+        Ok(output.stdout)
+    }
+
+    fn failed(&self, args: &[&str], stderr: String) -> OciError {
+        OciError::Runtime {
+            program: self.program.to_string(),
+            args: args.join(" "),
+            stderr,
+        }
+    }
+
+    /// Pulls the source's image for the platform being resolved for.
+    pub(crate) fn pull(
+        &self,
+        ctx: &ResolveContext<'_>,
+        reference: &str,
+        digest: Option<&str>,
+    ) -> Result<Pulled> {
+        let parsed = oci::source_reference(reference, digest)?;
+        let wanted = oci::wanted_platform(ctx);
+        let name = parsed.to_string();
+        ctx.config
+            .network
+            .check_host(&parsed.registry, || name.clone())?;
+        let _slots = ctx.limiter.download(&parsed.registry, ctx.cancel)?;
+        ctx.enter(Phase::Download);
+        self.run(&[
+            "pull",
+            "--quiet",
+            "--platform",
+            &runtime_platform(&wanted),
+            &name,
+        ])?;
+
+        let image = self.inspect(&name)?;
+        let platform = image.platform.canonical();
+        if platform != wanted {
+            return Err(OciError::NoMatchingPlatform {
+                reference: name,
+                platform: wanted,
+                available: platform,
+            }
+            .into());
+        }
+
+        // Repository digests are listed per name the image was pulled as.
+        let recorded = image.repo_digests.iter().find_map(|entry| {
+            let entry = Reference::parse(entry).ok()?;
+            (entry.registry == parsed.registry && entry.repository == parsed.repository)
+                .then_some(entry.digest)?
+        });
+        let Some(digest) = parsed.digest.clone().or(recorded) else {
+            return Err(OciError::Pull {
+                reference: name,
+                source: format!("{} recorded no digest for it", self.program).into(),
+            }
+            .into());
+        };
+        Ok(Pulled {
+            reference: oci::pinned(&parsed, &digest),
+            digest,
+            platform,
+            id: image.id,
+            config: image.config.unwrap_or_default(),
+        })
+    }
+
+    /// What the runtime knows about the local image `image`.
+    fn inspect(&self, image: &str) -> Result<Inspect> {
+        let inspected = self.run(&["image", "inspect", image])?;
+        let bad = |reason: String| OciError::Pull {
+            reference: image.to_string(),
+            source: reason.into(),
+        };
+        let inspect: Vec<Inspect> = serde_json::from_slice(&inspected)
+            .map_err(|e| bad(format!("bad `{} image inspect` output: {e}", self.program)))?;
+        inspect
+            .into_iter()
+            .next()
+            .ok_or_else(|| bad(format!("`{} image inspect` found nothing", self.program)).into())
+    }
+
+    /// The local image `image`. Podman reports IDs without the algorithm;
+    /// they get Docker's `sha256:` prefix.
+    pub(crate) fn local_image(&self, image: &str) -> Result<LocalImage> {
+        let image = self.inspect(image)?;
+        let id = if image.id.contains(':') {
+            image.id
+        } else {
+            format!("sha256:{}", image.id)
+        };
+        Ok(LocalImage {
+            id,
+            platform: image.platform.canonical(),
+            config: image.config.unwrap_or_default(),
+        })
+    }
+
+    /// Creates (without starting) a container of `image`, removed on drop.
+    pub(crate) fn create(&self, image: &str) -> Result<Container<'_>> {
+        // A command, for images that have none; the container never runs it.
+        let id = self.run(&["create", image, "sh"])?;
+        Ok(Container {
+            runtime: self,
+            id: String::from_utf8_lossy(&id).trim().to_string(),
+        })
+    }
+}
+
+/// A created container, removed again on drop.
+pub(crate) struct Container<'a> {
+    runtime: &'a Runtime,
+    id: String,
+}
+
+impl Container<'_> {
+    /// Streams the container's flattened filesystem as a tar archive.
+    pub(crate) fn export(&self) -> Result<Box<dyn Read>> {
+        let args = ["export", self.id.as_str()];
+        let child = Command::new(self.runtime.program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| self.runtime.failed(&args, e.to_string()))?;
+        Ok(Box::new(Export {
+            child,
+            program: self.runtime.program,
+        }))
+    }
+}
+
+impl Drop for Container<'_> {
+    fn drop(&mut self) {
+        let _ = self.runtime.run(&["rm", "--force", &self.id]);
+    }
+}
+
+/// The stdout of `<runtime> export`, failing at the end if the export did.
+struct Export {
+    child: Child,
+    program: &'static str,
+}
+
+impl Read for Export {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let stdout = self.child.stdout.as_mut().expect("stdout is piped");
+        let n = stdout.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            let status = self.child.wait()?;
+            if !status.success() {
+                return Err(std::io::Error::other(format!(
+                    "`{} export` failed with {status}",
+                    self.program
+                )));
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl Drop for Export {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A platform in the runtimes' `os/arch[/variant]` spelling, e.g. `linux/arm64`.
+pub(crate) fn runtime_platform(platform: &str) -> String {
+    let (os, arch) = platform.split_once('-').unwrap_or((platform, ""));
+    let arch = match arch {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "armv7" => "arm/v7",
+        "i586" | "i686" => "386",
+        "powerpc64le" => "ppc64le",
+        other => other,
+    };
+    format!("{os}/{arch}")
+}
@@ -0,0 +1,259 @@
+//! Credentials for GitHub, private HTTP hosts and OCI registries.
+//!
+//! Every authenticated request asks the resolver's [`CredentialProvider`] for the
+//! URL it is about to fetch. Unless configured otherwise that is
+//! [`FileCredentials`], reading `<cache_root>/credentials.toml`:
+//!
+//! ```toml
+//! ["github.com"]
+//! token = "ghp_..."
+//!
+//! ["registry.example.com"]
+//! username = "ci"
+//! password = "..."
+//! ```
+//!
+//! A host entry also covers its subdomains (`github.com` covers `api.github.com`).
+//! Secrets are never written to META or logs: [`Credential`]'s `Debug` output is
+//! redacted, and URLs are recorded without their user-info part.
+//!
+//! OCI registries the provider has nothing for are then looked up in the
+//! `docker login` state, see [`DockerCredentials`] (`oci` feature), before
+//! pulling anonymously.
+
+use std::{collections::HashMap, fmt, path::PathBuf};
+
+use serde::Deserialize;
+use url::Url;
+
+/// A secret to authenticate one request with.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credential {
+    /// Sent as `Authorization: Bearer <token>`.
+    Bearer(String),
+    Basic {
+        username: String,
+        password: String,
+    },
+}
+
+impl fmt::Debug for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Credential::Bearer(_) => f.write_str("Bearer(<redacted>)"),
+            Credential::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", &"<redacted>")
+                .finish(),
+        }
+    }
+}
+
+/// Supplies credentials for the URLs the resolver fetches.
+pub trait CredentialProvider: Send + Sync + 'static {
+    /// The credential to send to `url`, or `None` to fetch it anonymously.
+    fn credential_for(&self, url: &Url) -> Option<Credential>;
+}
+
+/// Reads per-host credentials from a TOML file; see the [module docs](self).
+///
+/// The file is read on every lookup, so edits apply without restarting, and a
+/// missing or malformed file means no credentials.
+#[derive(Debug, Clone)]
+pub struct FileCredentials {
+    path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct HostEntry {
+    token: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl FileCredentials {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// `<cache_root>/credentials.toml`.
+    pub fn in_cache_root(cache_root: &std::path::Path) -> Self {
+        Self::new(cache_root.join("credentials.toml"))
+    }
+}
+
+impl CredentialProvider for FileCredentials {
+    fn credential_for(&self, url: &Url) -> Option<Credential> {
+        let host = url.host_str()?;
+        let text = std::fs::read_to_string(&self.path).ok()?;
+        let hosts: HashMap<String, HostEntry> = toml::from_str(&text).ok()?;
+        // The most specific matching entry wins.
+        let (_, entry) = hosts
+            .iter()
+            .filter(|(name, _)| {
+                host == name.as_str()
+                    || host
+                        .strip_suffix(name.as_str())
+                        .is_some_and(|sub| sub.ends_with('.'))
+            })
+            .max_by_key(|(name, _)| name.len())?;
+        match (&entry.token, &entry.username) {
+            (Some(token), _) => Some(Credential::Bearer(token.clone())),
+            (None, Some(username)) => Some(Credential::Basic {
+                username: username.clone(),
+                password: entry.password.clone().unwrap_or_default(),
+            }),
+            (None, None) => None,
+        }
+    }
+}
+
+/// `url` without user name and password, for META, logs and error messages.
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub(crate) fn redact(url: &Url) -> String {
+    let mut url = url.clone();
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url.to_string()
+}
+
+/// Reads `docker login` state from `$DOCKER_CONFIG/config.json`, by default
+/// `~/.docker/config.json`.
+///
+/// A registry's `credHelpers` entry, or else the `credsStore`, is asked first by
+/// running `docker-credential-<helper> get`; inline `auths` entries come next.
+/// Identity tokens (OAuth refresh tokens, e.g. from `az acr login`) aren't
+/// supported and are skipped. Like [`FileCredentials`], the file is read on
+/// every lookup.
+#[cfg(feature = "oci")]
+#[derive(Debug, Clone)]
+pub struct DockerCredentials {
+    path: PathBuf,
+}
+
+#[cfg(feature = "oci")]
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct DockerConfig {
+    #[serde(default)]
+    auths: HashMap<String, DockerAuth>,
+    creds_store: Option<String>,
+    #[serde(default)]
+    cred_helpers: HashMap<String, String>,
+}
+
+#[cfg(feature = "oci")]
+#[derive(Deserialize)]
+struct DockerAuth {
+    /// base64 of `username:password`.
+    auth: Option<String>,
+    registrytoken: Option<String>,
+}
+
+#[cfg(feature = "oci")]
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HelperReply {
+    username: String,
+    secret: String,
+}
+
+#[cfg(feature = "oci")]
+impl DockerCredentials {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The config the `docker` CLI uses; `None` without a home directory.
+    pub fn from_env() -> Option<Self> {
+        let dir = match std::env::var_os("DOCKER_CONFIG") {
+            Some(dir) => PathBuf::from(dir),
+            None => std::env::home_dir()?.join(".docker"),
+        };
+        Some(Self::new(dir.join("config.json")))
+    }
+
+    /// Runs `docker-credential-<helper> get` for `server`.
+    fn from_helper(helper: &str, server: &str) -> Option<Credential> {
+        use std::{
+            io::Write,
+            process::{Command, Stdio},
+        };
+
+        let mut child = Command::new(format!("docker-credential-{helper}"))
+            .arg("get")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let _ = child.stdin.take()?.write_all(server.as_bytes());
+        let output = child.wait_with_output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let reply: HelperReply = serde_json::from_slice(&output.stdout).ok()?;
+        // Helpers return identity tokens under this user name.
+        (reply.username != "<token>").then_some(Credential::Basic {
+            username: reply.username,
+            password: reply.secret,
+        })
+    }
+}
+
+#[cfg(feature = "oci")]
+impl CredentialProvider for DockerCredentials {
+    fn credential_for(&self, url: &Url) -> Option<Credential> {
+        use base64::Engine;
+
+        let host = match (url.host_str()?, url.port()) {
+            // Docker Hub logins are stored under its v1 index URL.
+            ("docker.io" | "index.docker.io" | "registry-1.docker.io", _) => {
+                "index.docker.io".to_string()
+            }
+            (host, Some(port)) => format!("{host}:{port}"),
+            (host, None) => host.to_string(),
+        };
+        let text = std::fs::read(&self.path).ok()?;
+        let config: DockerConfig = serde_json::from_slice(&text).ok()?;
+        // Keys are bare hosts or URLs, e.g. `https://index.docker.io/v1/`.
+        let matches = |key: &str| {
+            let key = key.split_once("://").map_or(key, |(_, rest)| rest);
+            key.split('/').next() == Some(host.as_str())
+        };
+        let server = |key: Option<&String>| match key {
+            Some(key) => key.clone(),
+            None if host == "index.docker.io" => "https://index.docker.io/v1/".to_string(),
+            None => host.clone(),
+        };
+
+        let helper = config
+            .cred_helpers
+            .iter()
+            .find(|(key, _)| matches(key))
+            .map(|(key, helper)| (helper, Some(key)))
+            .or_else(|| {
+                let key = config.auths.keys().find(|key| matches(key));
+                config.creds_store.as_ref().map(|store| (store, key))
+            });
+        if let Some((helper, key)) = helper
+            && let Some(credential) = Self::from_helper(helper, &server(key))
+        {
+            return Some(credential);
+        }
+
+        let (_, entry) = config.auths.iter().find(|(key, _)| matches(key))?;
+        if let Some(token) = &entry.registrytoken {
+            return Some(Credential::Bearer(token.clone()));
+        }
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(entry.auth.as_ref()?.trim())
+            .ok()?;
+        let (username, password) = std::str::from_utf8(&decoded).ok()?.split_once(':')?;
+        Some(Credential::Basic {
+            username: username.to_string(),
+            password: password.to_string(),
+        })
+    }
+}
@@ -0,0 +1,640 @@
+//! Binaries from Debian/Ubuntu packages.
+//!
+//! [`ArtifactSource::Deb`](crate::ArtifactSource::Deb) takes a service's binary
+//! out of a `.deb`, without `dpkg` or root: the package's `data.tar` is
+//! unpacked into the cache entry's work directory, the binary and its
+//! companions are taken from `usr/bin/` (or `usr/sbin/`, `usr/local/bin/`,
+//! `bin/`), and cached like a release's.
+//!
+//! [`DebPackage::Repository`] downloads the package from the service's
+//! [`AptRepository`], checking it the way apt does:
+//!
+//! 1. `dists/<suite>/InRelease` is fetched and, if the repository has a
+//!    keyring, its signature checked with `gpgv`. Repositories without one
+//!    are checked against the [trust store](crate::trust)'s keys for their
+//!    host, if any, and signatures must be by the service's
+//!    [pinned signers](crate::trust#pinned-signers), if it has any
+//!    (`trust-store` feature).
+//! 2. The `Packages` index for the component and architecture (`amd64`,
+//!    `arm64`, `armhf` or `i386`) must match the hash the release file lists.
+//! 3. The package, the requested version or the newest one, must match the
+//!    hash the index lists.
+//!
+//! Entries are keyed by `deb-<suite>-<version>`. Without a keyring, a
+//! compromised repository can serve anything; the checks then only catch
+//! corruption.
+//!
+//! [`DebPackage::File`] unpacks a `.deb` on disk instead, e.g. one built by
+//! hand or from a mirror the resolver can't reach. It is taken as is, and
+//! keyed by its digest.
+
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    io::Read,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use url::Url;
+
+use crate::{
+    ResolveContext, ResolvedArtifact, binfmt,
+    cache::{self, CacheKey, CachePaths},
+    error::{FsError, LocateError, Result, UnpackError, VerifyError},
+    pipeline::{fetch_text, fetch_verified},
+    registry::ToolSpec,
+};
+
+/// Where binaries are looked for in the package's files, in order.
+const BIN_DIRS: &[&str] = &["usr/bin", "usr/sbin", "usr/local/bin", "bin"];
+
+/// Which `.deb` a [`Deb`](crate::ArtifactSource::Deb) source takes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DebPackage {
+    /// A package from the service's [`AptRepository`]:
+    /// `version` (e.g. `6.0.0` or `1:6.0.0-1`), or the newest.
+    Repository { version: Option<String> },
+    /// A `.deb` file on disk.
+    File(PathBuf),
+}
+
+/// An apt repository a service's packages are published in, like a
+/// `deb <url> <suite> <component>` line in `sources.list`.
+#[derive(Debug, Clone)]
+pub struct AptRepository {
+    url: String,
+    suite: String,
+    component: String,
+    package: String,
+    keyring: Option<PathBuf>,
+}
+
+impl AptRepository {
+    /// The package named `package` in `suite` (e.g. `bookworm`) of the
+    /// repository at `url`, in the `main` component.
+    pub fn new(
+        url: impl Into<String>,
+        suite: impl Into<String>,
+        package: impl Into<String>,
+    ) -> Self {
+        Self {
+            url: url.into(),
+            suite: suite.into(),
+            component: "main".into(),
+            package: package.into(),
+            keyring: None,
+        }
+    }
+
+    pub fn component(mut self, component: impl Into<String>) -> Self {
+        self.component = component.into();
+        self
+    }
+
+    /// Checks `InRelease` with `gpgv` against this keyring, e.g. the one
+    /// `signed-by` names in `sources.list`.
+    pub fn keyring(mut self, keyring: impl Into<PathBuf>) -> Self {
+        self.keyring = Some(keyring.into());
+        self
+    }
+
+    /// `path` under the repository's URL.
+    fn join(&self, path: &str) -> Option<Url> {
+        Url::parse(&format!("{}/{path}", self.url.trim_end_matches('/'))).ok()
+    }
+}
+
+/// A package's stanza in a `Packages` index.
+#[derive(Debug, Default)]
+struct Stanza {
+    package: String,
+    version: String,
+    architecture: String,
+    filename: String,
+    sha256: String,
+}
+
+/// Resolves the source; see the [module docs](self).
+pub(crate) fn resolve(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    package: &DebPackage,
+) -> Result<ResolvedArtifact> {
+    match package {
+        DebPackage::Repository { version } => {
+            let repo =
+                spec.apt
+                    .as_ref()
+                    .ok_or_else(|| crate::error::InputError::InvalidSource {
+                        service: spec.id.clone(),
+                        reason: "service has no apt repository".into(),
+                    })?;
+            from_repository(ctx, spec, repo, version.as_deref())
+        }
+        DebPackage::File(path) => {
+            let (digest, _) = cache::digest_file(path)?;
+            let (paths, bin_name) = entry(ctx, spec, &format!("file-{}", &digest[..16]))?;
+            let meta = cache::Meta {
+                url: Url::from_file_path(path).ok().map(String::from),
+                ..meta(spec, None)
+            };
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            unpack_into(ctx, spec, &paths, &bin_name, false, meta, |_| {
+                Ok((path.to_path_buf(), name.clone()))
+            })
+        }
+    }
+}
+
+fn from_repository(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    repo: &AptRepository,
+    version: Option<&str>,
+) -> Result<ResolvedArtifact> {
+    if let Some(version) = version {
+        let (paths, bin_name) = entry(ctx, spec, &format!("{}-{version}", repo.suite))?;
+        let path = paths.out.join(bin_name);
+        if cache::usable_entry(ctx, &paths, &path, ctx.platform)? && signer_ok(ctx, spec, &paths) {
+            return Ok(ResolvedArtifact::executable(path));
+        }
+    }
+    let index_error = |why: String| LocateError::ReleaseIndex {
+        service: spec.id.clone(),
+        version: version.unwrap_or("latest").to_string(),
+        why,
+    };
+    let arch = architecture(ctx.platform).ok_or_else(|| LocateError::NoAsset {
+        service: spec.id.clone(),
+        version: version.unwrap_or("latest").to_string(),
+        platform: ctx.platform.to_string(),
+    })?;
+    let url = |path: &str| {
+        repo.join(path)
+            .ok_or_else(|| index_error(format!("bad repository URL `{}`", repo.url)))
+    };
+
+    let in_release = url(&format!("dists/{}/InRelease", repo.suite))?;
+    let release = fetch_text(ctx, &in_release)?;
+    #[cfg(feature = "trust-store")]
+    let trusted = match (&repo.keyring, in_release.host_str()) {
+        (None, Some(host)) => {
+            ctx.config
+                .trust_store
+                .keyring_for(host, &ctx.config.cache_root, in_release.as_str())?
+        }
+        _ => None,
+    };
+    #[cfg(not(feature = "trust-store"))]
+    let trusted: Option<PathBuf> = None;
+    let signers = match repo.keyring.as_ref().or(trusted.as_ref()) {
+        Some(keyring) => check_signature(&release, keyring, &in_release)?,
+        None => Vec::new(),
+    };
+    #[cfg(feature = "trust-store")]
+    let signer = crate::trust::check_gpg(ctx, &spec.id, &signers, &in_release)?;
+    #[cfg(not(feature = "trust-store"))]
+    let (signer, _) = (None, signers);
+    let hashes = release_hashes(&clearsigned_text(&release));
+    let base = format!("{}/binary-{arch}/Packages", repo.component);
+    let (index_name, index_hash) = ["", ".gz", ".xz"]
+        .iter()
+        .map(|ext| format!("{base}{ext}"))
+        .find_map(|name| Some((name.clone(), hashes.get(&name)?.clone())))
+        .ok_or_else(|| index_error(format!("{in_release} lists no {base}")))?;
+    let packages = fetch_index(
+        ctx,
+        &url(&format!("dists/{}/{index_name}", repo.suite))?,
+        &index_hash,
+    )?;
+
+    let stanza = parse_packages(&packages)
+        .into_iter()
+        .filter(|s| {
+            s.package == repo.package && (s.architecture == arch || s.architecture == "all")
+        })
+        .filter(|s| version.is_none_or(|v| s.version == v || upstream(&s.version) == v))
+        .max_by(|a, b| compare_versions(&a.version, &b.version))
+        .ok_or_else(|| LocateError::NoAsset {
+            service: spec.id.clone(),
+            version: format!("{} {}", repo.package, version.unwrap_or("(any)")),
+            platform: ctx.platform.to_string(),
+        })?;
+    let deb_url = url(&stanza.filename)?;
+
+    let (paths, bin_name) = entry(ctx, spec, &format!("{}-{}", repo.suite, stanza.version))?;
+    let meta = cache::Meta {
+        url: Some(crate::credentials::redact(&deb_url)),
+        signer,
+        ..meta(spec, Some(stanza.version.clone()))
+    };
+    unpack_into(ctx, spec, &paths, &bin_name, true, meta, |work| {
+        let download = work.join("package.deb");
+        fetch_verified(
+            ctx,
+            &deb_url,
+            &stanza.sha256,
+            &download,
+            ctx.config.credential_provider().as_ref(),
+            4,
+        )?;
+        Ok((download, "package.deb".into()))
+    })
+}
+
+fn meta(spec: &ToolSpec, version: Option<String>) -> cache::Meta {
+    cache::Meta {
+        service: spec.id.as_str().to_string(),
+        source: "deb".into(),
+        release: version,
+        ..Default::default()
+    }
+}
+
+/// Cache entry of `spec`'s binary from the package `id` names.
+fn entry(ctx: &ResolveContext<'_>, spec: &ToolSpec, id: &str) -> Result<(CachePaths, String)> {
+    let key = CacheKey {
+        service: spec.id.as_str().to_string(),
+        // Epochs are spelled with a colon.
+        revision: format!("deb-{}", id.replace(':', "_")),
+        worktree_hash: None,
+        platform: ctx.platform.to_string(),
+        schema: spec.builder_schema,
+    };
+    let bin_name = spec
+        .binary_names_for(ctx.platform)
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| spec.id.as_str().to_string());
+    Ok((CachePaths::new(&ctx.config.cache_root, &key)?, bin_name))
+}
+
+/// Whether the entry in `paths` was checked against `spec`'s pinned signers,
+/// if it has any; see [`crate::trust`] (`trust-store` feature).
+fn signer_ok(ctx: &ResolveContext<'_>, spec: &ToolSpec, paths: &CachePaths) -> bool {
+    #[cfg(feature = "trust-store")]
+    return crate::trust::cached_ok(ctx, &spec.id, crate::trust::Signer::Gpg, &paths.meta);
+    #[cfg(not(feature = "trust-store"))]
+    {
+        let _ = (ctx, spec, paths);
+        true
+    }
+}
+
+/// Under the entry's lock, gets the `.deb` (as path and file name) with
+/// `fetch` into a work directory, unpacks it and finalizes the entry. With
+/// `signed`, existing entries must also have been checked against the
+/// service's pinned signers.
+fn unpack_into(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    paths: &CachePaths,
+    bin_name: &str,
+    signed: bool,
+    meta: cache::Meta,
+    fetch: impl FnOnce(&Path) -> Result<(PathBuf, String)>,
+) -> Result<ResolvedArtifact> {
+    let out_bin = paths.out.join(bin_name);
+    let cached = || -> Result<bool> {
+        Ok(cache::usable_entry(ctx, paths, &out_bin, ctx.platform)?
+            && (!signed || signer_ok(ctx, spec, paths)))
+    };
+    if cached()? {
+        return Ok(ResolvedArtifact::executable(out_bin));
+    }
+    paths.create_dirs()?;
+    let _lock = paths.lock(ctx)?; // released on drop
+    if cached()? {
+        return Ok(ResolvedArtifact::executable(out_bin));
+    }
+    let work = paths.staging()?;
+    let result = fetch(&work).and_then(|(deb, name)| {
+        let unpacked = work.join("unpacked");
+        crate::archive::extract(&deb, &name, &unpacked)?;
+        finalize(ctx, spec, &unpacked, &name, paths, bin_name, meta)
+    });
+    let _ = std::fs::remove_dir_all(&work);
+    Ok(ResolvedArtifact::executable(result?))
+}
+
+fn finalize(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    unpacked: &Path,
+    name: &str,
+    paths: &CachePaths,
+    bin_name: &str,
+    mut meta: cache::Meta,
+) -> Result<PathBuf> {
+    let names = spec.binary_names_for(ctx.platform);
+    let binary = BIN_DIRS
+        .iter()
+        .flat_map(|dir| names.iter().map(move |name| unpacked.join(dir).join(name)))
+        .find_map(|path| resolve_link(unpacked, path))
+        .ok_or_else(|| UnpackError::BinaryNotFound {
+            archive: name.to_string(),
+            tried: BIN_DIRS
+                .iter()
+                .map(|dir| format!("{dir}/{{{}}}", names.join(",")))
+                .collect::<Vec<_>>()
+                .join(", "),
+        })?;
+    let dir = binary.parent().unwrap_or(unpacked);
+    let companions: Vec<_> = spec
+        .companions
+        .values()
+        .map(|name| crate::platform::exe_name(name, ctx.platform))
+        .filter_map(|name| {
+            let path = resolve_link(unpacked, dir.join(&name))?;
+            cache::looks_executable(&path).then_some((name, path))
+        })
+        .collect();
+
+    binfmt::check(&binary, ctx.platform)?;
+    if ctx.targets_host() {
+        let what = format!(
+            "{} {} package",
+            spec.id.as_str(),
+            meta.release.as_deref().unwrap_or(name)
+        );
+        binfmt::check_glibc(&binary, &what)?;
+    }
+    meta.host = crate::platform::host();
+    meta.platform = ctx.platform.to_string();
+    meta.builder_schema = spec.builder_schema;
+    cache::finalize(
+        ctx,
+        paths,
+        bin_name,
+        &binary,
+        &companions,
+        spec.version_probe.as_deref().filter(|_| ctx.targets_host()),
+        false,
+        meta,
+    )
+}
+
+/// `path`, a file unpacked under `root`, with symlinks followed inside `root`:
+/// packages often link `usr/bin/x` to `/usr/lib/...`, which must not resolve
+/// to this machine's files.
+///
+/// Every component is resolved as if `root` were `/`: links in directories are
+/// followed too, absolute targets start over at `root`, and `..` stops there.
+fn resolve_link(root: &Path, path: PathBuf) -> Option<PathBuf> {
+    use std::path::Component;
+
+    // Names and `..`s still to resolve, the next one last.
+    fn parts(path: &Path) -> impl Iterator<Item = std::ffi::OsString> + '_ {
+        path.components()
+            .filter(|c| matches!(c, Component::Normal(_) | Component::ParentDir))
+            .rev()
+            .map(|c| c.as_os_str().to_owned())
+    }
+    let mut pending: Vec<_> = parts(path.strip_prefix(root).ok()?).collect();
+    let mut resolved = PathBuf::new();
+    // As many hops as Linux follows before giving up with ELOOP.
+    let mut hops = 0;
+    while let Some(part) = pending.pop() {
+        if part == ".." {
+            resolved.pop();
+            continue;
+        }
+        let candidate = root.join(&resolved).join(&part);
+        if !std::fs::symlink_metadata(&candidate).ok()?.is_symlink() {
+            resolved.push(part);
+            continue;
+        }
+        hops += 1;
+        if hops > 40 {
+            return None;
+        }
+        let target = std::fs::read_link(&candidate).ok()?;
+        if target.has_root() {
+            resolved.clear();
+        }
+        pending.extend(parts(&target));
+    }
+    let path = root.join(resolved);
+    std::fs::symlink_metadata(&path)
+        .ok()?
+        .is_file()
+        .then_some(path)
+}
+
+/// The Debian architecture of `platform`.
+fn architecture(platform: &str) -> Option<&'static str> {
+    Some(match crate::platform::normalize(platform).as_str() {
+        "linux-x86_64" => "amd64",
+        "linux-aarch64" => "arm64",
+        "linux-armv7" => "armhf",
+        "linux-i686" | "linux-i386" => "i386",
+        _ => return None,
+    })
+}
+
+/// Checks the signature on the clearsigned `text` fetched from `url`, and
+/// returns the fingerprints of the keys with valid signatures on it, each
+/// signing key's followed by its primary key's.
+fn check_signature(text: &str, keyring: &Path, url: &Url) -> Result<Vec<String>> {
+    let invalid = |reason: String| VerifyError::SignatureInvalid {
+        what: url.to_string(),
+        source: reason.into(),
+    };
+    let file =
+        std::env::temp_dir().join(format!("zcash-artifacts-InRelease-{}", std::process::id()));
+    cache::write_atomic(&file, text.as_bytes())?;
+    let output = Command::new("gpgv")
+        .args(["--status-fd", "1"])
+        .arg("--keyring")
+        .arg(keyring)
+        .arg(&file)
+        .output();
+    let _ = std::fs::remove_file(&file);
+    let output = output.map_err(|e| invalid(format!("could not run gpgv: {e}")))?;
+    if !output.status.success() {
+        return Err(invalid(String::from_utf8_lossy(&output.stderr).trim().to_string()).into());
+    }
+    // `[GNUPG:] VALIDSIG <fingerprint> <date> … <primary key fingerprint>`
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.strip_prefix("[GNUPG:] VALIDSIG "))
+        .flat_map(|fields| {
+            let fields: Vec<&str> = fields.split_whitespace().collect();
+            [fields.first(), fields.get(9)].map(|fpr| fpr.map(|fpr| fpr.to_string()))
+        })
+        .flatten()
+        .collect())
+}
+
+/// The signed text of a clearsigned message, or `text` if it isn't one.
+fn clearsigned_text(text: &str) -> String {
+    if !text.starts_with("-----BEGIN PGP SIGNED MESSAGE-----") {
+        return text.to_string();
+    }
+    text.lines()
+        // Past the armor headers, which end with a blank line.
+        .skip_while(|line| !line.trim().is_empty())
+        .skip(1)
+        .take_while(|line| !line.starts_with("-----BEGIN PGP SIGNATURE-----"))
+        .map(|line| line.strip_prefix("- ").unwrap_or(line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The `SHA256` section of a release file, as path -> hash.
+fn release_hashes(release: &str) -> BTreeMap<String, String> {
+    let mut hashes = BTreeMap::new();
+    let mut in_sha256 = false;
+    for line in release.lines() {
+        if !line.starts_with(' ') {
+            in_sha256 = line.trim_end() == "SHA256:";
+            continue;
+        }
+        if let (true, [hash, _size, path]) = (
+            in_sha256,
+            line.split_whitespace().collect::<Vec<_>>().as_slice(),
+        ) {
+            hashes.insert(path.to_string(), hash.to_ascii_lowercase());
+        }
+    }
+    hashes
+}
+
+/// Downloads a `Packages` index, checks it against `sha256` and decompresses it.
+fn fetch_index(ctx: &ResolveContext<'_>, url: &Url, sha256: &str) -> Result<String> {
+    let tmp = std::env::temp_dir().join(format!("zcash-artifacts-Packages-{}", std::process::id()));
+    let result = fetch_verified(
+        ctx,
+        url,
+        sha256,
+        &tmp,
+        ctx.config.credential_provider().as_ref(),
+        2,
+    )
+    .and_then(|()| {
+        let file = std::fs::File::open(&tmp).map_err(|e| FsError::Io {
+            context: format!("open {}", tmp.display()),
+            source: e,
+        })?;
+        let mut reader: Box<dyn Read> = match url.path() {
+            path if path.ends_with(".gz") => Box::new(flate2::read::GzDecoder::new(file)),
+            path if path.ends_with(".xz") => Box::new(lzma_rust2::XzReader::new(file, true)),
+            _ => Box::new(file),
+        };
+        let mut text = String::new();
+        reader
+            .read_to_string(&mut text)
+            .map_err(|e| UnpackError::Tool {
+                archive: crate::credentials::redact(url),
+                source: Box::new(e),
+            })?;
+        Ok(text)
+    });
+    let _ = std::fs::remove_file(&tmp);
+    result
+}
+
+fn parse_packages(text: &str) -> Vec<Stanza> {
+    text.split("\n\n")
+        .map(|block| {
+            let mut stanza = Stanza::default();
+            for line in block.lines() {
+                let Some((key, value)) = line.split_once(':') else {
+                    continue;
+                };
+                let value = value.trim().to_string();
+                match key {
+                    "Package" => stanza.package = value,
+                    "Version" => stanza.version = value,
+                    "Architecture" => stanza.architecture = value,
+                    "Filename" => stanza.filename = value,
+                    "SHA256" => stanza.sha256 = value.to_ascii_lowercase(),
+                    _ => {}
+                }
+            }
+            stanza
+        })
+        .filter(|stanza| !stanza.package.is_empty() && !stanza.filename.is_empty())
+        .collect()
+}
+
+/// A Debian version without its epoch and revision: `6.0.0` for `1:6.0.0-1`.
+fn upstream(version: &str) -> &str {
+    let version = version.split_once(':').map_or(version, |(_, rest)| rest);
+    version
+        .rsplit_once('-')
+        .map_or(version, |(upstream, _)| upstream)
+}
+
+/// Orders Debian versions as `dpkg --compare-versions` does.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    fn split(version: &str) -> (u64, &str, &str) {
+        let (epoch, rest) = match version.split_once(':') {
+            Some((epoch, rest)) => (epoch.parse().unwrap_or(0), rest),
+            None => (0, version),
+        };
+        let (upstream, revision) = rest.rsplit_once('-').unwrap_or((rest, ""));
+        (epoch, upstream, revision)
+    }
+    let (a_epoch, a_upstream, a_revision) = split(a);
+    let (b_epoch, b_upstream, b_revision) = split(b);
+    a_epoch
+        .cmp(&b_epoch)
+        .then_with(|| compare_part(a_upstream.as_bytes(), b_upstream.as_bytes()))
+        .then_with(|| compare_part(a_revision.as_bytes(), b_revision.as_bytes()))
+}
+
+/// dpkg's `verrevcmp`: runs of non-digits compare by character, with `~`
+/// before anything (even the end) and letters before other characters; runs
+/// of digits compare numerically.
+fn compare_part(a: &[u8], b: &[u8]) -> Ordering {
+    fn order(c: Option<&u8>) -> i32 {
+        match c {
+            None => 0,
+            Some(b'~') => -1,
+            Some(c) if c.is_ascii_digit() => 0,
+            Some(c) if c.is_ascii_alphabetic() => i32::from(*c),
+            Some(c) => i32::from(*c) + 256,
+        }
+    }
+    let digit = |s: &[u8], i: usize| s.get(i).is_some_and(u8::is_ascii_digit);
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        while (i < a.len() && !digit(a, i)) || (j < b.len() && !digit(b, j)) {
+            let (ac, bc) = (order(a.get(i)), order(b.get(j)));
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+            i += 1;
+            j += 1;
+        }
+        while a.get(i) == Some(&b'0') {
+            i += 1;
+        }
+        while b.get(j) == Some(&b'0') {
+            j += 1;
+        }
+        let mut first_diff = Ordering::Equal;
+        while digit(a, i) && digit(b, j) {
+            if first_diff == Ordering::Equal {
+                first_diff = a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+        if digit(a, i) {
+            return Ordering::Greater;
+        }
+        if digit(b, j) {
+            return Ordering::Less;
+        }
+        if first_diff != Ordering::Equal {
+            return first_diff;
+        }
+    }
+    Ordering::Equal
+}
@@ -0,0 +1,126 @@
+//! [`miette::Diagnostic`] for [`ArtifactError`], behind the `miette` feature,
+//! so that a CLI or test harness reporting with miette gets an error code and
+//! a hint at the fix:
+//!
+//! ```text
+//! zcash_artifacts::build-failed
+//!
+//!   × build script failed with exit code 2; see log at …/logs/build.log
+//!   │ last lines of the build log:
+//!   │ …
+//!   help: run the failing command in the worktree to reproduce it
+//! ```
+//!
+//! Failed builds carry the end of their log in the message itself, so it shows
+//! with plain `Display` too.
+//!
+//! The codes are `zcash_artifacts::` followed by the error's
+//! [kind](crate::ErrorKind::code).
+
+use std::fmt::Display;
+
+use miette::Diagnostic;
+
+use crate::error::{ArtifactError, FsError, LocateError, OciError, VerifyError};
+
+impl Diagnostic for ArtifactError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new(format!("zcash_artifacts::{}", self.kind())))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        let help = help(self).or_else(|| {
+            self.is_transient()
+                .then(|| "this may be a temporary failure; retrying may help".to_string())
+        })?;
+        Some(Box::new(help))
+    }
+}
+
+fn help(err: &ArtifactError) -> Option<String> {
+    Some(match err {
+        ArtifactError::Locate(LocateError::NoAsset { .. }) => {
+            "no release is published for this platform; use a Build source, or set \
+             `platform_override` to fetch another platform's"
+                .into()
+        }
+        #[cfg(feature = "http")]
+        ArtifactError::Fetch(crate::error::FetchError::Denied { .. }) => {
+            "resolve from the cache or a local path, or allow the host in \
+             `ResolverConfig::network` (or `ZCASH_ARTIFACTS_ALLOWED_HOSTS`)"
+                .into()
+        }
+        #[cfg(not(feature = "http"))]
+        ArtifactError::Fetch(crate::error::FetchError::Disabled { .. }) => {
+            "enable the `http` feature of zcash-artifacts".into()
+        }
+        ArtifactError::Verify(VerifyError::ChecksumMismatch { .. }) => {
+            "the download was corrupted or the asset has been replaced; if the new \
+             checksum is expected, update the pinned one"
+                .into()
+        }
+        ArtifactError::Verify(VerifyError::MissingChecksum { .. }) => {
+            "pin the asset's sha256 in the source".into()
+        }
+        ArtifactError::Verify(VerifyError::MissingLibraries { .. }) => {
+            "install the libraries, or use a Build source to link against the host's".into()
+        }
+        #[cfg(feature = "oci")]
+        ArtifactError::Oci(OciError::Unauthorized { .. }) => {
+            "add the registry's credentials to `credentials.toml` in the cache root, \
+             or log in with `docker login`"
+                .into()
+        }
+        #[cfg(feature = "oci")]
+        ArtifactError::Oci(OciError::NoRuntime | OciError::RuntimeUnavailable { .. }) => {
+            "install docker or podman, or use `OciMode::Extract` to run the image's \
+             binary directly"
+                .into()
+        }
+        #[cfg(feature = "oci")]
+        ArtifactError::Oci(OciError::TagDrifted { .. }) => {
+            "pin the image by digest, or accept the new one by updating the pin".into()
+        }
+        #[cfg(not(feature = "oci"))]
+        ArtifactError::Oci(OciError::Disabled { .. }) => {
+            "enable the `oci` feature of zcash-artifacts".into()
+        }
+        ArtifactError::Fs(FsError::InsufficientSpace { .. }) => {
+            "free some space, or point `cache_root` at a larger disk".into()
+        }
+        #[cfg(feature = "local-build")]
+        ArtifactError::Build(e) => return build_help(e),
+        ArtifactError::TimedOut { .. } => {
+            "raise `ResolveOptions::timeout` if the resolution was making progress; \
+             a cached or released artifact resolves faster than a build"
+                .into()
+        }
+        _ => return None,
+    })
+}
+
+#[cfg(feature = "local-build")]
+fn build_help(err: &crate::error::BuildError) -> Option<String> {
+    use crate::error::BuildError;
+
+    Some(match err {
+        BuildError::PreflightMissingTools { .. } => {
+            "install the missing tools, or use a Release source".into()
+        }
+        BuildError::DisabledFeature => "enable the `local-build` feature of zcash-artifacts".into(),
+        BuildError::ScriptFailed { .. } => {
+            "run the failing command in the worktree to reproduce it".into()
+        }
+        BuildError::IsolationUnavailable { .. } => {
+            "install the sandbox tool, or build with `BuildIsolation::None`".into()
+        }
+        BuildError::DirtyWorktree { repo } => format!(
+            "commit or stash the changes in {}, or build with `GitPolicy::AllowDirty`",
+            repo.display()
+        ),
+        BuildError::RefspecNotCheckedOut { refspec, repo, .. } => {
+            format!("run `git -C {} checkout {refspec}`", repo.display())
+        }
+        _ => return None,
+    })
+}
@@ -20,6 +20,24 @@ pub enum InputError {
 
     #[error("invalid source for {service:?}: {reason}")]
     InvalidSource { service: ServiceId, reason: String },
+
+    #[error("invalid registry manifest: {reason}")]
+    InvalidManifest { reason: String },
+
+    #[error("service dependency cycle: {cycle}")]
+    DependencyCycle { cycle: String },
+
+    #[error("no source given for {service:?}")]
+    MissingSource { service: ServiceId },
+
+    #[error("service {service:?} is already registered")]
+    DuplicateService { service: ServiceId },
+
+    #[error("invalid configuration in {origin}: {reason}")]
+    InvalidConfig { origin: String, reason: String },
+
+    #[error("invalid cache key {key}: {reason}")]
+    InvalidCacheKey { key: String, reason: String },
 }
 
 #[non_exhaustive]
@@ -38,6 +56,12 @@ pub enum LocateError {
         version: String,
         why: String,
     },
+
+    #[error("no provider layer could resolve the {source_kind} source")]
+    Unresolved { source_kind: &'static str },
+
+    #[error("service {service:?} is not registered")]
+    UnknownService { service: ServiceId },
 }
 
 #[non_exhaustive]
@@ -48,7 +72,7 @@ pub enum FetchError {
     Http {
         url: String,
         #[source]
-        source: reqwest::Error,
+        source: crate::transport::StatusError,
     },
 
     #[cfg(feature = "http")]
@@ -63,6 +87,10 @@ pub enum FetchError {
         source: Box<dyn std::error::Error + Send + Sync>,
     },
 
+    #[cfg(feature = "http")]
+    #[error("network policy ({policy}) does not allow fetching {url}")]
+    Denied { url: String, policy: String },
+
     #[cfg(not(feature = "http"))]
     #[error("http support disabled; cannot fetch {url}")]
     Disabled { url: String },
@@ -81,12 +109,61 @@ pub enum VerifyError {
     #[error("missing checksum for {url}")]
     MissingChecksum { url: String },
 
+    #[error("rebuild {rebuild} disagrees on {asset}: release has {expected}, rebuild has {actual}")]
+    RebuildMismatch {
+        asset: String,
+        /// `SHA256SUMS` of the rebuild.
+        rebuild: String,
+        expected: String,
+        actual: String,
+    },
+
     #[error("signature verification failed for {what}")]
     SignatureInvalid {
         what: String,
         #[source]
         source: Box<dyn std::error::Error + Send + Sync>,
     },
+
+    #[error("{path} reports version {actual}, which does not satisfy {expected}")]
+    VersionMismatch {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("could not determine the version of {path} to check it against {expected}")]
+    VersionUnknown { path: PathBuf, expected: String },
+
+    #[error("{path} is not a usable executable: {reason}")]
+    BadHeader { path: PathBuf, reason: String },
+
+    #[error("health check of {path} failed: {reason}")]
+    HealthCheckFailed { path: PathBuf, reason: String },
+
+    #[error("{path}: {} not found", missing.join(", "))]
+    MissingLibraries { path: PathBuf, missing: Vec<String> },
+
+    /// TUF metadata that failed the client workflow: too few valid
+    /// signatures, an older version than already seen, or expired.
+    #[cfg(feature = "tuf")]
+    #[error("TUF {role} metadata of {repository} rejected: {reason}")]
+    TufRejected {
+        repository: String,
+        role: String,
+        reason: String,
+    },
+
+    /// A download whose digest the transparency log has no valid entry for;
+    /// see `crate::transparency`.
+    #[cfg(feature = "transparency")]
+    #[error("{what} (sha256 {digest}) failed the check against transparency log {log}: {reason}")]
+    NotLogged {
+        what: String,
+        digest: String,
+        log: String,
+        reason: String,
+    },
 }
 
 #[non_exhaustive]
@@ -103,6 +180,12 @@ pub enum UnpackError {
         source: Box<dyn std::error::Error + Send + Sync>,
     },
 
+    #[error("no binary found in {archive} (tried {tried})")]
+    BinaryNotFound { archive: String, tried: String },
+
+    #[error("invalid archive layout hint `{pattern}`: {reason}")]
+    BadLayoutHint { pattern: String, reason: String },
+
     #[error("archive tool error for {archive}")]
     Tool {
         archive: String,
@@ -115,8 +198,8 @@ pub enum UnpackError {
 #[derive(Debug, Error)]
 pub enum OciError {
     #[cfg(feature = "oci")]
-    #[error("invalid OCI reference: {reference}")]
-    InvalidReference { reference: String },
+    #[error("invalid OCI reference `{reference}`: {reason}")]
+    InvalidReference { reference: String, reason: String },
 
     #[cfg(feature = "oci")]
     #[error("failed to pull image {reference}")]
@@ -126,10 +209,72 @@ pub enum OciError {
         source: Box<dyn std::error::Error + Send + Sync>,
     },
 
+    #[cfg(feature = "oci")]
+    #[error("failed to push to {reference}")]
+    Push {
+        reference: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[cfg(feature = "oci")]
+    #[error("attestations of {reference} rejected: {reason}")]
+    AttestationRejected { reference: String, reason: String },
+
     #[cfg(feature = "oci")]
     #[error("unauthorized for image {reference}")]
     Unauthorized { reference: String },
 
+    #[cfg(feature = "oci")]
+    #[error("digest mismatch for {what} of {reference}: expected {expected}, got {actual}")]
+    DigestMismatch {
+        reference: String,
+        /// `manifest`, or the blob's digest.
+        what: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[cfg(feature = "oci")]
+    #[error("tag {tag} of {reference} has drifted: pinned {pinned}, now {current}")]
+    TagDrifted {
+        reference: String,
+        tag: String,
+        pinned: String,
+        current: String,
+    },
+
+    #[cfg(feature = "oci")]
+    #[error("image {reference} has no {platform} variant (available: {available})")]
+    NoMatchingPlatform {
+        reference: String,
+        platform: String,
+        available: String,
+    },
+
+    #[cfg(feature = "oci")]
+    #[error("unsupported manifest type {media_type} for image {reference}")]
+    UnsupportedManifest {
+        reference: String,
+        media_type: String,
+    },
+
+    #[cfg(feature = "oci")]
+    #[error("container runtime `{program}` not found on PATH")]
+    RuntimeUnavailable { program: String },
+
+    #[cfg(feature = "oci")]
+    #[error("no container runtime (docker or podman) is reachable")]
+    NoRuntime,
+
+    #[cfg(feature = "oci")]
+    #[error("`{program} {args}` failed: {stderr}")]
+    Runtime {
+        program: String,
+        args: String,
+        stderr: String,
+    },
+
     #[cfg(not(feature = "oci"))]
     #[error("oci support disabled; cannot use {reference}")]
     Disabled { reference: String },
@@ -151,15 +296,45 @@ pub enum FsError {
         #[source]
         source: std::io::Error,
     },
+
+    #[error(
+        "not enough disk space for {what} on {}: {} MiB needed, {} MiB available",
+        path.display(),
+        needed >> 20,
+        available >> 20
+    )]
+    InsufficientSpace {
+        what: String,
+        path: PathBuf,
+        needed: u64,
+        available: u64,
+    },
 }
 
 #[non_exhaustive]
 #[derive(Debug, Error)]
 pub enum PlatformError {
-    #[error("unsupported platform for {service:?}: {platform}")]
+    #[error("unsupported platform for {service:?}: {platform} ({reason})")]
     Unsupported {
         service: ServiceId,
         platform: String,
+        reason: String,
+    },
+
+    #[error("{path} is built for {found}, which does not run on {platform}; {hint}")]
+    ArchMismatch {
+        path: PathBuf,
+        /// Architecture(s) in the binary's header, `+`-separated for universal ones.
+        found: String,
+        platform: String,
+        hint: String,
+    },
+
+    #[error("{what} requires {required}; host has {host} \u{2014} use the Build source instead")]
+    GlibcTooOld {
+        what: String,
+        required: String,
+        host: String,
     },
 }
 
@@ -182,6 +357,300 @@ pub enum ArtifactError {
     Fs(#[from] FsError),
     #[error(transparent)]
     Platform(#[from] PlatformError),
+    #[cfg(feature = "local-build")]
+    #[error(transparent)]
+    Build(#[from] BuildError),
+    /// The resolution's [`CancelToken`](crate::cancel::CancelToken) was
+    /// cancelled.
+    #[error("resolution cancelled")]
+    Cancelled,
+    /// The resolution ran past its
+    /// [`ResolveOptions::timeout`](crate::ResolveOptions::timeout).
+    #[error("resolution timed out after {after:?} while {phase}")]
+    TimedOut {
+        phase: crate::cancel::Phase,
+        after: std::time::Duration,
+    },
+}
+
+/// The category of an [`ArtifactError`], for branching on failures without
+/// matching the nested error enums. Variants are only ever added; their
+/// [codes](ErrorKind::code) don't change.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// A path, release asset, binary in an archive or release index that
+    /// doesn't exist.
+    NotFound,
+    /// A malformed source, manifest, reference or layout hint.
+    InvalidInput,
+    /// A download or registry request that failed in transit or with a
+    /// server error; see [`ArtifactError::is_transient`].
+    Network,
+    /// A registry or server that refused the credentials (or their absence).
+    Unauthorized,
+    /// A checksum, digest, signature, attestation, version or health check
+    /// that the artifact failed.
+    Verification,
+    /// Something this platform or build of the crate can't do: a platform
+    /// without assets, a binary for another architecture, a disabled feature.
+    Unsupported,
+    /// A required program, container runtime or sandbox isn't available.
+    MissingTool,
+    /// An external program (`git`, `docker`, an archive tool) failed.
+    ToolFailed,
+    /// A build script, remote build or nix build failed, or left no binary.
+    BuildFailed,
+    /// A [`GitPolicy`](crate::git::GitPolicy) or runtime setting forbade the
+    /// work, e.g. a dirty worktree, builds being disabled or offline mode.
+    PolicyViolation,
+    /// An archive that couldn't be extracted.
+    Unpack,
+    /// A filesystem error, including running out of space.
+    Io,
+    /// A resolution that was cancelled.
+    Cancelled,
+    /// A resolution that ran past its timeout.
+    TimedOut,
+}
+
+impl ErrorKind {
+    /// A stable kebab-case code, e.g. `not-found`, for logs and exit reports.
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorKind::NotFound => "not-found",
+            ErrorKind::InvalidInput => "invalid-input",
+            ErrorKind::Network => "network",
+            ErrorKind::Unauthorized => "unauthorized",
+            ErrorKind::Verification => "verification",
+            ErrorKind::Unsupported => "unsupported",
+            ErrorKind::MissingTool => "missing-tool",
+            ErrorKind::ToolFailed => "tool-failed",
+            ErrorKind::BuildFailed => "build-failed",
+            ErrorKind::PolicyViolation => "policy-violation",
+            ErrorKind::Unpack => "unpack",
+            ErrorKind::Io => "io",
+            ErrorKind::Cancelled => "cancelled",
+            ErrorKind::TimedOut => "timed-out",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// Lines of the build log kept in [`BuildError::ScriptFailed`].
+#[cfg(feature = "local-build")]
+pub const LOG_TAIL_LINES: usize = 50;
+
+/// Bytes read from the end of a build log for its tail; lines longer than
+/// this are cut.
+#[cfg(feature = "local-build")]
+const LOG_TAIL_BYTES: u64 = 64 << 10;
+
+#[cfg(feature = "local-build")]
+impl BuildError {
+    /// A [`ScriptFailed`](Self::ScriptFailed) error, with the tail of the log
+    /// at `log_path` if it can be read.
+    pub fn script_failed(exit_code: i32, log_path: impl Into<PathBuf>) -> Self {
+        let log_path = log_path.into();
+        let log_tail = read_log_tail(&log_path).unwrap_or_default();
+        BuildError::ScriptFailed {
+            exit_code,
+            log_path,
+            log_tail,
+        }
+    }
+}
+
+#[cfg(feature = "local-build")]
+fn read_log_tail(path: &std::path::Path) -> std::io::Result<String> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(LOG_TAIL_BYTES)))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    let text = String::from_utf8_lossy(&bytes);
+    let mut lines: Vec<&str> = text.lines().collect();
+    if len > LOG_TAIL_BYTES && lines.len() > 1 {
+        // The first line was most likely cut by the seek.
+        lines.remove(0);
+    }
+    let tail = &lines[lines.len().saturating_sub(LOG_TAIL_LINES)..];
+    Ok(tail.join("\n"))
+}
+
+#[cfg(feature = "local-build")]
+fn log_tail_suffix(tail: &str) -> String {
+    match tail.trim().is_empty() {
+        true => String::new(),
+        false => format!("\nlast lines of the build log:\n{tail}"),
+    }
+}
+
+impl ArtifactError {
+    /// The category of this error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ArtifactError::Input(e) => match e {
+                InputError::NotFound { .. } => ErrorKind::NotFound,
+                _ => ErrorKind::InvalidInput,
+            },
+            ArtifactError::Locate(e) => match e {
+                LocateError::Unresolved { .. } => ErrorKind::Unsupported,
+                _ => ErrorKind::NotFound,
+            },
+            ArtifactError::Fetch(e) => match e {
+                #[cfg(feature = "http")]
+                FetchError::Http { source, .. } => status_kind(source.0),
+                #[cfg(feature = "http")]
+                FetchError::Timeout { .. } | FetchError::Network { .. } => ErrorKind::Network,
+                #[cfg(feature = "http")]
+                FetchError::Denied { .. } => ErrorKind::PolicyViolation,
+                #[cfg(not(feature = "http"))]
+                FetchError::Disabled { .. } => ErrorKind::Unsupported,
+            },
+            ArtifactError::Verify(_) => ErrorKind::Verification,
+            ArtifactError::Unpack(e) => match e {
+                UnpackError::UnsupportedFormat { .. } => ErrorKind::Unsupported,
+                UnpackError::BinaryNotFound { .. } => ErrorKind::NotFound,
+                UnpackError::BadLayoutHint { .. } => ErrorKind::InvalidInput,
+                UnpackError::Tool { .. } => ErrorKind::ToolFailed,
+                _ => ErrorKind::Unpack,
+            },
+            ArtifactError::Oci(e) => match e {
+                #[cfg(feature = "oci")]
+                OciError::InvalidReference { .. } => ErrorKind::InvalidInput,
+                #[cfg(feature = "oci")]
+                OciError::Pull { source, .. } | OciError::Push { source, .. } => {
+                    chain_kind(source.as_ref())
+                }
+                #[cfg(feature = "oci")]
+                OciError::AttestationRejected { .. }
+                | OciError::DigestMismatch { .. }
+                | OciError::TagDrifted { .. } => ErrorKind::Verification,
+                #[cfg(feature = "oci")]
+                OciError::Unauthorized { .. } => ErrorKind::Unauthorized,
+                #[cfg(feature = "oci")]
+                OciError::RuntimeUnavailable { .. } | OciError::NoRuntime => ErrorKind::MissingTool,
+                #[cfg(feature = "oci")]
+                OciError::Runtime { .. } => ErrorKind::ToolFailed,
+                _ => ErrorKind::Unsupported,
+            },
+            ArtifactError::Fs(_) => ErrorKind::Io,
+            ArtifactError::Platform(_) => ErrorKind::Unsupported,
+            #[cfg(feature = "local-build")]
+            ArtifactError::Build(e) => match e {
+                BuildError::PreflightMissingTools { .. }
+                | BuildError::IsolationUnavailable { .. } => ErrorKind::MissingTool,
+                BuildError::DisabledRuntime
+                | BuildError::DirtyWorktree { .. }
+                | BuildError::RefspecNotCheckedOut { .. } => ErrorKind::PolicyViolation,
+                BuildError::DisabledFeature => ErrorKind::Unsupported,
+                BuildError::Git { .. } => ErrorKind::ToolFailed,
+                _ => ErrorKind::BuildFailed,
+            },
+            ArtifactError::Cancelled => ErrorKind::Cancelled,
+            ArtifactError::TimedOut { .. } => ErrorKind::TimedOut,
+        }
+    }
+
+    /// Whether retrying the same resolution might succeed: timeouts,
+    /// dropped connections, 5xx and 429 responses, and a cache entry
+    /// locked by another process. Everything else (checksum and signature
+    /// failures, missing assets, a dirty worktree, a failed build script)
+    /// needs something to change first.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            #[cfg(feature = "http")]
+            ArtifactError::Fetch(FetchError::Http { source, .. }) => status_transient(source.0),
+            #[cfg(feature = "http")]
+            ArtifactError::Fetch(FetchError::Timeout { .. } | FetchError::Network { .. }) => true,
+            #[cfg(feature = "oci")]
+            ArtifactError::Oci(OciError::Pull { source, .. } | OciError::Push { source, .. }) => {
+                chain_transient(source.as_ref())
+            }
+            ArtifactError::Fs(FsError::Io { source, .. }) => io_transient(source),
+            _ => false,
+        }
+    }
+}
+
+/// Whether anything in `err`'s source chain is a transient network or I/O
+/// failure.
+#[cfg(feature = "oci")]
+fn chain_transient(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut next = Some(err);
+    while let Some(err) = next {
+        if let Some(e) = err.downcast_ref::<crate::transport::StatusError>() {
+            return status_transient(e.0);
+        }
+        if let Some(e) = err.downcast_ref::<reqwest::Error>() {
+            return reqwest_transient(e);
+        }
+        if let Some(e) = err.downcast_ref::<std::io::Error>() {
+            return io_transient(e);
+        }
+        next = err.source();
+    }
+    false
+}
+
+/// The kind of the first HTTP failure in `err`'s source chain; anything
+/// else a registry did wrong (e.g. a malformed manifest) counts as a
+/// network failure.
+#[cfg(feature = "oci")]
+fn chain_kind(err: &(dyn std::error::Error + 'static)) -> ErrorKind {
+    let mut next = Some(err);
+    while let Some(err) = next {
+        if let Some(e) = err.downcast_ref::<crate::transport::StatusError>() {
+            return status_kind(e.0);
+        }
+        next = err.source();
+    }
+    ErrorKind::Network
+}
+
+#[cfg(feature = "http")]
+fn status_kind(status: u16) -> ErrorKind {
+    match status {
+        401 | 403 => ErrorKind::Unauthorized,
+        404 | 410 => ErrorKind::NotFound,
+        _ => ErrorKind::Network,
+    }
+}
+
+#[cfg(feature = "http")]
+fn status_transient(status: u16) -> bool {
+    (500..600).contains(&status) || status == 429
+}
+
+/// Whether a request that got no response from [`HttpTransport`](crate::transport::HttpTransport)
+/// might get one if retried.
+#[cfg(feature = "oci")]
+fn reqwest_transient(err: &reqwest::Error) -> bool {
+    err.is_timeout() || err.is_connect() || err.is_request() || err.is_body()
+}
+
+fn io_transient(err: &std::io::Error) -> bool {
+    use std::io::ErrorKind::*;
+    matches!(
+        err.kind(),
+        WouldBlock
+            | ResourceBusy
+            | Interrupted
+            | TimedOut
+            | ConnectionReset
+            | ConnectionAborted
+            | ConnectionRefused
+            | BrokenPipe
+            | UnexpectedEof
+    )
 }
 
 #[cfg(feature = "local-build")]
@@ -197,10 +666,26 @@ pub enum BuildError {
     #[error("build feature not enabled at compile time")]
     DisabledFeature,
 
-    #[error("build script failed with exit code {exit_code}; see log at {log_path}")]
+    #[error(
+        "build script failed with exit code {exit_code}; see log at {log_path}{}",
+        log_tail_suffix(log_tail)
+    )]
     ScriptFailed {
         exit_code: i32,
         log_path: std::path::PathBuf,
+        /// The last [`LOG_TAIL_LINES`] lines of the log, read when the build
+        /// failed, for CI runs whose logs can't be fetched afterwards.
+        log_tail: String,
+    },
+
+    #[error("build sandbox unavailable: {reason}")]
+    IsolationUnavailable { reason: String },
+
+    #[error("remote build on {host} failed to {action}: {reason}")]
+    Remote {
+        host: String,
+        action: String,
+        reason: String,
     },
 
     #[error("unknown build output; expected binary at {expected}")]
@@ -208,4 +693,23 @@ pub enum BuildError {
 
     #[error("worktree is dirty; cannot build")]
     DirtyWorktree { repo: std::path::PathBuf },
+
+    #[error("`git {args}` failed in {repo}: {stderr}")]
+    Git {
+        repo: std::path::PathBuf,
+        args: String,
+        stderr: String,
+    },
+
+    #[cfg(feature = "nix")]
+    #[error("`nix {args}` failed: {stderr}")]
+    Nix { args: String, stderr: String },
+
+    #[error("{refspec} resolves to {commit} but {repo} has {head} checked out")]
+    RefspecNotCheckedOut {
+        repo: std::path::PathBuf,
+        refspec: String,
+        commit: String,
+        head: String,
+    },
 }
@@ -0,0 +1,274 @@
+//! Where builds run.
+//!
+//! Recipes run their commands through the invocation's [`BuildExecutor`].
+//! [`LocalExecutor`], used unless
+//! [`BuildConfig::executor`](crate::BuildConfig::executor) names another, runs
+//! them in the worktree, in the configured sandbox. [`SshExecutor`] runs them on
+//! a remote builder instead, so laptops don't have to compile `zcashd`:
+//!
+//! 1. The worktree is copied to `<remote dir>/<repo name>-<path hash>` on the
+//!    builder with `rsync --delete`, leaving out what `.gitignore` ignores, so
+//!    the products of earlier builds stay there and builds are incremental.
+//! 2. The command runs there over `ssh`, with the build's environment and
+//!    priority; its output goes to the local build log.
+//! 3. The binary and its companions are copied back into the worktree with
+//!    `rsync`, then checked and cached like those of a local build.
+//!
+//! META records the builder in `executor`. The builder needs the build's
+//! tools, and must produce binaries for the platform being resolved for: the
+//! same OS and architecture, or a cross `target`. Sandboxes only apply to
+//! builds on this machine, and container image recipes always build into the
+//! local runtime. Cancelling a resolution kills `ssh`, not the remote build,
+//! which runs on until it next writes output.
+
+use std::{
+    fs::File,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::{
+    BuildExecutor, BuildInvocation, BuildIsolation,
+    error::{BuildError, FsError, Result},
+};
+
+/// Runs builds on this machine; see the [module docs](self).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalExecutor;
+
+impl BuildExecutor for LocalExecutor {
+    fn run(&self, command: Command, invocation: &BuildInvocation<'_>) -> Result<()> {
+        crate::recipe::run_local(command, invocation)
+    }
+}
+
+/// Runs builds on a remote builder over SSH; see the [module docs](self).
+///
+/// Needs `ssh` and `rsync` here, and `rsync` on the builder. Connections run
+/// in batch mode, so the builder must accept a key without prompting.
+#[derive(Debug, Clone)]
+pub struct SshExecutor {
+    host: String,
+    remote_dir: String,
+    ssh_options: Vec<String>,
+}
+
+impl SshExecutor {
+    /// Builds on `host`: anything `ssh` takes, e.g. `builder.lan`,
+    /// `me@10.0.0.5` or a `Host` from `~/.ssh/config`. Worktrees go to
+    /// `zcash-artifacts-builds` in the remote home directory.
+    pub fn new(host: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            remote_dir: "zcash-artifacts-builds".into(),
+            ssh_options: vec!["-o".into(), "BatchMode=yes".into()],
+        }
+    }
+
+    /// Directory on the builder holding the worktrees; relative to the remote
+    /// home directory unless absolute.
+    pub fn remote_dir(mut self, dir: impl Into<String>) -> Self {
+        self.remote_dir = dir.into();
+        self
+    }
+
+    /// Adds an `ssh` option, e.g. `-p2222` or `-i/path/to/key`; `rsync`
+    /// connects with the same options.
+    pub fn ssh_option(mut self, option: impl Into<String>) -> Self {
+        self.ssh_options.push(option.into());
+        self
+    }
+
+    /// Where the worktree at `repo` is copied to on the builder.
+    fn worktree(&self, repo: &Path) -> String {
+        let repo = std::fs::canonicalize(repo).unwrap_or_else(|_| repo.to_path_buf());
+        let name = repo
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "repo".into());
+        let hash = blake3::hash(repo.to_string_lossy().as_bytes()).to_hex();
+        format!(
+            "{}/{name}-{}",
+            self.remote_dir.trim_end_matches('/'),
+            &hash[..12]
+        )
+    }
+
+    /// The `rsync -e` value connecting like `ssh` does.
+    fn rsync_shell(&self) -> String {
+        std::iter::once("ssh".to_string())
+            .chain(self.ssh_options.iter().map(|option| quote(option)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Runs `command`, appending its output to the invocation's log.
+    fn logged(
+        &self,
+        mut command: Command,
+        invocation: &BuildInvocation<'_>,
+        action: &str,
+    ) -> Result<std::process::ExitStatus> {
+        let log = File::options()
+            .create(true)
+            .append(true)
+            .open(invocation.log)
+            .map_err(|e| FsError::Io {
+                context: format!("open {}", invocation.log.display()),
+                source: e,
+            })?;
+        let stderr = log.try_clone().map_err(|e| FsError::Io {
+            context: format!("dup {}", invocation.log.display()),
+            source: e,
+        })?;
+        command.stdin(Stdio::null()).stdout(log).stderr(stderr);
+        let program = command.get_program().to_string_lossy().into_owned();
+        crate::cancel::status(&mut command, invocation.cancel, |e| {
+            self.failed(action, format!("spawn {program}: {e}"))
+        })
+    }
+
+    fn failed(&self, action: &str, reason: String) -> crate::ArtifactError {
+        BuildError::Remote {
+            host: self.host.clone(),
+            action: action.to_string(),
+            reason,
+        }
+        .into()
+    }
+
+    /// Copies the worktree to the builder.
+    fn sync(&self, invocation: &BuildInvocation<'_>, worktree: &str) -> Result<()> {
+        let action = "sync the worktree";
+        let mut rsync = Command::new("rsync");
+        rsync
+            .args(["-a", "--delete", "--filter=:- .gitignore"])
+            .arg("-e")
+            .arg(self.rsync_shell())
+            .arg(format!(
+                "--rsync-path=mkdir -p {} && rsync",
+                quote(worktree)
+            ))
+            .arg(format!("{}/", invocation.repo.display()))
+            .arg(format!("{}:{worktree}/", self.host));
+        let status = self.logged(rsync, invocation, action)?;
+        if !status.success() {
+            return Err(self.failed(
+                action,
+                format!(
+                    "rsync exited with {status}; see log at {}",
+                    invocation.log.display()
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl BuildExecutor for SshExecutor {
+    fn run(&self, command: Command, invocation: &BuildInvocation<'_>) -> Result<()> {
+        if *invocation.isolation != BuildIsolation::None {
+            return Err(BuildError::IsolationUnavailable {
+                reason: format!(
+                    "sandboxes only apply to builds on this machine, not on {}",
+                    self.host
+                ),
+            }
+            .into());
+        }
+        File::create(invocation.log).map_err(|e| FsError::Io {
+            context: format!("create {}", invocation.log.display()),
+            source: e,
+        })?;
+        let worktree = self.worktree(invocation.repo);
+        self.sync(invocation, &worktree)?;
+
+        // `env` takes its unsets before its assignments; later assignments win.
+        let mut script = format!("cd {} && exec env", quote(&worktree));
+        let (set, unset): (Vec<_>, Vec<_>) = command
+            .get_envs()
+            .map(|(key, value)| (key.to_string_lossy(), value.map(|v| v.to_string_lossy())))
+            .chain(
+                invocation
+                    .env
+                    .iter()
+                    .map(|(key, value)| (key.into(), Some(value.into()))),
+            )
+            .partition(|(_, value)| value.is_some());
+        for (key, _) in unset {
+            script.push_str(&format!(" -u {}", quote(&key)));
+        }
+        for (key, value) in set {
+            let value = value.unwrap_or_default();
+            script.push_str(&format!(" {}", quote(&format!("{key}={value}"))));
+        }
+        if invocation.low_priority {
+            script.push_str(" nice -n 10");
+        }
+        let args = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .chain(invocation.extra_args.iter().cloned());
+        for arg in args {
+            script.push(' ');
+            script.push_str(&quote(&arg));
+        }
+
+        let mut ssh = Command::new("ssh");
+        ssh.args(&self.ssh_options).arg(&self.host).arg(script);
+        let status = self.logged(ssh, invocation, "run the build")?;
+        if !status.success() {
+            return Err(
+                BuildError::script_failed(status.code().unwrap_or(-1), invocation.log).into(),
+            );
+        }
+        Ok(())
+    }
+
+    fn fetch(&self, invocation: &BuildInvocation<'_>, dir: &Path, names: &[String]) -> Result<()> {
+        let action = "fetch the build outputs";
+        let worktree = self.worktree(invocation.repo);
+        let local = invocation.repo.join(dir);
+        std::fs::create_dir_all(&local).map_err(|e| FsError::Io {
+            context: format!("mkdir {}", local.display()),
+            source: e,
+        })?;
+        let mut rsync = Command::new("rsync");
+        rsync.arg("-a").arg("-e").arg(self.rsync_shell());
+        for name in names {
+            rsync.arg(format!("--include=/{name}"));
+        }
+        rsync
+            .arg("--exclude=*")
+            .arg(format!("{}:{worktree}/{}/", self.host, dir.display()))
+            .arg(format!("{}/", local.display()));
+        let status = self.logged(rsync, invocation, action)?;
+        if !status.success() {
+            return Err(self.failed(
+                action,
+                format!(
+                    "rsync exited with {status}; see log at {}",
+                    invocation.log.display()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn location(&self) -> Option<String> {
+        Some(format!("ssh://{}", self.host))
+    }
+}
+
+/// `arg` quoted for a POSIX shell.
+fn quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
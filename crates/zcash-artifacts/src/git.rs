@@ -6,3 +6,136 @@ pub enum GitPolicy {
     /// Allow dirty builds; cache key includes a worktree content hash.
     AllowDirty { hash_untracked: bool },
 }
+
+#[cfg(feature = "local-build")]
+pub(crate) use queries::*;
+
+#[cfg(feature = "local-build")]
+mod queries {
+    use std::{path::Path, process::Command};
+
+    use crate::error::{BuildError, FsError, Result};
+
+    /// Runs `git -C <repo> <args>` and returns its stdout.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(repo = %repo.display(), args = %args.join(" "), bytes = tracing::field::Empty),
+        )
+    )]
+    fn git(repo: &Path, args: &[&str]) -> Result<Vec<u8>> {
+        let out = Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(args)
+            .output()
+            .map_err(|e| FsError::Io {
+                context: format!("spawn git in {}", repo.display()),
+                source: e,
+            })?;
+        if !out.status.success() {
+            return Err(BuildError::Git {
+                repo: repo.to_path_buf(),
+                args: args.join(" "),
+                stderr: String::from_utf8_lossy(&out.stderr).trim().to_string(),
+            }
+            .into());
+        }
+        crate::trace::record!("bytes" = out.stdout.len());
+        Ok(out.stdout)
+    }
+
+    /// Resolves `refspec` to a full commit SHA.
+    pub(crate) fn resolve_commit(repo: &Path, refspec: &str) -> Result<String> {
+        let rev = format!("{refspec}^{{commit}}");
+        let out = git(repo, &["rev-parse", "--verify", "--quiet", &rev])?;
+        Ok(String::from_utf8_lossy(&out).trim().to_string())
+    }
+
+    /// Whether the worktree has uncommitted changes (optionally counting untracked files).
+    pub(crate) fn is_dirty(repo: &Path, include_untracked: bool) -> Result<bool> {
+        let untracked = if include_untracked {
+            "--untracked-files=normal"
+        } else {
+            "--untracked-files=no"
+        };
+        let out = git(repo, &["status", "--porcelain", untracked])?;
+        Ok(!out.is_empty())
+    }
+
+    /// Deterministic hash of the worktree's changes relative to `HEAD`.
+    ///
+    /// Covers the binary diff of tracked files and, if requested, the paths and
+    /// contents of untracked (non-ignored) files. Returns 16 hex chars of BLAKE3.
+    pub(crate) fn hash_worktree(repo: &Path, include_untracked: bool) -> Result<String> {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&worktree_diff(repo)?);
+
+        if include_untracked {
+            let listing = git(repo, &["ls-files", "--others", "--exclude-standard", "-z"])?;
+            let mut paths: Vec<&[u8]> = listing
+                .split(|b| *b == 0)
+                .filter(|p| !p.is_empty())
+                .collect();
+            paths.sort();
+            for rel in paths {
+                let rel = String::from_utf8_lossy(rel);
+                let path = repo.join(rel.as_ref());
+                let contents = std::fs::read(&path).map_err(|e| FsError::Io {
+                    context: format!("read {}", path.display()),
+                    source: e,
+                })?;
+                hasher.update(rel.as_bytes());
+                hasher.update(&[0]);
+                hasher.update(blake3::hash(&contents).as_bytes());
+            }
+        }
+
+        Ok(hasher.finalize().to_hex()[..16].to_string())
+    }
+
+    /// Binary diff of the tracked files' changes relative to `HEAD`, as `git
+    /// apply` takes it.
+    pub(crate) fn worktree_diff(repo: &Path) -> Result<Vec<u8>> {
+        git(repo, &["diff", "HEAD", "--binary", "--no-ext-diff"])
+    }
+
+    /// Checks `commit` out, detached, into a new worktree of `repo` at `dir`.
+    pub(crate) fn add_worktree(repo: &Path, dir: &Path, commit: &str) -> Result<()> {
+        let dir = dir.to_string_lossy();
+        git(
+            repo,
+            &["worktree", "add", "--detach", "--force", &dir, commit],
+        )
+        .map(drop)
+    }
+
+    /// Removes the worktree at `dir`, with whatever was built in it.
+    pub(crate) fn remove_worktree(repo: &Path, dir: &Path) -> Result<()> {
+        let dir = dir.to_string_lossy();
+        git(repo, &["worktree", "remove", "--force", &dir]).map(drop)
+    }
+
+    /// Committer date of `commit`, in seconds since the epoch.
+    pub(crate) fn commit_time(repo: &Path, commit: &str) -> Result<u64> {
+        let out = git(repo, &["show", "-s", "--format=%ct", commit])?;
+        let out = String::from_utf8_lossy(&out);
+        out.trim().parse().map_err(|_| {
+            crate::error::BuildError::Git {
+                repo: repo.to_path_buf(),
+                args: format!("show -s --format=%ct {commit}"),
+                stderr: format!("unexpected output `{}`", out.trim()),
+            }
+            .into()
+        })
+    }
+
+    /// URL of the `origin` remote, if the repo has one.
+    pub(crate) fn origin_url(repo: &Path) -> Option<String> {
+        let out = git(repo, &["config", "--get", "remote.origin.url"]).ok()?;
+        let url = String::from_utf8_lossy(&out).trim().to_string();
+        (!url.is_empty()).then_some(url)
+    }
+}
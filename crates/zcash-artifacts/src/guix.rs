@@ -0,0 +1,190 @@
+//! Release tarballs built reproducibly with [Guix](https://guix.gnu.org).
+//!
+//! zcashd's official releases are built with Guix, so anyone can rebuild them
+//! bit for bit and publish the hashes they got, as Bitcoin Core's `guix.sigs`
+//! builders do. [`ArtifactSource::Guix`](crate::ArtifactSource::Guix) fetches
+//! such a release for a service with [`GuixReleases`] on its spec:
+//!
+//! 1. The release's `SHA256SUMS` is fetched and the tarball for the platform
+//!    (named by its GNU triple, e.g. `zcash-6.0.0-x86_64-linux-gnu.tar.gz`)
+//!    looked up in it.
+//! 2. Each of the source's `rebuilds`, the `SHA256SUMS` of an independent
+//!    rebuild, must list the same hash for it. A rebuild that disagrees or
+//!    lacks the tarball fails the resolution.
+//! 3. The tarball is downloaded, checked against that hash and unpacked like a
+//!    release asset, and the service's binary and companions are cached.
+//!
+//! Entries are keyed by `guix-<version>`, apart from
+//! [`Release`](crate::ArtifactSource::Release)s of the same version, plus a
+//! hash of the rebuilds' URLs if there are any, so an entry is only reused for
+//! the rebuilds it was checked against; META lists them in `rebuilds`.
+//! Neither file's signature is checked; agreement between independent
+//! builders is what this adds over a checksum table.
+
+use url::Url;
+
+use crate::{
+    ResolveContext, ResolvedArtifact,
+    cache::{self, CacheKey, CachePaths},
+    error::{LocateError, Result, VerifyError},
+    pipeline::fetch_text,
+    registry::ToolSpec,
+};
+
+/// Where a service's Guix-built releases are published.
+///
+/// Templates may use `{version}` and `{triple}`, the GNU triple Guix builds
+/// for: `x86_64-linux-gnu`, `aarch64-linux-gnu`, `x86_64-apple-darwin`,
+/// `arm64-apple-darwin` or `x86_64-w64-mingw32`.
+#[derive(Debug, Clone)]
+pub struct GuixReleases {
+    tarball: String,
+    sums: String,
+}
+
+impl GuixReleases {
+    /// Tarballs at `tarball`, listed in the `SHA256SUMS` file at `sums`.
+    pub fn new(tarball: impl Into<String>, sums: impl Into<String>) -> Self {
+        Self {
+            tarball: tarball.into(),
+            sums: sums.into(),
+        }
+    }
+
+    fn render(template: &str, version: &str, triple: &str) -> Option<Url> {
+        Url::parse(
+            &template
+                .replace("{version}", version)
+                .replace("{triple}", triple),
+        )
+        .ok()
+    }
+}
+
+/// The GNU triple Guix builds `platform` as.
+fn triple(platform: &str) -> Option<&'static str> {
+    Some(match crate::platform::normalize(platform).as_str() {
+        "linux-x86_64" => "x86_64-linux-gnu",
+        "linux-aarch64" => "aarch64-linux-gnu",
+        "macos-x86_64" => "x86_64-apple-darwin",
+        "macos-arm64" => "arm64-apple-darwin",
+        "windows-x86_64" => "x86_64-w64-mingw32",
+        _ => return None,
+    })
+}
+
+/// Cache entry of the Guix build of `version` of `spec`, checked against
+/// `rebuilds`.
+fn entry(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    version: &str,
+    rebuilds: &[Url],
+) -> Result<(CachePaths, String)> {
+    let mut revision = format!("guix-{version}");
+    if !rebuilds.is_empty() {
+        let mut urls: Vec<String> = rebuilds.iter().map(Url::to_string).collect();
+        urls.sort();
+        urls.dedup();
+        let hash = blake3::hash(urls.join("\n").as_bytes()).to_hex();
+        revision.push_str(&format!("-{}", &hash[..8]));
+    }
+    let key = CacheKey {
+        service: spec.id.as_str().to_string(),
+        revision,
+        worktree_hash: None,
+        platform: ctx.platform.to_string(),
+        schema: spec.builder_schema,
+    };
+    let bin_name = spec
+        .binary_names_for(ctx.platform)
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| spec.id.as_str().to_string());
+    Ok((CachePaths::new(&ctx.config.cache_root, &key)?, bin_name))
+}
+
+/// The finished entry for the release and `rebuilds`, if any.
+pub(crate) fn cached(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    version: &str,
+    rebuilds: &[Url],
+) -> Result<Option<ResolvedArtifact>> {
+    let (paths, bin_name) = entry(ctx, spec, version, rebuilds)?;
+    let path = paths.out.join(bin_name);
+    Ok((cache::usable_entry(ctx, &paths, &path, ctx.platform)?
+        && crate::pipeline::checks_ok(ctx, Some(spec), &paths))
+    .then_some(ResolvedArtifact::executable(path)))
+}
+
+/// Checks the release against `rebuilds` and caches its binary; see the
+/// [module docs](self).
+pub(crate) fn fetch(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    releases: &GuixReleases,
+    version: &str,
+    rebuilds: &[Url],
+) -> Result<ResolvedArtifact> {
+    let no_asset = || LocateError::NoAsset {
+        service: spec.id.clone(),
+        version: version.to_string(),
+        platform: ctx.platform.to_string(),
+    };
+    spec.requirements.check(&spec.id, ctx.platform, true)?;
+    let triple = triple(ctx.platform).ok_or_else(no_asset)?;
+    let index = |template: &str| {
+        GuixReleases::render(template, version, triple).ok_or_else(|| LocateError::ReleaseIndex {
+            service: spec.id.clone(),
+            version: version.to_string(),
+            why: format!("`{template}` is not a URL template"),
+        })
+    };
+    let url = index(&releases.tarball)?;
+    let sums = index(&releases.sums)?;
+    let asset = url
+        .path_segments()
+        .and_then(|mut s| s.next_back())
+        .unwrap_or_default()
+        .to_string();
+
+    let checksum =
+        lookup(&fetch_text(ctx, &sums)?, &asset).ok_or_else(|| VerifyError::MissingChecksum {
+            url: crate::credentials::redact(&url),
+        })?;
+    for rebuild in rebuilds {
+        let theirs = lookup(&fetch_text(ctx, rebuild)?, &asset);
+        if theirs.as_deref() != Some(checksum.as_str()) {
+            return Err(VerifyError::RebuildMismatch {
+                asset,
+                rebuild: crate::credentials::redact(rebuild),
+                expected: checksum,
+                actual: theirs.unwrap_or_else(|| "no entry".into()),
+            }
+            .into());
+        }
+    }
+
+    let (paths, bin_name) = entry(ctx, spec, version, rebuilds)?;
+    let meta = cache::Meta {
+        service: spec.id.as_str().to_string(),
+        source: "guix".into(),
+        release: Some(version.to_string()),
+        rebuilds: rebuilds.iter().map(crate::credentials::redact).collect(),
+        ..Default::default()
+    };
+    crate::pipeline::download_into(ctx, Some(spec), &url, &checksum, &paths, &bin_name, meta)
+}
+
+/// The lowercased sha256 `sums` lists for the file `asset`.
+///
+/// Lines are `<sha256>  <name>` as `sha256sum` writes them; names may carry a
+/// directory, or a `*` for binary mode.
+fn lookup(sums: &str, asset: &str) -> Option<String> {
+    sums.lines().find_map(|line| {
+        let (hash, name) = line.trim().split_once(char::is_whitespace)?;
+        let name = name.trim_start().trim_start_matches('*');
+        (name.rsplit('/').next() == Some(asset)).then(|| hash.to_ascii_lowercase())
+    })
+}
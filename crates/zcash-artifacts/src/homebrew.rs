@@ -0,0 +1,379 @@
+//! Binaries from [Homebrew](https://brew.sh) kegs and bottles.
+//!
+//! Many Mac users already have `zebrad` from `brew install zebrad`.
+//! [`ArtifactSource::Homebrew`](crate::ArtifactSource::Homebrew) resolves a
+//! service from its formula, in this order:
+//!
+//! 1. **An installed keg**, when resolving for the host:
+//!    `<cellar>/<formula>/<version>/bin/<binary>`, or, without a version, the
+//!    keg `<prefix>/opt/<formula>` links to. The binary is used where it is,
+//!    as brew relocated it against the libraries of its prefix; it goes away
+//!    with `brew uninstall` or `brew cleanup`. The prefix is
+//!    `$HOMEBREW_PREFIX`, or whichever of `/opt/homebrew`, `/usr/local` and
+//!    `/home/linuxbrew/.linuxbrew` has the formula; the cellar is
+//!    `$HOMEBREW_CELLAR` or `<prefix>/Cellar`.
+//! 2. **A cached bottle** of the requested version.
+//! 3. **The formula's bottle**, as the formulae API
+//!    (`$HOMEBREW_API_DOMAIN`, `https://formulae.brew.sh/api` by default) lists
+//!    it: downloaded, checked against its sha256, unpacked, and the binary and
+//!    its companions cached under `brew-<formula>-<version>`. The API only has the
+//!    current version's bottles, so asking for another version needs the keg.
+//!
+//! Bottles are picked by their tag: `arm64_<macOS>` or `<macOS>` for Apple
+//! Silicon and Intel Macs, the newest one for a macOS release no later than
+//! the host's, and `arm64_linux` or `x86_64_linux` on Linux. Binaries that
+//! still carry brew's `@@HOMEBREW_PREFIX@@` placeholders only work once brew
+//! pours them, so they are refused.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+use url::Url;
+
+use crate::{
+    ResolveContext, ResolvedArtifact, binfmt,
+    cache::{self, CacheKey, CachePaths},
+    credentials::{Credential, CredentialProvider},
+    error::{FsError, LocateError, Result, UnpackError, VerifyError},
+    pipeline::{companions_of, fetch_text, fetch_verified},
+    registry::ToolSpec,
+};
+
+/// macOS releases by bottle tag, newest first.
+const MACOS: &[(&str, u32)] = &[
+    ("tahoe", 26),
+    ("sequoia", 15),
+    ("sonoma", 14),
+    ("ventura", 13),
+    ("monterey", 12),
+    ("big_sur", 11),
+    ("catalina", 10),
+];
+
+/// What the formulae API says about a formula.
+#[derive(Debug, Deserialize)]
+struct Formula {
+    versions: Versions,
+    #[serde(default)]
+    revision: u32,
+    bottle: Bottles,
+}
+
+#[derive(Debug, Deserialize)]
+struct Versions {
+    stable: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Bottles {
+    stable: Option<BottleFiles>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BottleFiles {
+    files: BTreeMap<String, Bottle>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Bottle {
+    url: String,
+    sha256: String,
+}
+
+impl Formula {
+    /// The version as kegs and bottles are named, with the formula's revision.
+    fn pkg_version(&self) -> String {
+        match self.revision {
+            0 => self.versions.stable.clone(),
+            revision => format!("{}_{revision}", self.versions.stable),
+        }
+    }
+}
+
+/// Resolves the source; see the [module docs](self).
+pub(crate) fn resolve(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    formula: &str,
+    version: Option<&str>,
+) -> Result<ResolvedArtifact> {
+    if ctx.targets_host()
+        && let Some(path) = keg_binary(spec, formula, version, ctx.platform)
+    {
+        binfmt::check(&path, ctx.platform)?;
+        return Ok(ResolvedArtifact::executable(path));
+    }
+    if let Some(version) = version {
+        let (paths, bin_name) = entry(ctx, spec, formula, version)?;
+        let path = paths.out.join(bin_name);
+        if cache::usable_entry(ctx, &paths, &path, ctx.platform)? {
+            return Ok(ResolvedArtifact::executable(path));
+        }
+    }
+
+    let api = std::env::var("HOMEBREW_API_DOMAIN")
+        .unwrap_or_else(|_| "https://formulae.brew.sh/api".into());
+    let index_error = |why: String| LocateError::ReleaseIndex {
+        service: spec.id.clone(),
+        version: version.unwrap_or("latest").to_string(),
+        why,
+    };
+    let api_url = Url::parse(&format!(
+        "{}/formula/{formula}.json",
+        api.trim_end_matches('/')
+    ))
+    .map_err(|e| index_error(format!("bad `HOMEBREW_API_DOMAIN`: {e}")))?;
+    let info: Formula = serde_json::from_str(&fetch_text(ctx, &api_url)?)
+        .map_err(|e| index_error(format!("unexpected formula JSON: {e}")))?;
+    let pkg_version = info.pkg_version();
+    if let Some(version) = version
+        && version != pkg_version
+        && version != info.versions.stable
+    {
+        return Err(index_error(format!(
+            "Homebrew only has bottles of {pkg_version}; install `{formula}` for others"
+        ))
+        .into());
+    }
+    let files = info.bottle.stable.map(|b| b.files).unwrap_or_default();
+    let (tag, bottle) = bottle_tags(ctx)
+        .into_iter()
+        .find_map(|tag| Some((tag.clone(), files.get(&tag)?)))
+        .ok_or_else(|| LocateError::NoAsset {
+            service: spec.id.clone(),
+            version: pkg_version.clone(),
+            platform: ctx.platform.to_string(),
+        })?;
+    let url = Url::parse(&bottle.url)
+        .map_err(|e| index_error(format!("bad bottle URL `{}`: {e}", bottle.url)))?;
+
+    let (paths, bin_name) = entry(ctx, spec, formula, &pkg_version)?;
+    let out_bin = paths.out.join(&bin_name);
+    paths.create_dirs()?;
+    let _lock = paths.lock(ctx)?; // released on drop
+    if cache::usable_entry(ctx, &paths, &out_bin, ctx.platform)? {
+        return Ok(ResolvedArtifact::executable(out_bin));
+    }
+    let work = paths.staging()?;
+    let meta = cache::Meta {
+        service: spec.id.as_str().to_string(),
+        source: "homebrew".into(),
+        release: Some(pkg_version.clone()),
+        url: Some(crate::credentials::redact(&url)),
+        ..Default::default()
+    };
+    let bottle_dir = format!("{formula}/{pkg_version}/bin");
+    let result = pour(
+        ctx,
+        spec,
+        &url,
+        &bottle.sha256,
+        &tag,
+        &bottle_dir,
+        &paths,
+        &bin_name,
+        meta,
+        &work,
+    );
+    let _ = std::fs::remove_dir_all(&work);
+    Ok(ResolvedArtifact::executable(result?))
+}
+
+/// Downloads and unpacks the bottle in `work`, and caches its binary.
+#[allow(clippy::too_many_arguments)]
+fn pour(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    url: &Url,
+    sha256: &str,
+    tag: &str,
+    bottle_dir: &str,
+    paths: &CachePaths,
+    bin_name: &str,
+    mut meta: cache::Meta,
+    work: &Path,
+) -> Result<PathBuf> {
+    let download = work.join("bottle.tar.gz");
+    let credentials = Ghcr(ctx.config.credential_provider());
+    fetch_verified(ctx, url, sha256, &download, &credentials, 4)?;
+    let archive = format!("{tag}.bottle.tar.gz");
+    let unpacked = work.join("unpacked");
+    crate::archive::extract(&download, &archive, &unpacked)?;
+    let bin_dir = unpacked.join(bottle_dir);
+    let names = spec.binary_names_for(ctx.platform);
+    let binary = names
+        .iter()
+        .map(|name| bin_dir.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| UnpackError::BinaryNotFound {
+            archive,
+            tried: names
+                .iter()
+                .map(|name| format!("{bottle_dir}/{name}"))
+                .collect::<Vec<_>>()
+                .join(", "),
+        })?;
+    let companions = companions_of(spec, &bin_dir, ctx.platform);
+    for path in std::iter::once(&binary).chain(companions.iter().map(|(_, path)| path)) {
+        unrelocated(path)?;
+    }
+
+    binfmt::check(&binary, ctx.platform)?;
+    let signature = ctx.config.codesign.enforce(&binary, ctx.platform)?;
+    meta.signing_identity = signature.and_then(|s| s.identity);
+    meta.host = crate::platform::host();
+    meta.platform = ctx.platform.to_string();
+    meta.builder_schema = spec.builder_schema;
+    cache::warn_quarantined(ctx, paths, bin_name, &binary, &companions);
+    cache::finalize(
+        ctx,
+        paths,
+        bin_name,
+        &binary,
+        &companions,
+        spec.version_probe.as_deref().filter(|_| ctx.targets_host()),
+        !ctx.config.keep_quarantine,
+        meta,
+    )
+}
+
+/// Fails if `path` still has brew's placeholders for the prefix it's poured into.
+fn unrelocated(path: &Path) -> Result<()> {
+    let bytes = std::fs::read(path).map_err(|e| FsError::Io {
+        context: format!("read {}", path.display()),
+        source: e,
+    })?;
+    if bytes.windows(11).any(|w| w == b"@@HOMEBREW_") {
+        return Err(VerifyError::BadHeader {
+            path: path.to_path_buf(),
+            reason: "the bottle must be relocated by brew; `brew install` it and resolve the \
+                     installed keg"
+                .into(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Cache entry of the bottle of `version` of `formula`, for `spec`.
+fn entry(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    formula: &str,
+    version: &str,
+) -> Result<(CachePaths, String)> {
+    let key = CacheKey {
+        service: spec.id.as_str().to_string(),
+        revision: format!("brew-{formula}-{version}"),
+        worktree_hash: None,
+        platform: ctx.platform.to_string(),
+        schema: spec.builder_schema,
+    };
+    let bin_name = spec
+        .binary_names_for(ctx.platform)
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| spec.id.as_str().to_string());
+    Ok((CachePaths::new(&ctx.config.cache_root, &key)?, bin_name))
+}
+
+/// The binary in the installed keg of `formula`, if there is one.
+fn keg_binary(
+    spec: &ToolSpec,
+    formula: &str,
+    version: Option<&str>,
+    platform: &str,
+) -> Option<PathBuf> {
+    let prefix = match std::env::var_os("HOMEBREW_PREFIX") {
+        Some(prefix) => PathBuf::from(prefix),
+        None => ["/opt/homebrew", "/usr/local", "/home/linuxbrew/.linuxbrew"]
+            .iter()
+            .map(PathBuf::from)
+            .find(|prefix| prefix.join("opt").join(formula).exists())?,
+    };
+    let cellar = std::env::var_os("HOMEBREW_CELLAR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| prefix.join("Cellar"));
+    let keg = match version {
+        // The newest revision of the version: `1.2.3`, `1.2.3_1`, ...
+        Some(version) => std::fs::read_dir(cellar.join(formula))
+            .ok()?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name().is_some_and(|name| {
+                    let name = name.to_string_lossy();
+                    name == version
+                        || name
+                            .strip_prefix(version)
+                            .and_then(|rest| rest.strip_prefix('_'))
+                            .is_some_and(|n| n.parse::<u32>().is_ok())
+                })
+            })
+            .max_by_key(|path| {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                name.rsplit_once('_')
+                    .and_then(|(_, n)| n.parse::<u32>().ok())
+                    .unwrap_or(0)
+            })?,
+        None => std::fs::canonicalize(prefix.join("opt").join(formula)).ok()?,
+    };
+    spec.binary_names_for(platform)
+        .iter()
+        .map(|name| keg.join("bin").join(name))
+        .find(|path| cache::looks_executable(path))
+}
+
+/// Bottle tags that run on the platform being resolved for, best first.
+fn bottle_tags(ctx: &ResolveContext<'_>) -> Vec<String> {
+    let mut tags = match ctx.platform {
+        "linux-x86_64" => vec!["x86_64_linux".to_string()],
+        "linux-aarch64" => vec!["arm64_linux".to_string()],
+        "macos-arm64" | "macos-x86_64" => {
+            let host = ctx.targets_host().then(macos_major).flatten();
+            let arch = if ctx.platform == "macos-arm64" {
+                "arm64_"
+            } else {
+                ""
+            };
+            MACOS
+                .iter()
+                .filter(|(_, major)| host.is_none_or(|host| *major <= host))
+                .map(|(name, _)| format!("{arch}{name}"))
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+    tags.push("all".into());
+    tags
+}
+
+/// Major version of the running macOS, e.g. 15; 10 for all of 10.x.
+fn macos_major() -> Option<u32> {
+    let out = std::process::Command::new("sw_vers")
+        .arg("-productVersion")
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&out.stdout)
+        .trim()
+        .split('.')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// The resolver's credentials, plus the anonymous token `ghcr.io` wants for
+/// public packages, where Homebrew keeps its bottles.
+struct Ghcr(Arc<dyn CredentialProvider>);
+
+impl CredentialProvider for Ghcr {
+    fn credential_for(&self, url: &Url) -> Option<Credential> {
+        self.0.credential_for(url).or_else(|| {
+            (url.host_str() == Some("ghcr.io")).then(|| Credential::Bearer("QQ==".into()))
+        })
+    }
+}
@@ -0,0 +1,114 @@
+//! Hooks run around every resolution.
+//!
+//! Register a [`ResolveHook`] with
+//! [`ArtifactResolver::hook`](crate::ArtifactResolver::hook) to change what
+//! is resolved, or act on what was, without writing a provider layer: rewrite
+//! URLs to a mirror (with credentials in their user-info, which is kept out of
+//! errors, META and events) before resolving, or copy each resolved binary's
+//! META to an artifact store after.
+//!
+//! ```no_run
+//! use zcash_artifacts::{ArtifactResolver, ResolvedArtifact, hooks};
+//!
+//! # fn demo(resolver: &mut ArtifactResolver) {
+//! resolver.hook(hooks::after(|_src, artifact, ctx| {
+//!     let ResolvedArtifact::Executable { provenance, .. } = artifact else {
+//!         return Ok(());
+//!     };
+//!     let Some(key) = &provenance.cache_key else {
+//!         return Ok(());
+//!     };
+//!     let meta = ctx.config.cache_root.join(key).join("meta/META.json");
+//!     let copy = format!("/mnt/artifacts/{}.json", key.replace('/', "-"));
+//!     // Archiving is best effort; don't fail the test run over it.
+//!     if let Err(e) = std::fs::copy(&meta, &copy) {
+//!         eprintln!("couldn't archive {}: {e}", meta.display());
+//!     }
+//!     Ok(())
+//! }));
+//! # }
+//! ```
+//!
+//! Hooks run on the resolving thread, in the order they were registered.
+//! [`before`](ResolveHook::before) hooks see the source as the caller gave it;
+//! [binary overrides](crate::ResolverConfig::binary_overrides) and
+//! [pins](crate::ResolverConfig::pins) apply to the source they leave.
+//! [`after`](ResolveHook::after) hooks run once the artifact passed the checks
+//! of its [`ResolveOptions`](crate::ResolveOptions). A hook that fails fails
+//! the resolution; hooks whose work is optional, like notifying a dashboard,
+//! should log their errors instead. Observers see the caller's source.
+
+use crate::{ArtifactSource, ResolveContext, ResolvedArtifact, error::Result};
+
+/// Runs before and after each resolution; see the [module docs](self). Both
+/// methods do nothing by default.
+pub trait ResolveHook: Send + Sync + 'static {
+    /// Runs before `source` is resolved, and may change it.
+    fn before(&self, source: &mut ArtifactSource, ctx: &ResolveContext<'_>) -> Result<()> {
+        let _ = (source, ctx);
+        Ok(())
+    }
+
+    /// Runs after `source`, as the `before` hooks left it, resolved to
+    /// `artifact`.
+    fn after(
+        &self,
+        source: &ArtifactSource,
+        artifact: &ResolvedArtifact,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<()> {
+        let _ = (source, artifact, ctx);
+        Ok(())
+    }
+}
+
+/// A hook running `f` before each resolution.
+pub fn before<F>(f: F) -> Before<F>
+where
+    F: Fn(&mut ArtifactSource, &ResolveContext<'_>) -> Result<()> + Send + Sync + 'static,
+{
+    Before(f)
+}
+
+/// A hook running `f` after each successful resolution.
+pub fn after<F>(f: F) -> After<F>
+where
+    F: Fn(&ArtifactSource, &ResolvedArtifact, &ResolveContext<'_>) -> Result<()>
+        + Send
+        + Sync
+        + 'static,
+{
+    After(f)
+}
+
+/// See [`before`].
+pub struct Before<F>(F);
+
+impl<F> ResolveHook for Before<F>
+where
+    F: Fn(&mut ArtifactSource, &ResolveContext<'_>) -> Result<()> + Send + Sync + 'static,
+{
+    fn before(&self, source: &mut ArtifactSource, ctx: &ResolveContext<'_>) -> Result<()> {
+        (self.0)(source, ctx)
+    }
+}
+
+/// See [`after`].
+pub struct After<F>(F);
+
+impl<F> ResolveHook for After<F>
+where
+    F: Fn(&ArtifactSource, &ResolvedArtifact, &ResolveContext<'_>) -> Result<()>
+        + Send
+        + Sync
+        + 'static,
+{
+    fn after(
+        &self,
+        source: &ArtifactSource,
+        artifact: &ResolvedArtifact,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<()> {
+        (self.0)(source, artifact, ctx)
+    }
+}
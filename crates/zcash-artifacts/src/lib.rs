@@ -1,15 +1,94 @@
+#[cfg(feature = "archive")]
+pub mod archive;
+#[cfg(feature = "oci")]
+pub mod attestation;
+pub mod binfmt;
+#[cfg(all(feature = "local-build", feature = "oci"))]
+pub mod build_cache;
+pub mod cache;
+pub mod cancel;
+pub mod codesign;
+pub mod compose;
+pub mod config;
+#[cfg(feature = "oci")]
+pub mod container;
+pub mod credentials;
+#[cfg(feature = "deb")]
+pub mod deb;
+#[cfg(feature = "miette")]
+mod diagnostic;
 mod error;
+#[cfg(feature = "local-build")]
+pub mod executor;
 pub mod git;
+#[cfg(feature = "http")]
+pub mod guix;
+#[cfg(all(feature = "http", feature = "archive"))]
+pub mod homebrew;
+pub mod hooks;
+mod lightwalletd;
+pub mod limits;
+mod macho;
+mod manifest;
+pub mod network;
+#[cfg(feature = "nix")]
+pub mod nix;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod observe;
+#[cfg(feature = "oci")]
+pub mod oci;
+#[cfg(feature = "oci")]
+pub mod oras;
+#[cfg(feature = "oci")]
+pub mod package;
+pub mod pipeline;
+pub mod plan;
+pub mod platform;
+pub mod probe;
+#[cfg(feature = "local-build")]
+pub mod recipe;
 pub mod registry;
+#[cfg(feature = "http")]
+pub mod release;
+#[cfg(feature = "local-build")]
+pub mod repro;
+pub mod sbom;
+#[cfg(feature = "signing")]
+pub mod signing;
+#[cfg(feature = "local-build")]
+pub mod slsa;
+pub mod stack;
+#[cfg(feature = "testcontainers")]
+pub mod testcontainers;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod trace;
+#[cfg(feature = "transparency")]
+pub mod transparency;
+#[cfg(feature = "http")]
+pub mod transport;
+#[cfg(feature = "trust-store")]
+pub mod trust;
+#[cfg(feature = "tuf")]
+pub mod tuf;
+pub mod warning;
+mod zainod;
 mod zcashd;
+mod zebrad;
 
-pub use error::{ArtifactError, Result};
+pub use error::{ArtifactError, ErrorKind, Result};
 
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use crate::registry::Registry;
 #[cfg(feature = "local-build")]
-use crate::{git::GitPolicy, registry::ServiceId};
+use crate::git::GitPolicy;
+use crate::registry::{Registry, ServiceId};
+#[cfg(feature = "http")]
+use url::Url;
 
 #[derive(Debug, Clone)]
 pub enum NodeKind {
@@ -23,20 +102,523 @@ pub enum IndexerKind {
     Zainod,
 }
 
-#[derive(Debug, Clone)]
+/// What a source resolved to.
+///
+/// Displays as its primary path (or image reference) followed by its
+/// provenance, and serializes to JSON objects tagged with a `kind` of
+/// `executable`, `bundle` or `oci-image`, with the variant's fields alongside;
+/// `None` fields of the provenance are left out:
+///
+/// ```json
+/// {
+///   "kind": "executable",
+///   "path": "/home/me/.cache/zcash-artifacts/zebrad/v2.0.0-linux-x86_64-v1/out/zebrad",
+///   "provenance": {
+///     "service": "zebrad",
+///     "source": "release",
+///     "version": "2.0.0",
+///     "cache_key": "zebrad/v2.0.0-linux-x86_64-v1",
+///     "digest": "4caee2f3…"
+///   }
+/// }
+/// ```
+#[non_exhaustive]
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
 pub enum ResolvedArtifact {
-    Executable { path: PathBuf },
-    // OciImage { reference: String }
+    Executable {
+        path: PathBuf,
+        /// Filled in by the resolver; providers can leave it to
+        /// [`ResolvedArtifact::executable`].
+        provenance: Provenance,
+    },
+    /// A service binary together with its companions (e.g. `zcash-cli`).
+    ///
+    /// `executables` is keyed by role; the service binary itself is keyed by the
+    /// service name. See [`ArtifactResolver::resolve_bundle`].
+    Bundle {
+        executables: std::collections::BTreeMap<String, PathBuf>,
+        provenance: Provenance,
+    },
+    /// A container image pulled from a registry; see [`oci::OciMode::Image`].
+    /// Also what `Build` sources with a [`recipe::DockerfileRecipe`] resolve to.
+    #[cfg(feature = "oci")]
+    OciImage {
+        /// Normalized reference pinned to the resolved digest, e.g.
+        /// `docker.io/zfnd/zebra:latest@sha256:…`; use it as the source's
+        /// reference to get the same image next time. Images built from a repo
+        /// have a local tag instead, e.g. `zcash-artifacts/zebrad:0123456789ab`.
+        reference: String,
+        /// Digest of the image manifest that was pulled. Container runtime
+        /// backends report the digest they recorded for the reference, which may
+        /// be the manifest list's; for built images, it is the image ID.
+        digest: String,
+        /// Platform of the image, e.g. `linux-x86_64`.
+        platform: String,
+        /// OCI image layout holding the pulled image, if it was pulled into the
+        /// cache rather than into a container runtime.
+        layout: Option<PathBuf>,
+        /// Entrypoint, default arguments, exposed ports and volumes from the
+        /// image config, for launchers that don't inspect the image themselves.
+        config: oci::ImageConfig,
+    },
+}
+
+impl std::fmt::Display for ResolvedArtifact {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResolvedArtifact::Executable { path, provenance } => {
+                write!(f, "{} ({provenance})", path.display())
+            }
+            ResolvedArtifact::Bundle {
+                executables,
+                provenance,
+            } => {
+                match self.primary_path() {
+                    Some(path) => write!(f, "{}", path.display())?,
+                    None => f.write_str("bundle")?,
+                }
+                let roles: Vec<&str> = executables
+                    .keys()
+                    .map(String::as_str)
+                    .filter(|role| provenance.service.as_ref().map(ServiceId::as_str) != Some(role))
+                    .collect();
+                if !roles.is_empty() {
+                    write!(f, " with {}", roles.join(", "))?;
+                }
+                write!(f, " ({provenance})")
+            }
+            #[cfg(feature = "oci")]
+            ResolvedArtifact::OciImage {
+                reference,
+                platform,
+                ..
+            } => write!(f, "{reference} ({platform} image)"),
+        }
+    }
+}
+
+impl ResolvedArtifact {
+    /// An executable whose provenance is left for the resolver to fill in.
+    pub fn executable(path: impl Into<PathBuf>) -> Self {
+        ResolvedArtifact::Executable {
+            path: path.into(),
+            provenance: Provenance::default(),
+        }
+    }
+
+    /// How to start the primary executable on this host; see [`binfmt::launch`].
+    ///
+    /// Lets harnesses smoke-test binaries built for another architecture under
+    /// qemu-user. `Ok(None)` when there is no primary executable.
+    pub fn launch(&self) -> crate::error::Result<Option<binfmt::Launch>> {
+        self.primary_path().map(binfmt::launch).transpose()
+    }
+
+    /// Path of the service binary itself.
+    ///
+    /// For bundles this is the executable keyed by the service name, or the only
+    /// executable if the bundle has no service.
+    pub fn primary_path(&self) -> Option<&Path> {
+        match self {
+            ResolvedArtifact::Executable { path, .. } => Some(path),
+            ResolvedArtifact::Bundle {
+                executables,
+                provenance,
+            } => match &provenance.service {
+                Some(service) => executables.get(service.as_str()).map(PathBuf::as_path),
+                None if executables.len() == 1 => executables.values().next().map(PathBuf::as_path),
+                None => None,
+            },
+            #[cfg(feature = "oci")]
+            ResolvedArtifact::OciImage { .. } => None,
+        }
+    }
+}
+
+/// Per-call options for [`ArtifactResolver::resolve_with`].
+///
+/// A binary downloaded or built by the call goes through the checks below
+/// while still staged, so one failing them never becomes a cache entry.
+#[derive(Debug, Clone, Default)]
+pub struct ResolveOptions {
+    /// Requirement the resolved binary's probed version must satisfy.
+    ///
+    /// The version is read with the service's [`VersionProbe`], or by running
+    /// `--version` when the service has none (or the source names no service).
+    /// Pre-release suffixes (`5.9.0-rc1`) are compared with semver rules, so a
+    /// requirement must name a pre-release to accept one.
+    pub expected_version: Option<semver::VersionReq>,
+
+    /// Fail if a resolved executable needs shared libraries the host lacks.
+    ///
+    /// Only applies when resolving for the host; see [`binfmt::missing_libraries`].
+    pub check_libraries: bool,
+
+    /// Run the resolved binary with the service's
+    /// [`health_check_args`](registry::ToolSpec::health_check_args) (`--version`
+    /// for sources without a service) and require exit code 0 within this timeout.
+    ///
+    /// Catches corrupt downloads, missing shared libraries and wrong-architecture
+    /// binaries before a harness starts a full node. Foreign binaries run under
+    /// qemu-user when available; see [`binfmt::launch`]. The binary runs
+    /// confined like the built-in probes: see [`probe`].
+    pub health_check: Option<std::time::Duration>,
+
+    /// Token that aborts the resolution once cancelled; see [`cancel`].
+    pub cancel: Option<cancel::CancelToken>,
+
+    /// Time the whole resolution may take, git, downloads, builds and checks
+    /// included, before it fails with [`ArtifactError::TimedOut`]; see
+    /// [`cancel`] for where it is checked.
+    pub timeout: Option<std::time::Duration>,
+}
+
+/// Where a resolved artifact came from.
+///
+/// For executables, everything but `service` and `source` is read from the
+/// META of the cache entry holding them, so binaries resolved in place (local
+/// paths, Homebrew installs) have only those two.
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct Provenance {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<ServiceId>,
+    /// The [kind](ArtifactSource::kind) of the source that was resolved, e.g.
+    /// `release` or `local-repo`; that of the [pin](ResolverConfig::pins) if
+    /// the service was pinned.
+    pub source: &'static str,
+    /// Version string reported by the service's [`VersionProbe`], if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Commit a build was made from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// The cache entry, relative to the cache root, e.g.
+    /// `zcashd/v6.0.0-linux-x86_64-v1`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_key: Option<String>,
+    /// BLAKE3 of the executable, as recorded when it was cached.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// What the service's [`CapabilityProbe`] found, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<probe::Capabilities>,
+}
+
+impl std::fmt::Display for Provenance {
+    /// E.g. `zebrad 2.0.0 from release`, or `zcashd from local-repo at 0123456789ab`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(service) = &self.service {
+            write!(f, "{} ", service.as_str())?;
+        }
+        if let Some(version) = &self.version {
+            write!(f, "{version} ")?;
+        }
+        f.write_str("from ")?;
+        f.write_str(if self.source.is_empty() {
+            "an unknown source"
+        } else {
+            self.source
+        })?;
+        if let Some(commit) = &self.commit {
+            let short: String = commit.chars().take(12).collect();
+            write!(f, " at {short}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Something that can turn an [`ArtifactSource`] into a [`ResolvedArtifact`].
+///
+/// Providers are layered: [`DefaultProvider`] asks each of its layers in turn and
+/// the first one returning `Some` wins, while `Ok(None)` passes the source on to
+/// the next layer. Attach one to a [`registry::ToolSpec`] to get a first shot at
+/// that service before any default layer runs. Closures of the right shape
+/// implement this trait too.
+pub trait ArtifactProvider: Send + Sync + 'static {
+    /// Name used to position other layers relative to this one.
+    fn name(&self) -> &str {
+        "custom"
+    }
+
+    fn resolve(
+        &self,
+        src: &ArtifactSource,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<Option<ResolvedArtifact>>;
+
+    /// What [`resolve`](ArtifactProvider::resolve) would do with `src`, found
+    /// without side effects; see [`plan`]. `Ok(None)` passes the source on, as
+    /// the default does for every source.
+    fn plan(&self, src: &ArtifactSource, ctx: &ResolveContext<'_>) -> Result<Option<plan::Step>> {
+        let _ = (src, ctx);
+        Ok(None)
+    }
+}
+
+impl<F> ArtifactProvider for F
+where
+    F: Fn(&ArtifactSource, &ResolveContext<'_>) -> Result<Option<ResolvedArtifact>>
+        + Send
+        + Sync
+        + 'static,
+{
+    fn resolve(
+        &self,
+        src: &ArtifactSource,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<Option<ResolvedArtifact>> {
+        self(src, ctx)
+    }
 }
 
-pub trait ArtifactProvider {
-    fn resolve(&self, src: &ArtifactSource) -> Result<ResolvedArtifact>;
+/// What a provider gets to see about the resolver it runs in.
+#[non_exhaustive]
+pub struct ResolveContext<'a> {
+    pub config: &'a ResolverConfig,
+    pub registry: &'a Registry,
+    /// Platform being resolved for, e.g. `linux-x86_64`; see
+    /// [`ResolverConfig::platform_override`].
+    pub platform: &'a str,
+    /// The resolution's [`ResolveOptions::cancel`].
+    pub cancel: Option<&'a cancel::CancelToken>,
+    pub(crate) observers: &'a observe::Observers,
+    #[cfg_attr(not(any(feature = "http", feature = "local-build")), allow(dead_code))]
+    pub(crate) limiter: &'a limits::Limiter,
+    /// The resolution's checks; new cache entries must pass them before
+    /// they're promoted, see [`ResolveContext::run_checks`].
+    pub(crate) checks: Option<&'a ResolveOptions>,
+    /// Executables promoted after passing `checks`, not checked again.
+    pub(crate) checked: std::sync::Mutex<Vec<PathBuf>>,
+    #[cfg(feature = "http")]
+    transport: &'a Arc<dyn transport::Transport>,
+}
+
+impl ResolveContext<'_> {
+    /// Whether binaries for [`ResolveContext::platform`] can run on this host.
+    pub fn targets_host(&self) -> bool {
+        self.platform == platform::host()
+    }
+
+    /// Fails with [`ArtifactError::Cancelled`] if the resolution was
+    /// cancelled, or [`ArtifactError::TimedOut`] if it ran out of time; for
+    /// providers doing long work of their own.
+    pub fn check_cancelled(&self) -> Result<()> {
+        self.cancel.map_or(Ok(()), cancel::CancelToken::check)
+    }
+
+    /// The resolver's [transport](ResolverConfig::transport).
+    #[cfg(feature = "http")]
+    pub(crate) fn transport(&self) -> &Arc<dyn transport::Transport> {
+        self.transport
+    }
+
+    /// Runs the checks of the resolution's [`ResolveOptions`] on `exe`, an
+    /// executable of `service`, and the shared-library check on `others`
+    /// resolved with it.
+    pub(crate) fn run_checks(
+        &self,
+        service: Option<&ServiceId>,
+        exe: &Path,
+        others: &[&Path],
+    ) -> Result<()> {
+        let Some(opts) = self.checks else {
+            return Ok(());
+        };
+        if let Some(cancel) = self.cancel
+            && (opts.expected_version.is_some()
+                || opts.check_libraries
+                || opts.health_check.is_some())
+        {
+            cancel.enter(cancel::Phase::Verify);
+            cancel.check()?;
+        }
+        let spec = service.and_then(|id| self.registry.get(id));
+        if let Some(req) = &opts.expected_version {
+            check_version(spec, exe, req)?;
+        }
+        if opts.check_libraries && self.targets_host() {
+            check_libraries(std::iter::once(exe).chain(others.iter().copied()))?;
+        }
+        if let Some(timeout) = opts.health_check {
+            let default_args = ["--version".to_string()];
+            let args = spec.map_or(&default_args[..], |spec| &spec.health_check_args);
+            probe::health_check(exe, args, timeout)?;
+        }
+        Ok(())
+    }
+
+    /// Records that `exe` was promoted after passing the resolution's checks.
+    pub(crate) fn mark_checked(&self, exe: &Path) {
+        if self.checks.is_some() {
+            self.checked
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .push(exe.to_path_buf());
+        }
+    }
+
+    /// Records that the resolution entered `phase`, for its
+    /// [`ArtifactError::TimedOut`].
+    pub(crate) fn enter(&self, phase: cancel::Phase) {
+        if let Some(cancel) = self.cancel {
+            cancel.enter(phase);
+        }
+    }
+
+    /// Reports `warning` to the resolver's observers; for providers doing
+    /// something allowed but worth auditing. See [`warning`].
+    pub fn warn(&self, warning: warning::Warning) {
+        trace::warning!(kind = warning.kind(), "{warning}");
+        self.emit(observe::Event::Warning { warning: &warning });
+    }
+
+    /// Tells the resolver's observers about `event`.
+    pub(crate) fn emit(&self, event: observe::Event<'_>) {
+        self.observers.emit(event);
+    }
+}
+
+/// An ordered stack of provider layers.
+///
+/// [`DefaultProvider::new`] holds the standard layers described in
+/// [`pipeline`]; insert your own relative to them by name.
+pub struct DefaultProvider {
+    layers: Vec<Arc<dyn ArtifactProvider>>,
+}
+
+impl Default for DefaultProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DefaultProvider {
+    /// The standard layers enabled by the crate's features.
+    pub fn new() -> Self {
+        let mut provider = Self::empty();
+        provider.push(pipeline::LocalLayer);
+        provider.push(pipeline::CacheLayer);
+        #[cfg(feature = "http")]
+        provider.push(pipeline::ReleaseLayer);
+        #[cfg(feature = "oci")]
+        provider.push(pipeline::OciLayer);
+        #[cfg(feature = "nix")]
+        provider.push(pipeline::NixLayer);
+        #[cfg(all(feature = "local-build", feature = "oci"))]
+        provider.push(pipeline::BuildCacheLayer);
+        #[cfg(feature = "local-build")]
+        provider.push(pipeline::BuildLayer);
+        provider
+    }
+
+    /// A provider with no layers; it resolves nothing until some are pushed.
+    pub fn empty() -> Self {
+        Self { layers: Vec::new() }
+    }
+
+    /// Appends `layer`, so it runs after every existing layer.
+    pub fn push(&mut self, layer: impl ArtifactProvider) -> &mut Self {
+        self.layers.push(Arc::new(layer));
+        self
+    }
+
+    /// Inserts `layer` right before the first layer named `anchor`.
+    ///
+    /// Fails with [`error::InputError::InvalidConfig`] if no layer is named `anchor`.
+    pub fn insert_before(
+        &mut self,
+        anchor: &str,
+        layer: impl ArtifactProvider,
+    ) -> Result<&mut Self> {
+        let at = self.position(anchor)?;
+        self.layers.insert(at, Arc::new(layer));
+        Ok(self)
+    }
+
+    /// Inserts `layer` right after the first layer named `anchor`.
+    ///
+    /// Fails with [`error::InputError::InvalidConfig`] if no layer is named `anchor`.
+    pub fn insert_after(
+        &mut self,
+        anchor: &str,
+        layer: impl ArtifactProvider,
+    ) -> Result<&mut Self> {
+        let at = self.position(anchor)? + 1;
+        self.layers.insert(at, Arc::new(layer));
+        Ok(self)
+    }
+
+    /// Names of the layers, in the order they run.
+    pub fn layer_names(&self) -> impl Iterator<Item = &str> {
+        self.layers.iter().map(|layer| layer.name())
+    }
+
+    fn position(&self, anchor: &str) -> Result<usize> {
+        self.layers
+            .iter()
+            .position(|layer| layer.name() == anchor)
+            .ok_or_else(|| {
+                error::InputError::InvalidConfig {
+                    origin: "DefaultProvider".into(),
+                    reason: format!("no provider layer named `{anchor}`"),
+                }
+                .into()
+            })
+    }
 }
 
-pub struct DefaultProvider;
 impl ArtifactProvider for DefaultProvider {
-    fn resolve(&self, _src: &ArtifactSource) -> Result<ResolvedArtifact> {
-        todo!()
+    fn name(&self) -> &str {
+        "default"
+    }
+
+    fn resolve(
+        &self,
+        src: &ArtifactSource,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<Option<ResolvedArtifact>> {
+        Ok(self
+            .resolve_layered(src, ctx)?
+            .map(|(resolved, _)| resolved))
+    }
+
+    fn plan(&self, src: &ArtifactSource, ctx: &ResolveContext<'_>) -> Result<Option<plan::Step>> {
+        Ok(self.plan_layered(src, ctx)?.map(|(step, _)| step))
+    }
+}
+
+impl DefaultProvider {
+    /// Like [`ArtifactProvider::resolve`], also naming the layer that resolved `src`.
+    fn resolve_layered(
+        &self,
+        src: &ArtifactSource,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<Option<(ResolvedArtifact, &str)>> {
+        for layer in &self.layers {
+            ctx.check_cancelled()?;
+            ctx.enter(cancel::Phase::Locate);
+            trace::span!(DEBUG, "layer", layer = layer.name());
+            if let Some(resolved) = layer.resolve(src, ctx)? {
+                return Ok(Some((resolved, layer.name())));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Like [`ArtifactProvider::plan`], also naming the layer that would resolve `src`.
+    fn plan_layered(
+        &self,
+        src: &ArtifactSource,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<Option<(plan::Step, &str)>> {
+        for layer in &self.layers {
+            if let Some(step) = layer.plan(src, ctx)? {
+                return Ok(Some((step, layer.name())));
+            }
+        }
+        Ok(None)
     }
 }
 
@@ -63,30 +645,708 @@ pub enum ArtifactSource {
 
         /// Defaults to `src/zcashd`
         expected_output: Option<PathBuf>,
+
+        /// Rust target triple to cross-compile for, e.g. `aarch64-unknown-linux-gnu`.
+        ///
+        /// `None` builds for the host. The result is cached and checked as a binary
+        /// for the triple's platform, and its version probe is skipped when that
+        /// isn't the host.
+        target: Option<String>,
     },
     #[cfg(feature = "http")]
     Url {
         url: Url,
         checksum: String,
     },
+    /// A release built reproducibly with Guix, optionally checked against
+    /// independent rebuilds; see [`guix`]. The service must have
+    /// [`registry::ToolSpec::guix`] set.
+    #[cfg(feature = "http")]
+    Guix {
+        service: ServiceId,
+        version: String,
+        /// `SHA256SUMS` files published by independent rebuilders, e.g. from a
+        /// `guix.sigs` repository; each must list the release's hash for the
+        /// tarball. Empty trusts the release's own `SHA256SUMS`.
+        rebuilds: Vec<Url>,
+    },
+    /// A Homebrew formula: an installed keg, or its bottle; see [`homebrew`].
+    #[cfg(all(feature = "http", feature = "archive"))]
+    Homebrew {
+        service: ServiceId,
+        /// Defaults to the service's name.
+        formula: Option<String>,
+        /// E.g. `2.0.0` or, with a formula revision, `2.0.0_1`. `None` takes
+        /// the linked keg, or the current bottle.
+        version: Option<String>,
+    },
+    /// The service's binary from a Debian package, without `dpkg`; see [`deb`].
+    /// Packages from a repository need [`registry::ToolSpec::apt`] set.
+    #[cfg(feature = "deb")]
+    Deb {
+        service: ServiceId,
+        package: deb::DebPackage,
+    },
+    /// An image in an OCI registry; what it resolves to depends on
+    /// [`oci::OciConfig::mode`].
     #[cfg(feature = "oci")]
     OciImage {
+        /// `[registry/]repository[:tag][@digest]`, as for `docker pull`.
         reference: String,
+        /// Pins the image, like an `@digest` suffix on `reference`. The pulled
+        /// manifest (or manifest list) must have this digest, and if `reference`
+        /// has a tag too, the tag must still point at it.
         digest: Option<String>,
+        /// Service whose binary to take from the image under
+        /// [`oci::OciMode::Extract`]; ignored when pulling whole images.
+        service: Option<ServiceId>,
+    },
+    /// A binary published as an ORAS artifact, e.g. with `oras push`; see
+    /// [`oras`]. Always resolves to an executable.
+    #[cfg(feature = "oci")]
+    OrasArtifact {
+        /// `[registry/]repository[:tag][@digest]`, as for `oras pull`.
+        reference: String,
+        /// Pins the artifact, like an `@digest` suffix on `reference`.
+        digest: Option<String>,
+        /// Service whose binary (and companions) to take from the artifact;
+        /// without one, the artifact must hold a single file.
+        service: Option<ServiceId>,
+    },
+    /// An output of a Nix flake, built (or substituted) with `nix build`; see
+    /// [`nix`]. Always resolves to an executable.
+    #[cfg(feature = "nix")]
+    Nix {
+        /// The flake, as `nix build` takes it, e.g. `github:org/infra` or a
+        /// path; pin a `rev` to resolve offline.
+        flake_ref: String,
+        /// Attribute of the output, e.g. `zebrad` or
+        /// `packages.x86_64-linux.zebrad`; its last component names the binary.
+        attr: String,
     },
 }
 
-/// Configuration for zcash-artifacts
+impl ArtifactSource {
+    /// Short, stable name of the source kind, as recorded in provenance.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ArtifactSource::LocalPath(_) => "local-path",
+            ArtifactSource::Release { .. } => "release",
+            #[cfg(feature = "local-build")]
+            ArtifactSource::Build { .. } => "local-repo",
+            #[cfg(feature = "http")]
+            ArtifactSource::Url { .. } => "url",
+            #[cfg(feature = "http")]
+            ArtifactSource::Guix { .. } => "guix",
+            #[cfg(all(feature = "http", feature = "archive"))]
+            ArtifactSource::Homebrew { .. } => "homebrew",
+            #[cfg(feature = "deb")]
+            ArtifactSource::Deb { .. } => "deb",
+            #[cfg(feature = "oci")]
+            ArtifactSource::OciImage { .. } => "oci",
+            #[cfg(feature = "oci")]
+            ArtifactSource::OrasArtifact { .. } => "oras",
+            #[cfg(feature = "nix")]
+            ArtifactSource::Nix { .. } => "nix",
+        }
+    }
+
+    /// The service this source refers to, if it names one.
+    pub fn service(&self) -> Option<&ServiceId> {
+        match self {
+            ArtifactSource::Release { service, .. } => Some(service),
+            #[cfg(feature = "http")]
+            ArtifactSource::Guix { service, .. } => Some(service),
+            #[cfg(all(feature = "http", feature = "archive"))]
+            ArtifactSource::Homebrew { service, .. } => Some(service),
+            #[cfg(feature = "deb")]
+            ArtifactSource::Deb { service, .. } => Some(service),
+            #[cfg(feature = "local-build")]
+            ArtifactSource::Build { service, .. } => Some(service),
+            #[cfg(feature = "oci")]
+            ArtifactSource::OciImage { service, .. } => service.as_ref(),
+            #[cfg(feature = "oci")]
+            ArtifactSource::OrasArtifact { service, .. } => service.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Configuration for zcash-artifacts; see [`ResolverConfig::builder`].
+#[non_exhaustive]
 pub struct ResolverConfig {
-    /// Where to store downloaded artifacts.
+    /// Where to store downloaded artifacts; see [`cache`] for the layout.
     ///
-    /// Defaults to ~/.cache/zcash-artifacts/<artifact-kind>/<version-or-rev>/<os>-<arch>
+    /// The builder defaults it to [`cache::default_root`].
     pub cache_root: PathBuf,
 
     /// The build configuration to use.
+    #[cfg(feature = "local-build")]
     pub build_config: BuildConfig,
+
+    /// Resolve artifacts for this platform instead of the host, e.g. to fetch a
+    /// `linux-aarch64` release on an x86_64 CI box for later deployment.
+    ///
+    /// Any spelling [`platform::Platform::parse`] accepts works. Release assets and
+    /// cache keys follow the override; version probes are skipped for foreign
+    /// platforms since their binaries can't run here.
+    pub platform_override: Option<String>,
+
+    /// Keep macOS's `com.apple.quarantine` attribute on downloaded binaries.
+    ///
+    /// By default it is stripped when a download is finalized, since Gatekeeper
+    /// refuses to spawn quarantined binaries that aren't notarized.
+    pub keep_quarantine: bool,
+
+    /// Signature check for downloaded macOS binaries; see [`codesign`].
+    pub codesign: codesign::CodeSignPolicy,
+
+    /// Keep only the slice for the resolved architecture of universal macOS
+    /// binaries, roughly halving their size in the cache. META records the
+    /// digest of the original as `universal_digest`.
+    pub thin_universal: bool,
+
+    /// Supplies tokens and passwords for authenticated downloads. `None` reads
+    /// `<cache_root>/credentials.toml`; see [`credentials`].
+    pub credentials: Option<Arc<dyn credentials::CredentialProvider>>,
+
+    /// Hosts downloads and registry calls may reach; see [`network`].
+    pub network: network::NetworkPolicy,
+
+    /// Sends the HTTP requests of downloads and registry calls. `None` is a
+    /// [`transport::HttpTransport`]; tests may answer from canned responses
+    /// instead, see [`transport`].
+    #[cfg(feature = "http")]
+    pub transport: Option<Arc<dyn transport::Transport>>,
+
+    /// Honor `<SERVICE>_BIN` environment variables, e.g. `ZCASHD_BIN`, ahead
+    /// of every source naming the service; see [`ResolverConfig::binary_override`].
+    pub binary_overrides: bool,
+
+    /// The source to resolve each of these services from, in place of the one
+    /// asked for; usually set by [configuration files](config). Binary
+    /// overrides still come first. [`ArtifactResolver::resolve_stack`] falls
+    /// back to them for services it is given no source for.
+    pub pins: std::collections::HashMap<ServiceId, ArtifactSource>,
+
+    /// How many downloads, builds and requests to one host may run at once,
+    /// across the resolutions of one resolver; see [`limits`].
+    pub limits: limits::ConcurrencyLimits,
+
+    /// Keep a rotating log of resolutions, failures and lock waits in
+    /// `<cache_root>/logs/resolver.log`; see [`observe::LogFile`].
+    pub log: bool,
+
+    /// Sign the META of the entries this resolver makes, and check the
+    /// signatures of those it finds; see [`signing`].
+    #[cfg(feature = "signing")]
+    pub meta_signing: signing::MetaSigning,
+
+    /// Keys release signatures are checked with, by default the
+    /// [bundled](trust::TrustStore::bundled) ones; see [`trust`].
+    #[cfg(feature = "trust-store")]
+    pub trust_store: trust::TrustStore,
+
+    /// The only signers each service's artifacts are accepted from; see
+    /// [pinned signers](trust#pinned-signers). Usually set by
+    /// [configuration files](config).
+    #[cfg(feature = "trust-store")]
+    pub signers: std::collections::HashMap<ServiceId, trust::SignerPins>,
+
+    /// The log release downloads are cross-checked against, by default none;
+    /// see [`transparency`].
+    #[cfg(feature = "transparency")]
+    pub transparency: transparency::TransparencyPolicy,
+
+    /// How `OciImage` sources are resolved.
+    #[cfg(feature = "oci")]
+    pub oci: oci::OciConfig,
+}
+
+impl ResolverConfig {
+    /// Starts a configuration with the defaults: the per-user cache directory,
+    /// the host platform, builds (`local-build` feature) not allowed and using
+    /// every CPU, and everything else as the fields describe.
+    ///
+    /// ```no_run
+    /// use zcash_artifacts::{ArtifactResolver, ResolverConfig};
+    ///
+    /// let resolver = ArtifactResolver::new(
+    ///     ResolverConfig::builder()
+    ///         .platform_override("linux-aarch64")
+    ///         .finish(),
+    /// );
+    /// ```
+    pub fn builder() -> ResolverConfigBuilder {
+        ResolverConfigBuilder {
+            config: ResolverConfig {
+                cache_root: cache::default_root(),
+                #[cfg(feature = "local-build")]
+                build_config: BuildConfig::default(),
+                platform_override: None,
+                keep_quarantine: false,
+                codesign: Default::default(),
+                thin_universal: false,
+                credentials: None,
+                network: Default::default(),
+                #[cfg(feature = "http")]
+                transport: None,
+                binary_overrides: false,
+                pins: Default::default(),
+                limits: Default::default(),
+                log: true,
+                #[cfg(feature = "signing")]
+                meta_signing: Default::default(),
+                #[cfg(feature = "trust-store")]
+                trust_store: trust::TrustStore::bundled(),
+                #[cfg(feature = "trust-store")]
+                signers: Default::default(),
+                #[cfg(feature = "transparency")]
+                transparency: Default::default(),
+                #[cfg(feature = "oci")]
+                oci: Default::default(),
+            },
+        }
+    }
+
+    /// The [default](Self::builder) configuration with the environment
+    /// applied; see [`ResolverConfigBuilder::env`] for the variables, and to
+    /// combine them with settings made in code.
+    pub fn from_env() -> Result<Self> {
+        Ok(Self::builder().env()?.finish())
+    }
+
+    /// The [default](Self::builder) configuration with the
+    /// [configuration files](config) found from the working directory
+    /// applied, then the environment: the environment wins over the files,
+    /// and the nearest file over the ones further up and the user's.
+    pub fn load() -> Result<Self> {
+        Ok(Self::builder().discover()?.env()?.finish())
+    }
+
+    /// The configured credential provider, or the file under the cache root.
+    pub fn credential_provider(&self) -> Arc<dyn credentials::CredentialProvider> {
+        self.credentials.clone().unwrap_or_else(|| {
+            Arc::new(credentials::FileCredentials::in_cache_root(
+                &self.cache_root,
+            ))
+        })
+    }
+
+    /// The binary `service` is overridden with, when
+    /// [`binary_overrides`](Self::binary_overrides) is on: the value of the
+    /// variable named after the service, uppercased with other characters than
+    /// letters and digits turned into `_`, and `_BIN` appended (`ZCASHD_BIN`,
+    /// `ZEBRAD_BIN`, `ZAINOD_BIN`, ...).
+    ///
+    /// An overridden service resolves to that file for every source naming
+    /// it, before its custom provider and the layers run, as if it were a
+    /// `LocalPath` source; the layer reported for it is `env`. Version and
+    /// health checks still apply.
+    pub fn binary_override(&self, service: &ServiceId) -> Option<PathBuf> {
+        if !self.binary_overrides {
+            return None;
+        }
+        env_var(&override_var(service)).map(PathBuf::from)
+    }
+
+    /// The canonical platform being resolved for: the override, or the host.
+    pub fn platform(&self) -> String {
+        match &self.platform_override {
+            Some(platform) => platform::normalize(platform),
+            None => platform::host(),
+        }
+    }
+}
+
+/// Builder for [`ResolverConfig`]; see [`ResolverConfig::builder`].
+pub struct ResolverConfigBuilder {
+    config: ResolverConfig,
 }
 
+impl ResolverConfigBuilder {
+    pub fn cache_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.config.cache_root = root.into();
+        self
+    }
+
+    /// See [`ResolverConfig::platform_override`].
+    pub fn platform_override(mut self, platform: impl Into<String>) -> Self {
+        self.config.platform_override = Some(platform.into());
+        self
+    }
+
+    pub fn keep_quarantine(mut self, keep: bool) -> Self {
+        self.config.keep_quarantine = keep;
+        self
+    }
+
+    pub fn codesign(mut self, policy: codesign::CodeSignPolicy) -> Self {
+        self.config.codesign = policy;
+        self
+    }
+
+    pub fn thin_universal(mut self, thin: bool) -> Self {
+        self.config.thin_universal = thin;
+        self
+    }
+
+    pub fn credentials(mut self, provider: impl credentials::CredentialProvider) -> Self {
+        self.config.credentials = Some(Arc::new(provider));
+        self
+    }
+
+    pub fn network(mut self, policy: network::NetworkPolicy) -> Self {
+        self.config.network = policy;
+        self
+    }
+
+    /// See [`ResolverConfig::transport`].
+    #[cfg(feature = "http")]
+    pub fn transport(mut self, transport: impl transport::Transport) -> Self {
+        self.config.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// See [`ResolverConfig::binary_override`].
+    pub fn binary_overrides(mut self, enabled: bool) -> Self {
+        self.config.binary_overrides = enabled;
+        self
+    }
+
+    /// See [`ResolverConfig::limits`].
+    pub fn limits(mut self, limits: limits::ConcurrencyLimits) -> Self {
+        self.config.limits = limits;
+        self
+    }
+
+    /// See [`ResolverConfig::log`].
+    pub fn log(mut self, enabled: bool) -> Self {
+        self.config.log = enabled;
+        self
+    }
+
+    /// See [`ResolverConfig::meta_signing`].
+    #[cfg(feature = "signing")]
+    pub fn meta_signing(mut self, signing: signing::MetaSigning) -> Self {
+        self.config.meta_signing = signing;
+        self
+    }
+
+    /// See [`ResolverConfig::trust_store`].
+    #[cfg(feature = "trust-store")]
+    pub fn trust_store(mut self, store: trust::TrustStore) -> Self {
+        self.config.trust_store = store;
+        self
+    }
+
+    /// See [`ResolverConfig::signers`].
+    #[cfg(feature = "trust-store")]
+    pub fn signers(mut self, service: ServiceId, pins: trust::SignerPins) -> Self {
+        self.config.signers.insert(service, pins);
+        self
+    }
+
+    /// See [`ResolverConfig::transparency`].
+    #[cfg(feature = "transparency")]
+    pub fn transparency(mut self, policy: transparency::TransparencyPolicy) -> Self {
+        self.config.transparency = policy;
+        self
+    }
+
+    /// See [`ResolverConfig::pins`].
+    pub fn pin(mut self, service: ServiceId, source: ArtifactSource) -> Self {
+        self.config.pins.insert(service, source);
+        self
+    }
+
+    /// Applies the [configuration file](config) at `path`. Like
+    /// [`env`](Self::env), it overrides what was set before.
+    pub fn file(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        config::apply(&mut self.config, path.as_ref(), false)?;
+        Ok(self)
+    }
+
+    /// Applies the [configuration files](config) that apply in the working
+    /// directory, the user's first and the nearest last. Only the user's file
+    /// may widen [signer pins](ResolverConfig::signers).
+    pub fn discover(mut self) -> Result<Self> {
+        let cwd = std::env::current_dir().map_err(|e| error::FsError::Io {
+            context: "get the working directory".into(),
+            source: e,
+        })?;
+        let user = config::user_path();
+        for path in config::discover(&cwd) {
+            let narrow_signers = user.as_ref() != Some(&path);
+            config::apply(&mut self.config, &path, narrow_signers)?;
+        }
+        Ok(self)
+    }
+
+    /// Applies the `ZCASH_ARTIFACTS_*` environment variables that are set
+    /// (and not empty), so CI can configure a resolver without code changes:
+    ///
+    /// | Variable | Sets |
+    /// |---|---|
+    /// | `ZCASH_ARTIFACTS_CACHE_DIR` | [`cache_root`](ResolverConfig::cache_root) |
+    /// | `ZCASH_ARTIFACTS_PLATFORM` | [`platform_override`](ResolverConfig::platform_override) |
+    /// | `ZCASH_ARTIFACTS_OFFLINE` | [`network`](ResolverConfig::network) to `Offline` when true |
+    /// | `ZCASH_ARTIFACTS_ALLOWED_HOSTS` | [`network`](ResolverConfig::network) to `Hosts`, comma-separated |
+    /// | `ZCASH_ARTIFACTS_BIN_OVERRIDES` | [`binary_overrides`](ResolverConfig::binary_overrides) |
+    /// | `ZCASH_ARTIFACTS_MAX_DOWNLOADS` | [`limits.downloads`](limits::ConcurrencyLimits::downloads) |
+    /// | `ZCASH_ARTIFACTS_MAX_BUILDS` | [`limits.builds`](limits::ConcurrencyLimits::builds) |
+    /// | `ZCASH_ARTIFACTS_MAX_PER_HOST` | [`limits.per_host`](limits::ConcurrencyLimits::per_host) |
+    /// | `ZCASH_ARTIFACTS_LOG` | [`log`](ResolverConfig::log) |
+    /// | `ZCASH_ARTIFACTS_ALLOW_BUILD` | [`BuildConfig::allow_build`] (`local-build` feature) |
+    /// | `ZCASH_ARTIFACTS_JOBS` | [`BuildConfig::default_jobs`], `0` for one per CPU (`local-build` feature) |
+    /// | `ZCASH_ARTIFACTS_BUILDER_ID` | [`BuildConfig::builder_id`] (`local-build` feature) |
+    /// | `ZCASH_ARTIFACTS_SIGNING_KEY` | `meta_signing.key`, from the key file at this path (`signing` feature) |
+    /// | `ZCASH_ARTIFACTS_TRUSTED_KEYS` | `meta_signing.trusted`, comma-separated (`signing` feature) |
+    /// | `ZCASH_ARTIFACTS_REQUIRE_SIGNED` | `meta_signing.require` (`signing` feature) |
+    /// | `ZCASH_ARTIFACTS_KEYRINGS` | keyrings added to [`trust_store`](ResolverConfig::trust_store), comma-separated paths (`trust-store` feature) |
+    /// | `ZCASH_ARTIFACTS_TRANSPARENCY_LOG` | `transparency.log`, a URL, or `rekor` for [Rekor](transparency::TransparencyPolicy::rekor) (`transparency` feature) |
+    /// | `ZCASH_ARTIFACTS_REQUIRE_LOGGED` | `transparency.require` (`transparency` feature) |
+    ///
+    /// Booleans are `1`, `true`, `yes` or `on`, and `0`, `false`, `no` or
+    /// `off`. Limits of `0` are unlimited. Offline wins over allowed hosts. Malformed values are
+    /// [`InputError::InvalidConfig`](error::InputError::InvalidConfig) errors
+    /// naming the variable.
+    ///
+    /// The environment overrides what was set before this call, and setters
+    /// called after it override the environment:
+    ///
+    /// ```no_run
+    /// # fn main() -> zcash_artifacts::Result<()> {
+    /// use zcash_artifacts::ResolverConfig;
+    ///
+    /// // CI may move the cache, but never makes this tool download anything.
+    /// let config = ResolverConfig::builder()
+    ///     .cache_root("/var/cache/zcash-artifacts")
+    ///     .env()?
+    ///     .network(zcash_artifacts::network::NetworkPolicy::Offline)
+    ///     .finish();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn env(mut self) -> Result<Self> {
+        if let Some(dir) = env_var("ZCASH_ARTIFACTS_CACHE_DIR") {
+            self.config.cache_root = dir.into();
+        }
+        if let Some(platform) = env_var("ZCASH_ARTIFACTS_PLATFORM") {
+            self.config.platform_override = Some(platform);
+        }
+        if let Some(hosts) = env_var("ZCASH_ARTIFACTS_ALLOWED_HOSTS") {
+            self.config.network = network::NetworkPolicy::Hosts(
+                hosts
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|host| !host.is_empty())
+                    .map(String::from)
+                    .collect(),
+            );
+        }
+        if env_flag("ZCASH_ARTIFACTS_OFFLINE")? == Some(true) {
+            self.config.network = network::NetworkPolicy::Offline;
+        }
+        if let Some(enabled) = env_flag("ZCASH_ARTIFACTS_BIN_OVERRIDES")? {
+            self.config.binary_overrides = enabled;
+        }
+        if let Some(enabled) = env_flag("ZCASH_ARTIFACTS_LOG")? {
+            self.config.log = enabled;
+        }
+        let limits = &mut self.config.limits;
+        for (var, limit) in [
+            ("ZCASH_ARTIFACTS_MAX_DOWNLOADS", &mut limits.downloads),
+            ("ZCASH_ARTIFACTS_MAX_BUILDS", &mut limits.builds),
+            ("ZCASH_ARTIFACTS_MAX_PER_HOST", &mut limits.per_host),
+        ] {
+            if let Some(value) = env_var(var) {
+                let max: usize = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid_env(var, format!("expected a number, got `{value}`")))?;
+                *limit = (max > 0).then_some(max);
+            }
+        }
+        #[cfg(feature = "local-build")]
+        {
+            if let Some(allow) = env_flag("ZCASH_ARTIFACTS_ALLOW_BUILD")? {
+                self.config.build_config.allow_build = allow;
+            }
+            if let Some(jobs) = env_var("ZCASH_ARTIFACTS_JOBS") {
+                let jobs: u32 = jobs.trim().parse().map_err(|_| {
+                    invalid_env(
+                        "ZCASH_ARTIFACTS_JOBS",
+                        format!("expected a number of jobs, got `{jobs}`"),
+                    )
+                })?;
+                self.config.build_config.default_jobs = (jobs > 0).then_some(jobs);
+            }
+            if let Some(id) = env_var("ZCASH_ARTIFACTS_BUILDER_ID") {
+                self.config.build_config.builder_id = Some(id);
+            }
+        }
+        #[cfg(feature = "signing")]
+        {
+            let signing = &mut self.config.meta_signing;
+            if let Some(path) = env_var("ZCASH_ARTIFACTS_SIGNING_KEY") {
+                signing.key = Some(signing::SigningKey::from_file(path)?);
+            }
+            if let Some(keys) = env_var("ZCASH_ARTIFACTS_TRUSTED_KEYS") {
+                signing.trusted = keys
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|key| !key.is_empty())
+                    .map(|key| {
+                        key.parse().map_err(|_| {
+                            invalid_env(
+                                "ZCASH_ARTIFACTS_TRUSTED_KEYS",
+                                format!("expected ed25519 public keys in hex, got `{key}`"),
+                            )
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+            }
+            if let Some(require) = env_flag("ZCASH_ARTIFACTS_REQUIRE_SIGNED")? {
+                signing.require = require;
+            }
+        }
+        #[cfg(feature = "trust-store")]
+        if let Some(keyrings) = env_var("ZCASH_ARTIFACTS_KEYRINGS") {
+            let mut store = std::mem::take(&mut self.config.trust_store);
+            for path in keyrings.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+                store = store.keyring(path)?;
+            }
+            self.config.trust_store = store;
+        }
+        #[cfg(feature = "transparency")]
+        {
+            if let Some(log) = env_var("ZCASH_ARTIFACTS_TRANSPARENCY_LOG") {
+                self.config.transparency.log = Some(match log.trim() {
+                    "rekor" => transparency::TransparencyPolicy::rekor(),
+                    log => url::Url::parse(log).map_err(|e| {
+                        invalid_env(
+                            "ZCASH_ARTIFACTS_TRANSPARENCY_LOG",
+                            format!("expected a URL or `rekor`, got `{log}`: {e}"),
+                        )
+                    })?,
+                });
+            }
+            if let Some(require) = env_flag("ZCASH_ARTIFACTS_REQUIRE_LOGGED")? {
+                self.config.transparency.require = require;
+            }
+        }
+        Ok(self)
+    }
+
+    #[cfg(feature = "oci")]
+    pub fn oci(mut self, oci: oci::OciConfig) -> Self {
+        self.config.oci = oci;
+        self
+    }
+
+    /// Replaces the whole build configuration; the setters below change one
+    /// field of it.
+    #[cfg(feature = "local-build")]
+    pub fn build_config(mut self, build_config: BuildConfig) -> Self {
+        self.config.build_config = build_config;
+        self
+    }
+
+    #[cfg(feature = "local-build")]
+    pub fn allow_build(mut self, allow: bool) -> Self {
+        self.config.build_config.allow_build = allow;
+        self
+    }
+
+    /// See [`BuildConfig::jobs_for`].
+    #[cfg(feature = "local-build")]
+    pub fn jobs(mut self, jobs: u32) -> Self {
+        self.config.build_config.default_jobs = Some(jobs);
+        self
+    }
+
+    #[cfg(feature = "local-build")]
+    pub fn git_policy(mut self, policy: GitPolicy) -> Self {
+        self.config.build_config.default_policy = policy;
+        self
+    }
+
+    #[cfg(feature = "local-build")]
+    pub fn low_priority(mut self, low: bool) -> Self {
+        self.config.build_config.low_priority = low;
+        self
+    }
+
+    #[cfg(feature = "local-build")]
+    pub fn isolation(mut self, isolation: BuildIsolation) -> Self {
+        self.config.build_config.isolation = isolation;
+        self
+    }
+
+    #[cfg(feature = "local-build")]
+    pub fn executor(mut self, executor: impl BuildExecutor) -> Self {
+        self.config.build_config.executor = Some(Arc::new(executor));
+        self
+    }
+
+    /// See [`BuildConfig::builder_id`].
+    #[cfg(feature = "local-build")]
+    pub fn builder_id(mut self, id: impl Into<String>) -> Self {
+        self.config.build_config.builder_id = Some(id.into());
+        self
+    }
+
+    pub fn finish(self) -> ResolverConfig {
+        self.config
+    }
+}
+
+/// The variable overriding `service`'s binary, e.g. `ZCASHD_BIN`.
+fn override_var(service: &ServiceId) -> String {
+    let mut var: String = service
+        .as_str()
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() => c.to_ascii_uppercase(),
+            _ => '_',
+        })
+        .collect();
+    var.push_str("_BIN");
+    var
+}
+
+/// The value of `var`, if set to something other than the empty string.
+fn env_var(var: &str) -> Option<String> {
+    std::env::var(var).ok().filter(|value| !value.is_empty())
+}
+
+fn env_flag(var: &'static str) -> Result<Option<bool>> {
+    let Some(value) = env_var(var) else {
+        return Ok(None);
+    };
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(Some(true)),
+        "0" | "false" | "no" | "off" => Ok(Some(false)),
+        _ => Err(invalid_env(
+            var,
+            format!("expected a boolean, got `{value}`"),
+        )),
+    }
+}
+
+fn invalid_env(var: &str, reason: String) -> ArtifactError {
+    error::InputError::InvalidConfig {
+        origin: format!("environment variable {var}"),
+        reason,
+    }
+    .into()
+}
+
+/// Build settings of a [`ResolverConfig`].
+///
+/// The default allows no builds, and runs them with one job per CPU, on
+/// clean worktrees only, unconfined and on this machine.
 #[cfg(feature = "local-build")]
 pub struct BuildConfig {
     pub allow_build: bool,
@@ -95,206 +1355,801 @@ pub struct BuildConfig {
     pub default_policy: GitPolicy,
     /// Default expected output (“src/zcashd”).
     pub default_expected_output: PathBuf,
+    /// Run build subprocesses at low CPU priority (`nice 10`) and, on Linux,
+    /// the lowest best-effort IO priority, so background builds don't bog
+    /// down the machine.
+    pub low_priority: bool,
+    /// Sandbox for build subprocesses; none by default.
+    pub isolation: BuildIsolation,
+    /// Where builds run; `None` runs them on this machine. See [`executor`].
+    pub executor: Option<Arc<dyn BuildExecutor>>,
+    /// Builder ID recorded in the [SLSA provenance](slsa) of builds, e.g.
+    /// `https://github.com/actions/runner/github-hosted` in CI; `None` records
+    /// the executor's location, or `urn:zcash-artifacts:builder:local`.
+    pub builder_id: Option<String>,
+}
+
+#[cfg(feature = "local-build")]
+impl Default for BuildConfig {
+    fn default() -> Self {
+        Self {
+            allow_build: false,
+            default_jobs: None,
+            default_policy: GitPolicy::RequireClean,
+            default_expected_output: PathBuf::from("src/zcashd"),
+            low_priority: false,
+            isolation: BuildIsolation::None,
+            executor: None,
+            builder_id: None,
+        }
+    }
+}
+
+/// How build scripts are confined.
+///
+/// Build scripts like `zcutil/build.sh` run arbitrary upstream code. Under a
+/// sandbox the whole filesystem is read-only to them except the worktree, the
+/// cache entry's log directory, the system temp directory and `writable`. The
+/// network stays reachable, since dependency downloads need it. The mode used
+/// is recorded in META.
+#[cfg(feature = "local-build")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum BuildIsolation {
+    /// Run builds directly.
+    #[default]
+    None,
+    /// Run builds under `bwrap` (bubblewrap), with their own PID namespace.
+    Bubblewrap { writable: Vec<PathBuf> },
+    /// Run builds in an unprivileged user and mount namespace set up with
+    /// util-linux `unshare` (2.38 or later). Linux only.
+    Unshare { writable: Vec<PathBuf> },
+}
+
+#[cfg(feature = "local-build")]
+impl BuildIsolation {
+    /// The name recorded in META, or `None` when builds aren't sandboxed.
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            BuildIsolation::None => None,
+            BuildIsolation::Bubblewrap { .. } => Some("bubblewrap"),
+            BuildIsolation::Unshare { .. } => Some("unshare"),
+        }
+    }
+}
+
+#[cfg(feature = "local-build")]
+impl BuildConfig {
+    /// Parallelism for a build of a service with the given defaults.
+    ///
+    /// A per-service `jobs` value wins over [`BuildConfig::default_jobs`], which
+    /// in turn wins over the number of available CPUs; the result is then
+    /// capped by the service's `max_jobs`.
+    pub fn jobs_for(&self, defaults: &registry::BuildDefaults) -> usize {
+        let jobs = defaults
+            .jobs
+            .or(self.default_jobs)
+            .map(|j| j as usize)
+            .unwrap_or_else(|| {
+                std::thread::available_parallelism()
+                    .map(Into::into)
+                    .unwrap_or(1)
+            });
+        let jobs = match defaults.max_jobs {
+            Some(max) => jobs.min(max as usize),
+            None => jobs,
+        };
+        jobs.max(1)
+    }
 }
 
 /// Minimal provider surface the consumer uses.
+///
+/// A resolver is `Send` and `Sync`, and every resolution takes `&self`, so one
+/// resolver in an [`Arc`] serves every thread of a test harness (or, through
+/// [`nonblocking::AsyncResolver`], every task):
+///
+/// - the configuration, registry and layers are read-only once resolution
+///   starts; [`provider_mut`](Self::provider_mut), [`observe`](Self::observe)
+///   and [`hook`](Self::hook) need `&mut self`, so come before sharing;
+/// - resolutions of the same cache entry wait for each other on its lock,
+///   which also holds against other processes, and the second one finds the
+///   entry the first finalized;
+/// - the [concurrency limits](ResolverConfig::limits) and the HTTP client,
+///   with its connection pool, are shared by all resolutions;
+/// - observers and hooks are called on the thread of each resolution, so
+///   observers see the events of concurrent resolutions interleaved.
 pub struct ArtifactResolver {
     config: ResolverConfig,
-    registry: Option<Registry>,
+    registry: Registry,
+    provider: DefaultProvider,
+    observers: observe::Observers,
+    hooks: Vec<Arc<dyn hooks::ResolveHook>>,
+    limiter: limits::Limiter,
+    #[cfg(feature = "http")]
+    transport: Arc<dyn transport::Transport>,
 }
 
+// Harnesses share resolvers across threads; keep that compiling.
+const _: () = {
+    const fn shareable<T: Send + Sync>() {}
+    shareable::<ArtifactResolver>();
+    shareable::<ResolverConfig>();
+    shareable::<Registry>();
+};
+
 impl ArtifactResolver {
+    /// Creates a resolver backed by [`Registry::with_builtins`].
     pub fn new(cfg: ResolverConfig) -> Self {
-        Self {
-            config: cfg,
-            registry: None,
-        }
+        Self::with_registry(cfg, Registry::with_builtins())
     }
 
     pub fn with_registry(cfg: ResolverConfig, registry: Registry) -> Self {
+        let mut observers = observe::Observers::new();
+        if cfg.log {
+            observers.push(observe::LogFile::in_cache_root(&cfg.cache_root));
+        }
         Self {
+            limiter: limits::Limiter::new(&cfg.limits),
+            registry,
+            provider: DefaultProvider::new(),
+            observers,
+            hooks: Vec::new(),
+            #[cfg(feature = "http")]
+            transport: cfg
+                .transport
+                .clone()
+                .unwrap_or_else(|| Arc::new(transport::HttpTransport::default())),
             config: cfg,
-            registry: Some(registry),
         }
     }
 
+    pub fn config(&self) -> &ResolverConfig {
+        &self.config
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// The layers every source is resolved through.
+    pub fn provider(&self) -> &DefaultProvider {
+        &self.provider
+    }
+
+    /// Mutable access to the layers, e.g. to add a mirror after the cache:
+    ///
+    /// ```no_run
+    /// use zcash_artifacts::{
+    ///     ArtifactProvider, ArtifactResolver, ArtifactSource, ResolveContext, ResolvedArtifact,
+    /// };
+    ///
+    /// struct Mirror;
+    ///
+    /// impl ArtifactProvider for Mirror {
+    ///     fn name(&self) -> &str {
+    ///         "mirror"
+    ///     }
+    ///
+    ///     fn resolve(
+    ///         &self,
+    ///         _src: &ArtifactSource,
+    ///         _ctx: &ResolveContext<'_>,
+    ///     ) -> zcash_artifacts::Result<Option<ResolvedArtifact>> {
+    ///         Ok(None) // look the artifact up on the mirror here
+    ///     }
+    /// }
+    ///
+    /// # fn add(resolver: &mut ArtifactResolver) -> zcash_artifacts::Result<()> {
+    /// resolver.provider_mut().insert_after("cache", Mirror)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn provider_mut(&mut self) -> &mut DefaultProvider {
+        &mut self.provider
+    }
+
+    /// Registers `observer` for the events of every resolution; see [`observe`].
+    pub fn observe(&mut self, observer: impl observe::Observer) -> &mut Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// Registers `hook` to run before and after every resolution; see [`hooks`].
+    pub fn hook(&mut self, hook: impl hooks::ResolveHook) -> &mut Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Resolves `src` with default [`ResolveOptions`].
     pub fn resolve(&self, src: &ArtifactSource) -> crate::error::Result<ResolvedArtifact> {
-        match src {
-            ArtifactSource::LocalPath(path_buf) => todo!(),
-            ArtifactSource::Release { service, version } => todo!(),
-            #[cfg(feature = "local-build")]
-            ArtifactSource::Build {
-                service,
-                repo,
-                refspec,
-                policy,
-                expected_output,
-            } => self.resolve_local_build(
-                service,
-                repo,
-                refspec.as_deref(),
-                *policy,
-                expected_output.as_deref(),
+        self.resolve_with(src, &ResolveOptions::default())
+    }
+
+    /// Resolves `src`, then enforces `opts` against the result.
+    pub fn resolve_with(
+        &self,
+        src: &ArtifactSource,
+        opts: &ResolveOptions,
+    ) -> crate::error::Result<ResolvedArtifact> {
+        self.resolve_observed(src, opts, &self.observers)
+    }
+
+    /// Like [`ArtifactResolver::resolve_with`], also returning what the
+    /// resolution did, whether or not it succeeded; see
+    /// [`observe::ResolutionTrace`].
+    pub fn resolve_traced(
+        &self,
+        src: &ArtifactSource,
+        opts: &ResolveOptions,
+    ) -> (
+        crate::error::Result<ResolvedArtifact>,
+        observe::ResolutionTrace,
+    ) {
+        let tracer = Arc::new(observe::Tracer::default());
+        let mut observers = self.observers.clone();
+        observers.push_shared(tracer.clone());
+        let result = self.resolve_observed(src, opts, &observers);
+        (result, tracer.finish())
+    }
+
+    /// Resolves each of `sources` with `opts` on a thread of its own, returning
+    /// their results in the same order.
+    ///
+    /// The resolutions share the resolver's
+    /// [concurrency limits](ResolverConfig::limits), and its cache: sources
+    /// resolving to the same cache entry wait for each other instead of
+    /// fetching it twice. A [timeout](ResolveOptions::timeout) applies to each
+    /// resolution, and [cancelling](ResolveOptions::cancel) stops them all.
+    pub fn resolve_many(
+        &self,
+        sources: &[ArtifactSource],
+        opts: &ResolveOptions,
+    ) -> Vec<crate::error::Result<ResolvedArtifact>> {
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = sources
+                .iter()
+                .map(|src| scope.spawn(move || self.resolve_with(src, opts)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect()
+        })
+    }
+
+    /// [`ArtifactResolver::resolve_with`], reporting to `observers`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "resolve",
+            skip_all,
+            err(Display),
+            fields(
+                source = src.kind(),
+                service = src.service().map(ServiceId::as_str),
+                platform = %self.config.platform(),
+                layer = tracing::field::Empty,
             ),
+        )
+    )]
+    fn resolve_observed(
+        &self,
+        src: &ArtifactSource,
+        opts: &ResolveOptions,
+        observers: &observe::Observers,
+    ) -> crate::error::Result<ResolvedArtifact> {
+        use observe::Event;
+
+        let started = std::time::Instant::now();
+        let platform = self.config.platform();
+        observers.emit(Event::ResolveStarted {
+            source: src,
+            platform: &platform,
+        });
+        let result = self.resolve_checked(src, opts, observers);
+        let elapsed = started.elapsed();
+        match &result {
+            Ok((artifact, layer)) => {
+                trace::record!("layer" = layer.as_str());
+                observers.emit(Event::Resolved {
+                    source: src,
+                    layer,
+                    artifact,
+                    elapsed,
+                });
+            }
+            Err(error) => {
+                if let ArtifactError::Verify(verify) = error {
+                    observers.emit(Event::VerificationFailed {
+                        source: src,
+                        error: verify,
+                    });
+                }
+                observers.emit(Event::ResolveFailed {
+                    source: src,
+                    error,
+                    elapsed,
+                });
+            }
         }
+        result.map(|(resolved, _)| resolved)
     }
 
-    fn resolve_local_path(&self, path: &PathBuf) -> crate::error::Result<ResolvedArtifact> {
-        use crate::error::{FsError, InputError};
-        use std::fs;
+    /// [`ArtifactResolver::resolve_with`], naming the layer that resolved `src`.
+    fn resolve_checked(
+        &self,
+        src: &ArtifactSource,
+        opts: &ResolveOptions,
+        observers: &observe::Observers,
+    ) -> crate::error::Result<(ResolvedArtifact, String)> {
+        let timed;
+        let cancel = match opts.timeout {
+            Some(after) => {
+                timed = cancel::CancelToken::with_timeout(opts.cancel.as_ref(), after);
+                Some(&timed)
+            }
+            None => opts.cancel.as_ref(),
+        };
+        let platform = self.config.platform();
+        let mut ctx = self.context(&platform, cancel, observers);
+        ctx.checks = Some(opts);
+        let mut hooked = None;
+        for hook in &self.hooks {
+            hook.before(hooked.get_or_insert_with(|| src.clone()), &ctx)?;
+        }
+        let src = hooked.as_ref().unwrap_or(src);
+        let (resolved, layer) = self.resolve_source(src, &ctx)?;
+        // Entries promoted by this resolution were checked while staged.
+        let checked = ctx
+            .checked
+            .get_mut()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Some(path) = resolved.primary_path()
+            && !checked.iter().any(|checked| checked == path)
+        {
+            let others: Vec<&Path> = match &resolved {
+                ResolvedArtifact::Bundle { executables, .. } => executables
+                    .values()
+                    .map(PathBuf::as_path)
+                    .filter(|other| *other != path)
+                    .collect(),
+                _ => Vec::new(),
+            };
+            ctx.run_checks(src.service(), path, &others)?;
+        }
+        for hook in &self.hooks {
+            hook.after(src, &resolved, &ctx)?;
+        }
+        Ok((resolved, layer))
+    }
 
-        let md = fs::metadata(path).map_err(|e| FsError::Io {
-            context: format!("stat {}", path.display()),
-            source: e,
+    /// Resolves `src` through the service's custom provider, if any, then the layers.
+    ///
+    /// Fails early if the service doesn't support the target platform, unless its
+    /// custom provider resolved it.
+    fn resolve_source(
+        &self,
+        src: &ArtifactSource,
+        ctx: &ResolveContext<'_>,
+    ) -> crate::error::Result<(ResolvedArtifact, String)> {
+        cache::migrate_legacy_entries(&self.config.cache_root);
+        if let Some(local) = self.overridden(src)?
+            && let Some(resolved) = pipeline::LocalLayer.resolve(&local, ctx)?
+        {
+            return Ok((
+                self.with_provenance(&local, src, resolved),
+                "env".to_string(),
+            ));
+        }
+        let src = self.pinned(src);
+        if let Some(spec) = src.service().and_then(|id| self.registry.get(id)) {
+            if let Some(provider) = &spec.provider
+                && let Some(resolved) = provider.resolve(src, ctx)?
+            {
+                return Ok((
+                    self.with_provenance(src, src, resolved),
+                    provider.name().to_string(),
+                ));
+            }
+            // The libc side is checked by the release layer once it knows which
+            // asset flavor it is about to use.
+            spec.requirements.check(&spec.id, ctx.platform, false)?;
+        }
+
+        let (resolved, layer) = self.provider.resolve_layered(src, ctx)?.ok_or_else(|| {
+            error::LocateError::Unresolved {
+                source_kind: src.kind(),
+            }
         })?;
-        if !md.is_file() {
-            return Err(InputError::NotFound { path: path.clone() }.into());
+        Ok((self.with_provenance(src, src, resolved), layer.to_string()))
+    }
+
+    /// Fills in what a provider left out of the [`Provenance`] of an
+    /// executable resolved from `resolved_from` for `asked`.
+    fn with_provenance(
+        &self,
+        resolved_from: &ArtifactSource,
+        asked: &ArtifactSource,
+        resolved: ResolvedArtifact,
+    ) -> ResolvedArtifact {
+        let ResolvedArtifact::Executable {
+            path,
+            mut provenance,
+        } = resolved
+        else {
+            return resolved;
+        };
+        if provenance.service.is_none() {
+            provenance.service = asked.service().cloned();
+        }
+        if provenance.source.is_empty() {
+            provenance.source = resolved_from.kind();
+        }
+        if let Some((key, meta)) = cache::entry_of(&self.config.cache_root, &path) {
+            provenance.version = provenance.version.or(meta.version_string);
+            provenance.commit = provenance.commit.or(meta.commit);
+            provenance.digest = provenance.digest.or(Some(meta.digest));
+            provenance.cache_key = provenance.cache_key.or(Some(key));
         }
-        #[cfg(unix)]
+        ResolvedArtifact::Executable { path, provenance }
+    }
+
+    /// What resolving `src` would do, found by taking only the read-only steps
+    /// of a resolution; see [`plan`].
+    ///
+    /// Fails where resolving would fail before doing any work, e.g. on a dirty
+    /// worktree, a platform without a release asset, or an asset URL that
+    /// doesn't answer.
+    pub fn plan(&self, src: &ArtifactSource) -> crate::error::Result<plan::Plan> {
+        let platform = self.config.platform();
+        let ctx = self.context(&platform, None, &self.observers);
+        let planned = |step, layer: &str| plan::Plan {
+            source: src.kind(),
+            platform: platform.clone(),
+            layer: layer.to_string(),
+            step,
+        };
+        if let Some(local) = self.overridden(src)?
+            && let Some(step) = pipeline::LocalLayer.plan(&local, &ctx)?
         {
-            use std::os::unix::fs::PermissionsExt;
-            if md.permissions().mode() & 0o111 == 0 {
-                return Err(InputError::NotExecutable { path: path.clone() }.into());
+            return Ok(planned(step, "env"));
+        }
+        let src = self.pinned(src);
+        if let Some(spec) = src.service().and_then(|id| self.registry.get(id)) {
+            if let Some(provider) = &spec.provider
+                && let Some(step) = provider.plan(src, &ctx)?
+            {
+                return Ok(planned(step, provider.name()));
             }
+            spec.requirements.check(&spec.id, &platform, false)?;
         }
 
-        Ok(ResolvedArtifact::Executable { path: path.clone() })
+        let (step, layer) = self.provider.plan_layered(src, &ctx)?.ok_or_else(|| {
+            error::LocateError::Unresolved {
+                source_kind: src.kind(),
+            }
+        })?;
+        Ok(planned(step, layer))
+    }
+
+    /// The `LocalPath` source standing in for `src`, if its service's binary
+    /// is [overridden](ResolverConfig::binary_override).
+    fn overridden(&self, src: &ArtifactSource) -> crate::error::Result<Option<ArtifactSource>> {
+        let Some(service) = src.service() else {
+            return Ok(None);
+        };
+        let Some(path) = self.config.binary_override(service) else {
+            return Ok(None);
+        };
+        if !path.is_file() {
+            return Err(error::InputError::InvalidConfig {
+                origin: format!("environment variable {}", override_var(service)),
+                reason: format!("{} is not a file", path.display()),
+            }
+            .into());
+        }
+        Ok(Some(ArtifactSource::LocalPath(path)))
     }
 
-    /// This methods does the following:
-    /// - Preflights git
-    /// - Identifies the tree state of the provided repository.
-    /// - Generates a cache key.
-    /// - If cache misses, builds.
-    ///     - TODO: EXPAND
-    /// - Returns the executable path.
-    fn resolve_local_build(
+    /// The [pin](ResolverConfig::pins) for `src`'s service, or `src`.
+    fn pinned<'a>(&'a self, src: &'a ArtifactSource) -> &'a ArtifactSource {
+        src.service()
+            .and_then(|id| self.config.pins.get(id))
+            .unwrap_or(src)
+    }
+
+    fn context<'a>(
+        &'a self,
+        platform: &'a str,
+        cancel: Option<&'a cancel::CancelToken>,
+        observers: &'a observe::Observers,
+    ) -> ResolveContext<'a> {
+        ResolveContext {
+            config: &self.config,
+            registry: &self.registry,
+            platform,
+            cancel,
+            observers,
+            limiter: &self.limiter,
+            checks: None,
+            checked: std::sync::Mutex::default(),
+            #[cfg(feature = "http")]
+            transport: &self.transport,
+        }
+    }
+
+    /// Resolves `src` and collects the service's companion binaries next to it.
+    ///
+    /// Companions are declared by [`registry::ToolSpec::companions`] and must sit
+    /// in the same directory as the service binary; missing ones are left out of
+    /// the bundle. Sources without a service
+    /// produce a bundle holding only the resolved executable.
+    pub fn resolve_bundle(&self, src: &ArtifactSource) -> crate::error::Result<ResolvedArtifact> {
+        let (path, provenance) = match self.resolve(src)? {
+            ResolvedArtifact::Executable { path, provenance } => (path, provenance),
+            other => return Ok(other),
+        };
+        let spec = src.service().and_then(|id| self.registry.get(id));
+
+        let primary = match spec {
+            Some(spec) => spec.id.as_str().to_string(),
+            None => path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        let mut executables = std::collections::BTreeMap::new();
+        if let Some(spec) = spec {
+            let dir = path.parent().unwrap_or(Path::new(""));
+            let platform = self.config.platform();
+            for (role, name) in &spec.companions {
+                let companion = dir.join(platform::exe_name(name, &platform));
+                if !companion.exists() {
+                    continue;
+                }
+                pipeline::check_executable(&companion)?;
+                executables.insert(role.clone(), companion);
+            }
+        }
+        let version = spec
+            .and_then(|spec| spec.version_probe.as_ref())
+            .and_then(|probe| probe.probe(&path))
+            .or(provenance.version);
+        let capabilities = spec
+            .and_then(|spec| spec.capability_probe.as_ref())
+            .map(|probe| probe.probe(&path));
+        executables.insert(primary, path);
+
+        Ok(ResolvedArtifact::Bundle {
+            executables,
+            provenance: Provenance {
+                version,
+                capabilities,
+                ..provenance
+            },
+        })
+    }
+
+    /// Runs the service's [`CapabilityProbe`] on the primary executable of
+    /// `resolved`; `None` if the service has no probe.
+    ///
+    /// Bundles already carry this in their [`Provenance`].
+    pub fn capabilities(
         &self,
-        service: &ServiceId,
-        repo: &Path,
-        refspec: Option<&str>,
-        policy: GitPolicy,
-        expected_output: Option<&Path>,
-    ) -> crate::error::Result<ResolvedArtifact> {
-        todo!()
-        // use crate::error::{BuildError, FsError};
-
-        // let refspec = refspec.unwrap_or("HEAD");
-        // let expected_output = expected_output.unwrap_or_else(|| Path::new("src/zcashd"));
-
-        // if !self.cfg.build_config.allow_build {
-        //     return Err(BuildError::DisabledFeature.into());
-        // }
-
-        // let commit = git_resolve_commit(repo, refspec)?; // -> String (full SHA)
-        // let dirty = git_is_dirty(repo)?; // -> bool
-        // let (allow_dirty, hash_untracked) = match policy {
-        //     GitPolicy::RequireClean => (false, false),
-        //     GitPolicy::AllowDirty { hash_untracked } => (true, hash_untracked),
-        // };
-        // if dirty && !allow_dirty {
-        //     return Err(BuildError::DirtyWorktree {
-        //         repo: repo.to_path_buf(),
-        //     }
-        //     .into());
-        // }
-        // let worktree_hash = if dirty && allow_dirty {
-        //     Some(hash_worktree(repo, hash_untracked)?) // -> String
-        // } else {
-        //     None
-        // };
-
-        // let host = detect_host_triple(self.cfg.platform_override.as_deref()); // "linux-x86_64" etc.
-        // let key = build_key(&commit, worktree_hash.as_deref(), &host); // "zcashd|<sha>[+h]|host|v1"
-        // let paths = cache_paths(&self.cfg.cache_root, "zcashd", &key); // {root, out, logs, meta}
-        // std::fs::create_dir_all(&paths.out).map_err(|e| FsError::Io {
-        //     context: format!("mkdir {}", paths.out.display()),
-        //     source: e,
-        // })?;
-        // std::fs::create_dir_all(&paths.logs).map_err(|e| FsError::Io {
-        //     context: format!("mkdir {}", paths.logs.display()),
-        //     source: e,
-        // })?;
-
-        // let out_bin = paths.out.join("zcashd");
-        // if looks_executable(&out_bin)? {
-        //     return Ok(ResolvedArtifact { path: out_bin });
-        // }
-
-        // preflight_tools(&[
-        //     "git",
-        //     "bash",
-        //     "make",
-        //     "gcc",
-        //     "g++",
-        //     "ar",
-        //     "ranlib",
-        //     "perl",
-        //     "autoconf",
-        //     "libtool",
-        //     "pkg-config",
-        // ])?;
-
-        // let _lock = acquire_lock(paths.root.join(".lock"))?; // drops on scope end
-
-        // // Re-check cache after lock (another thread/process may have built it)
-        // if looks_executable(&out_bin)? {
-        //     return Ok(ResolvedArtifact { path: out_bin });
-        // }
-
-        // let log_path = paths.logs.join(format!("build-{}.log", now_ts()));
-        // run_build_script(repo, jobs, &log_path)?; // wraps running ./zcutil/build.sh -j<jobs>
-
-        // let repo_bin = repo.join(expected_output);
-        // if !looks_executable(&repo_bin)? {
-        //     return Err(BuildError::MissingOutput { expected: repo_bin }.into());
-        // }
-
-        // atomic_copy(&repo_bin, &out_bin)?; // temp file + rename
-        // chmod_exec(&out_bin)?; // ensure +x
-
-        // let version_str = probe_version_string(&out_bin).ok();
-        // write_meta(
-        //     &paths.meta,
-        //     Meta {
-        //         service: "zcashd".into(),
-        //         source: "local-repo".into(),
-        //         repo: repo.to_path_buf(),
-        //         refspec: refspec.to_string(),
-        //         commit,
-        //         dirty,
-        //         worktree_hash,
-        //         jobs,
-        //         host,
-        //         built_at: now_ts(),
-        //         builder_schema: 1,
-        //         version_string: version_str,
-        //     },
-        // )?;
-
-        // Ok(ResolvedArtifact { path: out_bin })
+        src: &ArtifactSource,
+        resolved: &ResolvedArtifact,
+    ) -> Option<probe::Capabilities> {
+        if let ResolvedArtifact::Bundle { provenance, .. } = resolved
+            && provenance.capabilities.is_some()
+        {
+            return provenance.capabilities.clone();
+        }
+        let probe = src
+            .service()
+            .and_then(|id| self.registry.get(id))?
+            .capability_probe
+            .as_ref()?;
+        Some(probe.probe(resolved.primary_path()?))
+    }
+}
+
+/// Fails unless the version probed from `path`, with `spec`'s probe, meets `req`.
+fn check_version(
+    spec: Option<&registry::ToolSpec>,
+    path: &Path,
+    req: &semver::VersionReq,
+) -> crate::error::Result<()> {
+    use crate::error::VerifyError;
+
+    let probed = match spec.and_then(|spec| spec.version_probe.as_ref()) {
+        Some(probe) => probe.probe(path),
+        None => probe::RegexVersionProbe::semver().probe(path),
+    };
+    let Some(version) = probed.as_deref().and_then(probe::parse_semver) else {
+        return Err(VerifyError::VersionUnknown {
+            path: path.to_path_buf(),
+            expected: req.to_string(),
+        }
+        .into());
+    };
+    if !req.matches(&version) {
+        return Err(VerifyError::VersionMismatch {
+            path: path.to_path_buf(),
+            expected: req.to_string(),
+            actual: version.to_string(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Fails with the shared libraries any of the executables at `paths` can't load.
+fn check_libraries<'a>(paths: impl IntoIterator<Item = &'a Path>) -> crate::error::Result<()> {
+    for path in paths {
+        let missing = binfmt::missing_libraries(path)?;
+        if !missing.is_empty() {
+            return Err(error::VerifyError::MissingLibraries {
+                path: path.to_path_buf(),
+                missing,
+            }
+            .into());
+        }
     }
+    Ok(())
+}
+
+/// Everything a [`BuildRecipe`] needs to run one build.
+#[cfg(feature = "local-build")]
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct BuildInvocation<'a> {
+    pub repo: &'a Path,
+    pub jobs: usize,
+    /// File that stdout/stderr of the build must be written to.
+    pub log: &'a Path,
+    /// Extra environment for the build process.
+    pub env: &'a [(String, String)],
+    /// Extra arguments appended to the build command.
+    pub extra_args: &'a [String],
+    /// Rust target triple to cross-compile for; `None` builds for the host.
+    pub target: Option<&'a str>,
+    /// Lower the CPU and IO priority of the build; see [`BuildConfig::low_priority`].
+    pub low_priority: bool,
+    /// Sandbox to run the build in.
+    pub isolation: &'a BuildIsolation,
+    /// Where the build runs; recipes run their commands through
+    /// [`BuildExecutor::run`].
+    pub executor: &'a dyn BuildExecutor,
+    /// The resolution's [`ResolveOptions::cancel`]; executors kill the build
+    /// once it is cancelled.
+    pub cancel: Option<&'a cancel::CancelToken>,
 }
 
 /// How to build from a local repo.
 #[cfg(feature = "local-build")]
 pub trait BuildRecipe: Send + Sync + 'static {
     /// Run the build and return the repo-relative path to the binary (or absolute path).
-    fn build(
-        &self,
-        repo: &std::path::Path,
-        jobs: usize,
-        log: &std::path::Path,
-    ) -> crate::error::Result<std::path::PathBuf>;
+    fn build(&self, invocation: &BuildInvocation<'_>) -> crate::error::Result<std::path::PathBuf>;
+
+    /// Recipes that build a container image rather than a binary return
+    /// themselves here; [`pipeline::BuildLayer`] then calls
+    /// [`ImageRecipe::build_image`] instead of [`build`](Self::build).
+    #[cfg(feature = "oci")]
+    fn image(&self) -> Option<&dyn ImageRecipe> {
+        None
+    }
+}
+
+/// Where a build's commands run, e.g. on a remote builder; see [`executor`].
+#[cfg(feature = "local-build")]
+pub trait BuildExecutor: std::fmt::Debug + Send + Sync + 'static {
+    /// Runs `command` for `invocation`: in its repo, with its environment and
+    /// extra arguments, writing stdout and stderr to its log. Fails with
+    /// [`BuildError::ScriptFailed`](error::BuildError::ScriptFailed) if the
+    /// command does; [`BuildError::script_failed`](error::BuildError::script_failed)
+    /// reads the log's tail into it.
+    fn run(&self, command: std::process::Command, invocation: &BuildInvocation<'_>) -> Result<()>;
+
+    /// Makes the build outputs `names` in the repo-relative directory `dir`
+    /// available in the local worktree, skipping those the build didn't
+    /// produce. Executors running in the worktree have nothing to do.
+    fn fetch(&self, invocation: &BuildInvocation<'_>, dir: &Path, names: &[String]) -> Result<()> {
+        let _ = (invocation, dir, names);
+        Ok(())
+    }
+
+    /// Where builds run, as recorded in META (e.g. `ssh://builder`); `None`
+    /// for this machine.
+    fn location(&self) -> Option<String> {
+        None
+    }
+}
+
+/// How to build a local repo into a container image; see [`BuildRecipe::image`].
+#[cfg(all(feature = "local-build", feature = "oci"))]
+pub trait ImageRecipe: Send + Sync {
+    /// Builds the image and tags it `tag` in the runtime's store.
+    fn build_image(&self, invocation: &BuildInvocation<'_>, tag: &str) -> crate::error::Result<()>;
+
+    /// The runtime the image is built into, and looked up in on cache hits.
+    fn backend(&self) -> oci::OciBackend;
 }
 
 /// How to convert (service, version, platform) to a URL+checksum (post-MVP).
+///
+/// Static indexes implement [`asset_for`](Self::asset_for); those that fetch
+/// and check metadata first, like [`tuf::TufReleaseIndex`] (`tuf` feature),
+/// implement [`lookup`](Self::lookup) instead.
 #[cfg(feature = "http")]
 pub trait ReleaseIndex: Send + Sync + 'static {
-    fn asset_for(&self, version: &str, platform: &str) -> Option<(url::Url, String /* sha256 */)>;
+    /// `asset_name` is the spec's rendered [`registry::ToolSpec::asset_name`], if any.
+    /// `None` by default.
+    fn asset_for(
+        &self,
+        version: &str,
+        platform: &str,
+        asset_name: Option<&str>,
+    ) -> Option<(url::Url, String /* sha256 */)> {
+        let _ = (version, platform, asset_name);
+        None
+    }
+
+    /// Looks the asset up during a resolution, which may fetch through `ctx`
+    /// and fail; `Ok(None)` if the index has no such asset. Calls
+    /// [`asset_for`](Self::asset_for) by default.
+    fn lookup(
+        &self,
+        version: &str,
+        platform: &str,
+        asset_name: Option<&str>,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<Option<(url::Url, String /* sha256 */)>> {
+        let _ = ctx;
+        Ok(self.asset_for(version, platform, asset_name))
+    }
+}
+
+/// How to find out which flags and features a binary supports.
+pub trait CapabilityProbe: Send + Sync + 'static {
+    fn probe(&self, exe: &std::path::Path) -> probe::Capabilities;
 }
 
 /// How to extract a human-readable version string from a binary.
 pub trait VersionProbe: Send + Sync + 'static {
     fn probe(&self, exe: &std::path::Path) -> Option<String>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn provenance_shortens_commits_on_char_boundaries() {
+        let mut provenance = Provenance {
+            service: Some(registry::ZCASHD),
+            source: "local-repo",
+            commit: Some("0123456789abcdef".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            provenance.to_string(),
+            "zcashd from local-repo at 0123456789ab"
+        );
+        // Commits come from META, which need not hold a hex hash.
+        provenance.commit = Some("ééééééééééééé".into());
+        assert_eq!(
+            provenance.to_string(),
+            "zcashd from local-repo at éééééééééééé"
+        );
+        provenance.commit = Some("abc".into());
+        provenance.source = "";
+        assert_eq!(
+            provenance.to_string(),
+            "zcashd from an unknown source at abc"
+        );
+    }
+}
@@ -0,0 +1,13 @@
+use crate::registry::{Dependency, LIGHTWALLETD, ToolSpec, ZCASHD, ZEBRAD};
+
+pub fn spec_lightwalletd() -> ToolSpec {
+    let builder = ToolSpec::builder(LIGHTWALLETD)
+        .dependency(Dependency::OneOf(vec![ZCASHD, ZEBRAD]))
+        .binary_names(["lightwalletd"])
+        .expected_output("lightwalletd")
+        .ports([9067, 9068])
+        .ready_log("Starting gRPC server");
+    #[cfg(feature = "local-build")]
+    let builder = builder.build_recipe(crate::recipe::GoRecipe::new("lightwalletd"));
+    builder.finish()
+}
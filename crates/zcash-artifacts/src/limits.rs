@@ -0,0 +1,147 @@
+//! Limits on the work resolutions do at once.
+//!
+//! A resolver shares its [`ConcurrencyLimits`] between every resolution it
+//! runs, whether from [`ArtifactResolver::resolve_many`](crate::ArtifactResolver::resolve_many)
+//! or from threads sharing it, so that a matrix of sixteen services doesn't
+//! start sixteen `zcashd` builds at once:
+//!
+//! ```no_run
+//! use zcash_artifacts::{ResolverConfig, limits::ConcurrencyLimits};
+//!
+//! let config = ResolverConfig::builder()
+//!     .limits(ConcurrencyLimits {
+//!         downloads: Some(4),
+//!         builds: Some(1),
+//!         per_host: Some(2),
+//!     })
+//!     .finish();
+//! ```
+//!
+//! Downloads are release, package and bottle downloads, OCI blobs and
+//! container runtime pulls; the per-host limit also covers checksum lists and
+//! index lookups. Builds are local, remote, image and Nix builds. Resolutions
+//! over a limit wait for a slot, and can be cancelled while they do; see
+//! [`cancel`](crate::cancel).
+
+use std::{
+    collections::HashMap,
+    sync::{Condvar, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::{cancel::CancelToken, error::Result};
+
+/// How often a resolution waiting for a slot checks for cancellation.
+const POLL: Duration = Duration::from_millis(50);
+
+/// Maximum numbers of operations running at once; `None` is unlimited, which
+/// is the default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConcurrencyLimits {
+    /// Downloads, across hosts.
+    pub downloads: Option<usize>,
+    /// Builds, wherever they run.
+    pub builds: Option<usize>,
+    /// Requests to any one host (or registry), downloads included.
+    pub per_host: Option<usize>,
+}
+
+/// The slots of one resolver; see the [module docs](self).
+pub(crate) struct Limiter {
+    downloads: Slots,
+    builds: Slots,
+    hosts: Slots,
+}
+
+impl Limiter {
+    pub(crate) fn new(limits: &ConcurrencyLimits) -> Self {
+        Self {
+            downloads: Slots::new(limits.downloads),
+            builds: Slots::new(limits.builds),
+            hosts: Slots::new(limits.per_host),
+        }
+    }
+
+    /// Waits for a download slot, and one for `host`.
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    pub(crate) fn download(
+        &self,
+        host: &str,
+        cancel: Option<&CancelToken>,
+    ) -> Result<(Permit<'_>, Permit<'_>)> {
+        let download = self.downloads.acquire("", cancel)?;
+        Ok((download, self.request(host, cancel)?))
+    }
+
+    /// Waits for a slot for a request to `host`.
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    pub(crate) fn request(&self, host: &str, cancel: Option<&CancelToken>) -> Result<Permit<'_>> {
+        self.hosts.acquire(&host.to_ascii_lowercase(), cancel)
+    }
+
+    /// Waits for a build slot.
+    #[cfg_attr(not(feature = "local-build"), allow(dead_code))]
+    pub(crate) fn build(&self, cancel: Option<&CancelToken>) -> Result<Permit<'_>> {
+        self.builds.acquire("", cancel)
+    }
+}
+
+/// Counting semaphores, one per key.
+struct Slots {
+    limit: Option<usize>,
+    used: Mutex<HashMap<String, usize>>,
+    freed: Condvar,
+}
+
+impl Slots {
+    fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit,
+            used: Mutex::default(),
+            freed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, usize>> {
+        self.used.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn acquire(&self, key: &str, cancel: Option<&CancelToken>) -> Result<Permit<'_>> {
+        let Some(limit) = self.limit else {
+            return Ok(Permit(None));
+        };
+        let mut used = self.lock();
+        while used.get(key).copied().unwrap_or(0) >= limit.max(1) {
+            if let Some(cancel) = cancel {
+                cancel.check()?;
+            }
+            used = self
+                .freed
+                .wait_timeout(used, POLL)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        *used.entry(key.to_string()).or_default() += 1;
+        Ok(Permit(Some((self, key.to_string()))))
+    }
+}
+
+/// A slot, given back on drop.
+pub(crate) struct Permit<'a>(Option<(&'a Slots, String)>);
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let Some((slots, key)) = self.0.take() else {
+            return;
+        };
+        let mut used = slots.lock();
+        if let Some(count) = used.get_mut(&key) {
+            *count -= 1;
+            if *count == 0 {
+                used.remove(&key);
+            }
+        }
+        drop(used);
+        slots.freed.notify_all();
+    }
+}
@@ -0,0 +1,110 @@
+//! Just enough Mach-O parsing to inspect and thin universal ("fat") binaries.
+
+use std::{fs, path::Path};
+
+use crate::error::{FsError, Result, UnpackError};
+
+const FAT_MAGIC: u32 = 0xcafe_babe;
+const FAT_MAGIC_64: u32 = 0xcafe_babf;
+pub(crate) const CPU_TYPE_X86_64: u32 = 0x0100_0007;
+pub(crate) const CPU_TYPE_ARM64: u32 = 0x0100_000c;
+
+/// Canonical (OS-independent) architecture name of a Mach-O CPU type.
+pub(crate) fn arch_name(cpu: u32) -> Option<&'static str> {
+    match cpu {
+        CPU_TYPE_X86_64 => Some("x86_64"),
+        CPU_TYPE_ARM64 => Some("aarch64"),
+        _ => None,
+    }
+}
+
+/// One architecture slice of a universal binary.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Slice {
+    pub cpu: u32,
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// The slices of `bytes` if it is a universal binary.
+///
+/// Returns `None` for anything else, including Java class files, which share
+/// the magic, and for headers too truncated to read.
+pub(crate) fn fat_slices(bytes: &[u8]) -> Option<Vec<Slice>> {
+    let be32 = |at: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+    };
+    let be64 = |at: usize| -> Option<u64> {
+        Some(u64::from_be_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+    };
+    let magic = be32(0)?;
+    let count = be32(4)?;
+    let entry_len = match magic {
+        FAT_MAGIC => 20,
+        FAT_MAGIC_64 => 32,
+        _ => return None,
+    };
+    // Class files put their version here; real fat headers have a handful of slices.
+    if count == 0 || count > 16 {
+        return None;
+    }
+
+    (0..count as usize)
+        .map(|i| {
+            let at = 8 + i * entry_len;
+            let (offset, size) = if magic == FAT_MAGIC {
+                (u64::from(be32(at + 8)?), u64::from(be32(at + 12)?))
+            } else {
+                (be64(at + 8)?, be64(at + 16)?)
+            };
+            Some(Slice {
+                cpu: be32(at)?,
+                offset: offset as usize,
+                size: size as usize,
+            })
+        })
+        .collect()
+}
+
+/// Writes the `arch` slice of the universal binary at `src` to `dst`.
+///
+/// Returns `false`, writing nothing, if `src` is not a universal binary, and
+/// errors if it is one without an `arch` slice.
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub(crate) fn thin(src: &Path, arch: &str, dst: &Path) -> Result<bool> {
+    let bytes = fs::read(src).map_err(|e| FsError::Io {
+        context: format!("read {}", src.display()),
+        source: e,
+    })?;
+    let Some(slices) = fat_slices(&bytes) else {
+        return Ok(false);
+    };
+    let arch = crate::platform::Platform::parse(&format!("macos-{arch}"))
+        .map(|p| p.generic_arch().to_string())
+        .unwrap_or_else(|| arch.to_string());
+    let error = |reason: String| UnpackError::Entry {
+        archive: src.display().to_string(),
+        entry: format!("{arch} slice"),
+        source: reason.into(),
+    };
+    let Some(slice) = slices
+        .iter()
+        .find(|s| arch_name(s.cpu) == Some(arch.as_str()))
+    else {
+        let found: Vec<_> = slices
+            .iter()
+            .map(|s| arch_name(s.cpu).map_or_else(|| format!("{:#x}", s.cpu), str::to_string))
+            .collect();
+        return Err(error(format!("universal binary only has {}", found.join(", "))).into());
+    };
+    let bytes = slice
+        .offset
+        .checked_add(slice.size)
+        .and_then(|end| bytes.get(slice.offset..end))
+        .ok_or_else(|| error("slice extends past the end of the file".into()))?;
+    fs::write(dst, bytes).map_err(|e| FsError::Io {
+        context: format!("write {}", dst.display()),
+        source: e,
+    })?;
+    Ok(true)
+}
@@ -0,0 +1,213 @@
+//! Declarative service definitions loaded from TOML.
+//!
+//! A manifest describes one or more services without writing Rust:
+//!
+//! ```toml
+//! [services.zebrad]
+//! binary_names = ["zebrad"]
+//! expected_output = "target/release/zebrad"
+//! version_args = ["--version"]
+//! version_regex = 'zebrad (\S+)'
+//! archive_layout = ["zebrad-*/zebrad", "zebrad"]
+//! depends_on = ["zcashd|zebrad"]
+//! resources = ["zcash-params"]
+//! builder_schema = 1
+//! platforms = ["linux-x86_64", "linux-aarch64"]
+//! min_glibc = "2.31"
+//! asset_url = "https://example.org/v{version}/{asset}"
+//!
+//! [services.zebrad.asset_names]
+//! linux-x86_64 = "zebrad-{version}-x86_64-unknown-linux-gnu.tar.gz"
+//! linux-x86_64-musl = "zebrad-{version}-x86_64-unknown-linux-musl.tar.gz"
+//!
+//! [services.zebrad.companions]
+//! zebra-scanner = "zebra-scanner"
+//!
+//! [services.zebrad.platform_binary_names]
+//! windows-x86_64 = ["zebrad.exe"]
+//!
+//! [services.zebrad.checksums."2.0.0"]
+//! linux-x86_64 = "<sha256>"
+//! ```
+//!
+//! `asset_url` and `checksums` are only used when the `http` feature is enabled.
+//! Platform keys ending in `-musl` name musl builds, which musl hosts prefer.
+
+use std::{collections::HashMap, path::Path, path::PathBuf};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{
+    error::{FsError, InputError, Result},
+    platform::normalize,
+    probe::RegexVersionProbe,
+    registry::{Dependency, PlatformRequirements, Registry, ServiceId, ToolSpec},
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default)]
+    services: HashMap<String, ServiceEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ServiceEntry {
+    binary_names: Vec<String>,
+    #[serde(default)]
+    platform_binary_names: HashMap<String, Vec<String>>,
+    /// Defaults to the first binary name.
+    expected_output: Option<PathBuf>,
+    version_regex: Option<String>,
+    #[serde(default)]
+    archive_layout: Vec<String>,
+    #[serde(default)]
+    asset_names: HashMap<String, String>,
+    #[serde(default)]
+    companions: std::collections::BTreeMap<String, String>,
+    /// Service names; `a|b` means "one of a or b".
+    #[serde(default)]
+    depends_on: Vec<String>,
+    /// Non-service resources, e.g. `zcash-params`.
+    #[serde(default)]
+    resources: Vec<String>,
+    #[serde(default = "default_builder_schema")]
+    builder_schema: u32,
+    #[serde(default)]
+    platforms: Vec<String>,
+    min_glibc: Option<String>,
+    #[serde(default = "default_version_args")]
+    version_args: Vec<String>,
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    asset_url: Option<String>,
+    #[serde(default)]
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    checksums: HashMap<String, HashMap<String, String>>,
+}
+
+fn default_builder_schema() -> u32 {
+    1
+}
+
+fn default_version_args() -> Vec<String> {
+    vec!["--version".into()]
+}
+
+impl ServiceEntry {
+    fn into_spec(self, name: String) -> Result<ToolSpec> {
+        let invalid = |reason: String| InputError::InvalidManifest {
+            reason: format!("service `{name}`: {reason}"),
+        };
+
+        let Some(first) = self.binary_names.first() else {
+            return Err(invalid("`binary_names` must not be empty".into()).into());
+        };
+        let mut builder = ToolSpec::builder(ServiceId::new_owned(name.clone()))
+            .expected_output(self.expected_output.unwrap_or_else(|| PathBuf::from(first)))
+            .archive_layout(self.archive_layout)
+            .builder_schema(self.builder_schema);
+        let min_glibc = match self.min_glibc {
+            Some(v) => Some(
+                crate::platform::parse_version(&v)
+                    .ok_or_else(|| invalid(format!("bad `min_glibc` {v:?}")))?,
+            ),
+            None => None,
+        };
+        builder = builder.requirements(PlatformRequirements {
+            platforms: self.platforms.iter().map(|p| normalize(p)).collect(),
+            min_glibc,
+        });
+        for dep in self.depends_on {
+            let mut alternatives: Vec<ServiceId> = dep
+                .split('|')
+                .map(|s| ServiceId::new_owned(s.trim().to_string()))
+                .collect();
+            builder = builder.dependency(match alternatives.len() {
+                1 => Dependency::Service(alternatives.remove(0)),
+                _ => Dependency::OneOf(alternatives),
+            });
+        }
+        for resource in self.resources {
+            builder = builder.dependency(Dependency::Resource(resource));
+        }
+        for (role, file_name) in self.companions {
+            builder = builder.companion(role, file_name);
+        }
+        for (platform, pattern) in self.asset_names {
+            builder = builder.asset_name(platform, pattern);
+        }
+
+        if let Some(pattern) = self.version_regex {
+            let re =
+                Regex::new(&pattern).map_err(|e| invalid(format!("bad `version_regex`: {e}")))?;
+            builder = builder.version_probe(RegexVersionProbe::new(self.version_args, re));
+        }
+
+        #[cfg(feature = "http")]
+        if let Some(template) = self.asset_url {
+            let checksums = self
+                .checksums
+                .into_iter()
+                .map(|(version, by_platform)| {
+                    let by_platform = by_platform
+                        .into_iter()
+                        .map(|(platform, sha)| (normalize(&platform), sha))
+                        .collect();
+                    (version, by_platform)
+                })
+                .collect();
+            builder = builder.release_index(crate::release::TemplateReleaseIndex::new(
+                template, checksums,
+            ));
+        }
+
+        let default_names = self.binary_names;
+        let per_platform: HashMap<String, Vec<String>> = self
+            .platform_binary_names
+            .into_iter()
+            .map(|(platform, names)| (normalize(&platform), names))
+            .collect();
+        Ok(builder
+            .binary_names_fn(move |platform| {
+                per_platform
+                    .get(&normalize(platform))
+                    .unwrap_or(&default_names)
+                    .clone()
+            })
+            .finish())
+    }
+}
+
+impl Registry {
+    /// Loads a registry containing only the services declared in the TOML file at `path`.
+    pub fn from_manifest(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| FsError::Io {
+            context: format!("read {}", path.display()),
+            source: e,
+        })?;
+        Self::from_manifest_str(&text).map_err(|e| match e {
+            crate::ArtifactError::Input(InputError::InvalidManifest { reason }) => {
+                InputError::InvalidManifest {
+                    reason: format!("{}: {reason}", path.display()),
+                }
+                .into()
+            }
+            other => other,
+        })
+    }
+
+    /// Like [`Registry::from_manifest`], but parses an in-memory document.
+    pub fn from_manifest_str(text: &str) -> Result<Self> {
+        let manifest: Manifest = toml::from_str(text).map_err(|e| InputError::InvalidManifest {
+            reason: e.to_string(),
+        })?;
+
+        let mut registry = Registry::empty();
+        for (name, entry) in manifest.services {
+            registry = registry.register(entry.into_spec(name)?)?;
+        }
+        Ok(registry)
+    }
+}
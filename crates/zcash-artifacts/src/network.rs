@@ -0,0 +1,89 @@
+//! Which hosts resolution may reach.
+//!
+//! The policy is checked before every request the resolver makes itself:
+//! release and package downloads, checksum lists and index lookups, OCI
+//! registry calls (mirrors included) and container runtime pulls. Nix builds
+//! run with `--offline` under [`NetworkPolicy::Offline`]. Local builds are
+//! not confined; a build script fetching its dependencies does so regardless.
+
+use std::fmt;
+
+/// What the resolver may download from; see the [module docs](self).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum NetworkPolicy {
+    /// Any host.
+    #[default]
+    Online,
+    /// No host: only local files, cache hits and local builds resolve.
+    Offline,
+    /// Only these hosts and their subdomains, e.g. an internal mirror.
+    Hosts(Vec<String>),
+}
+
+impl fmt::Display for NetworkPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkPolicy::Online => f.write_str("online"),
+            NetworkPolicy::Offline => f.write_str("offline"),
+            NetworkPolicy::Hosts(hosts) => write!(f, "hosts {}", hosts.join(", ")),
+        }
+    }
+}
+
+impl NetworkPolicy {
+    /// Whether `host` may be reached. Hosts compare case-insensitively and
+    /// without their port.
+    pub fn allows(&self, host: &str) -> bool {
+        match self {
+            NetworkPolicy::Online => true,
+            NetworkPolicy::Offline => false,
+            NetworkPolicy::Hosts(hosts) => {
+                let host = strip_port(host).to_ascii_lowercase();
+                hosts.iter().any(|allowed| {
+                    let allowed = strip_port(allowed).to_ascii_lowercase();
+                    host == allowed
+                        || host
+                            .strip_suffix(allowed.as_str())
+                            .is_some_and(|sub| sub.ends_with('.'))
+                })
+            }
+        }
+    }
+
+    /// Fails with [`FetchError::Denied`](crate::error::FetchError::Denied)
+    /// unless `url`'s host may be reached.
+    #[cfg(feature = "http")]
+    pub(crate) fn check(&self, url: &url::Url) -> crate::Result<()> {
+        self.check_host(url.host_str().unwrap_or_default(), || {
+            crate::credentials::redact(url)
+        })
+    }
+
+    /// Like [`check`](Self::check), for requests made by other programs;
+    /// `target` names what would be fetched.
+    #[cfg(feature = "http")]
+    pub(crate) fn check_host(
+        &self,
+        host: &str,
+        target: impl FnOnce() -> String,
+    ) -> crate::Result<()> {
+        if self.allows(host) {
+            return Ok(());
+        }
+        Err(crate::error::FetchError::Denied {
+            url: target(),
+            policy: self.to_string(),
+        }
+        .into())
+    }
+}
+
+fn strip_port(host: &str) -> &str {
+    // Bracketed IPv6 literals keep their colons.
+    match host.rsplit_once(':') {
+        Some((name, port)) if !name.ends_with(':') && port.bytes().all(|b| b.is_ascii_digit()) => {
+            name
+        }
+        _ => host,
+    }
+}
@@ -0,0 +1,211 @@
+//! Binaries built by [Nix](https://nixos.org) from a flake.
+//!
+//! Infra that pins its node binaries with a flake can resolve them from the
+//! same flake: [`ArtifactSource::Nix`](crate::ArtifactSource::Nix) runs
+//!
+//! ```text
+//! nix build --no-link --json <flake_ref>#<attr>
+//! ```
+//!
+//! which builds the output, or substitutes it from a binary cache, and prints
+//! its store path, e.g. `/nix/store/<hash>-zebrad-2.0.0`. The binary is taken
+//! from the path's `bin/` (that of the `bin` output for packages split into
+//! several): the one named like the attribute's last component, e.g. `zebrad`
+//! for `packages.x86_64-linux.zebrad`, or else the only one there.
+//!
+//! It is cached as a regular executable entry under `nix/`, keyed by the store
+//! path's hash, so a flake that still evaluates to the same path is a cache hit
+//! without copying anything. Binaries in the store usually link against other
+//! store paths, so the entry holds a GC root for the store path in
+//! `meta/gcroot`, keeping `nix-collect-garbage` from removing what the cached
+//! binary needs. META records the flake output in `flake` and the store path
+//! in `store_path`.
+//!
+//! Nix evaluates the flake every time, as only it knows which store path the
+//! output currently is; use a locked flake reference (a `rev` or `narHash`)
+//! for resolutions that work offline, and under
+//! [`NetworkPolicy::Offline`](crate::network::NetworkPolicy::Offline), which
+//! passes `--offline`. Outputs are built for the system being
+//! resolved for (`--system`), which Nix may need a remote builder or emulation
+//! for when it isn't the host's. The `nix-command` and `flakes` experimental
+//! features are enabled for the command, so the host's Nix config needn't.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    ResolveContext, ResolvedArtifact, binfmt,
+    cache::{self, CacheKey, CachePaths},
+    cancel::Phase,
+    error::{BuildError, Result},
+    network::NetworkPolicy,
+};
+
+/// An entry of `nix build --json`'s output.
+#[derive(Debug, Deserialize)]
+struct Built {
+    outputs: BTreeMap<String, PathBuf>,
+}
+
+/// Builds `<flake_ref>#<attr>` and caches its binary; see the
+/// [module docs](self).
+pub(crate) fn build(
+    ctx: &ResolveContext<'_>,
+    flake_ref: &str,
+    attr: &str,
+) -> Result<ResolvedArtifact> {
+    let installable = format!("{flake_ref}#{attr}");
+    let mut args = vec!["build", "--no-link", "--json"];
+    if ctx.config.network == NetworkPolicy::Offline {
+        args.push("--offline");
+    }
+    let system = system(ctx.platform);
+    if !ctx.targets_host()
+        && let Some(system) = &system
+    {
+        args.extend(["--system", system]);
+    }
+    args.push(&installable);
+    let _slot = ctx.limiter.build(ctx.cancel)?;
+    ctx.enter(Phase::Build);
+    let stdout = nix(&args)?;
+    let unexpected = |reason: &str| BuildError::Nix {
+        args: args.join(" "),
+        stderr: format!("{reason}: {}", String::from_utf8_lossy(&stdout).trim()),
+    };
+    let built: Vec<Built> =
+        serde_json::from_slice(&stdout).map_err(|_| unexpected("unexpected output"))?;
+    let mut outputs = built
+        .into_iter()
+        .next()
+        .ok_or_else(|| unexpected("nothing built"))?
+        .outputs;
+    let store_path = ["bin", "out"]
+        .iter()
+        .find_map(|name| outputs.remove(*name))
+        .or_else(|| outputs.into_values().next())
+        .ok_or_else(|| unexpected("no outputs"))?;
+    let hash = store_path
+        .file_name()
+        .and_then(|name| name.to_str()?.split_once('-'))
+        .map(|(hash, _)| hash.to_string())
+        .ok_or_else(|| unexpected("not a store path"))?;
+
+    let (bin_name, binary) = binary(&store_path, attr, ctx.platform)?;
+    let key = CacheKey {
+        service: "nix".into(),
+        revision: hash,
+        worktree_hash: None,
+        platform: ctx.platform.to_string(),
+        schema: 1,
+    };
+    let paths = CachePaths::new(&ctx.config.cache_root, &key)?;
+    let out_bin = paths.out.join(&bin_name);
+    if cache::usable_entry(ctx, &paths, &out_bin, ctx.platform)? {
+        return Ok(ResolvedArtifact::executable(out_bin));
+    }
+    binfmt::check(&binary, ctx.platform)?;
+    paths.create_dirs()?;
+    let _lock = paths.lock(ctx)?; // released on drop
+    if cache::usable_entry(ctx, &paths, &out_bin, ctx.platform)? {
+        return Ok(ResolvedArtifact::executable(out_bin));
+    }
+
+    // Building a store path that exists only adds the link, registered as a GC root.
+    let gcroot = paths.meta.join("gcroot");
+    let gcroot_arg = gcroot.to_string_lossy();
+    let store_arg = store_path.to_string_lossy();
+    nix(&["build", "--out-link", &gcroot_arg, &store_arg])?;
+
+    let path = cache::finalize(
+        ctx,
+        &paths,
+        &bin_name,
+        &binary,
+        &[],
+        None,
+        false,
+        cache::Meta {
+            service: "nix".into(),
+            source: "nix".into(),
+            flake: Some(installable),
+            store_path: Some(store_path),
+            host: crate::platform::host(),
+            platform: ctx.platform.to_string(),
+            builder_schema: 1,
+            ..Default::default()
+        },
+    )?;
+    Ok(ResolvedArtifact::executable(path))
+}
+
+/// The binary in `<store_path>/bin` that `attr` names, as (file name, path).
+fn binary(store_path: &Path, attr: &str, platform: &str) -> Result<(String, PathBuf)> {
+    let bin = store_path.join("bin");
+    let wanted = crate::platform::exe_name(attr.rsplit('.').next().unwrap_or(attr), platform);
+    if cache::looks_executable(&bin.join(&wanted)) {
+        return Ok((wanted.clone(), bin.join(wanted)));
+    }
+    let mut found: Vec<(String, PathBuf)> = std::fs::read_dir(&bin)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| {
+            (
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.path(),
+            )
+        })
+        .filter(|(_, path)| cache::looks_executable(path))
+        .collect();
+    match found.len() {
+        1 => Ok(found.remove(0)),
+        _ => Err(BuildError::MissingOutput {
+            expected: bin.join(wanted),
+        }
+        .into()),
+    }
+}
+
+/// The Nix system string of `platform`, e.g. `aarch64-darwin` for `macos-arm64`.
+fn system(platform: &str) -> Option<String> {
+    let platform = crate::platform::Platform::parse(platform)?;
+    let os = match platform.os() {
+        "macos" => "darwin",
+        os => os,
+    };
+    Some(format!("{}-{os}", platform.generic_arch()))
+}
+
+/// Runs `nix` with `args`, returning its stdout.
+fn nix(args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("nix")
+        .args(["--extra-experimental-features", "nix-command flakes"])
+        .args(args)
+        .output()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => BuildError::PreflightMissingTools {
+                missing: "nix".into(),
+            },
+            _ => BuildError::Nix {
+                args: args.join(" "),
+                stderr: e.to_string(),
+            },
+        })?;
+    if !output.status.success() {
+        // Nix prints the build log's tail and the error last.
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let lines: Vec<&str> = stderr.trim().lines().collect();
+        return Err(BuildError::Nix {
+            args: args.join(" "),
+            stderr: lines[lines.len().saturating_sub(10)..].join("\n"),
+        }
+        .into());
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,125 @@
+//! An async facade over [`ArtifactResolver`], behind the `async` feature.
+//!
+//! The resolution pipeline is the same for both facades; only its HTTP client
+//! differs, see [`HttpTransport`](crate::transport::HttpTransport). The
+//! `blocking` feature (on by default) brings reqwest's blocking client; async
+//! harnesses can turn default features off and enable `async` alone, so the
+//! pipeline downloads with reqwest's async client on their runtime instead.
+//!
+//! Resolution still blocks its thread: it spawns git, build scripts and
+//! container runtimes, and waits on cache locks and downloads.
+//! [`AsyncResolver`] runs each call on Tokio's blocking thread pool, so async
+//! harnesses can await resolutions without stalling their executor:
+//!
+//! ```no_run
+//! # async fn run() -> zcash_artifacts::Result<()> {
+//! use zcash_artifacts::{
+//!     ArtifactResolver, ArtifactSource, ResolverConfig, nonblocking::AsyncResolver,
+//!     registry::ZEBRAD,
+//! };
+//!
+//! let resolver = AsyncResolver::new(ArtifactResolver::new(ResolverConfig::from_env()?));
+//! let zebrad = resolver
+//!     .resolve(ArtifactSource::Release {
+//!         service: ZEBRAD,
+//!         version: "2.0.0".into(),
+//!     })
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Calls must be made from within a Tokio runtime. Dropping a future doesn't
+//! stop the resolution it started; it runs to completion in the background
+//! unless cancelled through [`ResolveOptions::cancel`].
+
+use std::sync::Arc;
+
+use crate::{
+    ArtifactResolver, ArtifactSource, ResolveOptions, ResolvedArtifact, error::Result, observe,
+    plan,
+};
+
+/// A shared [`ArtifactResolver`] with async methods; cloning it is cheap.
+#[derive(Clone)]
+pub struct AsyncResolver {
+    inner: Arc<ArtifactResolver>,
+}
+
+impl From<Arc<ArtifactResolver>> for AsyncResolver {
+    fn from(inner: Arc<ArtifactResolver>) -> Self {
+        Self { inner }
+    }
+}
+
+impl AsyncResolver {
+    pub fn new(resolver: ArtifactResolver) -> Self {
+        Arc::new(resolver).into()
+    }
+
+    /// The resolver, for the blocking methods this facade doesn't wrap; call
+    /// them through [`run`](Self::run) from async code.
+    pub fn blocking(&self) -> &Arc<ArtifactResolver> {
+        &self.inner
+    }
+
+    /// Runs `f` on Tokio's blocking thread pool. Panics in `f` are resumed
+    /// in the caller.
+    pub async fn run<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&ArtifactResolver) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let inner = self.inner.clone();
+        match tokio::task::spawn_blocking(move || f(&inner)).await {
+            Ok(value) => value,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    /// See [`ArtifactResolver::resolve`].
+    pub async fn resolve(&self, src: ArtifactSource) -> Result<ResolvedArtifact> {
+        self.run(move |resolver| resolver.resolve(&src)).await
+    }
+
+    /// See [`ArtifactResolver::resolve_with`].
+    pub async fn resolve_with(
+        &self,
+        src: ArtifactSource,
+        opts: ResolveOptions,
+    ) -> Result<ResolvedArtifact> {
+        self.run(move |resolver| resolver.resolve_with(&src, &opts))
+            .await
+    }
+
+    /// See [`ArtifactResolver::resolve_traced`].
+    pub async fn resolve_traced(
+        &self,
+        src: ArtifactSource,
+        opts: ResolveOptions,
+    ) -> (Result<ResolvedArtifact>, observe::ResolutionTrace) {
+        self.run(move |resolver| resolver.resolve_traced(&src, &opts))
+            .await
+    }
+
+    /// See [`ArtifactResolver::resolve_many`].
+    pub async fn resolve_many(
+        &self,
+        sources: Vec<ArtifactSource>,
+        opts: ResolveOptions,
+    ) -> Vec<Result<ResolvedArtifact>> {
+        self.run(move |resolver| resolver.resolve_many(&sources, &opts))
+            .await
+    }
+
+    /// See [`ArtifactResolver::resolve_bundle`].
+    pub async fn resolve_bundle(&self, src: ArtifactSource) -> Result<ResolvedArtifact> {
+        self.run(move |resolver| resolver.resolve_bundle(&src))
+            .await
+    }
+
+    /// See [`ArtifactResolver::plan`].
+    pub async fn plan(&self, src: ArtifactSource) -> Result<plan::Plan> {
+        self.run(move |resolver| resolver.plan(&src)).await
+    }
+}
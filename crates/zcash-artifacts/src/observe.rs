@@ -0,0 +1,823 @@
+//! Structured events for dashboards and custom reporting.
+//!
+//! Register an [`Observer`] with
+//! [`ArtifactResolver::observe`](crate::ArtifactResolver::observe) to be told
+//! what each resolution does as it happens: whether the cache had it, what was
+//! downloaded and built, and how long that took. Unlike the `tracing` spans
+//! (see the `tracing` feature), events don't depend on any logging framework.
+//!
+//! ```no_run
+//! use std::sync::atomic::{AtomicU64, Ordering};
+//!
+//! use zcash_artifacts::{ArtifactResolver, observe::Event};
+//!
+//! # fn demo(resolver: &mut ArtifactResolver) {
+//! static DOWNLOADED: AtomicU64 = AtomicU64::new(0);
+//! resolver.observe(|event: &Event<'_>| {
+//!     if let Event::DownloadFinished { bytes, .. } = event {
+//!         DOWNLOADED.fetch_add(*bytes, Ordering::Relaxed);
+//!     }
+//! });
+//! # }
+//! ```
+//!
+//! Observers run synchronously on the resolving thread, in the order they
+//! were registered, so they should return quickly.
+//!
+//! For the events of a single resolution, e.g. to attach to a bug report,
+//! [`ArtifactResolver::resolve_traced`](crate::ArtifactResolver::resolve_traced)
+//! collects them into a [`ResolutionTrace`].
+//!
+//! With the `cli-progress` feature, [`ProgressBars`] renders downloads and
+//! builds as terminal progress bars, for command-line tools.
+//!
+//! Unless [`ResolverConfig::log`](crate::ResolverConfig::log) is turned off,
+//! every resolver also appends its events to a [`LogFile`] under the cache
+//! root, for postmortems on CI runners whose harness swallowed stderr.
+//!
+//! With the `metrics` feature, every resolver also feeds [`MetricsObserver`]'s
+//! counters and histograms to the [`metrics`](https://docs.rs/metrics)
+//! recorder, for services embedding the resolver to export, e.g. with
+//! `metrics-exporter-prometheus`.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{
+    ArtifactError, ArtifactSource, ResolvedArtifact, error::VerifyError, registry::ServiceId,
+    warning::Warning,
+};
+
+/// Receives the resolver's [`Event`]s. Closures taking `&Event` implement it too.
+pub trait Observer: Send + Sync + 'static {
+    fn on_event(&self, event: &Event<'_>);
+}
+
+impl<F> Observer for F
+where
+    F: Fn(&Event<'_>) + Send + Sync + 'static,
+{
+    fn on_event(&self, event: &Event<'_>) {
+        self(event)
+    }
+}
+
+/// Something the resolver did. More kinds may be added.
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// [`ArtifactResolver::resolve`](crate::ArtifactResolver::resolve) (or one
+    /// of its variants) was called.
+    ResolveStarted {
+        source: &'a ArtifactSource,
+        platform: &'a str,
+    },
+    /// The source resolved, and passed the checks asked for.
+    Resolved {
+        source: &'a ArtifactSource,
+        /// Name of the provider layer, or the service's own provider, that
+        /// resolved it.
+        layer: &'a str,
+        artifact: &'a ResolvedArtifact,
+        elapsed: Duration,
+    },
+    /// The source didn't resolve, or failed a check.
+    ResolveFailed {
+        source: &'a ArtifactSource,
+        error: &'a ArtifactError,
+        elapsed: Duration,
+    },
+    /// The cache had a finished entry for the source.
+    CacheHit {
+        source: &'a ArtifactSource,
+        artifact: &'a ResolvedArtifact,
+    },
+    /// The cache had no finished entry for a source it keeps entries for.
+    CacheMiss {
+        source: &'a ArtifactSource,
+        /// The entry it looked in, for releases, URLs and builds; see the
+        /// [cache docs](crate::cache) for how keys are derived.
+        entry: Option<&'a Path>,
+    },
+    /// A download of a release asset, package or image blob began; `url` is
+    /// without credentials. Small files read whole, like checksum lists and
+    /// API responses, aren't reported.
+    DownloadStarted {
+        url: &'a str,
+        /// As announced by the server.
+        size: Option<u64>,
+    },
+    /// Another chunk of a download arrived; `bytes` have been transferred so far.
+    DownloadProgress {
+        url: &'a str,
+        bytes: u64,
+        size: Option<u64>,
+    },
+    /// A download ended, having transferred `bytes`; `success` is false if it
+    /// was cut off or didn't match its checksum.
+    DownloadFinished {
+        url: &'a str,
+        bytes: u64,
+        elapsed: Duration,
+        success: bool,
+    },
+    /// A build began, into the cache entry at `entry`.
+    BuildStarted {
+        service: &'a ServiceId,
+        entry: &'a Path,
+        jobs: usize,
+    },
+    /// A build's command ended; the output is checked and cached afterwards.
+    BuildFinished {
+        service: &'a ServiceId,
+        entry: &'a Path,
+        elapsed: Duration,
+        success: bool,
+    },
+    /// A resolution failed a check: a checksum, signature, version or the
+    /// binary itself. Followed by [`Event::ResolveFailed`].
+    VerificationFailed {
+        source: &'a ArtifactSource,
+        error: &'a VerifyError,
+    },
+    /// A resolution had to wait for another one, in this process or another,
+    /// to release the lock of the cache entry at `entry`.
+    LockWaited { entry: &'a Path, waited: Duration },
+    /// The resolution did something allowed but worth auditing; see
+    /// [`warning`](crate::warning).
+    Warning { warning: &'a Warning },
+}
+
+/// The observers registered with a resolver.
+#[derive(Clone, Default)]
+pub(crate) struct Observers(Vec<Arc<dyn Observer>>);
+
+impl Observers {
+    /// No observers, apart from [`MetricsObserver`] with the `metrics` feature.
+    pub(crate) fn new() -> Self {
+        #[cfg(feature = "metrics")]
+        return Self(vec![Arc::new(MetricsObserver::new())]);
+        #[cfg(not(feature = "metrics"))]
+        Self::default()
+    }
+
+    pub(crate) fn push(&mut self, observer: impl Observer) {
+        self.0.push(Arc::new(observer));
+    }
+
+    pub(crate) fn push_shared(&mut self, observer: Arc<dyn Observer>) {
+        self.0.push(observer);
+    }
+
+    pub(crate) fn emit(&self, event: Event<'_>) {
+        for observer in &self.0 {
+            observer.on_event(&event);
+        }
+    }
+}
+
+/// Records events as [`metrics`](https://docs.rs/metrics) counters and
+/// histograms (durations in seconds), all prefixed `zcash_artifacts_`:
+///
+/// | metric | kind | labels |
+/// |--------|------|--------|
+/// | `resolutions_total` | counter | `source`, `outcome` (`ok` or `error`), `layer` |
+/// | `resolve_duration_seconds` | histogram | `source` |
+/// | `cache_hits_total`, `cache_misses_total` | counter | `source` |
+/// | `verification_failures_total` | counter | `source` |
+/// | `downloads_total` | counter | `outcome` |
+/// | `download_bytes_total` | counter | |
+/// | `download_duration_seconds` | histogram | |
+/// | `builds_total` | counter | `service`, `outcome` |
+/// | `build_duration_seconds` | histogram | `service` |
+/// | `lock_wait_seconds` | histogram | |
+/// | `warnings_total` | counter | `kind` |
+///
+/// `source` is the source's [kind](ArtifactSource::kind). The hit ratio is
+/// `cache_hits_total / (cache_hits_total + cache_misses_total)`.
+///
+/// Every resolver has one registered; the metrics go nowhere until the
+/// application installs a recorder.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default)]
+pub struct MetricsObserver;
+
+#[cfg(feature = "metrics")]
+impl MetricsObserver {
+    /// Describes the metrics to the installed recorder.
+    pub fn new() -> Self {
+        use metrics::{Unit, describe_counter, describe_histogram};
+
+        describe_counter!(
+            "zcash_artifacts_resolutions_total",
+            "Resolutions, by source kind, outcome and resolving layer"
+        );
+        describe_histogram!(
+            "zcash_artifacts_resolve_duration_seconds",
+            Unit::Seconds,
+            "Time spent resolving, checks included"
+        );
+        describe_counter!(
+            "zcash_artifacts_cache_hits_total",
+            "Resolutions the cache had a finished entry for"
+        );
+        describe_counter!(
+            "zcash_artifacts_cache_misses_total",
+            "Resolutions the cache had no finished entry for"
+        );
+        describe_counter!(
+            "zcash_artifacts_verification_failures_total",
+            "Resolutions that failed a checksum, signature, version or binary check"
+        );
+        describe_counter!("zcash_artifacts_downloads_total", "Downloads, by outcome");
+        describe_counter!(
+            "zcash_artifacts_download_bytes_total",
+            Unit::Bytes,
+            "Bytes downloaded"
+        );
+        describe_histogram!(
+            "zcash_artifacts_download_duration_seconds",
+            Unit::Seconds,
+            "Time spent downloading"
+        );
+        describe_counter!(
+            "zcash_artifacts_builds_total",
+            "Builds, by service and outcome"
+        );
+        describe_histogram!(
+            "zcash_artifacts_build_duration_seconds",
+            Unit::Seconds,
+            "Time spent in build commands"
+        );
+        describe_histogram!(
+            "zcash_artifacts_lock_wait_seconds",
+            Unit::Seconds,
+            "Time spent waiting for other resolutions' cache entry locks"
+        );
+        describe_counter!(
+            "zcash_artifacts_warnings_total",
+            "Warnings, by kind; see `warning::Warning`"
+        );
+        Self
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Observer for MetricsObserver {
+    fn on_event(&self, event: &Event<'_>) {
+        use metrics::{counter, histogram};
+
+        let outcome = |success: bool| if success { "ok" } else { "error" };
+        match event {
+            Event::Resolved {
+                source,
+                layer,
+                elapsed,
+                ..
+            } => {
+                counter!(
+                    "zcash_artifacts_resolutions_total",
+                    "source" => source.kind(),
+                    "outcome" => "ok",
+                    "layer" => layer.to_string(),
+                )
+                .increment(1);
+                histogram!("zcash_artifacts_resolve_duration_seconds", "source" => source.kind())
+                    .record(elapsed.as_secs_f64());
+            }
+            Event::ResolveFailed {
+                source, elapsed, ..
+            } => {
+                counter!(
+                    "zcash_artifacts_resolutions_total",
+                    "source" => source.kind(),
+                    "outcome" => "error",
+                    "layer" => "",
+                )
+                .increment(1);
+                histogram!("zcash_artifacts_resolve_duration_seconds", "source" => source.kind())
+                    .record(elapsed.as_secs_f64());
+            }
+            Event::CacheHit { source, .. } => {
+                counter!("zcash_artifacts_cache_hits_total", "source" => source.kind())
+                    .increment(1);
+            }
+            Event::CacheMiss { source, .. } => {
+                counter!("zcash_artifacts_cache_misses_total", "source" => source.kind())
+                    .increment(1);
+            }
+            Event::VerificationFailed { source, .. } => {
+                counter!("zcash_artifacts_verification_failures_total", "source" => source.kind())
+                    .increment(1);
+            }
+            Event::DownloadFinished {
+                bytes,
+                elapsed,
+                success,
+                ..
+            } => {
+                counter!("zcash_artifacts_downloads_total", "outcome" => outcome(*success))
+                    .increment(1);
+                counter!("zcash_artifacts_download_bytes_total").increment(*bytes);
+                histogram!("zcash_artifacts_download_duration_seconds")
+                    .record(elapsed.as_secs_f64());
+            }
+            Event::BuildFinished {
+                service,
+                elapsed,
+                success,
+                ..
+            } => {
+                let service = service.as_str().to_string();
+                counter!(
+                    "zcash_artifacts_builds_total",
+                    "service" => service.clone(),
+                    "outcome" => outcome(*success),
+                )
+                .increment(1);
+                histogram!("zcash_artifacts_build_duration_seconds", "service" => service)
+                    .record(elapsed.as_secs_f64());
+            }
+            Event::LockWaited { waited, .. } => {
+                histogram!("zcash_artifacts_lock_wait_seconds").record(waited.as_secs_f64());
+            }
+            Event::Warning { warning } => {
+                counter!("zcash_artifacts_warnings_total", "kind" => warning.kind()).increment(1);
+            }
+            Event::ResolveStarted { .. }
+            | Event::DownloadStarted { .. }
+            | Event::DownloadProgress { .. }
+            | Event::BuildStarted { .. } => {}
+        }
+    }
+}
+
+/// Draws a progress bar on stderr for each download and a spinner for each
+/// build, removed once they finish; failed ones stay, marked as failed.
+/// Nothing is drawn when stderr isn't a terminal.
+///
+/// ```no_run
+/// # fn demo(resolver: &mut zcash_artifacts::ArtifactResolver) {
+/// resolver.observe(zcash_artifacts::observe::ProgressBars::new());
+/// # }
+/// ```
+#[cfg(feature = "cli-progress")]
+#[derive(Debug, Default)]
+pub struct ProgressBars {
+    bars: indicatif::MultiProgress,
+    /// Bars in flight, by URL or entry.
+    active: Mutex<std::collections::HashMap<String, indicatif::ProgressBar>>,
+}
+
+#[cfg(feature = "cli-progress")]
+impl ProgressBars {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn start(&self, key: String, bar: indicatif::ProgressBar) {
+        let bar = self.bars.add(bar);
+        self.active.lock().unwrap().insert(key, bar);
+    }
+
+    fn finish(&self, key: &str, success: bool) {
+        let Some(bar) = self.active.lock().unwrap().remove(key) else {
+            return;
+        };
+        if success {
+            bar.finish_and_clear();
+        } else {
+            let message = format!("{} (failed)", bar.message());
+            bar.abandon_with_message(message);
+        }
+    }
+}
+
+#[cfg(feature = "cli-progress")]
+impl Observer for ProgressBars {
+    fn on_event(&self, event: &Event<'_>) {
+        use indicatif::{ProgressBar, ProgressStyle};
+
+        match event {
+            Event::DownloadStarted { url, size } => {
+                let name = url.rsplit('/').find(|s| !s.is_empty()).unwrap_or(url);
+                let bar = match size {
+                    Some(size) => ProgressBar::new(*size).with_style(
+                        ProgressStyle::with_template(
+                            "{msg} [{bar:30}] {bytes}/{total_bytes} {bytes_per_sec} {eta}",
+                        )
+                        .unwrap()
+                        .progress_chars("=> "),
+                    ),
+                    None => ProgressBar::new_spinner().with_style(
+                        ProgressStyle::with_template("{spinner} {msg} {bytes} {bytes_per_sec}")
+                            .unwrap(),
+                    ),
+                };
+                bar.set_message(format!("downloading {name}"));
+                self.start(url.to_string(), bar);
+            }
+            Event::DownloadProgress { url, bytes, .. } => {
+                if let Some(bar) = self.active.lock().unwrap().get(*url) {
+                    bar.set_position(*bytes);
+                }
+            }
+            Event::DownloadFinished { url, success, .. } => self.finish(url, *success),
+            Event::BuildStarted { service, entry, .. } => {
+                let bar = ProgressBar::new_spinner()
+                    .with_style(ProgressStyle::with_template("{spinner} {msg} {elapsed}").unwrap());
+                bar.set_message(format!("building {}", service.as_str()));
+                bar.enable_steady_tick(Duration::from_millis(100));
+                self.start(entry.display().to_string(), bar);
+            }
+            Event::BuildFinished { entry, success, .. } => {
+                self.finish(&entry.display().to_string(), *success)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Appends events to a log of JSON lines, rotated once it grows past 4 MiB;
+/// [`in_cache_root`](Self::in_cache_root) is the one every resolver writes,
+/// unless [`ResolverConfig::log`](crate::ResolverConfig::log) is off.
+///
+/// Each line has the time (`ts`, UTC), the `pid` and `thread` of the
+/// resolution, the `event` in kebab-case and its fields, durations in seconds:
+///
+/// ```text
+/// {"ts":"2025-09-29T14:21:03Z","pid":4242,"thread":"ThreadId(1)","event":"cache-miss","source":"release","service":"zebrad","entry":"…"}
+/// {"ts":"2025-09-29T14:21:09Z","pid":4242,"thread":"ThreadId(1)","event":"resolved","source":"release","service":"zebrad","layer":"release","elapsed":6.2,"artifact":{…}}
+/// ```
+///
+/// Download progress isn't logged, and URLs are without credentials.
+/// Rotation moves the log to `<name>.1`, shifting older ones up to `<name>.3`.
+/// Writing is best effort: a log that can't be written is skipped.
+#[derive(Debug)]
+pub struct LogFile {
+    path: PathBuf,
+    /// Serializes rotation and writes within the process.
+    lock: Mutex<()>,
+}
+
+impl LogFile {
+    /// Size past which the log is rotated.
+    const MAX_BYTES: u64 = 4 << 20;
+    /// Rotated logs kept.
+    const KEEP: u32 = 3;
+
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// `<cache_root>/logs/resolver.log`.
+    pub fn in_cache_root(cache_root: &Path) -> Self {
+        Self::new(cache_root.join("logs").join("resolver.log"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn append(&self, line: &str) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        if std::fs::metadata(&self.path).is_ok_and(|md| md.len() >= Self::MAX_BYTES) {
+            let rotated = |n: u32| {
+                let mut name = self.path.clone().into_os_string();
+                name.push(format!(".{n}"));
+                PathBuf::from(name)
+            };
+            for n in (1..Self::KEEP).rev() {
+                let _ = std::fs::rename(rotated(n), rotated(n + 1));
+            }
+            std::fs::rename(&self.path, rotated(1))?;
+        }
+        // Appends of one line are atomic, so processes sharing the cache
+        // don't interleave within lines.
+        let mut file = std::fs::File::options()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(format!("{line}\n").as_bytes())
+    }
+}
+
+impl Observer for LogFile {
+    fn on_event(&self, event: &Event<'_>) {
+        use serde_json::{Value, json};
+
+        let (fields, source) = match event {
+            Event::ResolveStarted { source, platform } => (
+                json!({ "event": "resolve-started", "platform": platform }),
+                Some(*source),
+            ),
+            Event::Resolved {
+                source,
+                layer,
+                artifact,
+                elapsed,
+            } => (
+                json!({
+                    "event": "resolved",
+                    "layer": layer,
+                    "elapsed": elapsed.as_secs_f64(),
+                    "artifact": artifact,
+                }),
+                Some(*source),
+            ),
+            Event::ResolveFailed {
+                source,
+                error,
+                elapsed,
+            } => (
+                json!({
+                    "event": "resolve-failed",
+                    "kind": error.kind().code(),
+                    "error": error.to_string(),
+                    "elapsed": elapsed.as_secs_f64(),
+                }),
+                Some(*source),
+            ),
+            Event::CacheHit { source, artifact } => (
+                json!({ "event": "cache-hit", "path": artifact.primary_path() }),
+                Some(*source),
+            ),
+            Event::CacheMiss { source, entry } => (
+                json!({ "event": "cache-miss", "entry": entry }),
+                Some(*source),
+            ),
+            Event::DownloadStarted { url, size } => (
+                json!({ "event": "download-started", "url": url, "size": size }),
+                None,
+            ),
+            Event::DownloadProgress { .. } => return,
+            Event::DownloadFinished {
+                url,
+                bytes,
+                elapsed,
+                success,
+            } => (
+                json!({
+                    "event": "download-finished",
+                    "url": url,
+                    "bytes": bytes,
+                    "elapsed": elapsed.as_secs_f64(),
+                    "success": success,
+                }),
+                None,
+            ),
+            Event::BuildStarted {
+                service,
+                entry,
+                jobs,
+            } => (
+                json!({
+                    "event": "build-started",
+                    "service": service,
+                    "entry": entry,
+                    "jobs": jobs,
+                }),
+                None,
+            ),
+            Event::BuildFinished {
+                service,
+                entry,
+                elapsed,
+                success,
+            } => (
+                json!({
+                    "event": "build-finished",
+                    "service": service,
+                    "entry": entry,
+                    "elapsed": elapsed.as_secs_f64(),
+                    "success": success,
+                }),
+                None,
+            ),
+            Event::VerificationFailed { source, error } => (
+                json!({ "event": "verification-failed", "error": error.to_string() }),
+                Some(*source),
+            ),
+            Event::LockWaited { entry, waited } => (
+                json!({
+                    "event": "lock-waited",
+                    "entry": entry,
+                    "waited": waited.as_secs_f64(),
+                }),
+                None,
+            ),
+            Event::Warning { warning } => (
+                json!({
+                    "event": "warning",
+                    "warning": warning,
+                    "message": warning.to_string(),
+                }),
+                None,
+            ),
+        };
+        let mut line = serde_json::Map::new();
+        line.insert("ts".into(), crate::cache::timestamp().into());
+        line.insert("pid".into(), std::process::id().into());
+        let thread = format!("{:?}", std::thread::current().id());
+        line.insert("thread".into(), thread.into());
+        if let Value::Object(fields) = fields {
+            line.extend(fields);
+        }
+        if let Some(source) = source {
+            line.insert("source".into(), source.kind().into());
+            if let Some(service) = source.service() {
+                line.insert("service".into(), service.as_str().into());
+            }
+        }
+        let _ = self.append(&Value::Object(line).to_string());
+    }
+}
+
+/// What one resolution did, step by step; see
+/// [`ArtifactResolver::resolve_traced`](crate::ArtifactResolver::resolve_traced).
+///
+/// Serializes to JSON, with durations in seconds, for attaching to bug
+/// reports. To answer why a source was rebuilt, look at the entry of its cache
+/// miss: its name holds the key, e.g. the commit and worktree hash of a build.
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResolutionTrace {
+    /// The source's [kind](ArtifactSource::kind).
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    pub platform: String,
+    /// Name of the layer that resolved the source, if one did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer: Option<String>,
+    pub steps: Vec<TraceStep>,
+    /// Of the whole resolution, checks included.
+    #[serde(serialize_with = "seconds")]
+    pub elapsed: Duration,
+    /// Why the resolution failed, if it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// What the resolution reported as [warnings](crate::warning), in order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+}
+
+/// One step of a [`ResolutionTrace`]. More kinds may be added.
+#[non_exhaustive]
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "step", rename_all = "kebab-case")]
+pub enum TraceStep {
+    CacheHit {
+        path: Option<PathBuf>,
+    },
+    CacheMiss {
+        entry: Option<PathBuf>,
+    },
+    Download {
+        /// Without credentials.
+        url: String,
+        size: Option<u64>,
+        bytes: u64,
+        #[serde(serialize_with = "seconds")]
+        elapsed: Duration,
+        success: bool,
+    },
+    Build {
+        service: String,
+        entry: PathBuf,
+        jobs: usize,
+        #[serde(serialize_with = "seconds")]
+        elapsed: Duration,
+        success: bool,
+    },
+    VerificationFailed {
+        error: String,
+    },
+    LockWaited {
+        entry: PathBuf,
+        #[serde(serialize_with = "seconds")]
+        waited: Duration,
+    },
+}
+
+fn seconds<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// Collects the [`ResolutionTrace`] of one resolution.
+#[derive(Default)]
+pub(crate) struct Tracer(Mutex<ResolutionTrace>);
+
+impl Tracer {
+    pub(crate) fn finish(&self) -> ResolutionTrace {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+impl Observer for Tracer {
+    fn on_event(&self, event: &Event<'_>) {
+        let mut trace = self.0.lock().unwrap();
+        match event {
+            Event::ResolveStarted { source, platform } => {
+                trace.source = source.kind().into();
+                trace.service = source.service().map(|id| id.as_str().into());
+                trace.platform = platform.to_string();
+            }
+            Event::Resolved { layer, elapsed, .. } => {
+                trace.layer = Some(layer.to_string());
+                trace.elapsed = *elapsed;
+            }
+            Event::ResolveFailed { error, elapsed, .. } => {
+                trace.error = Some(error.to_string());
+                trace.elapsed = *elapsed;
+            }
+            Event::CacheHit { artifact, .. } => trace.steps.push(TraceStep::CacheHit {
+                path: artifact.primary_path().map(Path::to_path_buf),
+            }),
+            Event::CacheMiss { entry, .. } => trace.steps.push(TraceStep::CacheMiss {
+                entry: entry.map(Path::to_path_buf),
+            }),
+            Event::DownloadStarted { url, size } => trace.steps.push(TraceStep::Download {
+                url: url.to_string(),
+                size: *size,
+                bytes: 0,
+                elapsed: Duration::ZERO,
+                success: false,
+            }),
+            Event::DownloadFinished {
+                url,
+                bytes,
+                elapsed,
+                success,
+            } => {
+                let started = trace.steps.iter_mut().rev().find_map(|step| match step {
+                    TraceStep::Download {
+                        url: started,
+                        bytes,
+                        elapsed,
+                        success,
+                        ..
+                    } if started == url => Some((bytes, elapsed, success)),
+                    _ => None,
+                });
+                if let Some(step) = started {
+                    (*step.0, *step.1, *step.2) = (*bytes, *elapsed, *success);
+                }
+            }
+            Event::BuildStarted {
+                service,
+                entry,
+                jobs,
+            } => trace.steps.push(TraceStep::Build {
+                service: service.as_str().into(),
+                entry: entry.to_path_buf(),
+                jobs: *jobs,
+                elapsed: Duration::ZERO,
+                success: false,
+            }),
+            Event::BuildFinished {
+                entry,
+                elapsed,
+                success,
+                ..
+            } => {
+                let started = trace.steps.iter_mut().rev().find_map(|step| match step {
+                    TraceStep::Build {
+                        entry: started,
+                        elapsed,
+                        success,
+                        ..
+                    } if started == entry => Some((elapsed, success)),
+                    _ => None,
+                });
+                if let Some(step) = started {
+                    (*step.0, *step.1) = (*elapsed, *success);
+                }
+            }
+            Event::VerificationFailed { error, .. } => {
+                trace.steps.push(TraceStep::VerificationFailed {
+                    error: error.to_string(),
+                })
+            }
+            Event::LockWaited { entry, waited } => trace.steps.push(TraceStep::LockWaited {
+                entry: entry.to_path_buf(),
+                waited: *waited,
+            }),
+            Event::Warning { warning } => trace.warnings.push((*warning).clone()),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,2178 @@
+//! Container images from OCI registries.
+//!
+//! [`ArtifactSource::OciImage`](crate::ArtifactSource::OciImage) sources are
+//! resolved against the registry's HTTP API (the OCI distribution spec) without a
+//! container runtime. Every manifest and blob is checked against its sha256
+//! digest before it is used.
+//!
+//! With [`OciMode::Image`], the image is pulled into the cache as an
+//! [OCI image layout](https://github.com/opencontainers/image-spec/blob/main/image-layout.md)
+//! and returned as [`ResolvedArtifact::OciImage`], for harnesses that run nodes
+//! in containers:
+//!
+//! ```text
+//! <cache_root>/oci/<digest prefix>-<platform>-v1/
+//!   out/                  # the image layout, e.g. `skopeo copy oci:<out> ...`
+//!     oci-layout
+//!     index.json
+//!     blobs/sha256/...
+//!   meta/META.json
+//! <cache_root>/oci/blobs/sha256/...  # layers, shared between images
+//! ```
+//!
+//! Layers are downloaded once per digest into the shared store and hard-linked
+//! into each image's layout, so image versions sharing base layers don't fetch
+//! them again. Deleting an image entry leaves its layers in the store until
+//! [`prune_layers`] removes them.
+//!
+//! With [`OciMode::Extract`], the image is pulled the same way, then the
+//! service binary (and its companions) is taken from the image's flattened
+//! filesystem and cached as a regular executable entry, keyed by the image
+//! digest. Nodes then run from the official image's binary without a container
+//! runtime. Symlinks and whiteouts are followed as a runtime would; zstd layers
+//! aren't supported.
+//!
+//! A reference to a multi-arch manifest list selects the entry for the
+//! architecture being resolved for (the host's, or that of
+//! [`platform_override`](crate::ResolverConfig::platform_override)); Linux images
+//! are selected on macOS and Windows too, as container runtimes run them in a VM.
+//! META records both the list's digest and that of the manifest used.
+//!
+//! Both modes can go through a container runtime instead, with its own auth,
+//! mirrors and proxies; see [`OciBackend`].
+//!
+//! Binaries pushed as ORAS artifacts rather than images are pulled the same
+//! way; see [`crate::oras`].
+//!
+//! SLSA provenance and SBOMs attached to images can be fetched, checked and
+//! kept with the cache entry; see [`crate::attestation`].
+//!
+//! Registries ask for credentials through the resolver's [`CredentialProvider`],
+//! keyed by the registry's `https://<host>/` URL, then, unless
+//! [`OciConfig::docker_login`] is off, through [`DockerCredentials`]; with
+//! neither, images are pulled anonymously.
+//!
+//! Registries are reached over HTTPS, except for those listed in
+//! [`OciConfig::insecure_registries`], which are reached over plain HTTP only
+//! and get no credentials unless [`OciConfig::insecure_credentials`] is set.
+//! Pulls can go through [`OciConfig::mirrors`] first, falling back to the
+//! registry itself.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    ResolveContext, ResolvedArtifact,
+    attestation::{self, AttestationPolicy},
+    cache::{self, CacheKey, CachePaths},
+    cancel::Phase,
+    credentials::{Credential, CredentialProvider, DockerCredentials},
+    error::{FsError, OciError, Result, UnpackError},
+    network::NetworkPolicy,
+    observe::Event,
+    registry::ToolSpec,
+    transport::{self, Method, Request, Response, Transport},
+    warning::Warning,
+};
+
+/// What `OciImage` sources resolve to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OciMode {
+    /// The binary of the source's `service` inside the image, cached like a
+    /// release asset; sources without a service are left to custom provider layers.
+    #[default]
+    Extract,
+    /// The pulled image itself, as [`ResolvedArtifact::OciImage`].
+    Image,
+}
+
+/// Where images are pulled from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OciBackend {
+    /// The registry's HTTP API, without a container runtime.
+    #[default]
+    Registry,
+    /// The Docker daemon, through the `docker` CLI; see [`crate::container`].
+    Docker,
+    /// Podman, through the `podman` CLI, rootless or not.
+    Podman,
+    /// The first of Docker and Podman that is installed and answers, else
+    /// [`Registry`](Self::Registry). Detected once per process.
+    Auto,
+}
+
+/// OCI settings of a [`ResolverConfig`](crate::ResolverConfig).
+#[derive(Debug, Clone)]
+pub struct OciConfig {
+    pub mode: OciMode,
+    pub backend: OciBackend,
+    /// Fall back to `docker login` state for registries the credential provider
+    /// has nothing for; see [`DockerCredentials`]. On by default.
+    pub docker_login: bool,
+    /// Repository shared as a cache of `Build` outputs, e.g.
+    /// `ghcr.io/example/zcash-builds`; see `crate::build_cache` (`local-build` feature).
+    pub build_cache: Option<String>,
+    /// Push fresh builds to [`build_cache`](Self::build_cache), rather than
+    /// only pulling from it.
+    pub push_builds: bool,
+    /// Whether to fetch, check and keep the attestations of pulled images.
+    pub attestations: AttestationPolicy,
+    /// Registries (`host[:port]`, as in references) that serve plain HTTP,
+    /// e.g. a test registry at `localhost:5000`. Others are only reached over
+    /// HTTPS. Entries pulled from them are marked `insecure` in META.
+    ///
+    /// These registries are reached anonymously; see
+    /// [`insecure_credentials`](Self::insecure_credentials).
+    pub insecure_registries: Vec<String>,
+    /// Send credentials over plain HTTP too: to
+    /// [`insecure_registries`](Self::insecure_registries) and to token realms
+    /// served over HTTP. They go over the wire in the clear, so each client
+    /// that may send them reports a
+    /// [`Warning::InsecureCredentials`](crate::warning::Warning::InsecureCredentials).
+    /// Off by default.
+    pub insecure_credentials: bool,
+    /// Mirrors to pull through, by registry (as in references, so `docker.io`
+    /// for Docker Hub), e.g. an internal pull-through cache for `docker.io` to
+    /// stay clear of Docker Hub's rate limits.
+    ///
+    /// Mirrors are `host[:port][/prefix]`; with a prefix, repositories are
+    /// looked up under it, as for Harbor's proxy-cache projects. As with
+    /// containerd's `hosts.toml`, they are tried in order, then the registry
+    /// itself: a mirror that fails (unreachable, an error status, content it
+    /// doesn't have) isn't asked again for the rest of the pull. Content is
+    /// checked against its digest whoever serves it. Pushes go to the registry.
+    pub mirrors: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for OciConfig {
+    fn default() -> Self {
+        Self {
+            mode: OciMode::default(),
+            backend: OciBackend::default(),
+            docker_login: true,
+            build_cache: None,
+            push_builds: false,
+            attestations: AttestationPolicy::default(),
+            insecure_registries: Vec::new(),
+            insecure_credentials: false,
+            mirrors: BTreeMap::new(),
+        }
+    }
+}
+
+impl OciConfig {
+    /// Whether `registry` is one of the [`insecure_registries`](Self::insecure_registries).
+    pub fn is_insecure(&self, registry: &str) -> bool {
+        self.insecure_registries.iter().any(|r| r == registry)
+    }
+}
+
+/// An image reference, `[registry/]repository[:tag][@digest]`, checked
+/// against the grammar of the distribution spec.
+///
+/// References are normalized as by `docker pull`, so spellings of the same
+/// image compare (and key caches) equal: Docker Hub (`docker.io`,
+/// `index.docker.io` or none) is `docker.io`, and its single-component
+/// repositories live under `library/`. Registry names are lowercased;
+/// repositories, tags and digests are case-sensitive and kept as given.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Reference {
+    /// Registry host, with its port if any, e.g. `ghcr.io` or `localhost:5000`.
+    pub registry: String,
+    /// Repository path, e.g. `zfnd/zebra` or `library/debian`.
+    pub repository: String,
+    pub tag: Option<String>,
+    /// `algorithm:hex`, e.g. `sha256:…`.
+    pub digest: Option<String>,
+}
+
+impl Reference {
+    /// Parses and normalizes `s`; fails with [`OciError::InvalidReference`]
+    /// naming the part that is invalid.
+    pub fn parse(s: &str) -> Result<Self> {
+        let invalid = |reason: String| OciError::InvalidReference {
+            reference: s.to_string(),
+            reason,
+        };
+        if s.is_empty() {
+            return Err(invalid("empty reference".into()).into());
+        }
+        if let Some(c) = s.chars().find(|c| c.is_whitespace() || c.is_control()) {
+            return Err(invalid(format!("contains {c:?}")).into());
+        }
+        let (name, digest) = match s.split_once('@') {
+            Some((name, digest)) => {
+                validate_digest(digest).map_err(invalid)?;
+                (name, Some(digest.to_string()))
+            }
+            None => (s, None),
+        };
+        let (name, tag) = match name.rsplit_once(':') {
+            Some((repo, tag)) if !tag.contains('/') => {
+                validate_tag(tag).map_err(invalid)?;
+                (repo, Some(tag.to_string()))
+            }
+            _ => (name, None),
+        };
+        if name.len() > 255 {
+            return Err(invalid(format!(
+                "name is {} characters long; at most 255 are allowed",
+                name.len()
+            ))
+            .into());
+        }
+        let (registry, repository) = match name.split_once('/') {
+            Some((first, rest))
+                if first.contains('.') || first.contains(':') || first == "localhost" =>
+            {
+                validate_registry(first).map_err(invalid)?;
+                (first.to_ascii_lowercase(), rest)
+            }
+            _ => ("docker.io".to_string(), name),
+        };
+        let registry = match registry.as_str() {
+            "index.docker.io" | "registry-1.docker.io" => "docker.io".to_string(),
+            _ => registry,
+        };
+        validate_repository(repository).map_err(invalid)?;
+        let repository = if registry == "docker.io" && !repository.contains('/') {
+            format!("library/{repository}")
+        } else {
+            repository.to_string()
+        };
+        Ok(Self {
+            registry,
+            repository,
+            tag,
+            digest,
+        })
+    }
+
+    /// `registry/repository`, without tag or digest.
+    pub fn name(&self) -> String {
+        format!("{}/{}", self.registry, self.repository)
+    }
+
+    /// Host serving the registry API; Docker Hub's differs from its name.
+    fn api_host(&self) -> &str {
+        match self.registry.as_str() {
+            "docker.io" => "registry-1.docker.io",
+            other => other,
+        }
+    }
+
+    /// The digest if pinned, else the tag (`latest` if none).
+    pub(crate) fn manifest_ref(&self) -> &str {
+        self.digest
+            .as_deref()
+            .or(self.tag.as_deref())
+            .unwrap_or("latest")
+    }
+}
+
+impl std::str::FromStr for Reference {
+    type Err = crate::ArtifactError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+/// Registry hosts: DNS labels or an `[IPv6]` address, and an optional port.
+fn validate_registry(registry: &str) -> std::result::Result<(), String> {
+    let (host, port) = match registry.rsplit_once(':') {
+        Some((host, port)) if !port.ends_with(']') => (host, Some(port)),
+        _ => (registry, None),
+    };
+    if let Some(port) = port
+        && port.parse::<u16>().is_err()
+    {
+        return Err(format!("registry port `{port}` is not a port number"));
+    }
+    if let Some(address) = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+        return address
+            .parse::<std::net::Ipv6Addr>()
+            .map(drop)
+            .map_err(|_| format!("registry `{registry}` has an invalid IPv6 address"));
+    }
+    let label_ok = |label: &str| {
+        !label.is_empty()
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+    };
+    if !host.split('.').all(label_ok) {
+        return Err(format!("registry `{registry}` is not a valid host name"));
+    }
+    Ok(())
+}
+
+/// Repositories: `/`-separated lowercase components, each alphanumerics joined
+/// by `.`, `_`, `__` or runs of `-`.
+fn validate_repository(repository: &str) -> std::result::Result<(), String> {
+    if repository.is_empty() {
+        return Err("repository is empty".into());
+    }
+    if repository.chars().any(|c| c.is_ascii_uppercase()) {
+        return Err(format!("repository `{repository}` must be lowercase"));
+    }
+    for component in repository.split('/') {
+        if component.is_empty() {
+            return Err(format!("repository `{repository}` has an empty component"));
+        }
+        let separators_ok = component
+            .split(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+            .filter(|separator| !separator.is_empty())
+            .all(|separator| {
+                matches!(separator, "." | "_" | "__") || separator.chars().all(|c| c == '-')
+            });
+        let ends_ok = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric());
+        if !component
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-'))
+            || !separators_ok
+            || !ends_ok(component.chars().next())
+            || !ends_ok(component.chars().last())
+        {
+            return Err(format!(
+                "repository component `{component}` must be lowercase alphanumerics \
+                 separated by `.`, `_`, `__` or `-`"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Tags: up to 128 word characters, `.` and `-`, not starting with either.
+fn validate_tag(tag: &str) -> std::result::Result<(), String> {
+    let word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let ok = tag.len() <= 128
+        && tag.chars().next().is_some_and(word)
+        && tag.chars().all(|c| word(c) || matches!(c, '.' | '-'));
+    if ok {
+        Ok(())
+    } else if tag.is_empty() {
+        Err("tag is empty".into())
+    } else {
+        Err(format!(
+            "tag `{tag}` must be up to 128 letters, digits, `_`, `.` and `-`, \
+             not starting with `.` or `-`"
+        ))
+    }
+}
+
+/// Digests: `algorithm:hex`, with the hex length of the known algorithms.
+fn validate_digest(digest: &str) -> std::result::Result<(), String> {
+    let Some((algorithm, hex)) = digest.split_once(':') else {
+        return Err(format!("digest `{digest}` is not `algorithm:hex`"));
+    };
+    let length = match algorithm {
+        "sha256" => 64,
+        "sha512" => 128,
+        _ => return Err(format!("digest algorithm `{algorithm}` is not supported")),
+    };
+    if hex.len() != length
+        || !hex
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+    {
+        return Err(format!(
+            "{algorithm} digest must be {length} lowercase hex characters, got `{hex}`"
+        ));
+    }
+    Ok(())
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.registry, self.repository)?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{tag}")?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{digest}")?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) const MANIFEST_TYPES: &[&str] = &[
+    "application/vnd.oci.image.manifest.v1+json",
+    "application/vnd.docker.distribution.manifest.v2+json",
+];
+
+/// Multi-arch manifest lists, which point at one manifest per platform.
+pub(crate) const INDEX_TYPES: &[&str] = &[
+    "application/vnd.oci.image.index.v1+json",
+    "application/vnd.docker.distribution.manifest.list.v2+json",
+];
+
+#[derive(Debug, Deserialize)]
+struct Descriptor {
+    digest: String,
+    size: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageManifest {
+    config: Descriptor,
+    layers: Vec<Descriptor>,
+}
+
+/// The `os`/`architecture` pair of an image config or manifest list entry.
+///
+/// Container runtimes' `image inspect` capitalizes the fields.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ImagePlatform {
+    #[serde(alias = "Os")]
+    os: String,
+    #[serde(alias = "Architecture")]
+    architecture: String,
+    #[serde(default, alias = "Variant")]
+    variant: Option<String>,
+}
+
+impl ImagePlatform {
+    /// The crate's platform string, e.g. `linux-armv7` for `linux/arm/v7`.
+    pub(crate) fn canonical(&self) -> String {
+        let arch = match (self.architecture.as_str(), self.variant.as_deref()) {
+            ("arm", Some("v7")) => "armv7",
+            (arch, _) => arch,
+        };
+        crate::platform::normalize(&format!("{}-{arch}", self.os))
+    }
+}
+
+/// How an image runs by default, from its config; what `docker run` would use
+/// without arguments.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "RawImageConfig")]
+pub struct ImageConfig {
+    /// The image's `ENTRYPOINT`, empty if it has none.
+    pub entrypoint: Vec<String>,
+    /// The image's `CMD`: the entrypoint's default arguments, or the command
+    /// itself without an entrypoint.
+    pub cmd: Vec<String>,
+    /// Environment, as `NAME=value`.
+    pub env: Vec<String>,
+    pub working_dir: Option<String>,
+    pub user: Option<String>,
+    /// Exposed ports, e.g. `18232/tcp`.
+    pub exposed_ports: Vec<String>,
+    /// Container paths the image declares as volumes, e.g. `/var/cache/zebrad-cache`;
+    /// data there outlives the container only if something is mounted there.
+    pub volumes: Vec<String>,
+}
+
+impl ImageConfig {
+    /// The command the image runs by default: entrypoint, then `cmd`.
+    pub fn command(&self) -> Vec<String> {
+        [self.entrypoint.as_slice(), self.cmd.as_slice()].concat()
+    }
+
+    /// The exposed TCP ports.
+    pub fn tcp_ports(&self) -> Vec<u16> {
+        self.exposed_ports
+            .iter()
+            .filter_map(|port| match port.split_once('/') {
+                Some((port, "tcp")) => port.parse().ok(),
+                Some(_) => None,
+                None => port.parse().ok(),
+            })
+            .collect()
+    }
+}
+
+/// An image config's `config` object, in both the OCI spelling and that of
+/// runtimes' `image inspect`. Absent fields may also be `null`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawImageConfig {
+    #[serde(default)]
+    entrypoint: Option<Vec<String>>,
+    #[serde(default)]
+    cmd: Option<Vec<String>>,
+    #[serde(default)]
+    env: Option<Vec<String>>,
+    #[serde(default)]
+    working_dir: Option<String>,
+    #[serde(default)]
+    user: Option<String>,
+    #[serde(default)]
+    exposed_ports: Option<BTreeMap<String, serde_json::Value>>,
+    #[serde(default)]
+    volumes: Option<BTreeMap<String, serde_json::Value>>,
+}
+
+impl From<RawImageConfig> for ImageConfig {
+    fn from(raw: RawImageConfig) -> Self {
+        let non_empty = |s: Option<String>| s.filter(|s| !s.is_empty());
+        Self {
+            entrypoint: raw.entrypoint.unwrap_or_default(),
+            cmd: raw.cmd.unwrap_or_default(),
+            env: raw.env.unwrap_or_default(),
+            working_dir: non_empty(raw.working_dir),
+            user: non_empty(raw.user),
+            exposed_ports: raw.exposed_ports.unwrap_or_default().into_keys().collect(),
+            volumes: raw.volumes.unwrap_or_default().into_keys().collect(),
+        }
+    }
+}
+
+/// The parts of an image config blob we use.
+#[derive(Debug, Deserialize)]
+struct ConfigBlob {
+    #[serde(flatten)]
+    platform: ImagePlatform,
+    #[serde(default)]
+    config: Option<ImageConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ImageIndex {
+    pub(crate) manifests: Vec<IndexEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct IndexEntry {
+    pub(crate) digest: String,
+    /// Missing on some entries, e.g. attestation manifests.
+    pub(crate) platform: Option<ImagePlatform>,
+}
+
+/// A manifest as served, with its verified digest.
+pub(crate) struct Fetched {
+    pub(crate) media_type: String,
+    pub(crate) body: Vec<u8>,
+    pub(crate) digest: String,
+}
+
+/// A registry API client for one repository, holding the authorization it was granted.
+pub(crate) struct Client<'a> {
+    reference: &'a Reference,
+    transport: Arc<dyn Transport>,
+    credentials: &'a dyn CredentialProvider,
+    docker_login: Option<DockerCredentials>,
+    network: NetworkPolicy,
+    /// The registry's [mirrors](OciConfig::mirrors), then the registry itself.
+    endpoints: Vec<Endpoint>,
+    /// The endpoint in use; earlier ones have failed.
+    current: usize,
+    /// Report failures as [`OciError::Push`] rather than [`OciError::Pull`].
+    pushing: bool,
+    /// Send credentials over plain HTTP; see [`OciConfig::insecure_credentials`].
+    cleartext_credentials: bool,
+}
+
+/// A host serving the registry API for a [`Client`].
+struct Endpoint {
+    host: String,
+    /// Prepended to the repository, for mirrors with a path.
+    prefix: Option<String>,
+    /// `http` for [insecure registries](OciConfig::insecure_registries), else `https`.
+    scheme: &'static str,
+    /// The authorization the host granted.
+    auth: Option<Credential>,
+    mirror: bool,
+}
+
+impl Endpoint {
+    fn new(host: &str, prefix: Option<&str>, config: &OciConfig, mirror: bool) -> Self {
+        Self {
+            host: host.to_string(),
+            prefix: prefix.map(|p| p.trim_matches('/').to_string()),
+            scheme: if config.is_insecure(host) {
+                "http"
+            } else {
+                "https"
+            },
+            auth: None,
+            mirror,
+        }
+    }
+}
+
+/// A request body's content type, and a function creating the body (again, if
+/// the request is retried after an auth challenge).
+pub(crate) type Body<'b> = (&'b str, &'b dyn Fn() -> Result<transport::Body>);
+
+impl<'a> Client<'a> {
+    pub(crate) fn new(
+        reference: &'a Reference,
+        credentials: &'a dyn CredentialProvider,
+        ctx: &ResolveContext<'_>,
+    ) -> Self {
+        let network = ctx.config.network.clone();
+        let config = &ctx.config.oci;
+        let mirrors = config
+            .mirrors
+            .get(&reference.registry)
+            .into_iter()
+            .flatten()
+            .map(|mirror| {
+                let (host, prefix) = match mirror.split_once('/') {
+                    Some((host, prefix)) => (host, Some(prefix)),
+                    None => (mirror.as_str(), None),
+                };
+                Endpoint::new(host, prefix, config, true)
+            });
+        let origin = Endpoint::new(reference.api_host(), None, config, false);
+        let client = Self {
+            reference,
+            transport: ctx.transport().clone(),
+            credentials,
+            network,
+            docker_login: config
+                .docker_login
+                .then(DockerCredentials::from_env)
+                .flatten(),
+            endpoints: mirrors.chain([origin]).collect(),
+            current: 0,
+            pushing: false,
+            cleartext_credentials: config.insecure_credentials,
+        };
+        if client.cleartext_credentials {
+            for endpoint in client.endpoints.iter().filter(|e| e.scheme == "http") {
+                if client.credential_for_host(&endpoint.host).is_some() {
+                    ctx.warn(Warning::InsecureCredentials {
+                        host: endpoint.host.clone(),
+                    });
+                }
+            }
+        }
+        client
+    }
+
+    fn endpoint(&self) -> &Endpoint {
+        &self.endpoints[self.current]
+    }
+
+    /// The mirror in use, if any.
+    fn mirror(&self) -> Option<String> {
+        let endpoint = self.endpoint();
+        endpoint.mirror.then(|| match &endpoint.prefix {
+            Some(prefix) => format!("{}/{prefix}", endpoint.host),
+            None => endpoint.host.clone(),
+        })
+    }
+
+    /// A client for pushing to `reference`'s repository.
+    #[cfg_attr(not(feature = "local-build"), allow(dead_code))]
+    pub(crate) fn for_push(
+        reference: &'a Reference,
+        credentials: &'a dyn CredentialProvider,
+        ctx: &ResolveContext<'_>,
+    ) -> Self {
+        let client = Self::new(reference, credentials, ctx);
+        let origin = client.endpoints.len() - 1;
+        Self {
+            pushing: true,
+            current: origin,
+            ..client
+        }
+    }
+
+    pub(crate) fn error(
+        &self,
+        source: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> OciError {
+        let reference = self.reference.to_string();
+        let source = source.into();
+        if self.pushing {
+            OciError::Push { reference, source }
+        } else {
+            OciError::Pull { reference, source }
+        }
+    }
+
+    /// The credential for the host in use; none over plain HTTP unless
+    /// [`OciConfig::insecure_credentials`] allows it.
+    fn credential(&self) -> Option<Credential> {
+        let endpoint = self.endpoint();
+        if endpoint.scheme == "http" && !self.cleartext_credentials {
+            return None;
+        }
+        self.credential_for_host(&endpoint.host)
+    }
+
+    fn credential_for_host(&self, host: &str) -> Option<Credential> {
+        let url = format!("https://{host}/").parse().ok()?;
+        self.credentials.credential_for(&url).or_else(|| {
+            self.docker_login
+                .as_ref()
+                .and_then(|docker| docker.credential_for(&url))
+        })
+    }
+
+    /// The API URL of `/v2/<repository>/<path>` on the host in use.
+    fn url(&self, path: &str) -> String {
+        let endpoint = self.endpoint();
+        let repository = &self.reference.repository;
+        match &endpoint.prefix {
+            Some(prefix) => format!(
+                "{}://{}/v2/{prefix}/{repository}/{path}",
+                endpoint.scheme, endpoint.host
+            ),
+            None => format!(
+                "{}://{}/v2/{repository}/{path}",
+                endpoint.scheme, endpoint.host
+            ),
+        }
+    }
+
+    /// GETs `/v2/<repository>/<path>` from the first host that has it: a
+    /// mirror answering with success, else the registry, whatever it answers.
+    fn fetch(&mut self, path: &str, accept: &[&str]) -> Result<Response> {
+        loop {
+            let url = self.url(path);
+            let response = self.send(Method::Get, &url, accept, None);
+            if !self.endpoint().mirror {
+                return response;
+            }
+            match response {
+                Ok(response) if response.is_success() => return Ok(response),
+                _ => self.current += 1,
+            }
+        }
+    }
+
+    /// GETs `/v2/<repository>/<path>`, failing on error statuses.
+    fn get(&mut self, path: &str, accept: &[&str]) -> Result<Response> {
+        let response = self.fetch(path, accept)?;
+        response
+            .error_for_status()
+            .map_err(|e| self.error(e).into())
+    }
+
+    /// Sends a request to `url`, answering an auth challenge once. Only
+    /// authorization failures are errors; other statuses are the caller's.
+    pub(crate) fn send(
+        &mut self,
+        method: Method,
+        url: &str,
+        accept: &[&str],
+        body: Option<Body<'_>>,
+    ) -> Result<Response> {
+        let url = url::Url::parse(url).map_err(|e| self.error(format!("bad URL {url}: {e}")))?;
+        self.network.check(&url)?;
+        let send = |client: &Self| -> Result<Response> {
+            let mut request = Request::new(method, url.clone()).header("Accept", accept.join(", "));
+            if let Some((content_type, body)) = body {
+                request = request.header("Content-Type", content_type).body(body()?);
+            }
+            // Bearer credentials are registry tokens (e.g. a GHCR PAT); basic ones
+            // are only sent where a challenge asks for them.
+            let request = request.auth(client.endpoint().auth.clone().or_else(|| {
+                client
+                    .credential()
+                    .filter(|c| matches!(c, Credential::Bearer(_)))
+            }));
+            client
+                .transport
+                .send(request)
+                .map_err(|e| client.error(e).into())
+        };
+
+        // A token granted for pulling may not cover a push; the registry then
+        // challenges again, with the wider scope.
+        let mut response = send(self)?;
+        if response.status == 401 {
+            let challenge = response
+                .header("WWW-Authenticate")
+                .unwrap_or_default()
+                .to_string();
+            let auth = self.authenticate(&challenge)?;
+            self.endpoints[self.current].auth = Some(auth);
+            response = send(self)?;
+        }
+        match response.status {
+            401 | 403 => Err(OciError::Unauthorized {
+                reference: self.reference.to_string(),
+            }
+            .into()),
+            _ => Ok(response),
+        }
+    }
+
+    /// Answers a `WWW-Authenticate` challenge: with a token obtained from the
+    /// challenge's realm for `Bearer`, or with the basic credential for `Basic`.
+    fn authenticate(&self, challenge: &str) -> Result<Credential> {
+        let unauthorized = || OciError::Unauthorized {
+            reference: self.reference.to_string(),
+        };
+        let credential = self.credential();
+        let Some(params) = challenge.strip_prefix("Bearer ") else {
+            return match credential {
+                Some(basic @ Credential::Basic { .. }) if challenge.starts_with("Basic") => {
+                    Ok(basic)
+                }
+                _ => Err(unauthorized().into()),
+            };
+        };
+        let params = parse_challenge(params);
+        let mut realm: url::Url = params
+            .iter()
+            .find(|(k, _)| k == "realm")
+            .and_then(|(_, v)| v.parse().ok())
+            .ok_or_else(unauthorized)?;
+        for key in ["service", "scope"] {
+            if let Some((_, value)) = params.iter().find(|(k, _)| k == key) {
+                realm.query_pairs_mut().append_pair(key, value);
+            }
+        }
+        self.network.check(&realm)?;
+        let cleartext = realm.scheme() == "http" && !self.cleartext_credentials;
+        let request = Request::new(Method::Get, realm)
+            .auth(credential.filter(|c| matches!(c, Credential::Basic { .. }) && !cleartext));
+
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            token: Option<String>,
+            access_token: Option<String>,
+        }
+        let mut body = Vec::new();
+        self.transport
+            .send(request)
+            .map_err(|e| self.error(e))?
+            .error_for_status()
+            .map_err(|e| self.error(e))?
+            .read_to_end(&mut body)
+            .map_err(|e| self.error(e))?;
+        let response: TokenResponse = serde_json::from_slice(&body)
+            .map_err(|e| self.error(format!("bad token response: {e}")))?;
+        response
+            .token
+            .or(response.access_token)
+            .map(Credential::Bearer)
+            .ok_or_else(|| unauthorized().into())
+    }
+
+    /// Fetches the manifest for `which` (a tag or digest) and verifies its digest.
+    pub(crate) fn manifest(&mut self, which: &str, accept: &[&str]) -> Result<Fetched> {
+        let response = self.get(&format!("manifests/{which}"), accept)?;
+        self.read_manifest(response, which)
+    }
+
+    /// Like [`manifest`](Self::manifest), but `None` if the registry has no such manifest.
+    #[cfg_attr(not(feature = "local-build"), allow(dead_code))]
+    pub(crate) fn find_manifest(
+        &mut self,
+        which: &str,
+        accept: &[&str],
+    ) -> Result<Option<Fetched>> {
+        let response = self.fetch(&format!("manifests/{which}"), accept)?;
+        if response.status == 404 {
+            return Ok(None);
+        }
+        let response = response.error_for_status().map_err(|e| self.error(e))?;
+        self.read_manifest(response, which).map(Some)
+    }
+
+    fn read_manifest(&self, mut response: Response, which: &str) -> Result<Fetched> {
+        let header = |name: &str| response.header(name).map(str::to_string);
+        let media_type = header("Content-Type").unwrap_or_default();
+        let announced = header("Docker-Content-Digest");
+        let mut body = Vec::new();
+        response.read_to_end(&mut body).map_err(|e| self.error(e))?;
+        let digest = sha256_digest(&body);
+        let expected = if which.starts_with("sha256:") {
+            Some(which.to_string())
+        } else {
+            announced
+        };
+        if let Some(expected) = expected
+            && expected != digest
+        {
+            return Err(OciError::DigestMismatch {
+                reference: self.reference.to_string(),
+                what: "manifest".into(),
+                expected,
+                actual: digest,
+            }
+            .into());
+        }
+        Ok(Fetched {
+            media_type,
+            body,
+            digest,
+        })
+    }
+
+    /// Streams the blob `digest` to `dst` (via a temp file), verifying it.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "blob",
+            skip_all,
+            fields(reference = %self.reference, digest, bytes = tracing::field::Empty),
+        )
+    )]
+    pub(crate) fn blob_to(
+        &mut self,
+        ctx: &ResolveContext<'_>,
+        digest: &str,
+        dst: &Path,
+    ) -> Result<()> {
+        let _slots = ctx.limiter.download(&self.endpoint().host, ctx.cancel)?;
+        ctx.enter(Phase::Download);
+        let path = format!("blobs/{digest}");
+        let mut response = self.get(&path, &["*/*"])?;
+        // The host that answered, mirror or not.
+        let url = self.url(&path);
+        let started = std::time::Instant::now();
+        ctx.emit(Event::DownloadStarted {
+            url: &url,
+            size: response.content_length(),
+        });
+        let mut bytes = 0;
+        let size = response.content_length();
+        let progress = |bytes| {
+            ctx.emit(Event::DownloadProgress {
+                url: &url,
+                bytes,
+                size,
+            })
+        };
+        let result = self.save_blob(
+            &mut response,
+            digest,
+            dst,
+            &mut bytes,
+            &progress,
+            ctx.cancel,
+        );
+        crate::trace::record!("bytes" = bytes);
+        ctx.emit(Event::DownloadFinished {
+            url: &url,
+            bytes,
+            elapsed: started.elapsed(),
+            success: result.is_ok(),
+        });
+        result
+    }
+
+    /// Streams `response` to `dst` for [`Client::blob_to`], counting its
+    /// length in `bytes` and reporting it to `progress` as it grows. The temp
+    /// file is removed if the resolution is cancelled.
+    fn save_blob(
+        &self,
+        response: &mut Response,
+        digest: &str,
+        dst: &Path,
+        bytes: &mut u64,
+        progress: &dyn Fn(u64),
+        cancel: Option<&crate::cancel::CancelToken>,
+    ) -> Result<()> {
+        // Per process, since several may fetch the same shared layer at once.
+        let tmp = dst.with_extension(format!("part-{}", std::process::id()));
+        let io = |context: String| move |e| FsError::Io { context, source: e };
+        let mut file =
+            std::fs::File::create(&tmp).map_err(io(format!("create {}", tmp.display())))?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            if let Some(Err(e)) = cancel.map(crate::cancel::CancelToken::check) {
+                drop(file);
+                let _ = std::fs::remove_file(&tmp);
+                return Err(e);
+            }
+            let n = response.read(&mut buf).map_err(|e| self.error(e))?;
+            if n == 0 {
+                break;
+            }
+            *bytes += n as u64;
+            hasher.update(&buf[..n]);
+            file.write_all(&buf[..n])
+                .map_err(io(format!("write {}", tmp.display())))?;
+            progress(*bytes);
+        }
+        drop(file);
+        let actual = format!("sha256:{}", hex(&hasher.finalize()));
+        if actual != digest {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(OciError::DigestMismatch {
+                reference: self.reference.to_string(),
+                what: digest.to_string(),
+                expected: digest.to_string(),
+                actual,
+            }
+            .into());
+        }
+        std::fs::rename(&tmp, dst).map_err(|e| {
+            FsError::Io {
+                context: format!("rename {} -> {}", tmp.display(), dst.display()),
+                source: e,
+            }
+            .into()
+        })
+    }
+
+    /// Uploads the blob `digest` in one request, unless the repository has it already.
+    #[cfg_attr(not(feature = "local-build"), allow(dead_code))]
+    pub(crate) fn push_blob(
+        &mut self,
+        digest: &str,
+        body: &dyn Fn() -> Result<transport::Body>,
+    ) -> Result<()> {
+        let url = self.url(&format!("blobs/{digest}"));
+        if self.send(Method::Head, &url, &[], None)?.is_success() {
+            return Ok(());
+        }
+
+        let start = self.url("blobs/uploads/");
+        let empty = || Ok(transport::Body::from(Vec::new()));
+        let response = self.send(
+            Method::Post,
+            &start,
+            &[],
+            Some(("application/octet-stream", &empty)),
+        )?;
+        let response = response.error_for_status().map_err(|e| self.error(e))?;
+        // The upload URL may be relative, and may carry a query of its own.
+        let mut upload = response
+            .header("Location")
+            .and_then(|location| url::Url::parse(&start).ok()?.join(location).ok())
+            .ok_or_else(|| self.error("upload started without a Location"))?;
+        upload.query_pairs_mut().append_pair("digest", digest);
+        self.send(
+            Method::Put,
+            upload.as_str(),
+            &[],
+            Some(("application/octet-stream", body)),
+        )?
+        .error_for_status()
+        .map_err(|e| self.error(e))?;
+        Ok(())
+    }
+
+    /// Uploads a manifest of `media_type` under `tag` and returns its digest.
+    #[cfg_attr(not(feature = "local-build"), allow(dead_code))]
+    pub(crate) fn push_manifest(
+        &mut self,
+        tag: &str,
+        media_type: &str,
+        manifest: &[u8],
+    ) -> Result<String> {
+        let url = self.url(&format!("manifests/{tag}"));
+        let body = || Ok(transport::Body::from(manifest.to_vec()));
+        self.send(Method::Put, &url, &[], Some((media_type, &body)))?
+            .error_for_status()
+            .map_err(|e| self.error(e))?;
+        Ok(sha256_digest(manifest))
+    }
+
+    /// The referrers index of the manifest `digest`, `None` where the
+    /// registry doesn't serve the referrers API.
+    pub(crate) fn referrers(&mut self, digest: &str) -> Result<Option<Vec<u8>>> {
+        let mut response = self.fetch(
+            &format!("referrers/{digest}"),
+            &["application/vnd.oci.image.index.v1+json"],
+        )?;
+        if !response.is_success() {
+            return Ok(None);
+        }
+        let mut body = Vec::new();
+        response.read_to_end(&mut body).map_err(|e| self.error(e))?;
+        Ok(Some(body))
+    }
+
+    /// Fetches a small blob into memory, verifying it.
+    pub(crate) fn blob(&mut self, digest: &str) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        self.get(&format!("blobs/{digest}"), &["*/*"])?
+            .read_to_end(&mut body)
+            .map_err(|e| self.error(e))?;
+        let actual = sha256_digest(&body);
+        if actual != digest {
+            return Err(OciError::DigestMismatch {
+                reference: self.reference.to_string(),
+                what: digest.to_string(),
+                expected: digest.to_string(),
+                actual,
+            }
+            .into());
+        }
+        Ok(body)
+    }
+}
+
+/// `key="value",...` pairs of a `WWW-Authenticate` challenge.
+fn parse_challenge(params: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut rest = params.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_string();
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        pairs.push((key, value.to_string()));
+        rest = after.trim_start_matches(',').trim();
+    }
+    pairs
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub(crate) fn sha256_digest(bytes: &[u8]) -> String {
+    format!("sha256:{}", hex(&Sha256::digest(bytes)))
+}
+
+/// The layout-like root whose `blobs/` keep each layer once, shared by every
+/// pulled image.
+fn layer_store(cache_root: &Path) -> PathBuf {
+    cache_root.join("oci")
+}
+
+/// Locks the layer store, shared for pulls or exclusively for pruning.
+fn lock_store(store: &Path, exclusive: bool) -> Result<std::fs::File> {
+    let path = store.join("blobs").join(".lock");
+    let io = |verb: &str| {
+        let context = format!("{verb} {}", path.display());
+        move |e| FsError::Io { context, source: e }
+    };
+    let file = std::fs::File::create(&path).map_err(io("create"))?;
+    if exclusive {
+        file.lock().map_err(io("lock"))?;
+    } else {
+        file.lock_shared().map_err(io("lock"))?;
+    }
+    Ok(file)
+}
+
+/// Hard-links `src` to `dst`, copying where links aren't possible (e.g. across
+/// filesystems). An existing `dst` is kept.
+fn link_or_copy(src: &Path, dst: &Path) -> Result<()> {
+    if dst.is_file() || std::fs::hard_link(src, dst).is_ok() {
+        return Ok(());
+    }
+    cache::copy_atomic(src, dst)
+}
+
+/// Removes layers from the shared store that no pulled image uses anymore,
+/// e.g. after image entries under `<cache_root>/oci/` were deleted. Returns the
+/// number of bytes freed.
+///
+/// Safe to run while other processes pull: it waits for their pulls to finish
+/// linking layers, and they wait for it.
+pub fn prune_layers(cache_root: &Path) -> Result<u64> {
+    let store = layer_store(cache_root);
+    if !store.join("blobs").is_dir() {
+        return Ok(0);
+    }
+    let _lock = lock_store(&store, true)?;
+
+    let mut used = std::collections::HashSet::new();
+    let entries = std::fs::read_dir(&store).into_iter().flatten();
+    for entry in entries.flatten() {
+        let blobs = entry.path().join("out").join("blobs").join("sha256");
+        for blob in std::fs::read_dir(blobs).into_iter().flatten().flatten() {
+            used.insert(blob.file_name());
+        }
+    }
+
+    let mut freed = 0;
+    let layers = std::fs::read_dir(store.join("blobs").join("sha256"));
+    for layer in layers.into_iter().flatten().flatten() {
+        if used.contains(&layer.file_name()) {
+            continue;
+        }
+        let size = layer.metadata().map(|md| md.len()).unwrap_or(0);
+        std::fs::remove_file(layer.path()).map_err(|e| FsError::Io {
+            context: format!("remove {}", layer.path().display()),
+            source: e,
+        })?;
+        freed += size;
+    }
+    Ok(freed)
+}
+
+/// The media type of a fetched manifest, without parameters.
+pub(crate) fn media_type(fetched: &Fetched) -> &str {
+    fetched
+        .media_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+}
+
+/// Blob path of `digest` inside an image layout.
+fn blob_path(layout: &Path, digest: &str) -> PathBuf {
+    let (algorithm, hex) = digest.split_once(':').unwrap_or(("sha256", digest));
+    layout.join("blobs").join(algorithm).join(hex)
+}
+
+/// The cache entry of the image `digest` (a manifest or manifest list) for `platform`.
+fn entry(ctx: &ResolveContext<'_>, digest: &str, platform: &str) -> Result<CachePaths> {
+    let hex = digest.strip_prefix("sha256:").unwrap_or(digest);
+    let key = CacheKey {
+        service: "oci".into(),
+        revision: hex.chars().take(16).collect(),
+        worktree_hash: None,
+        platform: platform.to_string(),
+        schema: 1,
+    };
+    CachePaths::new(&ctx.config.cache_root, &key)
+}
+
+/// Linux images run on macOS and Windows hosts too, inside a VM of the same
+/// architecture, so images are matched on architecture.
+pub(crate) fn wanted_platform(ctx: &ResolveContext<'_>) -> String {
+    let arch = crate::platform::Platform::parse(ctx.platform)
+        .map(|p| p.generic_arch().to_string())
+        .unwrap_or_default();
+    crate::platform::normalize(&format!("linux-{arch}"))
+}
+
+/// The reference of an `OciImage` source; its `digest` field pins the digest.
+pub(crate) fn source_reference(reference: &str, digest: Option<&str>) -> Result<Reference> {
+    let mut parsed = Reference::parse(reference)?;
+    if let Some(field) = digest {
+        validate_digest(field).map_err(|reason| OciError::InvalidReference {
+            reference: reference.to_string(),
+            reason: format!("digest field: {reason}"),
+        })?;
+    }
+    match (&parsed.digest, digest) {
+        (Some(inline), Some(field)) if inline != field => {
+            return Err(OciError::InvalidReference {
+                reference: reference.to_string(),
+                reason: format!("pins {inline}, but the digest field pins {field}"),
+            }
+            .into());
+        }
+        (_, Some(field)) => parsed.digest = Some(field.to_string()),
+        _ => {}
+    }
+    Ok(parsed)
+}
+
+/// `reference` pinned to `digest`, the form to put in a lockfile.
+pub(crate) fn pinned(reference: &Reference, digest: &str) -> Reference {
+    Reference {
+        digest: Some(digest.to_string()),
+        ..reference.clone()
+    }
+}
+
+/// The finished cache entry for the source, if it pins a digest that was pulled before.
+pub(crate) fn cached_image(
+    ctx: &ResolveContext<'_>,
+    reference: &str,
+    digest: Option<&str>,
+) -> Result<Option<ResolvedArtifact>> {
+    let parsed = source_reference(reference, digest)?;
+    let Some(digest) = &parsed.digest else {
+        return Ok(None);
+    };
+    let paths = entry(ctx, digest, &wanted_platform(ctx))?;
+    Ok(finished(ctx, &paths, &parsed))
+}
+
+fn finished(
+    ctx: &ResolveContext<'_>,
+    paths: &CachePaths,
+    reference: &Reference,
+) -> Option<ResolvedArtifact> {
+    if !paths.out.join("index.json").is_file() || !attestation::cached_ok(ctx, &paths.meta) {
+        return None;
+    }
+    let meta: cache::Meta =
+        serde_json::from_slice(&std::fs::read(paths.meta.join("META.json")).ok()?).ok()?;
+    let digest = meta.image_digest?;
+    let top = meta.index_digest.as_deref().unwrap_or(&digest);
+    let config = layout_config(&paths.out).ok()?;
+    Some(ResolvedArtifact::OciImage {
+        reference: pinned(reference, top).to_string(),
+        digest,
+        platform: meta.platform,
+        layout: Some(paths.out.clone()),
+        config,
+    })
+}
+
+/// Records in `meta` how `client` reached the registry its entry was pulled
+/// from: through a mirror, or over plain HTTP, which is also a warning.
+pub(crate) fn record_transport(
+    ctx: &ResolveContext<'_>,
+    meta: &mut cache::Meta,
+    client: &Client<'_>,
+) {
+    let endpoint = client.endpoint();
+    meta.mirror = client.mirror();
+    if endpoint.scheme == "http" {
+        let warning = Warning::InsecureRegistry {
+            host: endpoint.host.clone(),
+        };
+        meta.insecure = true;
+        meta.warnings.push(warning.to_string());
+        ctx.warn(warning);
+    }
+}
+
+/// Fails with [`OciError::TagDrifted`] unless `tag` still points at `digest`,
+/// either directly or through a manifest list containing it.
+pub(crate) fn check_tag(
+    client: &mut Client<'_>,
+    tag: &str,
+    digest: &str,
+    accept: &[&str],
+) -> Result<()> {
+    let current = client.manifest(tag, accept)?;
+    if current.digest == digest {
+        return Ok(());
+    }
+    if INDEX_TYPES.contains(&media_type(&current))
+        && let Ok(index) = serde_json::from_slice::<ImageIndex>(&current.body)
+        && index.manifests.iter().any(|m| m.digest == digest)
+    {
+        return Ok(());
+    }
+    Err(OciError::TagDrifted {
+        reference: client.reference.to_string(),
+        tag: tag.to_string(),
+        pinned: digest.to_string(),
+        current: current.digest,
+    }
+    .into())
+}
+
+/// Pulls the source's image into the cache as an image layout.
+///
+/// A source pinning both a tag and a digest must still agree with the registry
+/// on what the tag points at. The returned reference is pinned to the digest
+/// that was resolved, whether or not the source pinned one.
+pub(crate) fn pull_image(
+    ctx: &ResolveContext<'_>,
+    reference: &str,
+    digest: Option<&str>,
+) -> Result<ResolvedArtifact> {
+    let parsed = source_reference(reference, digest)?;
+    let provider = ctx.config.credential_provider();
+    let mut client = Client::new(&parsed, provider.as_ref(), ctx);
+
+    let wanted = wanted_platform(ctx);
+    let accept = [MANIFEST_TYPES, INDEX_TYPES].concat();
+    let top = client.manifest(parsed.manifest_ref(), &accept)?;
+    if let (Some(tag), Some(digest)) = (&parsed.tag, &parsed.digest) {
+        check_tag(&mut client, tag, digest, &accept)?;
+    }
+    let (index_digest, manifest) = if INDEX_TYPES.contains(&media_type(&top)) {
+        let index: ImageIndex = serde_json::from_slice(&top.body)
+            .map_err(|e| client.error(format!("bad manifest list: {e}")))?;
+        let platforms = || index.manifests.iter().filter_map(|m| m.platform.as_ref());
+        let Some(selected) = index
+            .manifests
+            .iter()
+            .find(|m| m.platform.as_ref().is_some_and(|p| p.canonical() == wanted))
+        else {
+            return Err(OciError::NoMatchingPlatform {
+                reference: parsed.to_string(),
+                platform: wanted,
+                available: platforms()
+                    .filter(|p| p.os != "unknown")
+                    .map(ImagePlatform::canonical)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            }
+            .into());
+        };
+        let digest = selected.digest.clone();
+        (Some(top.digest), client.manifest(&digest, MANIFEST_TYPES)?)
+    } else {
+        (None, top)
+    };
+    if !MANIFEST_TYPES.contains(&media_type(&manifest)) {
+        return Err(OciError::UnsupportedManifest {
+            reference: parsed.to_string(),
+            media_type: manifest.media_type,
+        }
+        .into());
+    }
+    let image: ImageManifest = serde_json::from_slice(&manifest.body)
+        .map_err(|e| client.error(format!("bad manifest: {e}")))?;
+    let config_bytes = client.blob(&image.config.digest)?;
+    let config: ConfigBlob = serde_json::from_slice(&config_bytes)
+        .map_err(|e| client.error(format!("bad image config: {e}")))?;
+    let platform = config.platform.canonical();
+    if platform != wanted {
+        return Err(OciError::NoMatchingPlatform {
+            reference: parsed.to_string(),
+            platform: wanted,
+            available: platform,
+        }
+        .into());
+    }
+
+    // Keyed by what a reference would pin: the list's digest for multi-arch images.
+    let reference = pinned(&parsed, index_digest.as_deref().unwrap_or(&manifest.digest));
+    let paths = entry(
+        ctx,
+        reference.digest.as_deref().unwrap_or_default(),
+        &platform,
+    )?;
+    if let Some(resolved) = finished(ctx, &paths, &parsed) {
+        return Ok(resolved);
+    }
+    paths.create_dirs()?;
+    let _lock = paths.lock(ctx)?; // released on drop
+    if let Some(resolved) = finished(ctx, &paths, &parsed) {
+        return Ok(resolved);
+    }
+    let attestations = attestation::verify(ctx, &reference, Some(&paths.meta))?;
+
+    let layout = &paths.out;
+    let store = layer_store(&ctx.config.cache_root);
+    for dir in [layout, &store].map(|dir| dir.join("blobs").join("sha256")) {
+        std::fs::create_dir_all(&dir).map_err(|e| FsError::Io {
+            context: format!("mkdir {}", dir.display()),
+            source: e,
+        })?;
+    }
+    // Shared, so pulls run side by side; `prune_layers` takes it exclusively.
+    let _store_lock = lock_store(&store, false)?;
+    let total: u64 = image.layers.iter().map(|layer| layer.size).sum();
+    let missing: u64 = image
+        .layers
+        .iter()
+        .filter(|layer| !blob_path(&store, &layer.digest).is_file())
+        .map(|layer| layer.size)
+        .sum();
+    cache::ensure_space(layout, missing, &format!("pulling {parsed}"))?;
+    for layer in &image.layers {
+        let shared = blob_path(&store, &layer.digest);
+        if !shared.is_file() {
+            client.blob_to(ctx, &layer.digest, &shared)?;
+        }
+        link_or_copy(&shared, &blob_path(layout, &layer.digest))?;
+    }
+    cache::write_atomic(&blob_path(layout, &image.config.digest), &config_bytes)?;
+    cache::write_atomic(&blob_path(layout, &manifest.digest), &manifest.body)?;
+    cache::write_atomic(
+        &layout.join("oci-layout"),
+        br#"{"imageLayoutVersion":"1.0.0"}"#,
+    )?;
+
+    let mut meta = cache::Meta {
+        service: "oci".into(),
+        source: "oci".into(),
+        image: Some(reference.to_string()),
+        image_digest: Some(manifest.digest.clone()),
+        index_digest,
+        attestations,
+        host: crate::platform::host(),
+        platform: platform.clone(),
+        built_at: cache::timestamp(),
+        builder_schema: 1,
+        size: total + image.config.size + manifest.body.len() as u64,
+        ..Default::default()
+    };
+    record_transport(ctx, &mut meta, &client);
+    meta.write(ctx, &paths)?;
+
+    // The index goes last: its presence marks a complete entry.
+    let mut descriptor = serde_json::json!({
+        "mediaType": media_type(&manifest),
+        "digest": manifest.digest,
+        "size": manifest.body.len(),
+    });
+    if let Some(tag) = &parsed.tag {
+        descriptor["annotations"] = serde_json::json!({ "org.opencontainers.image.ref.name": tag });
+    }
+    let index = serde_json::json!({ "schemaVersion": 2, "manifests": [descriptor] });
+    cache::write_atomic(
+        &layout.join("index.json"),
+        &serde_json::to_vec_pretty(&index).expect("index serializes"),
+    )?;
+
+    Ok(ResolvedArtifact::OciImage {
+        reference: reference.to_string(),
+        digest: manifest.digest,
+        platform,
+        layout: Some(paths.out.clone()),
+        config: config.config.unwrap_or_default(),
+    })
+}
+
+/// Cache entry of `spec`'s binary taken from the image a reference pins to
+/// `digest`, for the platform being resolved for.
+fn binary_entry(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    digest: &str,
+) -> Result<(CachePaths, String)> {
+    let hex = digest.strip_prefix("sha256:").unwrap_or(digest);
+    let key = CacheKey {
+        service: spec.id.as_str().to_string(),
+        revision: format!("oci-{}", hex.chars().take(16).collect::<String>()),
+        worktree_hash: None,
+        platform: ctx.platform.to_string(),
+        schema: spec.builder_schema,
+    };
+    let bin_name = spec
+        .binary_names_for(ctx.platform)
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| spec.id.as_str().to_string());
+    Ok((CachePaths::new(&ctx.config.cache_root, &key)?, bin_name))
+}
+
+/// The finished binary entry for the source, if it pins a digest whose binary
+/// was extracted before.
+pub(crate) fn cached_binary(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    reference: &str,
+    digest: Option<&str>,
+) -> Result<Option<ResolvedArtifact>> {
+    let parsed = source_reference(reference, digest)?;
+    let Some(digest) = &parsed.digest else {
+        return Ok(None);
+    };
+    let (paths, bin_name) = binary_entry(ctx, spec, digest)?;
+    let path = paths.out.join(bin_name);
+    Ok((cache::usable_entry(ctx, &paths, &path, ctx.platform)?
+        && attestation::cached_ok(ctx, &paths.meta)
+        && signer_ok(ctx, spec, &paths))
+    .then_some(ResolvedArtifact::executable(path)))
+}
+
+/// Whether the binary entry in `paths` was checked against `spec`'s pinned
+/// cosign signers, if it has any; see [`crate::trust`] (`trust-store` feature).
+fn signer_ok(ctx: &ResolveContext<'_>, spec: &ToolSpec, paths: &CachePaths) -> bool {
+    #[cfg(feature = "trust-store")]
+    return crate::trust::cached_ok(ctx, &spec.id, crate::trust::Signer::Cosign, &paths.meta);
+    #[cfg(not(feature = "trust-store"))]
+    {
+        let _ = (ctx, spec, paths);
+        true
+    }
+}
+
+/// Checks `image`, resolved for `service`, against the service's pinned
+/// cosign signers; see [`crate::trust`].
+#[cfg(feature = "trust-store")]
+pub(crate) fn check_signers(
+    ctx: &ResolveContext<'_>,
+    service: &crate::registry::ServiceId,
+    image: &ResolvedArtifact,
+) -> Result<()> {
+    if let ResolvedArtifact::OciImage { reference, .. } = image {
+        crate::trust::check_cosign(ctx, service, &Reference::parse(reference)?)?;
+    }
+    Ok(())
+}
+
+/// Pulls the source's image and caches `spec`'s binary from it, with the
+/// companions found next to it, like a release asset.
+///
+/// The binary is the shallowest file in the image's flattened filesystem named
+/// like one of the spec's binary names, following symlinks.
+pub(crate) fn extract_binary(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    reference: &str,
+    digest: Option<&str>,
+) -> Result<ResolvedArtifact> {
+    if let Some(runtime) = crate::container::Runtime::for_backend(ctx.config.oci.backend)? {
+        let pulled = runtime.pull(ctx, reference, digest)?;
+        let image_digest = pulled.digest.clone();
+        return extract_from(ctx, spec, &pulled.reference, image_digest, None, || {
+            let container = runtime.create(&pulled.id)?;
+            Ok(Layers {
+                names: vec![format!("{} export", runtime.program())],
+                open: Box::new(move |_| container.export()),
+            })
+        });
+    }
+
+    let ResolvedArtifact::OciImage {
+        reference,
+        digest: image_digest,
+        layout: Some(layout),
+        ..
+    } = pull_image(ctx, reference, digest)?
+    else {
+        unreachable!("pulled images have a layout");
+    };
+    let pulled: Option<cache::Meta> =
+        std::fs::read(layout.with_file_name("meta").join("META.json"))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok());
+    extract_from(
+        ctx,
+        spec,
+        &Reference::parse(&reference)?,
+        image_digest,
+        pulled.as_ref(),
+        || {
+            let digests = layout_layers(&layout)?;
+            Ok(Layers {
+                names: digests.clone(),
+                open: Box::new(move |layer| {
+                    let path = blob_path(&layout, &digests[layer]);
+                    let file = std::fs::File::open(&path).map_err(|e| FsError::Io {
+                        context: format!("open {}", path.display()),
+                        source: e,
+                    })?;
+                    Ok(Box::new(file))
+                }),
+            })
+        },
+    )
+}
+
+/// An image's filesystem as tar streams, bottom layer first.
+struct Layers<'a> {
+    /// Shown in errors, e.g. the layers' digests.
+    names: Vec<String>,
+    open: Box<dyn Fn(usize) -> Result<Box<dyn Read + 'a>> + 'a>,
+}
+
+impl<'a> Layers<'a> {
+    /// Reads layer `layer`, gzip-compressed or not.
+    fn archive(&self, layer: usize) -> Result<tar::Archive<Box<dyn Read + 'a>>> {
+        let name = &self.names[layer];
+        let mut stream = std::io::BufReader::new((self.open)(layer)?);
+        let magic = std::io::BufRead::fill_buf(&mut stream).map_err(layer_error(name))?;
+        let reader: Box<dyn Read> = match magic {
+            [0x1f, 0x8b, ..] => Box::new(flate2::read::GzDecoder::new(stream)),
+            // zstd
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => {
+                return Err(UnpackError::UnsupportedFormat {
+                    archive: format!("{name} (zstd layer)"),
+                }
+                .into());
+            }
+            _ => Box::new(stream),
+        };
+        Ok(tar::Archive::new(reader))
+    }
+}
+
+/// Caches `spec`'s binary from the image `reference` pins, reading its
+/// filesystem from `layers` unless another process got there first.
+fn extract_from<'a>(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    reference: &Reference,
+    image_digest: String,
+    pulled: Option<&cache::Meta>,
+    layers: impl FnOnce() -> Result<Layers<'a>>,
+) -> Result<ResolvedArtifact> {
+    let top = reference.digest.clone().unwrap_or_default();
+    let (paths, bin_name) = binary_entry(ctx, spec, &top)?;
+    let out_bin = paths.out.join(&bin_name);
+    paths.create_dirs()?;
+    let _lock = paths.lock(ctx)?; // released on drop
+    if cache::usable_entry(ctx, &paths, &out_bin, ctx.platform)?
+        && attestation::cached_ok(ctx, &paths.meta)
+        && signer_ok(ctx, spec, &paths)
+    {
+        return Ok(ResolvedArtifact::executable(out_bin));
+    }
+    let attestations = attestation::verify(ctx, reference, Some(&paths.meta))?;
+    #[cfg(feature = "trust-store")]
+    let signer = crate::trust::check_cosign(ctx, &spec.id, reference)?;
+    #[cfg(not(feature = "trust-store"))]
+    let signer = None;
+
+    let work = paths.staging()?;
+    let mut meta = cache::Meta {
+        service: spec.id.as_str().to_string(),
+        source: "oci".into(),
+        index_digest: (top != image_digest).then(|| top.clone()),
+        image: Some(reference.to_string()),
+        image_digest: Some(image_digest),
+        attestations,
+        signer,
+        host: crate::platform::host(),
+        platform: ctx.platform.to_string(),
+        builder_schema: spec.builder_schema,
+        ..Default::default()
+    };
+    // How the image entry the layers come from was pulled.
+    if let Some(pulled) = pulled {
+        meta.insecure = pulled.insecure;
+        meta.mirror = pulled.mirror.clone();
+        meta.warnings = pulled.warnings.clone();
+    }
+    let result =
+        layers().and_then(|layers| extract_in(ctx, spec, &layers, &paths, &bin_name, meta, &work));
+    let _ = std::fs::remove_dir_all(&work);
+    Ok(ResolvedArtifact::executable(result?))
+}
+
+fn extract_in(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    layers: &Layers<'_>,
+    paths: &CachePaths,
+    bin_name: &str,
+    meta: cache::Meta,
+    work: &Path,
+) -> Result<PathBuf> {
+    let tree = flatten(layers)?;
+    let image = meta.image.clone().unwrap_or_default();
+
+    // Candidates are the paths a shell would find a file at, links included.
+    let files: Vec<PathBuf> = tree
+        .keys()
+        .filter(|path| resolve(&tree, path).is_some())
+        .map(PathBuf::from)
+        .collect();
+    // Layout hints describe release archives, not image filesystems.
+    let chosen = crate::archive::pick_binary(&files, spec, &[], ctx.platform, &image)?;
+    let chosen = chosen.to_string_lossy();
+    let dir = chosen.rsplit_once('/').map_or("", |(dir, _)| dir);
+
+    // (name in `out/`, path in the image, layer holding the file, path in that layer)
+    let mut wanted = vec![(bin_name.to_string(), chosen.to_string())];
+    for name in spec.companions.values() {
+        let name = crate::platform::exe_name(name, ctx.platform);
+        let path = if dir.is_empty() {
+            name.clone()
+        } else {
+            format!("{dir}/{name}")
+        };
+        wanted.push((name, path));
+    }
+    let wanted: Vec<_> = wanted
+        .into_iter()
+        .filter_map(|(name, path)| {
+            let (file, layer) = resolve(&tree, &path)?;
+            Some((name, path, layer, file))
+        })
+        .collect();
+
+    let mut extracted = Vec::new();
+    for (layer, archive) in layers.names.iter().enumerate() {
+        let mut needed: Vec<_> = wanted.iter().filter(|w| w.2 == layer).collect();
+        if needed.is_empty() {
+            continue;
+        }
+        for entry in layers
+            .archive(layer)?
+            .entries()
+            .map_err(layer_error(archive))?
+        {
+            let mut entry = entry.map_err(layer_error(archive))?;
+            let Some(path) = entry_path(&entry) else {
+                continue;
+            };
+            let mode = entry.header().mode().unwrap_or(0o755);
+            // Names linking to the same file get copies of it.
+            let mut first: Option<PathBuf> = None;
+            for (name, shown, _, _) in needed.extract_if(.., |w| w.3 == path) {
+                let dst = work.join(name);
+                match &first {
+                    Some(src) => cache::copy_atomic(src, &dst)?,
+                    None => {
+                        let mut file = std::fs::File::create(&dst).map_err(|e| FsError::Io {
+                            context: format!("create {}", dst.display()),
+                            source: e,
+                        })?;
+                        std::io::copy(&mut entry, &mut file).map_err(|e| UnpackError::Entry {
+                            archive: archive.clone(),
+                            entry: shown.clone(),
+                            source: Box::new(e),
+                        })?;
+                        first = Some(dst.clone());
+                    }
+                }
+                set_mode(&dst, mode)?;
+                extracted.push((name.clone(), dst));
+            }
+            if needed.is_empty() {
+                break;
+            }
+        }
+    }
+
+    let Some((_, binary)) = extracted.iter().find(|(name, _)| name == bin_name) else {
+        return Err(UnpackError::BinaryNotFound {
+            archive: image,
+            tried: chosen.to_string(),
+        }
+        .into());
+    };
+    let binary = binary.clone();
+    crate::pipeline::check_executable(&binary)?;
+    crate::binfmt::check(&binary, ctx.platform)?;
+    if ctx.targets_host() && ctx.platform.starts_with("linux-") {
+        crate::binfmt::check_glibc(&binary, &format!("{} from {image}", spec.id.as_str()))?;
+    }
+    let companions: Vec<_> = extracted
+        .into_iter()
+        .filter(|(name, _)| name != bin_name)
+        .collect();
+    cache::finalize(
+        ctx,
+        paths,
+        bin_name,
+        &binary,
+        &companions,
+        spec.version_probe.as_deref().filter(|_| ctx.targets_host()),
+        false,
+        meta,
+    )
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o777)).map_err(|e| {
+        FsError::Chmod {
+            path: path.to_path_buf(),
+            source: e,
+        }
+        .into()
+    })
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+/// Layer digests of the image in `layout`, bottom first.
+fn layout_layers(layout: &Path) -> Result<Vec<String>> {
+    Ok(layout_manifest(layout)?
+        .map(|image| image.layers.into_iter().map(|layer| layer.digest).collect())
+        .unwrap_or_default())
+}
+
+/// The config of the image in `layout`.
+fn layout_config(layout: &Path) -> Result<ImageConfig> {
+    let Some(image) = layout_manifest(layout)? else {
+        return Ok(ImageConfig::default());
+    };
+    let blob: ConfigBlob = read_json(&blob_path(layout, &image.config.digest))?;
+    Ok(blob.config.unwrap_or_default())
+}
+
+/// The manifest of the (only) image in `layout`.
+fn layout_manifest(layout: &Path) -> Result<Option<ImageManifest>> {
+    let index: ImageIndex = read_json(&layout.join("index.json"))?;
+    let Some(manifest) = index.manifests.first() else {
+        return Ok(None);
+    };
+    read_json(&blob_path(layout, &manifest.digest)).map(Some)
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T> {
+    let bytes = std::fs::read(path).map_err(|e| FsError::Io {
+        context: format!("read {}", path.display()),
+        source: e,
+    })?;
+    serde_json::from_slice(&bytes).map_err(|e| {
+        FsError::Io {
+            context: format!("parse {}", path.display()),
+            source: std::io::Error::other(e),
+        }
+        .into()
+    })
+}
+
+/// What a path in an image's filesystem is, as far as finding files goes.
+#[derive(Debug)]
+enum Node {
+    File,
+    Symlink(String),
+    /// A hard link to the file at the given path.
+    Hardlink(String),
+}
+
+/// Replays the layers into a map of every non-directory path in the image to
+/// the layer that last wrote it, honouring whiteouts.
+fn flatten(layers: &Layers<'_>) -> Result<BTreeMap<String, (usize, Node)>> {
+    let mut tree = BTreeMap::new();
+    for (layer, archive) in layers.names.iter().enumerate() {
+        for entry in layers
+            .archive(layer)?
+            .entries()
+            .map_err(layer_error(archive))?
+        {
+            let entry = entry.map_err(layer_error(archive))?;
+            let Some(path) = entry_path(&entry) else {
+                continue;
+            };
+            let (dir, name) = path.rsplit_once('/').unwrap_or(("", &path));
+            // Whiteouts hide what lower layers put there.
+            if name == ".wh..wh..opq" {
+                remove_below(&mut tree, dir, layer);
+                continue;
+            }
+            if let Some(hidden) = name.strip_prefix(".wh.") {
+                let hidden = if dir.is_empty() {
+                    hidden.to_string()
+                } else {
+                    format!("{dir}/{hidden}")
+                };
+                // Only lower layers are hidden, wherever in this one the
+                // whiteout comes.
+                if tree
+                    .get(&hidden)
+                    .is_some_and(|(written, _)| *written < layer)
+                {
+                    tree.remove(&hidden);
+                }
+                remove_below(&mut tree, &hidden, layer);
+                continue;
+            }
+            let link = || {
+                entry
+                    .link_name()
+                    .ok()
+                    .flatten()
+                    .map(|target| target.to_string_lossy().into_owned())
+            };
+            let node = match entry.header().entry_type() {
+                tar::EntryType::Regular | tar::EntryType::Continuous => Node::File,
+                tar::EntryType::Symlink => match link() {
+                    Some(target) => Node::Symlink(target),
+                    None => continue,
+                },
+                tar::EntryType::Link => match link() {
+                    Some(target) => Node::Hardlink(normalize(&target)),
+                    None => continue,
+                },
+                // Directories are implied by the paths below them.
+                tar::EntryType::Directory => continue,
+                _ => {
+                    tree.remove(&path);
+                    continue;
+                }
+            };
+            // A file replacing a directory hides what was below it.
+            remove_below(&mut tree, &path, layer);
+            tree.insert(path, (layer, node));
+        }
+    }
+    Ok(tree)
+}
+
+/// Removes what layers below `layer` put under `dir`.
+fn remove_below(tree: &mut BTreeMap<String, (usize, Node)>, dir: &str, layer: usize) {
+    let prefix = if dir.is_empty() {
+        String::new()
+    } else {
+        format!("{dir}/")
+    };
+    let below: Vec<String> = tree
+        .range(prefix.clone()..)
+        .take_while(|(path, _)| path.starts_with(&prefix))
+        .filter(|(_, (written, _))| *written < layer)
+        .map(|(path, _)| path.clone())
+        .collect();
+    for path in below {
+        tree.remove(&path);
+    }
+}
+
+/// Follows symlinks, in any component of `path`, and hard links to a regular
+/// file; returns its path as stored in the tree and its layer.
+fn resolve(tree: &BTreeMap<String, (usize, Node)>, path: &str) -> Option<(String, usize)> {
+    let mut path = normalize(path);
+    // As many hops as Linux follows before giving up with ELOOP.
+    'hops: for _ in 0..40 {
+        let parts: Vec<&str> = path.split('/').collect();
+        for end in 1..=parts.len() {
+            let last = end == parts.len();
+            match tree.get(&parts[..end].join("/")) {
+                Some((_, Node::Symlink(target))) => {
+                    let base = if target.starts_with('/') {
+                        String::new()
+                    } else {
+                        parts[..end - 1].join("/")
+                    };
+                    path = normalize(&format!("{base}/{target}/{}", parts[end..].join("/")));
+                    continue 'hops;
+                }
+                Some((_, Node::Hardlink(target))) if last => {
+                    path = target.clone();
+                    continue 'hops;
+                }
+                Some((layer, Node::File)) if last => return Some((path, *layer)),
+                // A file used as a directory.
+                Some(_) => return None,
+                None => {}
+            }
+        }
+        return None;
+    }
+    None
+}
+
+/// `path` relative to the image's root, with `.` and `..` resolved.
+fn normalize(path: &str) -> String {
+    let mut parts = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// The normalized path of a layer entry; `None` for the root itself.
+fn entry_path<R: Read>(entry: &tar::Entry<'_, R>) -> Option<String> {
+    let path = entry.path().ok()?;
+    let path = normalize(&path.to_string_lossy());
+    (!path.is_empty()).then_some(path)
+}
+
+fn layer_error(archive: &str) -> impl Fn(std::io::Error) -> UnpackError + '_ {
+    move |e| UnpackError::Tool {
+        archive: archive.to_string(),
+        source: Box::new(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    enum Entry<'a> {
+        File(&'a str),
+        Dir(&'a str),
+        Symlink(&'a str, &'a str),
+        Hardlink(&'a str, &'a str),
+    }
+    use Entry::*;
+
+    /// An uncompressed layer holding `entries`, in order.
+    fn layer(entries: &[Entry<'_>]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for entry in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(0o755);
+            header.set_size(0);
+            let (path, kind, target) = match *entry {
+                File(path) => (path, tar::EntryType::Regular, None),
+                Dir(path) => (path, tar::EntryType::Directory, None),
+                Symlink(path, target) => (path, tar::EntryType::Symlink, Some(target)),
+                Hardlink(path, target) => (path, tar::EntryType::Link, Some(target)),
+            };
+            header.set_entry_type(kind);
+            match target {
+                Some(target) => builder.append_link(&mut header, path, target),
+                None => builder.append_data(&mut header, path, std::io::empty()),
+            }
+            .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    /// Replays `layers`, bottom first.
+    fn replay(layers: &[Vec<u8>]) -> BTreeMap<String, (usize, Node)> {
+        let layers = Layers {
+            names: (0..layers.len()).map(|i| format!("layer {i}")).collect(),
+            open: Box::new(move |i| Ok(Box::new(std::io::Cursor::new(layers[i].clone())))),
+        };
+        flatten(&layers).unwrap()
+    }
+
+    #[test]
+    fn whiteouts_hide_lower_layers_only() {
+        let base = layer(&[Dir("bin"), File("bin/zcashd"), File("bin/zcash-cli")]);
+        let tree = replay(&[
+            base.clone(),
+            layer(&[File("bin/.wh.zcash-cli"), File("./bin/.wh.zcashd")]),
+        ]);
+        assert_eq!(resolve(&tree, "bin/zcashd"), None);
+        assert_eq!(resolve(&tree, "bin/zcash-cli"), None);
+
+        // A layer replacing a file it also whites out keeps its own copy,
+        // whichever comes first in it.
+        for upper in [
+            layer(&[File("bin/zcashd"), File("bin/.wh.zcashd")]),
+            layer(&[File("bin/.wh.zcashd"), File("bin/zcashd")]),
+        ] {
+            let tree = replay(&[base.clone(), upper]);
+            assert_eq!(resolve(&tree, "bin/zcashd"), Some(("bin/zcashd".into(), 1)));
+            assert_eq!(
+                resolve(&tree, "bin/zcash-cli"),
+                Some(("bin/zcash-cli".into(), 0))
+            );
+        }
+
+        // Whiting out a directory hides everything below it.
+        let tree = replay(&[base, layer(&[File(".wh.bin"), File("bin/zcashd")])]);
+        assert_eq!(resolve(&tree, "bin/zcashd"), Some(("bin/zcashd".into(), 1)));
+        assert_eq!(resolve(&tree, "bin/zcash-cli"), None);
+    }
+
+    #[test]
+    fn opaque_directories_hide_lower_contents() {
+        let tree = replay(&[
+            layer(&[
+                File("opt/a"),
+                File("opt/b"),
+                File("opt/sub/c"),
+                File("other"),
+            ]),
+            layer(&[File("opt/b"), File("opt/.wh..wh..opq"), File("opt/d")]),
+        ]);
+        assert_eq!(resolve(&tree, "opt/a"), None);
+        assert_eq!(resolve(&tree, "opt/sub/c"), None);
+        assert_eq!(resolve(&tree, "opt/b"), Some(("opt/b".into(), 1)));
+        assert_eq!(resolve(&tree, "opt/d"), Some(("opt/d".into(), 1)));
+        assert_eq!(resolve(&tree, "other"), Some(("other".into(), 0)));
+    }
+
+    #[test]
+    fn files_replacing_directories_hide_their_contents() {
+        let tree = replay(&[layer(&[File("opt/x/y")]), layer(&[File("opt/x")])]);
+        assert_eq!(resolve(&tree, "opt/x/y"), None);
+        assert_eq!(resolve(&tree, "opt/x"), Some(("opt/x".into(), 1)));
+    }
+
+    #[test]
+    fn symlinks_are_followed_in_any_component() {
+        let tree = replay(&[
+            layer(&[File("usr/bin/zcashd"), Symlink("bin", "usr/bin")]),
+            layer(&[
+                Symlink("usr/local/bin/zcashd", "../../../bin/zcashd"),
+                Symlink("opt/zcashd", "/usr/local/bin/zcashd"),
+                Symlink("loop/a", "b"),
+                Symlink("loop/b", "a"),
+                Symlink("dangling", "nowhere"),
+            ]),
+        ]);
+        let target = Some(("usr/bin/zcashd".into(), 0));
+        assert_eq!(resolve(&tree, "bin/zcashd"), target);
+        assert_eq!(resolve(&tree, "usr/local/bin/zcashd"), target);
+        assert_eq!(resolve(&tree, "/opt/zcashd"), target);
+        assert_eq!(resolve(&tree, "loop/a"), None);
+        assert_eq!(resolve(&tree, "dangling"), None);
+        // A file used as a directory.
+        assert_eq!(resolve(&tree, "usr/bin/zcashd/x"), None);
+    }
+
+    #[test]
+    fn hard_links_resolve_to_their_target() {
+        let base = layer(&[File("opt/zcashd"), Hardlink("bin/zcashd", "./opt/zcashd")]);
+        let tree = replay(std::slice::from_ref(&base));
+        assert_eq!(resolve(&tree, "bin/zcashd"), Some(("opt/zcashd".into(), 0)));
+
+        let tree = replay(&[base, layer(&[File("opt/.wh.zcashd")])]);
+        assert_eq!(resolve(&tree, "bin/zcashd"), None);
+    }
+}
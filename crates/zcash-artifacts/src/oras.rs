@@ -0,0 +1,344 @@
+//! Binaries published as [ORAS](https://oras.land) artifacts.
+//!
+//! Some projects push their binaries to a registry as OCI artifacts rather
+//! than container images, e.g. with `oras push ghcr.io/org/zebrad:v2.0.0
+//! zebrad`: each layer is a file, named by its `org.opencontainers.image.title`
+//! annotation. [`ArtifactSource::OrasArtifact`](crate::ArtifactSource::OrasArtifact)
+//! sources are pulled from the registry API like images, whatever the
+//! [`OciBackend`](crate::oci::OciBackend) and [`OciMode`](crate::oci::OciMode):
+//! same credentials, mirrors, insecure registries and attestation policy, and
+//! every manifest and file is checked against its digest.
+//!
+//! - A manifest list selects the artifact whose `platform` is the one being
+//!   resolved for; unlike images, `darwin/arm64` for a macOS binary.
+//! - With a service, its binary is the file named like one of the service's
+//!   binary names, with its companions next to it. An artifact holding a single
+//!   `.tar.gz`, `.tgz` or `.zip` file is unpacked first and searched like a
+//!   release asset.
+//! - Without one, the artifact must hold a single file. It is cached under the
+//!   repository's last path component, e.g. `zebrad` for
+//!   `ghcr.io/org/zebrad:v2.0.0`.
+//!
+//! The binary is cached as a regular executable entry, keyed by the digest the
+//! reference pins or resolves to; META records the artifact in `image` and
+//! `image_digest`. Manifests with an image config are refused, as those are
+//! [`OciImage`](crate::ArtifactSource::OciImage)s.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    ResolveContext, ResolvedArtifact, attestation,
+    cache::{self, CacheKey, CachePaths},
+    error::{FsError, OciError, Result, UnpackError},
+    oci::{self, Client, ImageIndex, Reference},
+    registry::ToolSpec,
+};
+
+/// Names the file a layer holds.
+pub(crate) const TITLE: &str = "org.opencontainers.image.title";
+
+/// Config media types of container images, which aren't artifacts.
+const IMAGE_CONFIG_TYPES: &[&str] = &[
+    "application/vnd.oci.image.config.v1+json",
+    "application/vnd.docker.container.image.v1+json",
+];
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Manifest {
+    // Read by the build cache (`local-build` feature).
+    #[serde(default)]
+    #[cfg_attr(not(feature = "local-build"), allow(dead_code))]
+    pub(crate) artifact_type: Option<String>,
+    #[serde(default)]
+    config: Option<Config>,
+    pub(crate) layers: Vec<Layer>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Config {
+    media_type: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Layer {
+    #[cfg_attr(not(feature = "local-build"), allow(dead_code))]
+    pub(crate) media_type: String,
+    pub(crate) digest: String,
+    #[serde(default)]
+    pub(crate) annotations: BTreeMap<String, String>,
+}
+
+impl Layer {
+    pub(crate) fn title(&self) -> Option<&str> {
+        self.annotations.get(TITLE).map(String::as_str)
+    }
+}
+
+/// Downloads the titled layers of `layers` into `dir`, each under its title,
+/// and returns the titles. Untitled layers aren't files and are skipped.
+pub(crate) fn download(
+    ctx: &ResolveContext<'_>,
+    client: &mut Client<'_>,
+    layers: &[Layer],
+    dir: &Path,
+) -> Result<Vec<String>> {
+    std::fs::create_dir_all(dir).map_err(|e| FsError::Io {
+        context: format!("mkdir {}", dir.display()),
+        source: e,
+    })?;
+    let mut names = Vec::new();
+    for layer in layers {
+        let Some(name) = layer.title() else {
+            continue;
+        };
+        // Titles name files in `dir`, nothing else.
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            return Err(client.error(format!("bad file name `{name}`")).into());
+        }
+        client.blob_to(ctx, &layer.digest, &dir.join(name))?;
+        names.push(name.to_string());
+    }
+    Ok(names)
+}
+
+/// Cache entry of the binary from the artifact a reference pins to `digest`,
+/// for the platform being resolved for.
+fn entry(
+    ctx: &ResolveContext<'_>,
+    spec: Option<&ToolSpec>,
+    reference: &Reference,
+    digest: &str,
+) -> Result<(CachePaths, String)> {
+    let hex = digest.strip_prefix("sha256:").unwrap_or(digest);
+    let key = CacheKey {
+        service: spec.map_or("oras".into(), |spec| spec.id.as_str().to_string()),
+        revision: format!("oras-{}", hex.chars().take(16).collect::<String>()),
+        worktree_hash: None,
+        platform: ctx.platform.to_string(),
+        schema: spec.map_or(1, |spec| spec.builder_schema),
+    };
+    let bin_name = match spec {
+        Some(spec) => spec
+            .binary_names_for(ctx.platform)
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| spec.id.as_str().to_string()),
+        None => {
+            let name = reference.repository.rsplit('/').next().unwrap_or_default();
+            crate::platform::exe_name(name, ctx.platform)
+        }
+    };
+    Ok((CachePaths::new(&ctx.config.cache_root, &key)?, bin_name))
+}
+
+/// The finished entry for the source, if it pins a digest whose binary was
+/// pulled before.
+pub(crate) fn cached_artifact(
+    ctx: &ResolveContext<'_>,
+    spec: Option<&ToolSpec>,
+    reference: &str,
+    digest: Option<&str>,
+) -> Result<Option<ResolvedArtifact>> {
+    let parsed = oci::source_reference(reference, digest)?;
+    let Some(digest) = &parsed.digest else {
+        return Ok(None);
+    };
+    let (paths, bin_name) = entry(ctx, spec, &parsed, digest)?;
+    let path = paths.out.join(bin_name);
+    Ok((cache::usable_entry(ctx, &paths, &path, ctx.platform)?
+        && attestation::cached_ok(ctx, &paths.meta))
+    .then_some(ResolvedArtifact::executable(path)))
+}
+
+/// Pulls the source's artifact and caches the binary it holds; see the
+/// [module docs](self).
+///
+/// A source pinning both a tag and a digest must still agree with the
+/// registry on what the tag points at.
+pub(crate) fn pull_artifact(
+    ctx: &ResolveContext<'_>,
+    spec: Option<&ToolSpec>,
+    reference: &str,
+    digest: Option<&str>,
+) -> Result<ResolvedArtifact> {
+    let parsed = oci::source_reference(reference, digest)?;
+    let provider = ctx.config.credential_provider();
+    let mut client = Client::new(&parsed, provider.as_ref(), ctx);
+    let accept = [oci::MANIFEST_TYPES, oci::INDEX_TYPES].concat();
+    let top = client.manifest(parsed.manifest_ref(), &accept)?;
+    if let (Some(tag), Some(digest)) = (&parsed.tag, &parsed.digest) {
+        oci::check_tag(&mut client, tag, digest, &accept)?;
+    }
+    let reference = oci::pinned(&parsed, &top.digest);
+    let (paths, bin_name) = entry(ctx, spec, &reference, &top.digest)?;
+    let out_bin = paths.out.join(&bin_name);
+    paths.create_dirs()?;
+    let _lock = paths.lock(ctx)?; // released on drop
+    if cache::usable_entry(ctx, &paths, &out_bin, ctx.platform)?
+        && attestation::cached_ok(ctx, &paths.meta)
+    {
+        return Ok(ResolvedArtifact::executable(out_bin));
+    }
+
+    let top_digest = top.digest.clone();
+    let manifest = if oci::INDEX_TYPES.contains(&oci::media_type(&top)) {
+        let index: ImageIndex = serde_json::from_slice(&top.body)
+            .map_err(|e| client.error(format!("bad manifest list: {e}")))?;
+        let wanted = crate::platform::normalize(ctx.platform);
+        let platforms = || index.manifests.iter().filter_map(|m| m.platform.as_ref());
+        let Some(selected) = index
+            .manifests
+            .iter()
+            .find(|m| m.platform.as_ref().is_some_and(|p| p.canonical() == wanted))
+        else {
+            return Err(OciError::NoMatchingPlatform {
+                reference: reference.to_string(),
+                platform: wanted,
+                available: platforms()
+                    .map(oci::ImagePlatform::canonical)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            }
+            .into());
+        };
+        let digest = selected.digest.clone();
+        client.manifest(&digest, oci::MANIFEST_TYPES)?
+    } else {
+        top
+    };
+    if !oci::MANIFEST_TYPES.contains(&oci::media_type(&manifest)) {
+        return Err(OciError::UnsupportedManifest {
+            reference: reference.to_string(),
+            media_type: manifest.media_type,
+        }
+        .into());
+    }
+    let parsed_manifest: Manifest = serde_json::from_slice(&manifest.body)
+        .map_err(|e| client.error(format!("bad manifest: {e}")))?;
+    if parsed_manifest
+        .config
+        .as_ref()
+        .is_some_and(|config| IMAGE_CONFIG_TYPES.contains(&config.media_type.as_str()))
+    {
+        return Err(client
+            .error("a container image, not an artifact; use an `OciImage` source")
+            .into());
+    }
+    let attestations = attestation::verify(ctx, &reference, Some(&paths.meta))?;
+
+    let mut meta = cache::Meta {
+        service: spec.map_or("oras".into(), |spec| spec.id.as_str().to_string()),
+        source: "oras".into(),
+        index_digest: (manifest.digest != top_digest).then_some(top_digest),
+        image: Some(reference.to_string()),
+        image_digest: Some(manifest.digest.clone()),
+        attestations,
+        host: crate::platform::host(),
+        platform: ctx.platform.to_string(),
+        builder_schema: spec.map_or(1, |spec| spec.builder_schema),
+        ..Default::default()
+    };
+    oci::record_transport(ctx, &mut meta, &client);
+
+    let work = paths.staging()?;
+    let result = pull_into(
+        ctx,
+        spec,
+        &mut client,
+        &parsed_manifest,
+        &paths,
+        &bin_name,
+        meta,
+        &work,
+    );
+    let _ = std::fs::remove_dir_all(&work);
+    Ok(ResolvedArtifact::executable(result?))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn pull_into(
+    ctx: &ResolveContext<'_>,
+    spec: Option<&ToolSpec>,
+    client: &mut Client<'_>,
+    manifest: &Manifest,
+    paths: &CachePaths,
+    bin_name: &str,
+    meta: cache::Meta,
+    work: &Path,
+) -> Result<PathBuf> {
+    let files = work.join("files");
+    let names = download(ctx, client, &manifest.layers, &files)?;
+    let image = meta.image.clone().unwrap_or_default();
+    let is_archive = |name: &str| {
+        [".tar.gz", ".tgz", ".zip"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+    };
+
+    let (binary, companions) = match (spec, names.as_slice()) {
+        (_, []) => return Err(client.error("no titled files in the artifact").into()),
+        (Some(spec), [name]) if is_archive(name) => {
+            let unpacked = work.join("unpacked");
+            crate::archive::extract(&files.join(name), name, &unpacked)?;
+            let binary = crate::archive::locate_binary(&unpacked, spec, ctx.platform)?;
+            let companions = companions(spec, binary.parent().unwrap_or(&unpacked), ctx.platform);
+            (binary, companions)
+        }
+        (Some(spec), _) => {
+            let binary = crate::archive::locate_binary(&files, spec, ctx.platform)?;
+            let companions = companions(spec, binary.parent().unwrap_or(&files), ctx.platform);
+            (binary, companions)
+        }
+        (None, [name]) if is_archive(name) => {
+            return Err(UnpackError::UnsupportedFormat {
+                archive: name.clone(),
+            }
+            .into());
+        }
+        (None, [name]) => (files.join(name), Vec::new()),
+        (None, names) => {
+            return Err(client
+                .error(format!(
+                    "{} files in the artifact ({}); name the service to pick one",
+                    names.len(),
+                    names.join(", ")
+                ))
+                .into());
+        }
+    };
+    crate::binfmt::check(&binary, ctx.platform)?;
+    if ctx.targets_host() && ctx.platform.starts_with("linux-") {
+        let what = spec.map_or(bin_name, |spec| spec.id.as_str());
+        crate::binfmt::check_glibc(&binary, &format!("{what} from {image}"))?;
+    }
+    cache::finalize(
+        ctx,
+        paths,
+        bin_name,
+        &binary,
+        &companions,
+        spec.and_then(|spec| spec.version_probe.as_deref())
+            .filter(|_| ctx.targets_host()),
+        false,
+        meta,
+    )
+}
+
+/// The spec's companions found in `dir`. Files are downloaded without their
+/// mode, so presence is enough; `finalize` sets the exec bits.
+fn companions(spec: &ToolSpec, dir: &Path, platform: &str) -> Vec<(String, PathBuf)> {
+    spec.companions
+        .values()
+        .map(|name| crate::platform::exe_name(name, platform))
+        .map(|name| (name.clone(), dir.join(name)))
+        .filter(|(_, path)| path.is_file())
+        .collect()
+}
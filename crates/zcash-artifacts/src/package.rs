@@ -0,0 +1,251 @@
+//! Container images of resolved executables.
+//!
+//! [`ArtifactResolver::package_image`] wraps an
+//! [`Executable`](ResolvedArtifact::Executable) or
+//! [`Bundle`](ResolvedArtifact::Bundle), e.g. a freshly built dirty `zcashd`,
+//! into a minimal image, so it can be run by orchestration that only takes
+//! images (Kubernetes, CI services, testcontainers):
+//!
+//! ```text
+//! FROM <PackageOptions::base_image>
+//! COPY bin/ /usr/local/bin/                     # the executables
+//! COPY --from=zcash-params . /root/.zcash-params  # each resource given
+//! EXPOSE <the service's ToolSpec::ports>
+//! ENTRYPOINT ["/usr/local/bin/<primary>"]
+//! ```
+//!
+//! The image is built with `docker build` or `podman build` and stays in the
+//! runtime's store, tagged `zcash-artifacts/<service>:bin-<digest prefix>`
+//! after the primary executable unless [`PackageOptions::tag`] says otherwise.
+//! Resources are passed as named build contexts rather than copied, as
+//! `zcash-params` alone is over a gigabyte.
+//!
+//! Executables must be Linux binaries; the image is built for their
+//! architecture, which may need emulation in the runtime.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ArtifactResolver, ResolvedArtifact, cache,
+    container::Runtime,
+    error::{FsError, InputError, Result},
+    oci::OciBackend,
+    registry::ServiceId,
+};
+
+/// Where executables are installed in packaged images.
+const BIN_DIR: &str = "/usr/local/bin";
+
+/// How [`ArtifactResolver::package_image`] builds an image.
+#[derive(Debug, Clone)]
+pub struct PackageOptions {
+    /// Image to build on; `gcr.io/distroless/cc-debian12` by default, which
+    /// has the C and C++ runtime libraries the nodes link against. Static
+    /// binaries can use `scratch`.
+    pub base_image: String,
+    /// Host directory of each resource to copy in, by resource name, e.g.
+    /// `zcash-params`. Resources the service declares but that aren't given
+    /// here are left for the container's mounts.
+    pub resources: BTreeMap<String, PathBuf>,
+    /// Path of each resource in the image, by resource name. By default,
+    /// `zcash-params` goes to `/root/.zcash-params`.
+    pub resource_mounts: BTreeMap<String, String>,
+    /// The image's tag; see the [module docs](self) for the default.
+    pub tag: Option<String>,
+    /// The runtime to build with; [`OciBackend::Registry`] means the same as
+    /// [`OciBackend::Auto`].
+    pub backend: OciBackend,
+}
+
+impl Default for PackageOptions {
+    fn default() -> Self {
+        Self {
+            base_image: "gcr.io/distroless/cc-debian12".into(),
+            resources: BTreeMap::new(),
+            resource_mounts: BTreeMap::from([(
+                "zcash-params".into(),
+                "/root/.zcash-params".into(),
+            )]),
+            tag: None,
+            backend: OciBackend::Auto,
+        }
+    }
+}
+
+impl ArtifactResolver {
+    /// Packages `artifact`, resolved for `service`, into a container image;
+    /// see [`crate::package`].
+    ///
+    /// Returns the image as an [`OciImage`](ResolvedArtifact::OciImage)
+    /// without a `layout`, like images built from a repo.
+    pub fn package_image(
+        &self,
+        service: &ServiceId,
+        artifact: &ResolvedArtifact,
+        options: &PackageOptions,
+    ) -> Result<ResolvedArtifact> {
+        let invalid = |reason: String| InputError::InvalidSource {
+            service: service.clone(),
+            reason,
+        };
+        let (primary, executables): (&Path, Vec<&Path>) = match artifact {
+            ResolvedArtifact::Executable { path, .. } => (path, vec![path]),
+            ResolvedArtifact::Bundle { executables, .. } => {
+                let primary = artifact
+                    .primary_path()
+                    .ok_or_else(|| invalid("bundle has no primary executable".into()))?;
+                (
+                    primary,
+                    executables.values().map(PathBuf::as_path).collect(),
+                )
+            }
+            ResolvedArtifact::OciImage { .. } => {
+                return Err(invalid("already a container image".into()).into());
+            }
+        };
+
+        // The image is for the architecture the binaries are built for.
+        let arch = match crate::binfmt::sniff(primary)? {
+            Some(info) if info.format == crate::binfmt::Format::Elf && info.os.is_none() => {
+                info.archs.into_iter().next().unwrap_or_default()
+            }
+            _ => {
+                return Err(
+                    invalid(format!("{} is not a Linux executable", primary.display())).into(),
+                );
+            }
+        };
+        let platform = crate::platform::normalize(&format!("linux-{arch}"));
+        for path in &executables {
+            crate::binfmt::check(path, &platform)?;
+        }
+
+        let resources: Vec<(&str, &Path, &str)> = self
+            .resources_of(service)
+            .into_iter()
+            .filter_map(|name| Some((name, options.resources.get(name)?.as_path())))
+            .map(|(name, dir)| {
+                let mount = options.resource_mounts.get(name).ok_or_else(|| {
+                    invalid(format!(
+                        "no path for resource `{name}`; \
+                         set it in `PackageOptions::resource_mounts`"
+                    ))
+                })?;
+                Ok((name, dir, mount.as_str()))
+            })
+            .collect::<Result<_>>()?;
+
+        let tag = match &options.tag {
+            Some(tag) => tag.clone(),
+            None => {
+                let (digest, _) = cache::digest_file(primary)?;
+                format!("zcash-artifacts/{}:bin-{}", service.as_str(), &digest[..12])
+            }
+        };
+
+        let runtime = Runtime::for_build(options.backend)?;
+        // Next to the cache, so executables are hard-linked rather than copied.
+        let context = self
+            .config
+            .cache_root
+            .join(format!(".package-{}", std::process::id()));
+        let result = build_in(
+            &runtime,
+            &context,
+            &executables,
+            primary,
+            &resources,
+            self.registry()
+                .get(service)
+                .map(|spec| spec.ports.as_slice())
+                .unwrap_or_default(),
+            options,
+            &platform,
+            &tag,
+        );
+        let _ = std::fs::remove_dir_all(&context);
+        result?;
+
+        let image = runtime.local_image(&tag)?;
+        Ok(ResolvedArtifact::OciImage {
+            reference: tag,
+            digest: image.id,
+            platform: image.platform,
+            layout: None,
+            config: image.config,
+        })
+    }
+}
+
+/// Writes the build context into `context` and builds the image as `tag`.
+#[allow(clippy::too_many_arguments)]
+fn build_in(
+    runtime: &Runtime,
+    context: &Path,
+    executables: &[&Path],
+    primary: &Path,
+    resources: &[(&str, &Path, &str)],
+    ports: &[u16],
+    options: &PackageOptions,
+    platform: &str,
+    tag: &str,
+) -> Result<()> {
+    let bin = context.join("bin");
+    std::fs::create_dir_all(&bin).map_err(|e| FsError::Io {
+        context: format!("mkdir {}", bin.display()),
+        source: e,
+    })?;
+    for path in executables {
+        let dst = bin.join(file_name(path));
+        if std::fs::hard_link(path, &dst).is_err() {
+            cache::copy_atomic(path, &dst)?;
+        }
+    }
+
+    let mut dockerfile = format!("FROM {}\nCOPY bin/ {BIN_DIR}/\n", options.base_image);
+    for (name, _, mount) in resources {
+        dockerfile.push_str(&format!("COPY --from={name} . {mount}\n"));
+    }
+    if !ports.is_empty() {
+        let ports: Vec<_> = ports.iter().map(u16::to_string).collect();
+        dockerfile.push_str(&format!("EXPOSE {}\n", ports.join(" ")));
+    }
+    dockerfile.push_str(&format!(
+        "ENTRYPOINT [\"{BIN_DIR}/{}\"]\n",
+        file_name(primary)
+    ));
+    let dockerfile_path = context.join("Dockerfile");
+    cache::write_atomic(&dockerfile_path, dockerfile.as_bytes())?;
+
+    let platform = crate::container::runtime_platform(platform);
+    let contexts: Vec<String> = resources
+        .iter()
+        .map(|(name, dir, _)| format!("{name}={}", dir.display()))
+        .collect();
+    let dockerfile_arg = dockerfile_path.to_string_lossy();
+    let context_arg = context.to_string_lossy();
+    let mut args = vec![
+        "build",
+        "--quiet",
+        "--file",
+        &dockerfile_arg,
+        "--tag",
+        tag,
+        "--platform",
+        &platform,
+    ];
+    for named in &contexts {
+        args.extend(["--build-context", named]);
+    }
+    args.push(&context_arg);
+    runtime.run(&args).map(drop)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
@@ -0,0 +1,1688 @@
+//! The standard layers behind [`DefaultProvider`](crate::DefaultProvider).
+//!
+//! Each layer handles the sources it understands and returns `Ok(None)` for the
+//! rest, so the next layer gets a turn. In order:
+//!
+//! - [`LocalLayer`] (`local`): `LocalPath` sources.
+//! - [`CacheLayer`] (`cache`): finalized cache entries for releases, URLs, builds and
+//!   pinned images and artifacts.
+//! - [`ReleaseLayer`] (`release`, `http` feature): downloads, verifies and caches
+//!   `Release`, `Url`, `Guix`, `Homebrew` and `Deb` sources.
+//! - [`OciLayer`] (`oci`, `oci` feature): pulls `OciImage` sources and extracts
+//!   service binaries from them, and the binaries of `OrasArtifact` sources.
+//! - [`NixLayer`] (`nix`, `nix` feature): builds `Nix` sources with `nix build`
+//!   and caches their binary; see [`crate::nix`].
+//! - [`BuildCacheLayer`] (`build-cache`, `local-build` and `oci` features): pulls
+//!   `Build` sources from a registry shared as a build cache; see [`crate::build_cache`].
+//! - [`BuildLayer`] (`build`, `local-build` feature): builds `Build` sources into the cache,
+//!   or, for image recipes, into the container runtime.
+//!
+//! Custom layers (e.g. a corporate mirror) are usually inserted after `cache`, so
+//! they run before anything touches the network.
+
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "local-build")]
+use crate::git::{self, GitPolicy};
+#[cfg(feature = "http")]
+use crate::transport::Method;
+#[cfg(any(feature = "http", feature = "local-build"))]
+use crate::warning::Warning;
+use crate::{
+    ArtifactProvider, ArtifactSource, ResolveContext, ResolvedArtifact, binfmt,
+    cache::{self, CacheKey, CachePaths},
+    error::{FsError, InputError, LocateError, Result},
+    observe::Event,
+    plan::Step,
+    registry::{ServiceId, ToolSpec},
+};
+
+/// Resolves [`ArtifactSource::LocalPath`] after checking it is executable and,
+/// if its header is recognized, built for the target platform.
+pub struct LocalLayer;
+
+impl ArtifactProvider for LocalLayer {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    fn resolve(
+        &self,
+        src: &ArtifactSource,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<Option<ResolvedArtifact>> {
+        let ArtifactSource::LocalPath(path) = src else {
+            return Ok(None);
+        };
+        check_executable(path)?;
+        // Files we can't identify are the caller's business; known formats must fit.
+        if binfmt::sniff(path)?.is_some() {
+            binfmt::check(path, ctx.platform)?;
+        }
+        Ok(Some(ResolvedArtifact::executable(path.clone())))
+    }
+
+    fn plan(&self, src: &ArtifactSource, ctx: &ResolveContext<'_>) -> Result<Option<Step>> {
+        Ok(self.resolve(src, ctx)?.and_then(|resolved| match resolved {
+            ResolvedArtifact::Executable { path, .. } => Some(Step::Local { path }),
+            _ => None,
+        }))
+    }
+}
+
+/// Returns finalized cache entries without downloading or building anything.
+///
+/// A `Build` source still consults git to compute its key; see the
+/// [cache docs](crate::cache) for how keys are derived. With signing
+/// configured, hits must pass their signature check; see `crate::signing`
+/// (`signing` feature).
+pub struct CacheLayer;
+
+impl ArtifactProvider for CacheLayer {
+    fn name(&self) -> &str {
+        "cache"
+    }
+
+    fn resolve(
+        &self,
+        src: &ArtifactSource,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<Option<ResolvedArtifact>> {
+        let (cached, entry) = cached(src, ctx)?;
+        match &cached {
+            Some(artifact) => {
+                crate::trace::debug!(path = ?artifact.primary_path(), "cache hit");
+                ctx.emit(Event::CacheHit {
+                    source: src,
+                    artifact,
+                });
+            }
+            None if keeps_entries(src) => ctx.emit(Event::CacheMiss {
+                source: src,
+                entry: entry.as_deref(),
+            }),
+            None => {}
+        }
+        Ok(cached)
+    }
+
+    fn plan(&self, src: &ArtifactSource, ctx: &ResolveContext<'_>) -> Result<Option<Step>> {
+        Ok(cached(src, ctx)?
+            .0
+            .map(|artifact| Step::Cached { artifact }))
+    }
+}
+
+/// Whether [`CacheLayer`] looks up entries for `src`.
+fn keeps_entries(src: &ArtifactSource) -> bool {
+    match src {
+        ArtifactSource::Release { .. } => true,
+        #[cfg(feature = "http")]
+        ArtifactSource::Url { .. } | ArtifactSource::Guix { .. } => true,
+        #[cfg(feature = "local-build")]
+        ArtifactSource::Build { .. } => true,
+        #[cfg(feature = "oci")]
+        ArtifactSource::OciImage { .. } | ArtifactSource::OrasArtifact { .. } => true,
+        _ => false,
+    }
+}
+
+/// [`CacheLayer`]'s lookup, with the entry it looked in when the source's key
+/// leads to one.
+fn cached(
+    src: &ArtifactSource,
+    ctx: &ResolveContext<'_>,
+) -> Result<(Option<ResolvedArtifact>, Option<PathBuf>)> {
+    let entry = match src {
+        #[cfg(feature = "oci")]
+        ArtifactSource::OciImage {
+            reference,
+            digest,
+            service,
+        } => {
+            use crate::{container::Runtime, oci::OciMode};
+
+            return match (ctx.config.oci.mode, service) {
+                // Runtimes keep their own images; asking them is the cache lookup.
+                (OciMode::Image, _) if Runtime::for_backend(ctx.config.oci.backend)?.is_some() => {
+                    Ok(None)
+                }
+                (OciMode::Image, _) => {
+                    let hit = crate::oci::cached_image(ctx, reference, digest.as_deref())?;
+                    #[cfg(feature = "trust-store")]
+                    if let (Some(service), Some(image)) = (service, &hit) {
+                        crate::oci::check_signers(ctx, service, image)?;
+                    }
+                    Ok(hit)
+                }
+                (OciMode::Extract, Some(service)) => crate::oci::cached_binary(
+                    ctx,
+                    registered(ctx, service)?,
+                    reference,
+                    digest.as_deref(),
+                ),
+                (OciMode::Extract, None) => Ok(None),
+            }
+            .map(|hit| (hit, None));
+        }
+        #[cfg(feature = "oci")]
+        ArtifactSource::OrasArtifact {
+            reference,
+            digest,
+            service,
+        } => {
+            let spec = service.as_ref().map(|s| registered(ctx, s)).transpose()?;
+            return crate::oras::cached_artifact(ctx, spec, reference, digest.as_deref())
+                .map(|hit| (hit, None));
+        }
+        ArtifactSource::Release { service, version } => {
+            let spec = registered(ctx, service)?;
+            let mut entry = release_entry(ctx, spec, version, ctx.platform)?;
+            let mut platform = ctx.platform;
+            // Without a native entry, one of an x86_64 asset used with Rosetta.
+            if let Some(fallback) = rosetta_fallback(ctx)
+                && !entry.0.out.join(&entry.1).exists()
+            {
+                let x86 = release_entry(ctx, spec, version, fallback)?;
+                if x86.0.out.join(&x86.1).exists() {
+                    (entry, platform) = (x86, fallback);
+                }
+            }
+            let (paths, bin_name) = entry;
+            if !checks_ok(ctx, Some(spec), &paths) {
+                return Ok((None, Some(paths.root)));
+            }
+            Some((paths, bin_name, platform.to_string()))
+        }
+        #[cfg(feature = "http")]
+        ArtifactSource::Url { url, checksum } => {
+            let (paths, bin_name) = url_entry(ctx, url, checksum)?;
+            Some((paths, bin_name, ctx.platform.to_string()))
+        }
+        #[cfg(feature = "http")]
+        ArtifactSource::Guix {
+            service,
+            version,
+            rebuilds,
+        } => {
+            return crate::guix::cached(ctx, registered(ctx, service)?, version, rebuilds)
+                .map(|hit| (hit, None));
+        }
+        #[cfg(feature = "local-build")]
+        ArtifactSource::Build {
+            service,
+            repo,
+            refspec,
+            policy,
+            expected_output,
+            target,
+        } => {
+            let spec = registered(ctx, service)?;
+            let state = BuildState::prepare(
+                ctx,
+                spec,
+                repo,
+                refspec.as_deref(),
+                *policy,
+                expected_output.as_deref(),
+                target.as_deref(),
+            )?;
+            // Reported here, where every resolution of the source looks first.
+            if let Some(worktree_hash) = &state.worktree_hash {
+                ctx.warn(Warning::DirtyBuild {
+                    repo: repo.clone(),
+                    worktree_hash: worktree_hash.clone(),
+                });
+            }
+            #[cfg(feature = "oci")]
+            if let Some(image) = spec.build.as_ref().and_then(|recipe| recipe.image()) {
+                let runtime = crate::container::Runtime::for_build(image.backend())?;
+                return Ok((built_image(&state, &runtime), Some(state.paths.root)));
+            }
+            Some((state.paths, state.bin_name, state.platform))
+        }
+        _ => None,
+    };
+    let Some((paths, bin_name, platform)) = entry else {
+        return Ok((None, None));
+    };
+    let path = paths.out.join(bin_name);
+    let hit = cache::usable_entry(ctx, &paths, &path, &platform)?
+        .then_some(ResolvedArtifact::executable(path));
+    Ok((hit, Some(paths.root)))
+}
+
+/// Whether the release entry in `paths` passed the checks configured now:
+/// against `spec`'s pinned minisign signers, if it has any (see
+/// [`crate::trust`], `trust-store` feature), and against a required
+/// transparency log (see `crate::transparency`, `transparency` feature).
+pub(crate) fn checks_ok(
+    ctx: &ResolveContext<'_>,
+    spec: Option<&ToolSpec>,
+    paths: &CachePaths,
+) -> bool {
+    #[cfg(feature = "transparency")]
+    if spec.is_some() && !crate::transparency::cached_ok(ctx, &paths.meta) {
+        return false;
+    }
+    #[cfg(feature = "trust-store")]
+    if let Some(spec) = spec {
+        return crate::trust::cached_ok(ctx, &spec.id, crate::trust::Signer::Minisign, &paths.meta);
+    }
+    let _ = (ctx, spec, paths);
+    true
+}
+
+/// Downloads `Release` and `Url` sources, verifies their sha256 and caches them;
+/// likewise `Guix` sources, after checking them against their rebuilds (see
+/// [`crate::guix`]), the bottles of `Homebrew` sources without an
+/// installed keg (see `crate::homebrew`, `archive` feature), and the packages
+/// of `Deb` sources (see `crate::deb`, `deb` feature).
+///
+/// Release assets ending in `.tar.gz`/`.tgz` or `.zip` are extracted (with the `archive`
+/// feature) and the binary is found with [`crate::archive::locate_binary`];
+/// anything else is taken to be the binary itself.
+#[cfg(feature = "http")]
+pub struct ReleaseLayer;
+
+#[cfg(feature = "http")]
+impl ArtifactProvider for ReleaseLayer {
+    fn name(&self) -> &str {
+        "release"
+    }
+
+    fn resolve(
+        &self,
+        src: &ArtifactSource,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<Option<ResolvedArtifact>> {
+        match src {
+            ArtifactSource::Release { service, version } => {
+                let spec = registered(ctx, service)?;
+                let mut meta = cache::Meta {
+                    service: service.as_str().to_string(),
+                    source: src.kind().into(),
+                    release: Some(version.clone()),
+                    ..Default::default()
+                };
+                let Some((url, checksum, platform)) = release_asset(ctx, spec, version, &mut meta)?
+                else {
+                    return Ok(None);
+                };
+                let (paths, bin_name) = release_entry(ctx, spec, version, &platform)?;
+                download_into(ctx, Some(spec), &url, &checksum, &paths, &bin_name, meta).map(Some)
+            }
+            ArtifactSource::Url { url, checksum } => {
+                let (paths, bin_name) = url_entry(ctx, url, checksum)?;
+                let meta = cache::Meta {
+                    service: "url".into(),
+                    source: src.kind().into(),
+                    ..Default::default()
+                };
+                download_into(ctx, None, url, checksum, &paths, &bin_name, meta).map(Some)
+            }
+            ArtifactSource::Guix {
+                service,
+                version,
+                rebuilds,
+            } => {
+                let spec = registered(ctx, service)?;
+                let releases = spec
+                    .guix
+                    .as_ref()
+                    .ok_or_else(|| InputError::InvalidSource {
+                        service: service.clone(),
+                        reason: "service has no Guix releases".into(),
+                    })?;
+                crate::guix::fetch(ctx, spec, releases, version, rebuilds).map(Some)
+            }
+            #[cfg(feature = "archive")]
+            ArtifactSource::Homebrew {
+                service,
+                formula,
+                version,
+            } => {
+                let formula = formula.as_deref().unwrap_or(service.as_str());
+                crate::homebrew::resolve(
+                    ctx,
+                    registered(ctx, service)?,
+                    formula,
+                    version.as_deref(),
+                )
+                .map(Some)
+            }
+            #[cfg(feature = "deb")]
+            ArtifactSource::Deb { service, package } => {
+                crate::deb::resolve(ctx, registered(ctx, service)?, package).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn plan(&self, src: &ArtifactSource, ctx: &ResolveContext<'_>) -> Result<Option<Step>> {
+        let download = |url: &url::Url, paths: CachePaths| -> Result<Option<Step>> {
+            ctx.config.network.check(url)?;
+            Ok(Some(Step::Download {
+                url: Some(crate::credentials::redact(url)),
+                bytes: content_length(ctx, url, ctx.config.credential_provider().as_ref())?,
+                entry: Some(paths.root),
+            }))
+        };
+        // These find their asset in an index, which planning doesn't fetch.
+        let indexed = |service| -> Result<Option<Step>> {
+            registered(ctx, service)?;
+            Ok(Some(Step::Download {
+                url: None,
+                bytes: None,
+                entry: None,
+            }))
+        };
+        match src {
+            ArtifactSource::Release { service, version } => {
+                let spec = registered(ctx, service)?;
+                let mut meta = cache::Meta::default();
+                let Some((url, _, platform)) = release_asset(ctx, spec, version, &mut meta)? else {
+                    return Ok(None);
+                };
+                download(&url, release_entry(ctx, spec, version, &platform)?.0)
+            }
+            ArtifactSource::Url { url, checksum } => {
+                download(url, url_entry(ctx, url, checksum)?.0)
+            }
+            ArtifactSource::Guix { service, .. } => indexed(service),
+            #[cfg(feature = "archive")]
+            ArtifactSource::Homebrew { service, .. } => indexed(service),
+            #[cfg(feature = "deb")]
+            ArtifactSource::Deb { service, .. } => indexed(service),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// The asset of `spec`'s release `version` for the target platform, with its
+/// checksum and the platform it is for; `None` if the service publishes no
+/// releases. Falling back to an x86_64 asset under Rosetta is recorded in
+/// `meta`.
+#[cfg(feature = "http")]
+fn release_asset(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    version: &str,
+    meta: &mut cache::Meta,
+) -> Result<Option<(url::Url, String, String)>> {
+    let Some(index) = &spec.releases else {
+        return Ok(None);
+    };
+    let lookup = |platform: &str| {
+        let asset_name = spec.asset_name(version, platform);
+        index.lookup(version, platform, asset_name.as_deref(), ctx)
+    };
+    // On musl hosts a musl asset wins; glibc ones must pass the glibc check.
+    if is_musl_host(ctx)
+        && let Some((url, checksum)) = lookup(&crate::platform::musl_flavor(ctx.platform))?
+    {
+        return Ok(Some((url, checksum, ctx.platform.to_string())));
+    }
+    spec.requirements.check(&spec.id, ctx.platform, true)?;
+    if let Some((url, checksum)) = lookup(ctx.platform)? {
+        return Ok(Some((url, checksum, ctx.platform.to_string())));
+    }
+    // With Rosetta, an x86_64 asset still runs if there is no native one.
+    if let Some(fallback) = rosetta_fallback(ctx)
+        && let Some((url, checksum)) = lookup(fallback)?
+    {
+        let warning = Warning::PlatformFallback {
+            version: version.to_string(),
+            requested: ctx.platform.to_string(),
+            used: fallback.to_string(),
+        };
+        meta.platform = fallback.into();
+        meta.warnings.push(warning.to_string());
+        ctx.warn(warning);
+        return Ok(Some((url, checksum, fallback.to_string())));
+    }
+    Err(LocateError::NoAsset {
+        service: spec.id.clone(),
+        version: version.to_string(),
+        platform: ctx.platform.to_string(),
+    }
+    .into())
+}
+
+/// Pulls `OciImage` sources, and under [`OciMode::Extract`](crate::oci::OciMode::Extract)
+/// caches their service's binary; see [`crate::oci`]. Also caches the binaries
+/// of `OrasArtifact` sources; see [`crate::oras`].
+#[cfg(feature = "oci")]
+pub struct OciLayer;
+
+#[cfg(feature = "oci")]
+impl ArtifactProvider for OciLayer {
+    fn name(&self) -> &str {
+        "oci"
+    }
+
+    fn resolve(
+        &self,
+        src: &ArtifactSource,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<Option<ResolvedArtifact>> {
+        use crate::{
+            container::Runtime,
+            oci::{self, OciMode},
+        };
+
+        match src {
+            ArtifactSource::OciImage {
+                reference,
+                digest,
+                service,
+            } => match (ctx.config.oci.mode, service) {
+                (OciMode::Image, _) => {
+                    let image = match Runtime::for_backend(ctx.config.oci.backend)? {
+                        Some(runtime) => {
+                            let pulled = runtime.pull(ctx, reference, digest.as_deref())?;
+                            crate::attestation::verify(ctx, &pulled.reference, None)?;
+                            pulled.into_resolved()
+                        }
+                        None => oci::pull_image(ctx, reference, digest.as_deref())?,
+                    };
+                    #[cfg(feature = "trust-store")]
+                    if let Some(service) = service {
+                        oci::check_signers(ctx, service, &image)?;
+                    }
+                    Ok(Some(image))
+                }
+                (OciMode::Extract, Some(service)) => {
+                    let spec = registered(ctx, service)?;
+                    oci::extract_binary(ctx, spec, reference, digest.as_deref()).map(Some)
+                }
+                // Nothing to extract without a service; left to custom layers.
+                (OciMode::Extract, None) => Ok(None),
+            },
+            ArtifactSource::OrasArtifact {
+                reference,
+                digest,
+                service,
+            } => {
+                let spec = service.as_ref().map(|s| registered(ctx, s)).transpose()?;
+                crate::oras::pull_artifact(ctx, spec, reference, digest.as_deref()).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn plan(&self, src: &ArtifactSource, ctx: &ResolveContext<'_>) -> Result<Option<Step>> {
+        use crate::oci::{self, OciMode};
+
+        let (reference, digest) = match src {
+            ArtifactSource::OciImage {
+                reference,
+                digest,
+                service,
+            } => match (ctx.config.oci.mode, service) {
+                (OciMode::Image, _) => (reference, digest),
+                (OciMode::Extract, Some(service)) => {
+                    registered(ctx, service)?;
+                    (reference, digest)
+                }
+                (OciMode::Extract, None) => return Ok(None),
+            },
+            ArtifactSource::OrasArtifact {
+                reference,
+                digest,
+                service,
+            } => {
+                service.as_ref().map(|s| registered(ctx, s)).transpose()?;
+                (reference, digest)
+            }
+            _ => return Ok(None),
+        };
+        let reference = oci::source_reference(reference, digest.as_deref())?;
+        Ok(Some(Step::Pull {
+            reference: reference.to_string(),
+        }))
+    }
+}
+
+/// Builds `Nix` sources with `nix build` and caches their binary, keyed by its
+/// store path; see [`crate::nix`]. Nix evaluates the flake on every resolution,
+/// so [`CacheLayer`] leaves these sources alone.
+#[cfg(feature = "nix")]
+pub struct NixLayer;
+
+#[cfg(feature = "nix")]
+impl ArtifactProvider for NixLayer {
+    fn name(&self) -> &str {
+        "nix"
+    }
+
+    fn resolve(
+        &self,
+        src: &ArtifactSource,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<Option<ResolvedArtifact>> {
+        let ArtifactSource::Nix { flake_ref, attr } = src else {
+            return Ok(None);
+        };
+        crate::nix::build(ctx, flake_ref, attr).map(Some)
+    }
+
+    fn plan(&self, src: &ArtifactSource, _ctx: &ResolveContext<'_>) -> Result<Option<Step>> {
+        let ArtifactSource::Nix { flake_ref, attr } = src else {
+            return Ok(None);
+        };
+        Ok(Some(Step::Nix {
+            flake_ref: flake_ref.clone(),
+            attr: attr.clone(),
+        }))
+    }
+}
+
+/// Pulls `Build` sources from the [build cache](crate::build_cache) repository,
+/// if one is configured, instead of building them.
+#[cfg(all(feature = "local-build", feature = "oci"))]
+pub struct BuildCacheLayer;
+
+#[cfg(all(feature = "local-build", feature = "oci"))]
+impl ArtifactProvider for BuildCacheLayer {
+    fn name(&self) -> &str {
+        "build-cache"
+    }
+
+    fn resolve(
+        &self,
+        src: &ArtifactSource,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<Option<ResolvedArtifact>> {
+        let ArtifactSource::Build {
+            service,
+            repo,
+            refspec,
+            policy,
+            expected_output,
+            target,
+        } = src
+        else {
+            return Ok(None);
+        };
+        if ctx.config.oci.build_cache.is_none() {
+            return Ok(None);
+        }
+        let spec = registered(ctx, service)?;
+        // Images stay in the runtime they were built into.
+        if spec
+            .build
+            .as_ref()
+            .is_some_and(|recipe| recipe.image().is_some())
+        {
+            return Ok(None);
+        }
+        let state = BuildState::prepare(
+            ctx,
+            spec,
+            repo,
+            refspec.as_deref(),
+            *policy,
+            expected_output.as_deref(),
+            target.as_deref(),
+        )?;
+        // Dirty worktrees are keyed by local edits, so nobody else has them.
+        if state.key.worktree_hash.is_some() {
+            return Ok(None);
+        }
+        let out_bin = state.paths.out.join(&state.bin_name);
+        state.paths.create_dirs()?;
+        let _lock = state.paths.lock(ctx)?; // released on drop
+        if cache::usable_entry(ctx, &state.paths, &out_bin, &state.platform)? {
+            return Ok(Some(ResolvedArtifact::executable(out_bin)));
+        }
+        let pulled = crate::build_cache::pull(
+            ctx,
+            spec,
+            &state.key,
+            &state.paths,
+            &state.bin_name,
+            &state.platform,
+        )?;
+        Ok(pulled.map(ResolvedArtifact::executable))
+    }
+}
+
+/// Builds `Build` sources with the service's [`BuildRecipe`](crate::BuildRecipe).
+///
+/// Image recipes (`BuildRecipe::image`, `oci` feature) build into the container
+/// runtime instead. Their entry holds only META, recording the image's tag and
+/// ID; it counts as cached while the runtime still has that image under the tag.
+#[cfg(feature = "local-build")]
+pub struct BuildLayer;
+
+#[cfg(feature = "local-build")]
+impl ArtifactProvider for BuildLayer {
+    fn name(&self) -> &str {
+        "build"
+    }
+
+    /// This methods does the following:
+    /// - Preflights git
+    /// - Identifies the tree state of the provided repository.
+    /// - Generates a cache key.
+    /// - If cache misses, builds (under the per-key lock) and finalizes the entry:
+    ///     - copies the binary (and any companions) into `out/`,
+    ///     - writes `meta/META.json`.
+    /// - Returns the executable path.
+    fn resolve(
+        &self,
+        src: &ArtifactSource,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<Option<ResolvedArtifact>> {
+        use crate::{BuildInvocation, error::BuildError};
+
+        let ArtifactSource::Build {
+            service,
+            repo,
+            refspec,
+            policy,
+            expected_output,
+            target,
+        } = src
+        else {
+            return Ok(None);
+        };
+        let spec = registered(ctx, service)?;
+        let recipe = spec
+            .build
+            .as_ref()
+            .ok_or_else(|| InputError::InvalidSource {
+                service: service.clone(),
+                reason: "service has no build recipe".into(),
+            })?;
+
+        if !ctx.config.build_config.allow_build {
+            return Err(BuildError::DisabledRuntime.into());
+        }
+
+        let state = BuildState::prepare(
+            ctx,
+            spec,
+            repo,
+            refspec.as_deref(),
+            *policy,
+            expected_output.as_deref(),
+            target.as_deref(),
+        )?;
+        #[cfg(feature = "oci")]
+        if let Some(image) = recipe.image() {
+            return build_image(ctx, src, spec, image, &state).map(Some);
+        }
+        let platform = state.platform.as_str();
+        let out_bin = state.paths.out.join(&state.bin_name);
+        if cache::usable_entry(ctx, &state.paths, &out_bin, platform)? {
+            return Ok(Some(ResolvedArtifact::executable(out_bin)));
+        }
+
+        state.paths.create_dirs()?;
+        let _lock = state.paths.lock(ctx)?; // released on drop
+
+        // Re-check after the lock: another process may have built it meanwhile.
+        if cache::usable_entry(ctx, &state.paths, &out_bin, platform)? {
+            return Ok(Some(ResolvedArtifact::executable(out_bin)));
+        }
+
+        let executor: &dyn crate::BuildExecutor = match &ctx.config.build_config.executor {
+            Some(executor) => executor.as_ref(),
+            None => &crate::executor::LocalExecutor,
+        };
+        // Remote builders need the space on their side.
+        if let Some(needed) = spec.build_defaults.disk_estimate
+            && executor.location().is_none()
+        {
+            cache::ensure_space(repo, needed, &format!("building {}", service.as_str()))?;
+        }
+        let jobs = ctx.config.build_config.jobs_for(&spec.build_defaults);
+        let log_path = state.paths.logs.join(format!(
+            "build-{}.log",
+            cache::timestamp().replace(':', "-")
+        ));
+        let invocation = BuildInvocation {
+            repo,
+            jobs,
+            log: &log_path,
+            env: &spec.build_defaults.env,
+            extra_args: &spec.build_defaults.extra_args,
+            target: target.as_deref(),
+            low_priority: ctx.config.build_config.low_priority,
+            isolation: &ctx.config.build_config.isolation,
+            executor,
+            cancel: ctx.cancel,
+        };
+        // Taken before the build, which may touch tracked files.
+        let changes = match state.worktree_hash {
+            Some(_) => Some(crate::git::worktree_diff(repo)?),
+            None => None,
+        };
+        let started_on = cache::timestamp();
+        let built = {
+            crate::trace::span!(
+                INFO,
+                "build",
+                service = service.as_str(),
+                entry = %state.paths.root.display(),
+                jobs,
+                executor = executor.location().as_deref().unwrap_or("local"),
+            );
+            let started = std::time::Instant::now();
+            ctx.emit(Event::BuildStarted {
+                service,
+                entry: &state.paths.root,
+                jobs,
+            });
+            let _slot = ctx.limiter.build(ctx.cancel)?;
+            ctx.enter(crate::cancel::Phase::Build);
+            let built = recipe.build(&invocation);
+            ctx.emit(Event::BuildFinished {
+                service,
+                entry: &state.paths.root,
+                elapsed: started.elapsed(),
+                success: built.is_ok(),
+            });
+            built?
+        };
+
+        let output = expected_output.as_deref().unwrap_or(&built);
+        let repo_bin = build_output(spec, &invocation, output, platform)?;
+
+        let path = cache::finalize(
+            ctx,
+            &state.paths,
+            &state.bin_name,
+            &repo_bin,
+            &companions_of(spec, repo_bin.parent().unwrap_or(repo), platform),
+            spec.version_probe
+                .as_deref()
+                .filter(|_| platform == crate::platform::host()),
+            false,
+            cache::Meta {
+                service: service.as_str().to_string(),
+                source: src.kind().into(),
+                repo: Some(repo.clone()),
+                refspec: Some(state.refspec.clone()),
+                commit: Some(state.commit.clone()),
+                dirty: state.worktree_hash.is_some(),
+                worktree_hash: state.worktree_hash.clone(),
+                jobs: Some(jobs),
+                isolation: ctx.config.build_config.isolation.name().map(Into::into),
+                executor: executor.location(),
+                target: target.clone(),
+                host: crate::platform::host(),
+                platform: platform.to_string(),
+                builder_schema: spec.builder_schema,
+                ..Default::default()
+            },
+        )?;
+        crate::slsa::write(
+            ctx,
+            &state.paths,
+            &crate::slsa::Build {
+                service: service.as_str(),
+                refspec: &state.refspec,
+                commit: &state.commit,
+                changes: changes.as_deref(),
+                platform,
+                builder_schema: spec.builder_schema,
+                invocation: &invocation,
+                started_on,
+            },
+        )?;
+        #[cfg(feature = "oci")]
+        crate::build_cache::push(ctx, &state.key, &state.paths, &state.bin_name)?;
+        Ok(Some(ResolvedArtifact::executable(path)))
+    }
+
+    fn plan(&self, src: &ArtifactSource, ctx: &ResolveContext<'_>) -> Result<Option<Step>> {
+        use crate::error::BuildError;
+
+        let ArtifactSource::Build {
+            service,
+            repo,
+            refspec,
+            policy,
+            expected_output,
+            target,
+        } = src
+        else {
+            return Ok(None);
+        };
+        let spec = registered(ctx, service)?;
+        if spec.build.is_none() {
+            return Err(InputError::InvalidSource {
+                service: service.clone(),
+                reason: "service has no build recipe".into(),
+            }
+            .into());
+        }
+        if !ctx.config.build_config.allow_build {
+            return Err(BuildError::DisabledRuntime.into());
+        }
+        let state = BuildState::prepare(
+            ctx,
+            spec,
+            repo,
+            refspec.as_deref(),
+            *policy,
+            expected_output.as_deref(),
+            target.as_deref(),
+        )?;
+        Ok(Some(Step::Build {
+            service: service.clone(),
+            repo: repo.clone(),
+            commit: state.commit,
+            dirty: state.worktree_hash.is_some(),
+            entry: state.paths.root,
+            jobs: ctx.config.build_config.jobs_for(&spec.build_defaults),
+        }))
+    }
+}
+
+/// Builds an image for [`BuildLayer`], unless the runtime still has the one
+/// META records. The entry holds only META and the build logs.
+#[cfg(all(feature = "local-build", feature = "oci"))]
+fn build_image(
+    ctx: &ResolveContext<'_>,
+    src: &ArtifactSource,
+    spec: &ToolSpec,
+    image: &dyn crate::ImageRecipe,
+    state: &BuildState,
+) -> Result<ResolvedArtifact> {
+    use crate::BuildInvocation;
+
+    let ArtifactSource::Build { repo, target, .. } = src else {
+        unreachable!("only `Build` sources are built");
+    };
+    let runtime = crate::container::Runtime::for_build(image.backend())?;
+    if let Some(built) = built_image(state, &runtime) {
+        return Ok(built);
+    }
+
+    state.paths.create_dirs()?;
+    let _lock = state.paths.lock(ctx)?; // released on drop
+    if let Some(built) = built_image(state, &runtime) {
+        return Ok(built);
+    }
+
+    // One tag per entry, so rebuilding one commit doesn't untag another's image.
+    let short = |hash: &str| hash.get(..12).unwrap_or(hash).to_string();
+    let mut tag = format!(
+        "zcash-artifacts/{}:{}",
+        spec.id.as_str(),
+        short(&state.commit)
+    );
+    if let Some(hash) = &state.worktree_hash {
+        tag = format!("{tag}-{}", short(hash));
+    }
+    if target.is_some() {
+        tag = format!("{tag}-{}", state.platform);
+    }
+
+    let jobs = ctx.config.build_config.jobs_for(&spec.build_defaults);
+    let log_path = state.paths.logs.join(format!(
+        "build-{}.log",
+        cache::timestamp().replace(':', "-")
+    ));
+    let _slot = ctx.limiter.build(ctx.cancel)?;
+    ctx.enter(crate::cancel::Phase::Build);
+    image.build_image(
+        &BuildInvocation {
+            repo,
+            jobs,
+            log: &log_path,
+            env: &spec.build_defaults.env,
+            extra_args: &spec.build_defaults.extra_args,
+            target: target.as_deref(),
+            low_priority: ctx.config.build_config.low_priority,
+            isolation: &ctx.config.build_config.isolation,
+            // Images land in the local runtime.
+            executor: &crate::executor::LocalExecutor,
+            cancel: ctx.cancel,
+        },
+        &tag,
+    )?;
+    let built = runtime.local_image(&tag)?;
+
+    cache::Meta {
+        service: spec.id.as_str().to_string(),
+        source: src.kind().into(),
+        repo: Some(repo.clone()),
+        refspec: Some(state.refspec.clone()),
+        commit: Some(state.commit.clone()),
+        dirty: state.worktree_hash.is_some(),
+        worktree_hash: state.worktree_hash.clone(),
+        jobs: Some(jobs),
+        isolation: ctx.config.build_config.isolation.name().map(Into::into),
+        target: target.clone(),
+        image: Some(tag.clone()),
+        image_digest: Some(built.id.clone()),
+        host: crate::platform::host(),
+        platform: built.platform.clone(),
+        built_at: cache::timestamp(),
+        builder_schema: spec.builder_schema,
+        ..Default::default()
+    }
+    .write(ctx, &state.paths)?;
+    Ok(ResolvedArtifact::OciImage {
+        reference: tag,
+        digest: built.id,
+        platform: built.platform,
+        layout: None,
+        config: built.config,
+    })
+}
+
+/// The image META of `state`'s entry records, if the runtime still has it
+/// under the recorded tag.
+#[cfg(all(feature = "local-build", feature = "oci"))]
+fn built_image(
+    state: &BuildState,
+    runtime: &crate::container::Runtime,
+) -> Option<ResolvedArtifact> {
+    let meta: cache::Meta =
+        serde_json::from_slice(&std::fs::read(state.paths.meta.join("META.json")).ok()?).ok()?;
+    let (tag, digest) = (meta.image?, meta.image_digest?);
+    // Removed or retagged images (e.g. by `docker image prune`) are rebuilt.
+    let image = runtime.local_image(&tag).ok()?;
+    (image.id == digest).then_some(ResolvedArtifact::OciImage {
+        reference: tag,
+        digest,
+        platform: meta.platform,
+        layout: None,
+        config: image.config,
+    })
+}
+
+/// Brings the binary a build left at `output`, and its companions, back from
+/// the executor, and returns where the binary is.
+#[cfg(feature = "local-build")]
+pub(crate) fn build_output(
+    spec: &ToolSpec,
+    invocation: &crate::BuildInvocation<'_>,
+    output: &Path,
+    platform: &str,
+) -> Result<PathBuf> {
+    use crate::error::BuildError;
+
+    if output.is_relative()
+        && let Some(name) = output.file_name()
+    {
+        // The binary, under either spelling, and its companions.
+        let name = name.to_string_lossy();
+        let mut names = vec![name.to_string(), crate::platform::exe_name(&name, platform)];
+        names.extend(
+            spec.companions
+                .values()
+                .map(|name| crate::platform::exe_name(name, platform)),
+        );
+        names.dedup();
+        invocation
+            .executor
+            .fetch(invocation, output.parent().unwrap_or(Path::new("")), &names)?;
+    }
+    let repo_bin = invocation.repo.join(output);
+    // Recipes and callers may name the output without the `.exe` suffix.
+    let repo_bin = match repo_bin.file_name() {
+        Some(name) if !repo_bin.exists() => {
+            let name = crate::platform::exe_name(&name.to_string_lossy(), platform);
+            repo_bin.with_file_name(name)
+        }
+        _ => repo_bin,
+    };
+    if !cache::looks_executable(&repo_bin) {
+        return Err(BuildError::MissingOutput { expected: repo_bin }.into());
+    }
+    binfmt::check(&repo_bin, platform)?;
+    Ok(repo_bin)
+}
+
+/// Git state and cache location of one `Build` source.
+#[cfg(feature = "local-build")]
+struct BuildState {
+    refspec: String,
+    commit: String,
+    worktree_hash: Option<String>,
+    /// What the [build cache](crate::build_cache) tags entries with.
+    #[cfg_attr(not(feature = "oci"), allow(dead_code))]
+    key: CacheKey,
+    paths: CachePaths,
+    bin_name: String,
+    /// Canonical platform of the binary the build produces.
+    platform: String,
+}
+
+#[cfg(feature = "local-build")]
+impl BuildState {
+    fn prepare(
+        ctx: &ResolveContext<'_>,
+        spec: &ToolSpec,
+        repo: &Path,
+        refspec: Option<&str>,
+        policy: GitPolicy,
+        expected_output: Option<&Path>,
+        target: Option<&str>,
+    ) -> Result<Self> {
+        use crate::error::{BuildError, PlatformError};
+
+        // Without a platform override the target decides what we build; with one,
+        // the build must produce that platform's binaries.
+        let platform = match target {
+            Some(triple) => crate::platform::normalize(triple),
+            None => crate::platform::host(),
+        };
+        if !ctx.targets_host() && platform != ctx.platform {
+            let reason = match target {
+                Some(triple) => format!("target {triple} produces {platform} binaries"),
+                None => format!(
+                    "local builds produce {platform} binaries; set `target` to cross-compile"
+                ),
+            };
+            return Err(PlatformError::Unsupported {
+                service: spec.id.clone(),
+                platform: ctx.platform.to_string(),
+                reason,
+            }
+            .into());
+        }
+
+        ctx.enter(crate::cancel::Phase::Git);
+        let refspec = refspec.unwrap_or("HEAD");
+        // We build whatever is checked out, so the refspec must point at it.
+        let commit = git::resolve_commit(repo, refspec)?;
+        let head = git::resolve_commit(repo, "HEAD")?;
+        if commit != head {
+            return Err(BuildError::RefspecNotCheckedOut {
+                repo: repo.to_path_buf(),
+                refspec: refspec.to_string(),
+                commit,
+                head,
+            }
+            .into());
+        }
+
+        let (allow_dirty, hash_untracked) = match policy {
+            GitPolicy::RequireClean => (false, false),
+            GitPolicy::AllowDirty { hash_untracked } => (true, hash_untracked),
+        };
+        let dirty = git::is_dirty(repo, hash_untracked)?;
+        if dirty && !allow_dirty {
+            return Err(BuildError::DirtyWorktree {
+                repo: repo.to_path_buf(),
+            }
+            .into());
+        }
+        let worktree_hash = if dirty {
+            Some(git::hash_worktree(repo, hash_untracked)?)
+        } else {
+            None
+        };
+
+        let key = CacheKey {
+            service: spec.id.as_str().to_string(),
+            revision: commit.clone(),
+            worktree_hash: worktree_hash.clone(),
+            // The triple, not just its platform: a cross build is a different toolchain.
+            platform: target.map_or_else(|| platform.clone(), str::to_string),
+            schema: spec.builder_schema,
+        };
+        let bin_name = expected_output
+            .unwrap_or(&spec.default_expected_output)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| spec.id.as_str().to_string());
+        let bin_name = crate::platform::exe_name(&bin_name, &platform);
+        Ok(Self {
+            refspec: refspec.to_string(),
+            commit,
+            worktree_hash,
+            paths: CachePaths::new(&ctx.config.cache_root, &key)?,
+            key,
+            bin_name,
+            platform,
+        })
+    }
+}
+
+/// Cache entry of a release of `spec`, keyed by version and by the `platform`
+/// of the asset: the target platform, or the one [`rosetta_fallback`] names.
+///
+/// Musl hosts get their own entries, since the asset chosen for them may differ.
+fn release_entry(
+    ctx: &ResolveContext<'_>,
+    spec: &ToolSpec,
+    version: &str,
+    platform: &str,
+) -> Result<(CachePaths, String)> {
+    let key_platform = if is_musl_host(ctx) {
+        crate::platform::musl_flavor(platform)
+    } else {
+        platform.to_string()
+    };
+    let key = CacheKey {
+        service: spec.id.as_str().to_string(),
+        revision: version.to_string(),
+        worktree_hash: None,
+        platform: key_platform,
+        schema: spec.builder_schema,
+    };
+    let bin_name = spec
+        .binary_names_for(platform)
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| spec.id.as_str().to_string());
+    Ok((CachePaths::new(&ctx.config.cache_root, &key)?, bin_name))
+}
+
+/// The entry [`CacheLayer`] looks in for a `Release` or `Url` source, the
+/// binary's name in it, and the META a download into it would record; `None`
+/// for other sources.
+#[cfg(feature = "testing")]
+pub(crate) fn seedable_entry(
+    src: &ArtifactSource,
+    ctx: &ResolveContext<'_>,
+) -> Result<Option<(CachePaths, String, cache::Meta)>> {
+    let meta = cache::Meta {
+        source: src.kind().into(),
+        host: crate::platform::host(),
+        platform: ctx.platform.to_string(),
+        builder_schema: 1,
+        ..Default::default()
+    };
+    Ok(match src {
+        ArtifactSource::Release { service, version } => {
+            let spec = registered(ctx, service)?;
+            let (paths, bin_name) = release_entry(ctx, spec, version, ctx.platform)?;
+            let meta = cache::Meta {
+                service: service.as_str().to_string(),
+                release: Some(version.clone()),
+                builder_schema: spec.builder_schema,
+                ..meta
+            };
+            Some((paths, bin_name, meta))
+        }
+        #[cfg(feature = "http")]
+        ArtifactSource::Url { url, checksum } => {
+            let (paths, bin_name) = url_entry(ctx, url, checksum)?;
+            let meta = cache::Meta {
+                service: "url".into(),
+                url: Some(crate::credentials::redact(url)),
+                ..meta
+            };
+            Some((paths, bin_name, meta))
+        }
+        _ => None,
+    })
+}
+
+/// Cache entry of a bare URL, keyed by its checksum.
+///
+/// Fails with [`InputError::InvalidSource`] unless `checksum` is a SHA-256 digest
+/// (64 hex digits), so a malformed pin never shares an entry with a real one.
+#[cfg(feature = "http")]
+fn url_entry(
+    ctx: &ResolveContext<'_>,
+    url: &url::Url,
+    checksum: &str,
+) -> Result<(CachePaths, String)> {
+    if checksum.len() != 64 || !checksum.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(InputError::InvalidSource {
+            service: ServiceId::new_static("url"),
+            reason: format!("checksum {checksum:?} is not a SHA-256 digest (64 hex digits)"),
+        }
+        .into());
+    }
+    let key = CacheKey {
+        service: "url".into(),
+        revision: checksum.to_ascii_lowercase(),
+        worktree_hash: None,
+        platform: ctx.platform.to_string(),
+        schema: 1,
+    };
+    let bin_name = url
+        .path_segments()
+        .and_then(|mut s| s.next_back())
+        .filter(|s| !s.is_empty())
+        .unwrap_or("download")
+        .to_string();
+    Ok((CachePaths::new(&ctx.config.cache_root, &key)?, bin_name))
+}
+
+/// Downloads `url` under the entry's lock, verifies it and finalizes the entry.
+///
+/// Intermediate files live in a per-process work directory inside the entry,
+/// which is removed whether or not finalization succeeds.
+#[cfg(feature = "http")]
+pub(crate) fn download_into(
+    ctx: &ResolveContext<'_>,
+    spec: Option<&ToolSpec>,
+    url: &url::Url,
+    checksum: &str,
+    paths: &CachePaths,
+    bin_name: &str,
+    meta: cache::Meta,
+) -> Result<ResolvedArtifact> {
+    let out_bin = paths.out.join(bin_name);
+    paths.create_dirs()?;
+    let _lock = paths.lock(ctx)?; // released on drop
+    if cache::usable_entry(ctx, paths, &out_bin, ctx.platform)? && checks_ok(ctx, spec, paths) {
+        return Ok(ResolvedArtifact::executable(out_bin));
+    }
+
+    let work = paths.staging()?;
+    let result = download_in(ctx, spec, url, checksum, paths, bin_name, meta, &work);
+    let _ = std::fs::remove_dir_all(&work);
+    Ok(ResolvedArtifact::executable(result?))
+}
+
+#[cfg(feature = "http")]
+#[allow(clippy::too_many_arguments)]
+fn download_in(
+    ctx: &ResolveContext<'_>,
+    spec: Option<&ToolSpec>,
+    url: &url::Url,
+    checksum: &str,
+    paths: &CachePaths,
+    bin_name: &str,
+    mut meta: cache::Meta,
+    work: &Path,
+) -> Result<PathBuf> {
+    use crate::error::UnpackError;
+
+    let asset = url
+        .path_segments()
+        .and_then(|mut s| s.next_back())
+        .unwrap_or_default()
+        .to_string();
+    let download = work.join("download");
+    let is_archive = [".tar.gz", ".tgz", ".zip"]
+        .iter()
+        .any(|ext| asset.ends_with(ext));
+    // Room for the download and the cached copy, plus the unpacked tree of archives.
+    let space_factor = if is_archive { 4 } else { 2 };
+    fetch_verified(
+        ctx,
+        url,
+        checksum,
+        &download,
+        ctx.config.credential_provider().as_ref(),
+        space_factor,
+    )?;
+    #[cfg(feature = "transparency")]
+    if let Some(spec) = spec {
+        meta.log_entry = crate::transparency::check(ctx, &spec.id, url, checksum)?;
+    }
+    #[cfg(feature = "trust-store")]
+    if let Some(spec) = spec {
+        meta.signer = crate::trust::check_minisign(ctx, &spec.id, url, &download)?;
+    }
+    let (mut binary, companions) = match spec {
+        Some(spec) if is_archive => {
+            #[cfg(feature = "archive")]
+            {
+                let unpacked = work.join("unpacked");
+                crate::archive::extract(&download, &asset, &unpacked)?;
+                let binary = crate::archive::locate_binary(&unpacked, spec, ctx.platform)?;
+                let companions =
+                    companions_of(spec, binary.parent().unwrap_or(&unpacked), ctx.platform);
+                (binary, companions)
+            }
+            #[cfg(not(feature = "archive"))]
+            {
+                let _ = spec;
+                return Err(UnpackError::UnsupportedFormat { archive: asset }.into());
+            }
+        }
+        None if is_archive => {
+            return Err(UnpackError::UnsupportedFormat { archive: asset }.into());
+        }
+        _ => (download, Vec::new()),
+    };
+
+    if meta.platform.is_empty() {
+        meta.platform = ctx.platform.to_string();
+    }
+    if ctx.config.thin_universal
+        && let Some(platform) = crate::platform::Platform::parse(&meta.platform)
+        && platform.os() == "macos"
+    {
+        let thinned = work.join("thinned");
+        if crate::macho::thin(&binary, platform.arch(), &thinned)? {
+            meta.universal_digest = Some(cache::digest_file(&binary)?.0);
+            binary = thinned;
+        }
+    }
+
+    binfmt::check(&binary, &meta.platform)?;
+    if ctx.targets_host() && meta.platform.starts_with("linux-") {
+        let what = match &meta.release {
+            Some(version) => format!("{} {version} asset", meta.service),
+            None => crate::credentials::redact(url),
+        };
+        binfmt::check_glibc(&binary, &what)?;
+    }
+    let signature = ctx.config.codesign.enforce(&binary, ctx.platform)?;
+    meta.signing_identity = signature.and_then(|s| s.identity);
+    meta.url = Some(crate::credentials::redact(url));
+    meta.host = crate::platform::host();
+    meta.builder_schema = spec.map_or(1, |spec| spec.builder_schema);
+    cache::warn_quarantined(ctx, paths, bin_name, &binary, &companions);
+    cache::finalize(
+        ctx,
+        paths,
+        bin_name,
+        &binary,
+        &companions,
+        spec.and_then(|spec| spec.version_probe.as_deref())
+            .filter(|_| ctx.targets_host()),
+        !ctx.config.keep_quarantine,
+        meta,
+    )
+}
+
+/// Streams `url` to `dst` and checks its sha256 against `expected`.
+///
+/// Requests carry whatever `credentials` has for the URL; errors, META and
+/// observers only ever see the URL without its user-info. When the server
+/// announces a length, `space_factor` times that must be free next to `dst`
+/// before anything is written.
+#[cfg(feature = "http")]
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "download",
+        skip_all,
+        fields(url = %crate::credentials::redact(url), bytes = tracing::field::Empty),
+    )
+)]
+pub(crate) fn fetch_verified(
+    ctx: &ResolveContext<'_>,
+    url: &url::Url,
+    expected: &str,
+    dst: &Path,
+    credentials: &dyn crate::credentials::CredentialProvider,
+    space_factor: u64,
+) -> Result<()> {
+    use crate::error::FetchError;
+
+    ctx.config.network.check(url)?;
+    let _slots = ctx
+        .limiter
+        .download(url.host_str().unwrap_or_default(), ctx.cancel)?;
+    ctx.enter(crate::cancel::Phase::Download);
+    let shown = crate::credentials::redact(url);
+    let mut response = send(ctx, authorized(Method::Get, url, credentials))?
+        .error_for_status()
+        .map_err(|source| FetchError::Http {
+            url: shown.clone(),
+            source,
+        })?;
+    if let (Some(len), Some(dir)) = (response.content_length(), dst.parent()) {
+        cache::ensure_space(
+            dir,
+            len.saturating_mul(space_factor),
+            &format!("downloading {shown}"),
+        )?;
+    }
+
+    let started = std::time::Instant::now();
+    ctx.emit(Event::DownloadStarted {
+        url: &shown,
+        size: response.content_length(),
+    });
+    let mut bytes = 0;
+    let size = response.content_length();
+    let progress = |bytes| {
+        ctx.emit(Event::DownloadProgress {
+            url: &shown,
+            bytes,
+            size,
+        })
+    };
+    let result = save_verified(
+        &mut response,
+        dst,
+        expected,
+        &shown,
+        &mut bytes,
+        &progress,
+        ctx.cancel,
+    );
+    crate::trace::record!("bytes" = bytes);
+    ctx.emit(Event::DownloadFinished {
+        url: &shown,
+        bytes,
+        elapsed: started.elapsed(),
+        success: result.is_ok(),
+    });
+    result
+}
+
+/// Writes `response` to `dst`, counting its length in `bytes` and reporting
+/// it to `progress` as it grows, and checks its sha256 against `expected`; a
+/// mismatching file, or one whose download was cancelled, is removed.
+#[cfg(feature = "http")]
+fn save_verified(
+    response: &mut crate::transport::Response,
+    dst: &Path,
+    expected: &str,
+    shown: &str,
+    bytes: &mut u64,
+    progress: &dyn Fn(u64),
+    cancel: Option<&crate::cancel::CancelToken>,
+) -> Result<()> {
+    use std::io::{Read, Write};
+
+    use sha2::{Digest, Sha256};
+
+    use crate::error::{FetchError, VerifyError};
+
+    let mut file = std::fs::File::create(dst).map_err(|e| FsError::Io {
+        context: format!("create {}", dst.display()),
+        source: e,
+    })?;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        if let Some(Err(e)) = cancel.map(crate::cancel::CancelToken::check) {
+            drop(file);
+            let _ = std::fs::remove_file(dst);
+            return Err(e);
+        }
+        let n = response.read(&mut buf).map_err(|e| FetchError::Network {
+            url: shown.to_string(),
+            source: Box::new(e),
+        })?;
+        if n == 0 {
+            break;
+        }
+        file.write_all(&buf[..n]).map_err(|e| FsError::Io {
+            context: format!("write {}", dst.display()),
+            source: e,
+        })?;
+        *bytes += n as u64;
+        progress(*bytes);
+    }
+    drop(file);
+
+    let io = |e| FsError::Io {
+        context: format!("hash {}", dst.display()),
+        source: e,
+    };
+    let mut hasher = Sha256::new();
+    let mut file = std::fs::File::open(dst).map_err(io)?;
+    loop {
+        let n = file.read(&mut buf).map_err(io)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    let actual: String = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    if !actual.eq_ignore_ascii_case(expected) {
+        let _ = std::fs::remove_file(dst);
+        return Err(VerifyError::ChecksumMismatch {
+            url: shown.to_string(),
+            expected: expected.to_string(),
+            actual,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Fetches a small text file, e.g. a checksum list or an API response.
+#[cfg(feature = "http")]
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "download", skip_all, fields(url = %crate::credentials::redact(url)))
+)]
+pub(crate) fn fetch_text(ctx: &ResolveContext<'_>, url: &url::Url) -> Result<String> {
+    ctx.config.network.check(url)?;
+    let _slot = ctx
+        .limiter
+        .request(url.host_str().unwrap_or_default(), ctx.cancel)?;
+    use std::io::Read;
+
+    let mut response = send(
+        ctx,
+        crate::transport::Request::new(Method::Get, url.clone()),
+    )?
+    .error_for_status()
+    .map_err(|source| crate::error::FetchError::Http {
+        url: crate::credentials::redact(url),
+        source,
+    })?;
+    let mut text = String::new();
+    response
+        .read_to_string(&mut text)
+        .map_err(|e| crate::error::FetchError::Network {
+            url: crate::credentials::redact(url),
+            source: Box::new(e),
+        })?;
+    Ok(text)
+}
+
+/// The size of `url` as its server announces it to a `HEAD` request, for
+/// [plans](crate::plan); sent with the same credentials as a download.
+#[cfg(feature = "http")]
+pub(crate) fn content_length(
+    ctx: &ResolveContext<'_>,
+    url: &url::Url,
+    credentials: &dyn crate::credentials::CredentialProvider,
+) -> Result<Option<u64>> {
+    let response = send(ctx, authorized(Method::Head, url, credentials))?
+        .error_for_status()
+        .map_err(|source| crate::error::FetchError::Http {
+            url: crate::credentials::redact(url),
+            source,
+        })?;
+    Ok(response.content_length())
+}
+
+/// A request for `url` carrying whatever `credentials` has for it.
+#[cfg(feature = "http")]
+fn authorized(
+    method: Method,
+    url: &url::Url,
+    credentials: &dyn crate::credentials::CredentialProvider,
+) -> crate::transport::Request {
+    let credential = credentials.credential_for(url);
+    // A provided credential replaces any user-info in the URL rather than clashing with it.
+    let mut target = url.clone();
+    if credential.is_some() {
+        let _ = target.set_username("");
+        let _ = target.set_password(None);
+    }
+    crate::transport::Request::new(method, target).auth(credential)
+}
+
+/// Sends `request` through the resolver's transport; failing to get a
+/// response is a [`FetchError::Network`](crate::error::FetchError::Network)
+/// error.
+#[cfg(feature = "http")]
+pub(crate) fn send(
+    ctx: &ResolveContext<'_>,
+    request: crate::transport::Request,
+) -> Result<crate::transport::Response> {
+    let url = crate::credentials::redact(&request.url);
+    ctx.transport()
+        .send(request)
+        .map_err(|source| crate::error::FetchError::Network { url, source }.into())
+}
+
+/// The spec's companions for `platform` that exist in `dir`, as (file name, path) pairs.
+#[cfg_attr(
+    not(any(all(feature = "http", feature = "archive"), feature = "local-build")),
+    allow(dead_code)
+)]
+pub(crate) fn companions_of(spec: &ToolSpec, dir: &Path, platform: &str) -> Vec<(String, PathBuf)> {
+    spec.companions
+        .values()
+        .map(|name| crate::platform::exe_name(name, platform))
+        .map(|name| (name.clone(), dir.join(name)))
+        .filter(|(_, path)| cache::looks_executable(path))
+        .collect()
+}
+
+/// The platform of the x86_64 assets that run on this host with Rosetta 2,
+/// when resolving for it on Apple Silicon and Rosetta is installed.
+fn rosetta_fallback(ctx: &ResolveContext<'_>) -> Option<&'static str> {
+    (ctx.targets_host() && ctx.platform == "macos-arm64" && crate::platform::rosetta_installed())
+        .then_some("macos-x86_64")
+}
+
+/// Whether artifacts are resolved for this host and it runs musl.
+fn is_musl_host(ctx: &ResolveContext<'_>) -> bool {
+    ctx.targets_host() && crate::platform::host_libc() == Some(crate::platform::Libc::Musl)
+}
+
+pub(crate) fn registered<'a>(
+    ctx: &ResolveContext<'a>,
+    service: &ServiceId,
+) -> Result<&'a ToolSpec> {
+    ctx.registry.get(service).ok_or_else(|| {
+        LocateError::UnknownService {
+            service: service.clone(),
+        }
+        .into()
+    })
+}
+
+/// Ensures `path` is a regular file we may execute.
+pub(crate) fn check_executable(path: &Path) -> Result<()> {
+    let md = std::fs::metadata(path).map_err(|e| FsError::Io {
+        context: format!("stat {}", path.display()),
+        source: e,
+    })?;
+    if !md.is_file() {
+        return Err(InputError::NotFound {
+            path: path.to_path_buf(),
+        }
+        .into());
+    }
+    #[cfg(unix)]
+    let executable = cache::may_execute(&md);
+    // No exec bit off Unix; go by the extensions the OS will launch.
+    #[cfg(not(unix))]
+    let executable = path.extension().is_some_and(|ext| {
+        ["exe", "com", "bat", "cmd"]
+            .iter()
+            .any(|known| ext.eq_ignore_ascii_case(known))
+    });
+    if !executable {
+        return Err(InputError::NotExecutable {
+            path: path.to_path_buf(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,127 @@
+//! Dry runs: what resolving a source would do, without doing it.
+//!
+//! [`ArtifactResolver::plan`](crate::ArtifactResolver::plan) takes the
+//! read-only steps of a resolution: it resolves git refspecs and hashes dirty
+//! worktrees, computes cache keys, looks entries up, picks release assets and
+//! asks servers for their size with a `HEAD` request. It downloads, extracts,
+//! builds and locks nothing. The one write it may make is restoring the exec
+//! bit of a cached binary, as a cache lookup does.
+//!
+//! ```no_run
+//! use zcash_artifacts::{ArtifactResolver, ArtifactSource, plan::Step};
+//!
+//! # fn preflight(resolver: &ArtifactResolver, src: &ArtifactSource) -> zcash_artifacts::Result<()> {
+//! let plan = resolver.plan(src)?;
+//! if let Step::Build { .. } = plan.step {
+//!     eprintln!("cache miss, CI will build: {plan}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Each layer plans through [`ArtifactProvider::plan`](crate::ArtifactProvider::plan),
+//! and layers that don't implement it are passed over, so a plan is only as
+//! good as the layers that answer. Of the standard ones, `build-cache` doesn't
+//! ask its registry, so a planned build may still be pulled from it, and the
+//! bottles of `Homebrew` sources and the packages of `Deb` sources are
+//! planned without reading their indexes.
+
+use std::{fmt, path::PathBuf};
+
+use crate::{ResolvedArtifact, registry::ServiceId};
+
+/// What [`ArtifactResolver::plan`](crate::ArtifactResolver::plan) found.
+#[non_exhaustive]
+#[derive(Debug, Clone)]
+pub struct Plan {
+    /// The source's [kind](crate::ArtifactSource::kind).
+    pub source: &'static str,
+    /// Platform being resolved for.
+    pub platform: String,
+    /// Name of the layer (or service provider) that would resolve the source.
+    pub layer: String,
+    pub step: Step,
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} source for {}: {} ({} layer)",
+            self.source, self.platform, self.step, self.layer
+        )
+    }
+}
+
+/// The work a resolution would do.
+#[non_exhaustive]
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// Use a file that is already in place.
+    Local { path: PathBuf },
+    /// Return a finalized cache entry.
+    Cached { artifact: ResolvedArtifact },
+    /// Download into the cache.
+    Download {
+        /// Without credentials; `None` when the URL comes from an index that
+        /// planning doesn't fetch.
+        url: Option<String>,
+        /// As announced by the server.
+        bytes: Option<u64>,
+        /// Cache entry the download would be finalized into.
+        entry: Option<PathBuf>,
+    },
+    /// Pull an image or artifact from an OCI registry.
+    Pull { reference: String },
+    /// Build a service from a local repository into the cache.
+    Build {
+        service: ServiceId,
+        repo: PathBuf,
+        commit: String,
+        /// Whether uncommitted changes would be built too.
+        dirty: bool,
+        entry: PathBuf,
+        jobs: usize,
+    },
+    /// Evaluate and build (or substitute) a flake output with `nix build`.
+    Nix { flake_ref: String, attr: String },
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Local { path } => write!(f, "use {}", path.display()),
+            Step::Cached { artifact } => match artifact.primary_path() {
+                Some(path) => write!(f, "cache hit at {}", path.display()),
+                None => f.write_str("cache hit"),
+            },
+            Step::Download { url, bytes, .. } => {
+                f.write_str("download")?;
+                if let Some(url) = url {
+                    write!(f, " {url}")?;
+                }
+                match bytes {
+                    Some(bytes) => write!(f, " ({bytes} bytes)"),
+                    None => Ok(()),
+                }
+            }
+            Step::Pull { reference } => write!(f, "pull {reference}"),
+            Step::Build {
+                service,
+                commit,
+                dirty,
+                jobs,
+                ..
+            } => {
+                let short = &commit[..commit.len().min(12)];
+                let dirty = if *dirty { " with local changes" } else { "" };
+                write!(
+                    f,
+                    "build {} at {short}{dirty} with {jobs} jobs",
+                    service.as_str()
+                )
+            }
+            Step::Nix { flake_ref, attr } => write!(f, "nix build {flake_ref}#{attr}"),
+        }
+    }
+}
@@ -0,0 +1,361 @@
+//! Host platform detection.
+//!
+//! Platforms are identified by `<os>-<arch>` strings such as `linux-x86_64` or
+//! `macos-arm64`. This canonical form is part of every cache key and of the
+//! platform keys in registries and manifests, so it is stable:
+//!
+//! - `os` is one of `linux`, `macos`, `windows` or `freebsd`, otherwise
+//!   `target-lexicon`'s name for the OS;
+//! - `arch` is `x86_64`, `aarch64` or `armv7` (32-bit ARMv7 with hardware float,
+//!   Debian's `armhf`, as on Raspberry Pi OS), otherwise `target-lexicon`'s name
+//!   for the architecture; 64-bit ARM is spelled `arm64` on macOS, as Apple and
+//!   upstream release assets do.
+//!
+//! [`Platform::parse`] accepts common aliases (`darwin`, `amd64`, `aarch64` on
+//! macOS, ...) and full Rust target triples, and [`normalize`] turns any of them
+//! into the canonical form.
+
+use std::{fmt, process::Command, str::FromStr};
+
+use target_lexicon::{Architecture, ArmArchitecture, OperatingSystem, Triple};
+
+/// An operating system and CPU architecture pair, in canonical spelling.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Platform {
+    os: String,
+    /// Architecture in its OS-independent spelling (`aarch64`, never `arm64`).
+    arch: String,
+}
+
+impl Platform {
+    /// The platform this process runs on.
+    ///
+    /// This is the platform the crate was compiled for, except that an x86_64
+    /// build running under Rosetta on Apple Silicon reports the real hardware,
+    /// `macos-arm64`, so native assets are preferred.
+    pub fn host() -> Self {
+        let mut host = Self::from_triple(&target_lexicon::HOST);
+        if is_translated() {
+            host.arch = "aarch64".into();
+        }
+        host
+    }
+
+    /// The platform the crate was compiled for, ignoring Rosetta.
+    pub fn compiled() -> Self {
+        Self::from_triple(&target_lexicon::HOST)
+    }
+
+    /// The platform of a Rust target triple, e.g. `aarch64-apple-darwin`.
+    pub fn from_triple(triple: &Triple) -> Self {
+        let os = match triple.operating_system {
+            OperatingSystem::Linux => "linux".to_string(),
+            OperatingSystem::Darwin(_) | OperatingSystem::MacOSX(_) => "macos".to_string(),
+            OperatingSystem::Windows => "windows".to_string(),
+            OperatingSystem::Freebsd => "freebsd".to_string(),
+            other => other.to_string(),
+        };
+        let arch = match triple.architecture {
+            Architecture::X86_64 => "x86_64".to_string(),
+            Architecture::Aarch64(_) => "aarch64".to_string(),
+            Architecture::Arm(
+                ArmArchitecture::Armv7
+                | ArmArchitecture::Armv7a
+                | ArmArchitecture::Armv7ve
+                | ArmArchitecture::Thumbv7a
+                | ArmArchitecture::Thumbv7neon,
+            ) => "armv7".to_string(),
+            other => other.to_string(),
+        };
+        Self { os, arch }
+    }
+
+    /// Parses `<os>-<arch>` (with aliases) or a Rust target triple.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim().to_ascii_lowercase();
+        if let Some((os, arch)) = s.split_once('-')
+            && !arch.contains('-')
+            && let Some(os) = canonical_os(os)
+        {
+            return Some(Self {
+                os: os.to_string(),
+                arch: canonical_arch(arch).to_string(),
+            });
+        }
+        Triple::from_str(&s).ok().map(|t| Self::from_triple(&t))
+    }
+
+    pub fn os(&self) -> &str {
+        &self.os
+    }
+
+    /// The architecture in its OS-independent spelling (`aarch64`, never `arm64`).
+    pub fn generic_arch(&self) -> &str {
+        &self.arch
+    }
+
+    /// The architecture as spelled in the canonical string (`arm64` on macOS).
+    pub fn arch(&self) -> &str {
+        match (self.os.as_str(), self.arch.as_str()) {
+            ("macos", "aarch64") => "arm64",
+            (_, arch) => arch,
+        }
+    }
+}
+
+impl Platform {
+    /// Suffix executables carry on this platform: `.exe` on Windows, none elsewhere.
+    pub fn exe_suffix(&self) -> &'static str {
+        if self.os == "windows" { ".exe" } else { "" }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.os, self.arch())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s).ok_or_else(|| format!("unrecognized platform `{s}`"))
+    }
+}
+
+fn canonical_os(os: &str) -> Option<&'static str> {
+    Some(match os {
+        "linux" => "linux",
+        "macos" | "darwin" | "osx" | "macosx" | "apple" => "macos",
+        "windows" | "win" | "win32" | "win64" => "windows",
+        "freebsd" => "freebsd",
+        _ => return None,
+    })
+}
+
+fn canonical_arch(arch: &str) -> &str {
+    match arch {
+        "x86_64" | "amd64" | "x64" => "x86_64",
+        "aarch64" | "arm64" => "aarch64",
+        "armv7" | "armv7l" | "armv7a" | "armv7hf" | "armhf" => "armv7",
+        other => other,
+    }
+}
+
+/// The canonical spelling of `s` if it is an `<os>-<arch>` string (aliases allowed)
+/// for a known OS and one of the architectures this crate names itself.
+///
+/// Stricter than [`normalize`], so it can pick platform spellings out of
+/// strings like cache directory names without misreading other text.
+pub(crate) fn canonical_alias(s: &str) -> Option<String> {
+    let (os, arch) = s.split_once('-')?;
+    let os = canonical_os(os)?;
+    let arch = canonical_arch(arch);
+    ["x86_64", "aarch64", "armv7"].contains(&arch).then(|| {
+        Platform {
+            os: os.to_string(),
+            arch: arch.to_string(),
+        }
+        .to_string()
+    })
+}
+
+/// Whether this process is an x86_64 binary translated by Rosetta 2.
+pub fn is_translated() -> bool {
+    if !cfg!(all(target_os = "macos", target_arch = "x86_64")) {
+        return false;
+    }
+    static TRANSLATED: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+    *TRANSLATED.get_or_init(|| {
+        // Prints 1 under Rosetta, 0 natively; `-i` ignores the key on Intel Macs.
+        Command::new("sysctl")
+            .args(["-in", "sysctl.proc_translated"])
+            .output()
+            .is_ok_and(|out| String::from_utf8_lossy(&out.stdout).trim() == "1")
+    })
+}
+
+/// Whether Rosetta 2 is installed on this Apple Silicon Mac, so x86_64
+/// binaries run on it; always true while this process itself is translated.
+pub fn rosetta_installed() -> bool {
+    if !cfg!(target_os = "macos") {
+        return false;
+    }
+    if is_translated() {
+        return true;
+    }
+    static INSTALLED: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+    *INSTALLED.get_or_init(|| {
+        // `arch` can't launch x86_64 binaries without Rosetta.
+        Command::new("arch")
+            .args(["-x86_64", "/usr/bin/true"])
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    })
+}
+
+/// The canonical platform string of the running host.
+pub fn host() -> String {
+    Platform::host().to_string()
+}
+
+/// `name` with `platform`'s executable suffix, unless it already ends in it.
+pub fn exe_name(name: &str, platform: &str) -> String {
+    let suffix = Platform::parse(platform).map_or("", |p| p.exe_suffix());
+    if name.ends_with(suffix) {
+        name.to_string()
+    } else {
+        format!("{name}{suffix}")
+    }
+}
+
+/// Canonicalizes a platform string, leaving unrecognized ones untouched.
+pub fn normalize(platform: &str) -> String {
+    Platform::parse(platform)
+        .map(|p| p.to_string())
+        .unwrap_or_else(|| platform.to_string())
+}
+
+/// The host's glibc version as `(major, minor)`, or `None` if it is not glibc-based
+/// or the version cannot be determined.
+pub fn host_glibc() -> Option<(u32, u32)> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    // Prints e.g. `glibc 2.36`; fails on musl.
+    let out = Command::new("getconf")
+        .arg("GNU_LIBC_VERSION")
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&out.stdout);
+    parse_version(text.trim().strip_prefix("glibc ")?)
+}
+
+/// The C library a Linux host runs programs against.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Libc {
+    Glibc { major: u32, minor: u32 },
+    Musl,
+}
+
+impl fmt::Display for Libc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Libc::Glibc { major, minor } => write!(f, "glibc {major}.{minor}"),
+            Libc::Musl => f.write_str("musl"),
+        }
+    }
+}
+
+/// The host's libc, or `None` off Linux or if it cannot be determined.
+pub fn host_libc() -> Option<Libc> {
+    if let Some((major, minor)) = host_glibc() {
+        return Some(Libc::Glibc { major, minor });
+    }
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    // musl installs its dynamic loader as e.g. `/lib/ld-musl-x86_64.so.1`.
+    let has_musl_loader = std::fs::read_dir("/lib").is_ok_and(|entries| {
+        entries
+            .flatten()
+            .any(|e| e.file_name().to_string_lossy().starts_with("ld-musl-"))
+    });
+    (has_musl_loader || cfg!(target_env = "musl")).then_some(Libc::Musl)
+}
+
+/// Platform key of the musl flavor of `platform`'s release assets, e.g.
+/// `linux-x86_64-musl`. Such keys are not normalized away by [`normalize`].
+pub fn musl_flavor(platform: &str) -> String {
+    format!("{platform}-musl")
+}
+
+/// Parses a `major.minor[.patch]` version string, ignoring anything after `minor`.
+pub(crate) fn parse_version(s: &str) -> Option<(u32, u32)> {
+    let mut parts = s.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aliases_normalize_to_canonical_spelling() {
+        for (input, expected) in [
+            ("linux-x86_64", "linux-x86_64"),
+            ("linux-amd64", "linux-x86_64"),
+            ("Linux-AMD64", "linux-x86_64"),
+            ("linux-arm64", "linux-aarch64"),
+            ("darwin-aarch64", "macos-arm64"),
+            ("macos-aarch64", "macos-arm64"),
+            ("osx-x64", "macos-x86_64"),
+            ("win64-amd64", "windows-x86_64"),
+            ("linux-armhf", "linux-armv7"),
+            ("freebsd-amd64", "freebsd-x86_64"),
+        ] {
+            assert_eq!(normalize(input), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn triples_normalize_to_canonical_spelling() {
+        for (input, expected) in [
+            ("x86_64-unknown-linux-gnu", "linux-x86_64"),
+            ("x86_64-unknown-linux-musl", "linux-x86_64"),
+            ("aarch64-apple-darwin", "macos-arm64"),
+            ("x86_64-pc-windows-msvc", "windows-x86_64"),
+            ("armv7-unknown-linux-gnueabihf", "linux-armv7"),
+            ("x86_64-unknown-freebsd", "freebsd-x86_64"),
+        ] {
+            assert_eq!(normalize(input), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn canonical_strings_round_trip() {
+        for canonical in [
+            "linux-x86_64",
+            "linux-aarch64",
+            "macos-arm64",
+            "windows-x86_64",
+        ] {
+            let platform: Platform = canonical.parse().unwrap();
+            assert_eq!(platform.to_string(), canonical);
+            assert_eq!(normalize(canonical), canonical);
+        }
+    }
+
+    #[test]
+    fn host_is_canonical() {
+        assert_eq!(normalize(&host()), host());
+    }
+
+    #[test]
+    fn unknown_platforms_are_left_alone() {
+        assert_eq!(normalize("plan9-mips"), "plan9-mips");
+        assert_eq!(canonical_alias("apple-darwin"), None);
+        assert_eq!(canonical_alias("linux-gnu"), None);
+        assert_eq!(
+            canonical_alias("darwin-aarch64").as_deref(),
+            Some("macos-arm64")
+        );
+    }
+
+    #[test]
+    fn exe_names_follow_the_platform() {
+        assert_eq!(exe_name("zebrad", "windows-x86_64"), "zebrad.exe");
+        assert_eq!(exe_name("zebrad.exe", "win64-amd64"), "zebrad.exe");
+        assert_eq!(exe_name("zebrad", "linux-x86_64"), "zebrad");
+        assert_eq!(musl_flavor("linux-x86_64"), "linux-x86_64-musl");
+    }
+}
@@ -0,0 +1,436 @@
+//! Built-in [`VersionProbe`] and [`CapabilityProbe`] implementations, and the
+//! post-resolve health check.
+//!
+//! Probes and health checks run binaries that may have just been downloaded,
+//! so they run them with as little as they need:
+//!
+//! - an environment of `PATH` alone (and `SystemRoot` on Windows), `LC_ALL=C`,
+//!   and `HOME` and the temp directory variables pointing at a fresh, empty
+//!   directory, which is also the working directory and is removed afterwards.
+//!   It is created under a random name, only accessible to the current user,
+//!   and never one that already existed;
+//! - on Unix, limits of 60 s CPU time, 64 MiB per written file, 256 open files,
+//!   no core dumps and, on Linux for native binaries, 4 GiB of address space;
+//! - no network where that is available: in a network namespace of their own
+//!   on Linux, with util-linux `unshare` (2.38 or later) and unprivileged user
+//!   namespaces, and under `sandbox-exec` on macOS. Elsewhere they run without.
+//!
+//! The built-in probes give up on binaries still running after 30 s. Output
+//! is read until the same deadline only, so a binary that leaves a child
+//! holding its stdout or stderr open doesn't hold the resolution up.
+//! Custom probes run binaries however they like.
+
+use std::{
+    collections::BTreeSet,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::{
+        Arc, Mutex, OnceLock, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use regex::Regex;
+
+use crate::{
+    CapabilityProbe, VersionProbe,
+    binfmt::Launch,
+    error::{Result, VerifyError},
+};
+
+/// How long the built-in probes let a binary run.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Runs the executable with `args` and extracts the version with `pattern`.
+///
+/// The first capture group is returned if the pattern has one, otherwise the
+/// whole match. Both stdout and stderr are searched, in that order.
+pub struct RegexVersionProbe {
+    args: Vec<String>,
+    pattern: Regex,
+}
+
+impl RegexVersionProbe {
+    pub fn new(args: Vec<String>, pattern: Regex) -> Self {
+        Self { args, pattern }
+    }
+
+    /// Runs `--version` and picks the first semver-looking token, without a leading `v`.
+    pub fn semver() -> Self {
+        Self::new(
+            vec!["--version".into()],
+            Regex::new(r"v?(\d+\.\d+\.\d+(?:-[0-9A-Za-z.-]+)?)").expect("valid regex"),
+        )
+    }
+}
+
+/// Parses the first semver-looking token (`1.2.3`, `v5.9.0-rc1`) out of `text`.
+pub fn parse_semver(text: &str) -> Option<semver::Version> {
+    let re = Regex::new(r"(\d+\.\d+\.\d+(?:-[0-9A-Za-z.-]+)?)").expect("valid regex");
+    re.find_iter(text)
+        .find_map(|m| semver::Version::parse(m.as_str()).ok())
+}
+
+impl VersionProbe for RegexVersionProbe {
+    fn probe(&self, exe: &Path) -> Option<String> {
+        let launch = crate::binfmt::launch(exe).ok()?;
+        let output = run_confined(&launch, &self.args, PROBE_TIMEOUT).ok()?;
+        [output.stdout, output.stderr].iter().find_map(|stream| {
+            let text = String::from_utf8_lossy(stream);
+            let caps = self.pattern.captures(&text)?;
+            let m = caps.get(1).or_else(|| caps.get(0))?;
+            Some(m.as_str().trim().to_string())
+        })
+    }
+}
+
+/// What a binary supports, for tests that need to skip unsupported features.
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Capabilities {
+    /// Command-line flags the binary advertises, without leading dashes
+    /// (`rpcport`, `config`).
+    pub flags: BTreeSet<String>,
+    /// Named features enabled by version cut-ins; see
+    /// [`HelpCapabilityProbe::feature_since`].
+    pub features: BTreeSet<String>,
+}
+
+impl Capabilities {
+    /// Whether the binary accepts `flag`, given with or without dashes.
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.contains(flag.trim_start_matches('-'))
+    }
+
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.contains(feature)
+    }
+}
+
+/// Collects flags from the binary's help output (`--help` by default) and
+/// derives features from its `--version` output.
+pub struct HelpCapabilityProbe {
+    args: Vec<String>,
+    cut_ins: Vec<(String, semver::Version)>,
+}
+
+impl HelpCapabilityProbe {
+    pub fn new(args: Vec<String>) -> Self {
+        Self {
+            args,
+            cut_ins: Vec::new(),
+        }
+    }
+
+    /// Runs `--help`.
+    pub fn help() -> Self {
+        Self::new(vec!["--help".into()])
+    }
+
+    /// Reports `feature` for binaries whose version is at least `since`.
+    pub fn feature_since(mut self, feature: impl Into<String>, since: semver::Version) -> Self {
+        self.cut_ins.push((feature.into(), since));
+        self
+    }
+}
+
+impl CapabilityProbe for HelpCapabilityProbe {
+    fn probe(&self, exe: &Path) -> Capabilities {
+        let flag = Regex::new(r"(?:^|[\s,\[(])--?([A-Za-z][A-Za-z0-9_-]*)").expect("valid regex");
+        let flags = crate::binfmt::launch(exe)
+            .ok()
+            .and_then(|launch| run_confined(&launch, &self.args, PROBE_TIMEOUT).ok())
+            .map(|output| {
+                [output.stdout, output.stderr]
+                    .iter()
+                    .flat_map(|stream| {
+                        let text = String::from_utf8_lossy(stream);
+                        flag.captures_iter(&text)
+                            .map(|caps| caps[1].to_string())
+                            .collect::<Vec<_>>()
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let version = if self.cut_ins.is_empty() {
+            None
+        } else {
+            RegexVersionProbe::semver()
+                .probe(exe)
+                .as_deref()
+                .and_then(parse_semver)
+        };
+        let features = self
+            .cut_ins
+            .iter()
+            .filter(|(_, since)| version.as_ref().is_some_and(|v| v >= since))
+            .map(|(feature, _)| feature.clone())
+            .collect();
+        Capabilities { flags, features }
+    }
+}
+
+/// Runs `exe` with `args` (under qemu-user if need be), confined, and fails
+/// unless it exits 0 within `timeout`.
+pub(crate) fn health_check(exe: &Path, args: &[String], timeout: Duration) -> Result<()> {
+    let failed = |reason: String| VerifyError::HealthCheckFailed {
+        path: exe.to_path_buf(),
+        reason,
+    };
+    let output = run_confined(&crate::binfmt::launch(exe)?, args, timeout).map_err(failed)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let last = stderr
+            .lines()
+            .rev()
+            .find(|l| !l.trim().is_empty())
+            .unwrap_or("");
+        return Err(failed(format!(
+            "`{}` exited with {}: {last}",
+            args.join(" "),
+            output.status
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Runs `launch` with `args`, confined as the [module docs](self) describe,
+/// and collects its output; fails with the reason if it couldn't start or
+/// was still running after `timeout`, when it is killed.
+pub(crate) fn run_confined(
+    launch: &Launch,
+    args: &[String],
+    timeout: Duration,
+) -> std::result::Result<Output, String> {
+    let dir =
+        ScratchDir::new().map_err(|e| format!("could not create a working directory: {e}"))?;
+    let mut inner = launch.command();
+    inner.args(args);
+    let mut child = confine(inner, &dir.0, launch.interpreter.is_none())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not start: {e}"))?;
+    // Drained on the side so a chatty binary can't block on a full pipe.
+    type Drained = (Arc<Mutex<Vec<u8>>>, JoinHandle<()>);
+    fn drain(pipe: Option<impl Read + Send + 'static>) -> Option<Drained> {
+        pipe.map(|mut pipe| {
+            let bytes = Arc::new(Mutex::new(Vec::new()));
+            let sink = Arc::clone(&bytes);
+            let reader = std::thread::spawn(move || {
+                let mut buf = [0; 8192];
+                while let Ok(n @ 1..) = pipe.read(&mut buf) {
+                    sink.lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .extend_from_slice(&buf[..n]);
+                }
+            });
+            (bytes, reader)
+        })
+    }
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(20)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("`{}` timed out after {timeout:?}", args.join(" ")));
+            }
+            Err(e) => return Err(format!("could not wait: {e}")),
+        }
+    };
+    // Children the binary left running may keep the pipes open: read until
+    // the deadline (or shortly after the exit, if that was close to it) only,
+    // and leave the readers to finish whenever they do.
+    let deadline = deadline.max(Instant::now() + Duration::from_millis(200));
+    let collect = |drained: Option<Drained>| {
+        let Some((bytes, reader)) = drained else {
+            return Vec::new();
+        };
+        while !reader.is_finished() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        std::mem::take(&mut *bytes.lock().unwrap_or_else(PoisonError::into_inner))
+    };
+    Ok(Output {
+        status,
+        stdout: collect(stdout),
+        stderr: collect(stderr),
+    })
+}
+
+/// Environment variables probed binaries keep.
+const KEPT_ENV: &[&str] = if cfg!(windows) {
+    &["PATH", "SystemRoot", "windir"]
+} else {
+    &["PATH"]
+};
+
+/// The sandbox profile that keeps `sandbox-exec`ed binaries off the network.
+const NO_NETWORK_PROFILE: &str = "(version 1) (allow default) (deny network*)";
+
+/// What probed binaries are kept off the network with.
+#[derive(Debug, Clone, Copy)]
+enum NetworkSandbox {
+    Unshare,
+    SandboxExec,
+}
+
+/// The sandbox that works here, tried once per process.
+fn network_sandbox() -> Option<NetworkSandbox> {
+    static SANDBOX: OnceLock<Option<NetworkSandbox>> = OnceLock::new();
+    *SANDBOX.get_or_init(|| {
+        let works = |command: &mut Command| {
+            command
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .is_ok_and(|status| status.success())
+        };
+        if cfg!(target_os = "linux")
+            && works(Command::new("unshare").args([
+                "--user",
+                "--map-current-user",
+                "--net",
+                "true",
+            ]))
+        {
+            Some(NetworkSandbox::Unshare)
+        } else if cfg!(target_os = "macos")
+            && works(Command::new("sandbox-exec").args(["-p", NO_NETWORK_PROFILE, "/usr/bin/true"]))
+        {
+            Some(NetworkSandbox::SandboxExec)
+        } else {
+            None
+        }
+    })
+}
+
+/// Wraps `inner` to run in `dir` with a scrubbed environment, resource limits
+/// and, where available, no network; `native` is false under an emulator.
+fn confine(inner: Command, dir: &Path, native: bool) -> Command {
+    let mut command = match network_sandbox() {
+        Some(NetworkSandbox::Unshare) => {
+            let mut command = Command::new("unshare");
+            command.args(["--user", "--map-current-user", "--net", "--"]);
+            command.arg(inner.get_program());
+            command
+        }
+        Some(NetworkSandbox::SandboxExec) => {
+            let mut command = Command::new("sandbox-exec");
+            command.args(["-p", NO_NETWORK_PROFILE]);
+            command.arg(inner.get_program());
+            command
+        }
+        None => Command::new(inner.get_program()),
+    };
+    command.args(inner.get_args()).env_clear();
+    for key in KEPT_ENV {
+        if let Some(value) = std::env::var_os(key) {
+            command.env(key, value);
+        }
+    }
+    let scratch: &[&str] = if cfg!(windows) {
+        &["USERPROFILE", "TEMP", "TMP"]
+    } else {
+        &["HOME", "TMPDIR"]
+    };
+    for key in scratch {
+        command.env(key, dir);
+    }
+    command.env("LC_ALL", "C");
+    for (key, value) in inner.get_envs() {
+        if let Some(value) = value {
+            command.env(key, value);
+        }
+    }
+    command.current_dir(dir);
+    limit_resources(&mut command, native);
+    command
+}
+
+/// Applies the limits of the [module docs](self) to `command` and its
+/// children. Failures are ignored, like those of unsupported limits.
+fn limit_resources(command: &mut Command, native: bool) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        const CPU_SECONDS: libc::rlim_t = 60;
+        const FILE_SIZE: libc::rlim_t = 64 << 20;
+        const OPEN_FILES: libc::rlim_t = 256;
+        #[cfg(target_os = "linux")]
+        const ADDRESS_SPACE: libc::rlim_t = 4 << 30;
+
+        // SAFETY: the hook only makes async-signal-safe syscalls.
+        unsafe {
+            command.pre_exec(move || {
+                let limit = |max| libc::rlimit {
+                    rlim_cur: max,
+                    rlim_max: max,
+                };
+                libc::setrlimit(libc::RLIMIT_CPU, &limit(CPU_SECONDS));
+                libc::setrlimit(libc::RLIMIT_FSIZE, &limit(FILE_SIZE));
+                libc::setrlimit(libc::RLIMIT_NOFILE, &limit(OPEN_FILES));
+                libc::setrlimit(libc::RLIMIT_CORE, &limit(0));
+                // Emulators reserve the guest's whole address space up front.
+                #[cfg(target_os = "linux")]
+                if native {
+                    libc::setrlimit(libc::RLIMIT_AS, &limit(ADDRESS_SPACE));
+                }
+                Ok(())
+            });
+        }
+    }
+    let _ = (command, native);
+}
+
+/// A fresh, empty directory under the system temp directory, removed on drop.
+struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    /// Creates the directory under a random name, mode 0700 on Unix; names
+    /// already taken, by anyone, are never reused.
+    fn new() -> std::io::Result<Self> {
+        use std::hash::{BuildHasher, RandomState};
+
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let mut builder = std::fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        let mut attempts = 0;
+        loop {
+            // Randomly keyed per process and hasher, so names can't be guessed.
+            let random = RandomState::new().hash_one(NEXT.fetch_add(1, Ordering::Relaxed));
+            let dir = std::env::temp_dir().join(format!(
+                "zcash-artifacts-probe-{}-{random:016x}",
+                std::process::id()
+            ));
+            match builder.create(&dir) {
+                Ok(()) => return Ok(Self(dir)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && attempts < 16 => {
+                    attempts += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,381 @@
+//! Reusable [`BuildRecipe`] building blocks.
+
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    BuildInvocation, BuildIsolation, BuildRecipe,
+    error::{BuildError, FsError, Result},
+    platform::Platform,
+};
+#[cfg(feature = "oci")]
+use crate::{ImageRecipe, container::Runtime, oci::OciBackend};
+
+/// Runs `command` with the invocation's [executor](BuildInvocation::executor).
+pub(crate) fn run_logged(command: Command, inv: &BuildInvocation<'_>) -> Result<()> {
+    inv.executor.run(command, inv)
+}
+
+/// Runs `command` in the invocation's repo with its env, logging stdout/stderr to the log file.
+///
+/// The invocation's extra arguments are appended to `command`, which then runs
+/// in the invocation's sandbox, at lowered priority if
+/// [`BuildInvocation::low_priority`] is set.
+pub(crate) fn run_local(mut command: Command, inv: &BuildInvocation<'_>) -> Result<()> {
+    let stdout = File::create(inv.log).map_err(|e| FsError::Io {
+        context: format!("create {}", inv.log.display()),
+        source: e,
+    })?;
+    let stderr = stdout.try_clone().map_err(|e| FsError::Io {
+        context: format!("dup {}", inv.log.display()),
+        source: e,
+    })?;
+
+    command.args(inv.extra_args);
+    let mut command = isolate(command, inv)?;
+    if inv.low_priority {
+        lower_priority(&mut command);
+    }
+    let program = command.get_program().to_string_lossy().into_owned();
+    command
+        .envs(inv.env.iter().map(|(k, v)| (k, v)))
+        .current_dir(inv.repo)
+        .stdout(stdout)
+        .stderr(stderr);
+    let status = crate::cancel::status(&mut command, inv.cancel, |e| {
+        FsError::Io {
+            context: format!("spawn {program} in {}", inv.repo.display()),
+            source: e,
+        }
+        .into()
+    })?;
+    if !status.success() {
+        return Err(BuildError::script_failed(status.code().unwrap_or(-1), inv.log).into());
+    }
+    Ok(())
+}
+
+/// Sets up the mounts of an `unshare` sandbox, then drops back to the caller's
+/// uid and gid. Arguments: uid, gid, writable paths, `--`, the command.
+///
+/// Every mount must end up read-only or the script exits with 125, so a
+/// sandbox that META records as `unshare` never has writable leftovers. The
+/// flags the kernel locks in a user namespace (nosuid, nodev, noexec, atime)
+/// are carried over, since remounting without them is refused.
+const UNSHARE_SETUP: &str = r#"
+uid=$1 gid=$2
+shift 2
+# mountinfo fields: 5 is the mount point, with octal escapes like \040 for a
+# space, and 6 its per-mount flags.
+while read -r _ _ _ _ m opts _; do
+    m=$(printf '%b.' "$(printf '%s' "$m" | sed 's/\\/\\0/g')") && m=${m%.}
+    case $m in
+    /proc | /proc/*) continue ;; # the uid map below is written through /proc
+    esac
+    flags=ro
+    for o in $(printf '%s' "$opts" | tr , ' '); do
+        case $o in
+        nosuid | nodev | noexec | noatime | nodiratime | relatime) flags=$flags,$o ;;
+        esac
+    done
+    mount -o "remount,bind,$flags" "$m" || exit 125
+done < /proc/self/mountinfo
+while [ "$1" != -- ]; do
+    mount --bind "$1" "$1" && mount -o remount,bind,rw "$1" || exit 125
+    shift
+done
+shift
+cd "$(pwd -P)" || exit 125 # re-enter the worktree through its new mount
+exec unshare --user --map-user="$uid" --map-group="$gid" -- "$@"
+"#;
+
+/// Wraps `command` in the invocation's sandbox, if any.
+fn isolate(command: Command, inv: &BuildInvocation<'_>) -> Result<Command> {
+    let (tool, extra) = match inv.isolation {
+        BuildIsolation::None => return Ok(command),
+        BuildIsolation::Bubblewrap { writable } => ("bwrap", writable),
+        BuildIsolation::Unshare { writable } => ("unshare", writable),
+    };
+    if matches!(inv.isolation, BuildIsolation::Unshare { .. }) && !cfg!(target_os = "linux") {
+        return Err(BuildError::IsolationUnavailable {
+            reason: "unshare sandboxes need Linux".into(),
+        }
+        .into());
+    }
+    if crate::binfmt::find_on_path(tool).is_none() {
+        return Err(BuildError::IsolationUnavailable {
+            reason: format!("`{tool}` not found on PATH"),
+        }
+        .into());
+    }
+
+    let mut writable = vec![inv.repo.to_path_buf(), std::env::temp_dir()];
+    writable.extend(inv.log.parent().map(Path::to_path_buf));
+    writable.extend(extra.iter().cloned());
+    // Paths must exist to be mounted over. Mounting a parent later would hide
+    // the mounts below it, so only the outermost paths are kept.
+    writable.retain(|path| path.exists());
+    writable.sort();
+    writable.dedup_by(|path, outer| path.starts_with(outer));
+
+    let mut sandboxed = Command::new(tool);
+    match inv.isolation {
+        BuildIsolation::Bubblewrap { .. } => {
+            sandboxed.args(["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc"]);
+            for path in &writable {
+                sandboxed.arg("--bind").arg(path).arg(path);
+            }
+            sandboxed
+                .args(["--unshare-user", "--unshare-pid", "--unshare-ipc"])
+                .arg("--die-with-parent")
+                .arg("--chdir")
+                .arg(inv.repo);
+        }
+        _ => {
+            #[cfg(unix)]
+            // SAFETY: getters without side effects or failure modes.
+            let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+            #[cfg(not(unix))]
+            let (uid, gid) = (0, 0);
+            sandboxed
+                .args([
+                    "--user",
+                    "--map-root-user",
+                    "--mount",
+                    "--propagation",
+                    "private",
+                ])
+                .args(["--", "sh", "-c", UNSHARE_SETUP, "sh"])
+                .arg(uid.to_string())
+                .arg(gid.to_string())
+                .args(&writable);
+        }
+    }
+    sandboxed
+        .arg("--")
+        .arg(command.get_program())
+        .args(command.get_args());
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => sandboxed.env(key, value),
+            None => sandboxed.env_remove(key),
+        };
+    }
+    Ok(sandboxed)
+}
+
+/// Makes `command` start at nice 10 and, on Linux, IO priority best-effort/7.
+/// Children of the build (make, compilers) inherit both. Failures are ignored:
+/// a build at normal priority beats no build.
+fn lower_priority(command: &mut Command) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        // SAFETY: the hook only makes async-signal-safe syscalls.
+        unsafe {
+            command.pre_exec(|| {
+                libc::setpriority(libc::PRIO_PROCESS, 0, 10);
+                #[cfg(target_os = "linux")]
+                {
+                    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+                    const IOPRIO_CLASS_BE: libc::c_int = 2;
+                    libc::syscall(
+                        libc::SYS_ioprio_set,
+                        IOPRIO_WHO_PROCESS,
+                        0,
+                        (IOPRIO_CLASS_BE << 13) | 7,
+                    );
+                }
+                Ok(())
+            });
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = command;
+}
+
+/// Builds a binary target with `cargo build --release --bin <bin>`.
+///
+/// The output is expected at `target/release/<bin>` (plus `.exe` on Windows), or
+/// at `target/<triple>/release/<bin>` when cross-compiling with `--target`.
+pub struct CargoRecipe {
+    bin: String,
+}
+
+impl CargoRecipe {
+    pub fn new(bin: impl Into<String>) -> Self {
+        Self { bin: bin.into() }
+    }
+}
+
+impl BuildRecipe for CargoRecipe {
+    fn build(&self, inv: &BuildInvocation<'_>) -> Result<PathBuf> {
+        let mut cmd = Command::new("cargo");
+        cmd.args(["build", "--release", "--bin", &self.bin])
+            .arg(format!("-j{}", inv.jobs));
+        let dir = match inv.target {
+            Some(triple) => {
+                cmd.args(["--target", triple]);
+                PathBuf::from("target").join(triple).join("release")
+            }
+            None => PathBuf::from("target/release"),
+        };
+        run_logged(cmd, inv)?;
+        Ok(dir.join(exe_name(&self.bin, inv)))
+    }
+}
+
+/// Builds a Go main package at the repo root with `go build -o <bin> .`.
+///
+/// Cross builds set `GOOS`/`GOARCH` (and `GOARM`) from the target's platform.
+pub struct GoRecipe {
+    bin: String,
+}
+
+impl GoRecipe {
+    pub fn new(bin: impl Into<String>) -> Self {
+        Self { bin: bin.into() }
+    }
+}
+
+impl BuildRecipe for GoRecipe {
+    fn build(&self, inv: &BuildInvocation<'_>) -> Result<PathBuf> {
+        // `go build -o` writes exactly the given name, so add the suffix ourselves.
+        let out = exe_name(&self.bin, inv);
+        let mut cmd = Command::new("go");
+        cmd.args(["build", "-o", &out])
+            .arg(format!("-p={}", inv.jobs))
+            .arg(".");
+        if let Some(platform) = inv.target.and_then(Platform::parse) {
+            let goos = match platform.os() {
+                "macos" => "darwin",
+                os => os,
+            };
+            let goarch = match platform.generic_arch() {
+                "x86_64" => "amd64",
+                "aarch64" => "arm64",
+                arch if arch.starts_with("armv") => {
+                    cmd.env("GOARM", arch.trim_start_matches("armv"));
+                    "arm"
+                }
+                arch => arch,
+            };
+            cmd.env("GOOS", goos).env("GOARCH", goarch);
+        }
+        run_logged(cmd, inv)?;
+        Ok(PathBuf::from(out))
+    }
+}
+
+/// Builds the repo's Dockerfile into a container image with `docker build` or
+/// `podman build` (Buildah); see [`BuildRecipe::image`].
+///
+/// The image stays in the runtime's store, tagged per commit and worktree
+/// state, and the `Build` source resolves to
+/// [`ResolvedArtifact::OciImage`](crate::ResolvedArtifact::OciImage). Cross
+/// builds ask for the target's architecture with `--platform linux/<arch>`.
+#[cfg(feature = "oci")]
+pub struct DockerfileRecipe {
+    dockerfile: PathBuf,
+    context: PathBuf,
+    backend: OciBackend,
+}
+
+#[cfg(feature = "oci")]
+impl DockerfileRecipe {
+    /// Builds `Dockerfile` at the repo root, with the repo as context, using
+    /// whichever runtime [`OciBackend::Auto`] finds.
+    pub fn new() -> Self {
+        Self {
+            dockerfile: PathBuf::from("Dockerfile"),
+            context: PathBuf::from("."),
+            backend: OciBackend::Auto,
+        }
+    }
+
+    /// The Dockerfile to build, relative to the repo.
+    pub fn dockerfile(mut self, path: impl Into<PathBuf>) -> Self {
+        self.dockerfile = path.into();
+        self
+    }
+
+    /// The build context, relative to the repo.
+    pub fn context(mut self, path: impl Into<PathBuf>) -> Self {
+        self.context = path.into();
+        self
+    }
+
+    /// The runtime to build with; [`OciBackend::Registry`] means the same as
+    /// [`OciBackend::Auto`].
+    pub fn backend(mut self, backend: OciBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Runs the build, writing the image ID next to the log, and returns that file.
+    fn run(&self, inv: &BuildInvocation<'_>, tag: Option<&str>) -> Result<PathBuf> {
+        let runtime = Runtime::for_build(self.backend)?;
+        let iidfile = inv.log.with_extension("iid");
+        let mut cmd = Command::new(runtime.program());
+        cmd.arg("build")
+            .arg("--file")
+            .arg(&self.dockerfile)
+            .arg("--iidfile")
+            .arg(&iidfile);
+        if let Some(tag) = tag {
+            cmd.args(["--tag", tag]);
+        }
+        if let Some(platform) = inv.target.and_then(Platform::parse) {
+            let platform = format!("linux-{}", platform.generic_arch());
+            cmd.arg("--platform")
+                .arg(crate::container::runtime_platform(&platform));
+        }
+        cmd.arg(&self.context);
+        run_logged(cmd, inv)?;
+        Ok(iidfile)
+    }
+}
+
+#[cfg(feature = "oci")]
+impl Default for DockerfileRecipe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "oci")]
+impl BuildRecipe for DockerfileRecipe {
+    /// Builds an untagged image. There is no binary, so this returns the file
+    /// holding the image's ID; [`crate::pipeline::BuildLayer`] calls
+    /// [`ImageRecipe::build_image`] instead.
+    fn build(&self, inv: &BuildInvocation<'_>) -> Result<PathBuf> {
+        self.run(inv, None)
+    }
+
+    fn image(&self) -> Option<&dyn ImageRecipe> {
+        Some(self)
+    }
+}
+
+#[cfg(feature = "oci")]
+impl ImageRecipe for DockerfileRecipe {
+    fn build_image(&self, inv: &BuildInvocation<'_>, tag: &str) -> Result<()> {
+        self.run(inv, Some(tag)).map(drop)
+    }
+
+    fn backend(&self) -> OciBackend {
+        self.backend
+    }
+}
+
+/// `bin` with the executable suffix of the invocation's target (or the host).
+fn exe_name(bin: &str, inv: &BuildInvocation<'_>) -> String {
+    match inv.target {
+        Some(triple) => crate::platform::exe_name(bin, triple),
+        None => format!("{bin}{}", std::env::consts::EXE_SUFFIX),
+    }
+}
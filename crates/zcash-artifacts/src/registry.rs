@@ -1,6 +1,21 @@
-use crate::{BuildRecipe, VersionProbe, zcashd::spec_zcashd};
+use std::sync::Arc;
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg(feature = "local-build")]
+use crate::BuildRecipe;
+#[cfg(feature = "http")]
+use crate::ReleaseIndex;
+use crate::{
+    ArtifactProvider, CapabilityProbe, VersionProbe,
+    error::{InputError, LocateError, PlatformError, Result},
+    lightwalletd::spec_lightwalletd,
+    platform::normalize,
+    zainod::spec_zainod,
+    zcashd::spec_zcashd,
+    zebrad::spec_zebrad,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, serde::Serialize)]
+#[serde(transparent)]
 pub struct ServiceId(std::borrow::Cow<'static, str>);
 
 impl ServiceId {
@@ -10,30 +25,438 @@ impl ServiceId {
     pub fn new_owned(s: String) -> Self {
         Self(std::borrow::Cow::Owned(s))
     }
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
-// TODO: Add constants for `zebrad`, `lightwaleltd` and `zainod`
 pub const ZCASHD: ServiceId = ServiceId::new_static("zcashd");
-// pub const ZEBRAD: ServiceId = ServiceId::new_static("zebrad");
+pub const ZEBRAD: ServiceId = ServiceId::new_static("zebrad");
+pub const LIGHTWALLETD: ServiceId = ServiceId::new_static("lightwalletd");
+pub const ZAINOD: ServiceId = ServiceId::new_static("zainod");
+
+/// Maps a platform triple (e.g. `"linux-x86_64"`) to candidate binary names.
+pub type BinaryNames = Arc<dyn Fn(&str) -> Vec<String> + Send + Sync>;
 
 /// Describes how to handle a service: what binary to expect, how to find it, etc.
+#[derive(Clone)]
 pub struct ToolSpec {
     pub id: ServiceId,
 
     /// Candidate binary names per platform (used to locate executables in archives or after builds).
-    pub binary_names: fn(&str /* platform triple */) -> &'static [&'static str],
+    ///
+    /// Names may omit the `.exe` suffix; [`ToolSpec::binary_names_for`] adds it on Windows.
+    pub binary_names: BinaryNames,
 
     /// Default relative path to the built binary inside a repo (for local-build).
     pub default_expected_output: std::path::PathBuf,
 
+    /// Glob patterns locating the binary inside an extracted release archive,
+    /// relative to the extraction root and tried in order (e.g. `zcash-*/bin/zcashd`).
+    ///
+    /// When empty, the unpacker falls back to searching for [`ToolSpec::binary_names`].
+    pub archive_layout: Vec<String>,
+
+    /// Release asset file name per platform triple, e.g.
+    /// `"linux-x86_64" => "zebrad-{version}-x86_64-unknown-linux-gnu.tar.gz"`.
+    ///
+    /// `{version}` is substituted by [`ToolSpec::asset_name`]; release indexes
+    /// receive the rendered name. Musl or static builds go under the
+    /// [`musl_flavor`](crate::platform::musl_flavor) key (`linux-x86_64-musl`) and
+    /// are preferred on musl hosts.
+    pub asset_names: std::collections::HashMap<String, String>,
+
+    /// Where the service's binaries can run.
+    pub requirements: PlatformRequirements,
+
+    /// Companion binaries shipped alongside the service binary, as role -> file name
+    /// (e.g. `"zcash-cli" => "zcash-cli"`). They are expected in the same directory.
+    pub companions: std::collections::BTreeMap<String, String>,
+
     /// Optional strategies (all are optional in MVP).
     #[cfg(feature = "local-build")]
-    pub build: Option<&'static dyn BuildRecipe>,
+    pub build: Option<Arc<dyn BuildRecipe>>,
+    #[cfg(feature = "http")]
+    pub releases: Option<Arc<dyn ReleaseIndex>>, // post-MVP if you want
+    /// Where Guix-built releases are published; see [`crate::guix`].
+    #[cfg(feature = "http")]
+    pub guix: Option<crate::guix::GuixReleases>,
+    /// Where the service's Debian packages are published; see [`crate::deb`].
+    #[cfg(feature = "deb")]
+    pub apt: Option<crate::deb::AptRepository>,
+    pub version_probe: Option<Arc<dyn VersionProbe>>,
+    /// Reports supported flags and features; see
+    /// [`ArtifactResolver::capabilities`](crate::ArtifactResolver::capabilities).
+    pub capability_probe: Option<Arc<dyn CapabilityProbe>>,
+
+    /// TCP ports the service listens on, across the networks it can run on;
+    /// published by [`crate::compose`] and [`crate::package`], and exposed by
+    /// `crate::testcontainers` (`testcontainers` feature).
+    pub ports: Vec<u16>,
+
+    /// A line the service prints to its console once it is ready, which
+    /// `crate::testcontainers` waits for; `None` to consider it ready once started.
+    pub ready_log: Option<String>,
+
+    /// Arguments for the opt-in post-resolve health check
+    /// ([`ResolveOptions::health_check`](crate::ResolveOptions::health_check)),
+    /// which must make the binary exit 0 quickly. `--version` by default.
+    pub health_check_args: Vec<String>,
+
+    /// Runs before the resolver's layers for this service; returning `None` falls
+    /// through to them.
+    pub provider: Option<Arc<dyn ArtifactProvider>>,
+
+    /// What this service needs running (or present) alongside it.
+    pub dependencies: Vec<Dependency>,
+
+    /// Version of this service's cache layout/build recipe, folded into cache keys.
+    ///
+    /// Bump it when a recipe change makes previously cached builds invalid.
+    pub builder_schema: u32,
+
+    /// Service-specific overrides for the global [`crate::BuildConfig`].
+    #[cfg(feature = "local-build")]
+    pub build_defaults: BuildDefaults,
+}
+
+/// Something a service needs in order to run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Dependency {
+    /// Another service, e.g. an indexer's full node.
+    Service(ServiceId),
+    /// Any one of several services, in order of preference.
+    OneOf(Vec<ServiceId>),
+    /// A non-service resource the caller must provide, e.g. `zcash-params`.
+    Resource(String),
+}
+
+/// Platforms a service supports, checked before resolving anything for it.
+#[derive(Debug, Clone, Default)]
+pub struct PlatformRequirements {
+    /// Supported platform strings (e.g. `linux-x86_64`); empty means any.
+    pub platforms: Vec<String>,
+    /// Minimum glibc `(major, minor)` needed by prebuilt Linux binaries.
+    ///
+    /// Checked before downloading. Downloaded binaries are checked again against
+    /// the `GLIBC_*` symbol versions they actually require.
+    pub min_glibc: Option<(u32, u32)>,
+}
+
+impl PlatformRequirements {
+    /// Fails with [`PlatformError::Unsupported`] if `service` can't run on `platform`.
+    ///
+    /// The glibc requirement only applies to `prebuilt` binaries on the host
+    /// platform; anything built locally links against the host's own libc.
+    pub fn check(&self, service: &ServiceId, platform: &str, prebuilt: bool) -> Result<()> {
+        let platform = &normalize(platform);
+        let unsupported = |reason: String| PlatformError::Unsupported {
+            service: service.clone(),
+            platform: platform.to_string(),
+            reason,
+        };
+        if !self.platforms.is_empty() && !self.platforms.iter().any(|p| normalize(p) == *platform) {
+            return Err(unsupported(format!("supported: {}", self.platforms.join(", "))).into());
+        }
+        if let Some((major, minor)) = self.min_glibc
+            && prebuilt
+            && platform.starts_with("linux-")
+            && *platform == crate::platform::host()
+        {
+            match crate::platform::host_glibc() {
+                Some(host) if host >= (major, minor) => {}
+                Some((h_major, h_minor)) => {
+                    return Err(unsupported(format!(
+                        "asset requires glibc >= {major}.{minor}, host has {h_major}.{h_minor}"
+                    ))
+                    .into());
+                }
+                None => {
+                    let host = match crate::platform::host_libc() {
+                        Some(libc) => format!("host is {libc}"),
+                        None => "host glibc not found".into(),
+                    };
+                    return Err(unsupported(format!(
+                        "asset requires glibc >= {major}.{minor}, {host}"
+                    ))
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Per-service build settings, layered over [`crate::BuildConfig`].
+#[cfg(feature = "local-build")]
+#[derive(Debug, Clone, Default)]
+pub struct BuildDefaults {
+    /// Jobs to use for this service, overriding `BuildConfig::default_jobs`.
+    pub jobs: Option<u32>,
+    /// Upper bound on jobs, e.g. for recipes that run out of memory when too parallel.
+    pub max_jobs: Option<u32>,
+    /// Environment variables the recipe needs set for the build.
+    pub env: Vec<(String, String)>,
+    /// Extra arguments passed to the build script.
+    pub extra_args: Vec<String>,
+    /// Free space, in bytes, the build needs on the repository's filesystem.
+    /// Checked before building when set.
+    pub disk_estimate: Option<u64>,
+}
+
+impl ToolSpec {
+    /// Candidate binary names on `platform`, with the platform's executable suffix.
+    pub fn binary_names_for(&self, platform: &str) -> Vec<String> {
+        (self.binary_names)(platform)
+            .iter()
+            .map(|name| crate::platform::exe_name(name, platform))
+            .collect()
+    }
+
+    /// The release asset name for `version` on `platform`, if the spec declares one.
+    pub fn asset_name(&self, version: &str, platform: &str) -> Option<String> {
+        self.asset_names
+            .get(&normalize(platform))
+            .map(|pattern| pattern.replace("{version}", version))
+    }
+
+    /// Starts building a spec for `id`.
+    ///
+    /// Without further configuration the binary is named after the service on
+    /// every platform, the expected build output is `<id>` relative to the repo,
+    /// and no strategies are attached.
+    pub fn builder(id: ServiceId) -> ToolSpecBuilder {
+        ToolSpecBuilder {
+            id,
+            binary_names: None,
+            expected_output: None,
+            archive_layout: Vec::new(),
+            asset_names: Default::default(),
+            requirements: PlatformRequirements::default(),
+            companions: Default::default(),
+            dependencies: Vec::new(),
+            builder_schema: 1,
+            #[cfg(feature = "local-build")]
+            build: None,
+            #[cfg(feature = "http")]
+            releases: None,
+            #[cfg(feature = "http")]
+            guix: None,
+            #[cfg(feature = "deb")]
+            apt: None,
+            version_probe: None,
+            capability_probe: None,
+            health_check_args: vec!["--version".into()],
+            ports: Vec::new(),
+            ready_log: None,
+            provider: None,
+            #[cfg(feature = "local-build")]
+            build_defaults: BuildDefaults::default(),
+        }
+    }
+}
+
+/// Builder for [`ToolSpec`]; see [`ToolSpec::builder`].
+pub struct ToolSpecBuilder {
+    id: ServiceId,
+    binary_names: Option<BinaryNames>,
+    expected_output: Option<std::path::PathBuf>,
+    archive_layout: Vec<String>,
+    asset_names: std::collections::HashMap<String, String>,
+    requirements: PlatformRequirements,
+    companions: std::collections::BTreeMap<String, String>,
+    dependencies: Vec<Dependency>,
+    builder_schema: u32,
+    #[cfg(feature = "local-build")]
+    build: Option<Arc<dyn BuildRecipe>>,
+    #[cfg(feature = "http")]
+    releases: Option<Arc<dyn ReleaseIndex>>,
+    #[cfg(feature = "http")]
+    guix: Option<crate::guix::GuixReleases>,
+    #[cfg(feature = "deb")]
+    apt: Option<crate::deb::AptRepository>,
+    version_probe: Option<Arc<dyn VersionProbe>>,
+    capability_probe: Option<Arc<dyn CapabilityProbe>>,
+    health_check_args: Vec<String>,
+    ports: Vec<u16>,
+    ready_log: Option<String>,
+    provider: Option<Arc<dyn ArtifactProvider>>,
+    #[cfg(feature = "local-build")]
+    build_defaults: BuildDefaults,
+}
+
+impl ToolSpecBuilder {
+    /// Uses the same candidate binary names on every platform.
+    pub fn binary_names<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let names: Vec<String> = names.into_iter().map(Into::into).collect();
+        self.binary_names = Some(Arc::new(move |_: &str| names.clone()));
+        self
+    }
+
+    /// Picks candidate binary names per platform triple.
+    pub fn binary_names_fn(
+        mut self,
+        f: impl Fn(&str) -> Vec<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.binary_names = Some(Arc::new(f));
+        self
+    }
+
+    pub fn expected_output(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.expected_output = Some(path.into());
+        self
+    }
+
+    /// Glob patterns locating the binary inside extracted archives; see [`ToolSpec::archive_layout`].
+    pub fn archive_layout<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.archive_layout = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Release asset name pattern for `platform`; see [`ToolSpec::asset_names`].
+    pub fn asset_name(mut self, platform: impl Into<String>, pattern: impl Into<String>) -> Self {
+        self.asset_names
+            .insert(normalize(&platform.into()), pattern.into());
+        self
+    }
+
+    pub fn requirements(mut self, requirements: PlatformRequirements) -> Self {
+        self.requirements = requirements;
+        self
+    }
+
+    /// Declares a companion binary; see [`ToolSpec::companions`].
+    pub fn companion(mut self, role: impl Into<String>, file_name: impl Into<String>) -> Self {
+        self.companions.insert(role.into(), file_name.into());
+        self
+    }
+
+    pub fn dependency(mut self, dependency: Dependency) -> Self {
+        self.dependencies.push(dependency);
+        self
+    }
+
+    /// Cache schema version for this service; see [`ToolSpec::builder_schema`].
+    pub fn builder_schema(mut self, schema: u32) -> Self {
+        self.builder_schema = schema;
+        self
+    }
+
+    #[cfg(feature = "local-build")]
+    pub fn build_recipe(mut self, recipe: impl BuildRecipe) -> Self {
+        self.build = Some(Arc::new(recipe));
+        self
+    }
+
+    #[cfg(feature = "local-build")]
+    pub fn build_defaults(mut self, defaults: BuildDefaults) -> Self {
+        self.build_defaults = defaults;
+        self
+    }
+
+    #[cfg(feature = "http")]
+    pub fn release_index(mut self, index: impl ReleaseIndex) -> Self {
+        self.releases = Some(Arc::new(index));
+        self
+    }
+
+    /// Where Guix-built releases are published; see [`ToolSpec::guix`].
     #[cfg(feature = "http")]
-    pub releases: Option<&'static dyn ReleaseIndex>, // post-MVP if you want
-    pub version_probe: Option<&'static dyn VersionProbe>,
+    pub fn guix_releases(mut self, releases: crate::guix::GuixReleases) -> Self {
+        self.guix = Some(releases);
+        self
+    }
+
+    /// Where the service's Debian packages are published; see [`ToolSpec::apt`].
+    #[cfg(feature = "deb")]
+    pub fn apt_repository(mut self, repository: crate::deb::AptRepository) -> Self {
+        self.apt = Some(repository);
+        self
+    }
+
+    pub fn version_probe(mut self, probe: impl VersionProbe) -> Self {
+        self.version_probe = Some(Arc::new(probe));
+        self
+    }
+
+    pub fn capability_probe(mut self, probe: impl CapabilityProbe) -> Self {
+        self.capability_probe = Some(Arc::new(probe));
+        self
+    }
+
+    /// Arguments for the health check; see [`ToolSpec::health_check_args`].
+    pub fn health_check_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.health_check_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// TCP ports the service listens on; see [`ToolSpec::ports`].
+    pub fn ports(mut self, ports: impl IntoIterator<Item = u16>) -> Self {
+        self.ports = ports.into_iter().collect();
+        self
+    }
+
+    /// The console line marking the service ready; see [`ToolSpec::ready_log`].
+    pub fn ready_log(mut self, line: impl Into<String>) -> Self {
+        self.ready_log = Some(line.into());
+        self
+    }
+
+    /// Gives `provider` the first shot at resolving this service.
+    pub fn provider(mut self, provider: impl ArtifactProvider) -> Self {
+        self.provider = Some(Arc::new(provider));
+        self
+    }
+
+    pub fn finish(self) -> ToolSpec {
+        let name = self.id.as_str().to_string();
+        ToolSpec {
+            binary_names: self
+                .binary_names
+                .unwrap_or_else(|| Arc::new(move |_: &str| vec![name.clone()])),
+            default_expected_output: self
+                .expected_output
+                .unwrap_or_else(|| self.id.as_str().into()),
+            archive_layout: self.archive_layout,
+            asset_names: self.asset_names,
+            requirements: self.requirements,
+            companions: self.companions,
+            dependencies: self.dependencies,
+            builder_schema: self.builder_schema,
+            id: self.id,
+            #[cfg(feature = "local-build")]
+            build: self.build,
+            #[cfg(feature = "http")]
+            releases: self.releases,
+            #[cfg(feature = "http")]
+            guix: self.guix,
+            #[cfg(feature = "deb")]
+            apt: self.apt,
+            version_probe: self.version_probe,
+            capability_probe: self.capability_probe,
+            health_check_args: self.health_check_args,
+            ports: self.ports,
+            ready_log: self.ready_log,
+            provider: self.provider,
+            #[cfg(feature = "local-build")]
+            build_defaults: self.build_defaults,
+        }
+    }
 }
 
+/// The set of services the resolver knows how to handle, keyed by [`ServiceId`].
 pub struct Registry {
     tools: std::collections::HashMap<ServiceId, ToolSpec>,
 }
@@ -43,7 +466,10 @@ impl Default for Registry {
         let mut tools: std::collections::HashMap<ServiceId, ToolSpec> =
             std::collections::HashMap::new();
         tools.insert(ZCASHD, spec_zcashd());
-        Self { tools: tools }
+        tools.insert(ZEBRAD, spec_zebrad());
+        tools.insert(LIGHTWALLETD, spec_lightwalletd());
+        tools.insert(ZAINOD, spec_zainod());
+        Self { tools }
     }
 }
 
@@ -58,10 +484,141 @@ impl Registry {
         }
     }
 
-    pub fn register(mut self, spec: ToolSpec) -> Self {
-        todo!()
+    /// Adds a new service to the registry.
+    ///
+    /// Fails with [`InputError::DuplicateService`] if a spec with the same id is
+    /// already registered; use [`Registry::replace`] to swap an existing entry.
+    pub fn register(mut self, spec: ToolSpec) -> Result<Self> {
+        if self.tools.contains_key(&spec.id) {
+            return Err(InputError::DuplicateService { service: spec.id }.into());
+        }
+        self.tools.insert(spec.id.clone(), spec);
+        Ok(self)
+    }
+
+    /// Inserts `spec`, returning the spec previously registered under the same id, if any.
+    pub fn replace(&mut self, spec: ToolSpec) -> Option<ToolSpec> {
+        self.tools.insert(spec.id.clone(), spec)
     }
+
     pub fn get(&self, id: &ServiceId) -> Option<&ToolSpec> {
-        todo!()
+        self.tools.get(id)
+    }
+
+    /// Edits a registered spec in place, e.g. to swap only its build recipe:
+    ///
+    /// ```no_run
+    /// # #[cfg(feature = "local-build")]
+    /// # fn demo(recipe: std::sync::Arc<dyn zcash_artifacts::BuildRecipe>) -> zcash_artifacts::Result<()> {
+    /// use zcash_artifacts::registry::{Registry, ZCASHD};
+    ///
+    /// let mut registry = Registry::with_builtins();
+    /// registry.update(&ZCASHD, |spec| spec.build = Some(recipe))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Fails with [`LocateError::UnknownService`] if `id` isn't registered, and
+    /// with [`InputError::InvalidSource`] if `f` changes the spec's id. `f` edits
+    /// a copy, so the registered spec is untouched unless the update succeeds.
+    pub fn update(&mut self, id: &ServiceId, f: impl FnOnce(&mut ToolSpec)) -> Result<()> {
+        let registered = self
+            .tools
+            .get_mut(id)
+            .ok_or_else(|| LocateError::UnknownService {
+                service: id.clone(),
+            })?;
+        let mut spec = registered.clone();
+        f(&mut spec);
+        if spec.id != *id {
+            return Err(InputError::InvalidSource {
+                service: id.clone(),
+                reason: format!("update changed the service id to {:?}", spec.id),
+            }
+            .into());
+        }
+        *registered = spec;
+        Ok(())
+    }
+
+    /// Layers `overrides` over `base`: specs in `overrides` replace those with the
+    /// same id in `base`, and everything else in `base` is kept.
+    pub fn merged(base: Registry, overrides: Registry) -> Registry {
+        let mut tools = base.tools;
+        tools.extend(overrides.tools);
+        Registry { tools }
+    }
+
+    /// Iterates over the ids of all registered services, in no particular order.
+    pub fn services(&self) -> impl Iterator<Item = &ServiceId> {
+        self.tools.keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+
+    #[test]
+    fn update_changing_the_id_changes_nothing() {
+        let mut registry = Registry::with_builtins();
+        let before = registry
+            .get(&ZCASHD)
+            .unwrap()
+            .default_expected_output
+            .clone();
+        let err = registry
+            .update(&ZCASHD, |spec| {
+                spec.default_expected_output = "elsewhere".into();
+                spec.id = ZEBRAD;
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput, "{err}");
+        let spec = registry.get(&ZCASHD).unwrap();
+        assert_eq!(spec.id, ZCASHD);
+        assert_eq!(spec.default_expected_output, before);
+
+        registry
+            .update(&ZCASHD, |spec| {
+                spec.default_expected_output = "elsewhere".into()
+            })
+            .unwrap();
+        assert_eq!(
+            registry.get(&ZCASHD).unwrap().default_expected_output,
+            std::path::Path::new("elsewhere")
+        );
+    }
+
+    #[test]
+    fn update_of_unknown_service_is_not_found() {
+        let mut registry = Registry::with_builtins();
+        let err = registry
+            .update(&ServiceId::new_static("demo"), |_| {})
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound, "{err}");
+    }
+
+    #[test]
+    fn specs_carry_their_ports_and_ready_line() {
+        let registry = Registry::with_builtins();
+        let zcashd = registry.get(&ZCASHD).unwrap();
+        assert_eq!(zcashd.ports, [8232, 8233, 18232, 18233, 18344]);
+        assert_eq!(
+            zcashd.ready_log.as_deref(),
+            Some("init message: Done loading")
+        );
+        let zainod = registry.get(&ZAINOD).unwrap();
+        assert_eq!(zainod.ports, [8137]);
+        assert_eq!(zainod.ready_log, None);
+
+        let custom = ToolSpec::builder(ServiceId::new_static("zallet"))
+            .ports([28232])
+            .ready_log("listening")
+            .finish();
+        assert_eq!(custom.ports, [28232]);
+        assert_eq!(custom.ready_log.as_deref(), Some("listening"));
+        let bare = ToolSpec::builder(ServiceId::new_static("zallet")).finish();
+        assert!(bare.ports.is_empty() && bare.ready_log.is_none());
     }
 }
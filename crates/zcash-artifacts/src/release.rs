@@ -0,0 +1,66 @@
+//! Built-in [`ReleaseIndex`] implementations.
+
+use std::collections::HashMap;
+
+use crate::ReleaseIndex;
+
+/// Builds asset URLs from a template and looks checksums up in a static table.
+///
+/// The template may use `{version}`, `{platform}`, `{os}` and `{arch}`, where
+/// `os` and `arch` are the two halves of the platform triple (`linux-x86_64`),
+/// and `{asset}` for the spec's asset name. Checksum tables are keyed by
+/// canonical platform strings (see [`crate::platform`]). Assets without a known checksum, or
+/// templates using `{asset}` on platforms without an asset name, are not offered.
+pub struct TemplateReleaseIndex {
+    url_template: String,
+    /// version -> platform -> sha256
+    checksums: HashMap<String, HashMap<String, String>>,
+}
+
+impl TemplateReleaseIndex {
+    pub fn new(
+        url_template: impl Into<String>,
+        checksums: HashMap<String, HashMap<String, String>>,
+    ) -> Self {
+        Self {
+            url_template: url_template.into(),
+            checksums,
+        }
+    }
+}
+
+/// Renders `template` as [`TemplateReleaseIndex`] documents; `None` if it uses
+/// `{asset}` and there is no asset name.
+pub(crate) fn render(
+    template: &str,
+    version: &str,
+    platform: &str,
+    asset_name: Option<&str>,
+) -> Option<String> {
+    let (os, arch) = platform.split_once('-').unwrap_or((platform, ""));
+    let rendered = template
+        .replace("{version}", version)
+        .replace("{platform}", platform)
+        .replace("{os}", os)
+        .replace("{arch}", arch);
+    match asset_name {
+        Some(asset) => Some(rendered.replace("{asset}", asset)),
+        None if rendered.contains("{asset}") => None,
+        None => Some(rendered),
+    }
+}
+
+impl ReleaseIndex for TemplateReleaseIndex {
+    fn asset_for(
+        &self,
+        version: &str,
+        platform: &str,
+        asset_name: Option<&str>,
+    ) -> Option<(url::Url, String)> {
+        let platform = &crate::platform::normalize(platform);
+        let checksum = self.checksums.get(version)?.get(platform)?;
+        let url =
+            url::Url::parse(&render(&self.url_template, version, platform, asset_name)?).ok()?;
+        Some((url, checksum.clone()))
+    }
+}
@@ -0,0 +1,291 @@
+//! Checks that a service builds reproducibly.
+//!
+//! [`ArtifactResolver::check_reproducible`] builds one commit of a repo twice,
+//! each time in a fresh worktree of its own, and compares the BLAKE3 digests
+//! of what the builds produced: the service binary and its companions. Both
+//! builds run with the service's recipe and the resolver's
+//! [`BuildConfig`](crate::BuildConfig), and a normalized environment:
+//!
+//! - `SOURCE_DATE_EPOCH`, the commit's committer date;
+//! - `TZ=UTC`, `LC_ALL=C` and `LANG=C`;
+//! - the service's build environment, then [`ReproOptions::env`].
+//!
+//! The rest of the environment is inherited. The worktrees being at different
+//! paths, builds embedding their source path differ; remapping it, e.g. with
+//! `RUSTFLAGS=--remap-path-prefix=…`, is up to the recipe or the options.
+//!
+//! ```no_run
+//! # fn main() -> zcash_artifacts::Result<()> {
+//! use zcash_artifacts::{ArtifactResolver, ResolverConfig, registry, repro::ReproOptions};
+//!
+//! let resolver = ArtifactResolver::new(ResolverConfig::from_env()?);
+//! let report =
+//!     resolver.check_reproducible(&registry::ZEBRAD, "../zebra".as_ref(), &ReproOptions::default())?;
+//! for file in report.differences() {
+//!     eprintln!("{} differs: {:?} vs {:?}", file.name, file.first, file.second);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Everything is kept under `<cache_root>/repro/<service>-<commit prefix>/`,
+//! which each check of the commit starts afresh:
+//!
+//! ```text
+//! first/ second/            # the worktrees, removed unless `keep_worktrees`
+//! outputs/first/<file>      # copies of what each build produced, to diff
+//! outputs/second/<file>
+//! logs/first.log logs/second.log
+//! report.json               # the `ReproReport`
+//! ```
+//!
+//! Only committed files are built; the repo's own worktree is left alone.
+//! Recipes building images (`oci` feature) can't be checked.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{
+    ArtifactResolver, BuildInvocation, cache,
+    error::{BuildError, FsError, InputError, Result},
+    git,
+    observe::Event,
+    pipeline,
+    registry::ServiceId,
+};
+
+/// How [`ArtifactResolver::check_reproducible`] builds.
+#[derive(Debug, Clone, Default)]
+pub struct ReproOptions {
+    /// Commit to build; `HEAD` by default.
+    pub refspec: Option<String>,
+    /// Rust target triple to cross-compile for; `None` builds for the host.
+    pub target: Option<String>,
+    /// Environment for both builds, on top of the normalized one.
+    pub env: Vec<(String, String)>,
+    /// Keep the worktrees, e.g. to look into intermediate files. They stay
+    /// registered with the repo until `git worktree remove`d.
+    pub keep_worktrees: bool,
+}
+
+/// What [`ArtifactResolver::check_reproducible`] found.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReproReport {
+    pub service: String,
+    pub commit: String,
+    /// `SOURCE_DATE_EPOCH` both builds ran with.
+    pub source_date_epoch: u64,
+    /// The files compared, the service binary first.
+    pub files: Vec<ReproFile>,
+    /// Where everything is kept; see the [module docs](self).
+    pub dir: PathBuf,
+    /// How long each build took.
+    pub durations: [Duration; 2],
+}
+
+/// A file the builds produced, with its BLAKE3 digest from each; `None` if
+/// a build didn't produce it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReproFile {
+    /// The file's name, e.g. `zcashd` or `zcash-cli`.
+    pub name: String,
+    pub first: Option<String>,
+    pub second: Option<String>,
+}
+
+impl ReproFile {
+    pub fn matches(&self) -> bool {
+        self.first.is_some() && self.first == self.second
+    }
+}
+
+impl ReproReport {
+    /// Whether both builds produced the same files, bit for bit.
+    pub fn is_reproducible(&self) -> bool {
+        self.files.iter().all(ReproFile::matches)
+    }
+
+    /// The files that differ, or that only one build produced.
+    pub fn differences(&self) -> impl Iterator<Item = &ReproFile> {
+        self.files.iter().filter(|file| !file.matches())
+    }
+}
+
+/// The builds, in the order they run.
+const BUILDS: [&str; 2] = ["first", "second"];
+
+impl ArtifactResolver {
+    /// Builds `service` from `repo` twice and compares the outputs; see
+    /// [`crate::repro`].
+    ///
+    /// Outputs that differ are reported, not errors; failing builds are.
+    pub fn check_reproducible(
+        &self,
+        service: &ServiceId,
+        repo: &Path,
+        options: &ReproOptions,
+    ) -> Result<ReproReport> {
+        let platform = match &options.target {
+            Some(triple) => crate::platform::normalize(triple),
+            None => crate::platform::host(),
+        };
+        let ctx = self.context(&platform, None, &self.observers);
+        let spec = pipeline::registered(&ctx, service)?;
+        let invalid = |reason: &str| InputError::InvalidSource {
+            service: service.clone(),
+            reason: reason.into(),
+        };
+        let recipe = spec
+            .build
+            .as_ref()
+            .ok_or_else(|| invalid("service has no build recipe"))?;
+        #[cfg(feature = "oci")]
+        if recipe.image().is_some() {
+            return Err(invalid("image recipes can't be checked for reproducibility").into());
+        }
+        if !self.config.build_config.allow_build {
+            return Err(BuildError::DisabledRuntime.into());
+        }
+
+        let commit = git::resolve_commit(repo, options.refspec.as_deref().unwrap_or("HEAD"))?;
+        let epoch = git::commit_time(repo, &commit)?;
+
+        let dir = self.config.cache_root.join("repro").join(format!(
+            "{}-{}",
+            service.as_str(),
+            &commit[..12]
+        ));
+        let io = |context: String| move |e| FsError::Io { context, source: e };
+        if dir.exists() {
+            for build in BUILDS {
+                let _ = git::remove_worktree(repo, &dir.join(build));
+            }
+            std::fs::remove_dir_all(&dir).map_err(io(format!("remove {}", dir.display())))?;
+        }
+        let logs = dir.join("logs");
+        std::fs::create_dir_all(&logs).map_err(io(format!("mkdir {}", logs.display())))?;
+
+        let mut env = vec![
+            ("SOURCE_DATE_EPOCH".to_string(), epoch.to_string()),
+            ("TZ".into(), "UTC".into()),
+            ("LC_ALL".into(), "C".into()),
+            ("LANG".into(), "C".into()),
+        ];
+        env.extend(spec.build_defaults.env.iter().cloned());
+        env.extend(options.env.iter().cloned());
+
+        let executor: &dyn crate::BuildExecutor = match &self.config.build_config.executor {
+            Some(executor) => executor.as_ref(),
+            None => &crate::executor::LocalExecutor,
+        };
+        let jobs = self.config.build_config.jobs_for(&spec.build_defaults);
+
+        let mut outputs: Vec<Vec<(String, PathBuf)>> = Vec::new();
+        let mut durations = [Duration::ZERO; 2];
+        for (n, build) in BUILDS.into_iter().enumerate() {
+            let worktree = dir.join(build);
+            git::add_worktree(repo, &worktree, &commit)?;
+            let log = logs.join(format!("{build}.log"));
+            let invocation = BuildInvocation {
+                repo: &worktree,
+                jobs,
+                log: &log,
+                env: &env,
+                extra_args: &spec.build_defaults.extra_args,
+                target: options.target.as_deref(),
+                low_priority: self.config.build_config.low_priority,
+                isolation: &self.config.build_config.isolation,
+                executor,
+                cancel: None,
+            };
+            let started = Instant::now();
+            ctx.emit(Event::BuildStarted {
+                service,
+                entry: &worktree,
+                jobs,
+            });
+            let built = {
+                let _slot = ctx.limiter.build(None)?;
+                ctx.enter(crate::cancel::Phase::Build);
+                recipe
+                    .build(&invocation)
+                    .and_then(|built| pipeline::build_output(spec, &invocation, &built, &platform))
+            };
+            durations[n] = started.elapsed();
+            ctx.emit(Event::BuildFinished {
+                service,
+                entry: &worktree,
+                elapsed: durations[n],
+                success: built.is_ok(),
+            });
+            let kept = built.and_then(|binary| {
+                let name = binary.file_name().unwrap_or_default().to_string_lossy();
+                let mut files = vec![(name.into_owned(), binary.clone())];
+                files.extend(pipeline::companions_of(
+                    spec,
+                    binary.parent().unwrap_or(&worktree),
+                    &platform,
+                ));
+                keep_outputs(&dir.join("outputs").join(build), files)
+            });
+            if !options.keep_worktrees {
+                let _ = git::remove_worktree(repo, &worktree);
+            }
+            outputs.push(kept?);
+        }
+
+        let mut files: Vec<ReproFile> = Vec::new();
+        for (n, built) in outputs.iter().enumerate() {
+            for (name, path) in built {
+                let (digest, _) = cache::digest_file(path)?;
+                let file = match files.iter_mut().find(|file| file.name == *name) {
+                    Some(file) => file,
+                    None => {
+                        files.push(ReproFile {
+                            name: name.clone(),
+                            first: None,
+                            second: None,
+                        });
+                        files.last_mut().expect("just pushed")
+                    }
+                };
+                match n {
+                    0 => file.first = Some(digest),
+                    _ => file.second = Some(digest),
+                }
+            }
+        }
+
+        let report = ReproReport {
+            service: service.as_str().to_string(),
+            commit,
+            source_date_epoch: epoch,
+            files,
+            dir,
+            durations,
+        };
+        let json = serde_json::to_vec_pretty(&report).expect("report serializes");
+        cache::write_atomic(&report.dir.join("report.json"), &json)?;
+        Ok(report)
+    }
+}
+
+/// Copies `files` into `dir`, each under its name, and returns the copies.
+fn keep_outputs(dir: &Path, files: Vec<(String, PathBuf)>) -> Result<Vec<(String, PathBuf)>> {
+    std::fs::create_dir_all(dir).map_err(|e| FsError::Io {
+        context: format!("mkdir {}", dir.display()),
+        source: e,
+    })?;
+    files
+        .into_iter()
+        .map(|(name, path)| {
+            let kept = dir.join(&name);
+            cache::copy_atomic(&path, &kept)?;
+            Ok((name, kept))
+        })
+        .collect()
+}
@@ -0,0 +1,457 @@
+//! CycloneDX SBOMs of what a run resolved.
+//!
+//! An [`Sbom`] lists resolved artifacts as CycloneDX 1.5 components: the
+//! binaries with their BLAKE3 digests, where they were downloaded from, the
+//! commit and worktree state builds were made from, and pulled images with
+//! their manifest digests. Cached artifacts are described from their META;
+//! see the [cache docs](crate::cache). Register an [`SbomRecorder`] to collect
+//! every resolution of a run, and write the document at the end:
+//!
+//! ```no_run
+//! # fn main() -> zcash_artifacts::Result<()> {
+//! use zcash_artifacts::{
+//!     ArtifactResolver, ArtifactSource, ResolverConfig, registry, sbom::SbomRecorder,
+//! };
+//!
+//! let mut resolver = ArtifactResolver::new(ResolverConfig::from_env()?);
+//! let recorder = SbomRecorder::new();
+//! resolver.hook(recorder.clone());
+//!
+//! resolver.resolve(&ArtifactSource::Release {
+//!     service: registry::ZEBRAD,
+//!     version: "2.0.0".into(),
+//! })?;
+//!
+//! recorder.sbom().write_cyclonedx("target/zcash-artifacts.cdx.json")?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Artifacts resolved more than once, e.g. by several tests, are listed once.
+//! Binaries outside the cache, like local paths and
+//! [overrides](crate::ResolverConfig::binary_overrides), are hashed when
+//! added; nothing more is known about where they came from.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+
+use crate::{
+    ArtifactSource, ResolveContext, ResolvedArtifact, cache,
+    error::{FsError, InputError, Result},
+    hooks::ResolveHook,
+    registry::ServiceId,
+};
+
+/// A list of resolved artifacts, written as a CycloneDX document; see the
+/// [module docs](self).
+#[derive(Debug, Clone, Default)]
+pub struct Sbom {
+    components: Vec<Component>,
+}
+
+impl Sbom {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `artifact`, reading the META of cached ones under `cache_root`.
+    /// Adding an artifact again does nothing. A bundle without a primary
+    /// executable is an [`InputError::InvalidSource`].
+    pub fn add(&mut self, artifact: &ResolvedArtifact, cache_root: &Path) -> Result<()> {
+        self.insert(Component::of(artifact, cache_root)?);
+        Ok(())
+    }
+
+    fn insert(&mut self, component: Component) {
+        if !self
+            .components
+            .iter()
+            .any(|c| c.bom_ref == component.bom_ref)
+        {
+            self.components.push(component);
+        }
+    }
+
+    /// Number of artifacts listed.
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    /// The CycloneDX 1.5 document, in JSON.
+    pub fn to_cyclonedx(&self) -> serde_json::Value {
+        serde_json::json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.5",
+            "version": 1,
+            "metadata": {
+                "timestamp": cache::timestamp(),
+                "tools": {
+                    "components": [{
+                        "type": "application",
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                    }],
+                },
+            },
+            "components": self.components,
+        })
+    }
+
+    /// Writes [the document](Self::to_cyclonedx) to `path`.
+    pub fn write_cyclonedx(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_vec_pretty(&self.to_cyclonedx()).expect("SBOM serializes");
+        std::fs::write(path, json).map_err(|e| {
+            FsError::Io {
+                context: format!("write {}", path.display()),
+                source: e,
+            }
+            .into()
+        })
+    }
+}
+
+/// A [hook](crate::hooks) adding every artifact a resolver resolves to an
+/// [`Sbom`]. Clones share the SBOM, so keep one to read it after registering
+/// another.
+#[derive(Debug, Clone, Default)]
+pub struct SbomRecorder {
+    sbom: Arc<Mutex<Sbom>>,
+}
+
+impl SbomRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What was recorded so far.
+    pub fn sbom(&self) -> Sbom {
+        self.sbom.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl ResolveHook for SbomRecorder {
+    fn after(
+        &self,
+        _source: &ArtifactSource,
+        artifact: &ResolvedArtifact,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<()> {
+        // Built outside the lock; hashing a binary outside the cache is slow.
+        let component = Component::of(artifact, &ctx.config.cache_root)?;
+        self.sbom
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(component);
+        Ok(())
+    }
+}
+
+/// A CycloneDX component.
+#[derive(Debug, Clone, Serialize)]
+struct Component {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(rename = "bom-ref")]
+    bom_ref: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    purl: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hashes: Vec<Hash>,
+    #[serde(rename = "externalReferences", skip_serializing_if = "Vec::is_empty")]
+    external_references: Vec<ExternalReference>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pedigree: Option<Pedigree>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    properties: Vec<Property>,
+    /// A bundle's companions.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    components: Vec<Component>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Hash {
+    alg: &'static str,
+    content: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ExternalReference {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    url: String,
+}
+
+/// Where a build came from: the commit, and whether the worktree had changes.
+#[derive(Debug, Clone, Serialize)]
+struct Pedigree {
+    commits: Vec<Commit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notes: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Commit {
+    uid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Property {
+    name: String,
+    value: String,
+}
+
+impl Component {
+    fn of(artifact: &ResolvedArtifact, cache_root: &Path) -> Result<Self> {
+        match artifact {
+            ResolvedArtifact::Executable { path, provenance } => {
+                Self::executable(path, provenance, cache_root)
+            }
+            ResolvedArtifact::Bundle {
+                executables,
+                provenance,
+            } => {
+                let primary = artifact
+                    .primary_path()
+                    .ok_or_else(|| InputError::InvalidSource {
+                        service: provenance
+                            .service
+                            .clone()
+                            .unwrap_or_else(|| ServiceId::new_static(provenance.source)),
+                        reason: "bundle has no primary executable".into(),
+                    })?;
+                let mut component = Self::executable(primary, provenance, cache_root)?;
+                for (role, path) in executables {
+                    if path != primary {
+                        let mut companion = Self::file(path)?;
+                        companion.name = role.clone();
+                        component.components.push(companion);
+                    }
+                }
+                Ok(component)
+            }
+            #[cfg(feature = "oci")]
+            ResolvedArtifact::OciImage {
+                reference,
+                digest,
+                platform,
+                ..
+            } => Ok(Self::image(reference, digest, platform)),
+        }
+    }
+
+    /// A binary, described by its META if it is cached.
+    fn executable(path: &Path, provenance: &crate::Provenance, cache_root: &Path) -> Result<Self> {
+        let Some((key, meta)) = cache::entry_of(cache_root, path) else {
+            let mut component = Self::file(path)?;
+            if let Some(service) = &provenance.service {
+                component.name = service.as_str().to_string();
+            }
+            component.version = provenance.version.clone();
+            component.property("source", provenance.source);
+            return Ok(component);
+        };
+
+        // URL sources have no service; name them after the asset instead.
+        let name = match (&provenance.service, &meta.url) {
+            (Some(service), _) => service.as_str().to_string(),
+            (None, Some(url)) => url
+                .rsplit('/')
+                .find(|segment| !segment.is_empty())
+                .unwrap_or(url)
+                .to_string(),
+            (None, None) => meta.service.clone(),
+        };
+        let mut component = Self::new("application", key.clone(), name, Some(meta.digest.clone()));
+        component.version = meta
+            .release
+            .clone()
+            .or_else(|| provenance.version.clone())
+            .or_else(|| meta.commit.clone());
+        if let Some(url) = &meta.url {
+            component.external_references.push(ExternalReference {
+                kind: "distribution",
+                url: url.clone(),
+            });
+        }
+        if let Some(image) = &meta.image {
+            component.purl = Some(oci_purl(image, meta.image_digest.as_deref()));
+        }
+        if let Some(commit) = &meta.commit {
+            component.pedigree = Some(Pedigree {
+                commits: vec![Commit {
+                    uid: commit.clone(),
+                    url: meta.repo.as_ref().map(|repo| file_url(repo)),
+                }],
+                notes: meta.dirty.then(|| match &meta.worktree_hash {
+                    Some(hash) => format!("built with uncommitted changes (worktree {hash})"),
+                    None => "built with uncommitted changes".to_string(),
+                }),
+            });
+        }
+
+        component.property("source", &meta.source);
+        component.property("cache-key", &key);
+        component.property("platform", &meta.platform);
+        component.property("built-at", &meta.built_at);
+        let optional = [
+            ("refspec", &meta.refspec),
+            ("worktree-hash", &meta.worktree_hash),
+            ("target", &meta.target),
+            ("isolation", &meta.isolation),
+            ("executor", &meta.executor),
+            ("flake", &meta.flake),
+            ("version-string", &meta.version_string),
+            ("signing-identity", &meta.signing_identity),
+            ("universal-digest", &meta.universal_digest),
+        ];
+        for (name, value) in optional {
+            if let Some(value) = value {
+                component.property(name, value);
+            }
+        }
+        if let Some(store_path) = &meta.store_path {
+            component.property("store-path", &store_path.display().to_string());
+        }
+        for rebuild in &meta.rebuilds {
+            component.property("rebuild", rebuild);
+        }
+        Ok(component)
+    }
+
+    /// A file known only by its path and contents.
+    fn file(path: &Path) -> Result<Self> {
+        let (digest, _) = cache::digest_file(path)?;
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |n| n.to_string_lossy().into(),
+        );
+        Ok(Self::new(
+            "application",
+            path.display().to_string(),
+            name,
+            Some(digest),
+        ))
+    }
+
+    #[cfg(feature = "oci")]
+    fn image(reference: &str, digest: &str, platform: &str) -> Self {
+        let name = reference
+            .split(['@', ':'])
+            .next()
+            .and_then(|repo| repo.rsplit('/').next())
+            .unwrap_or(reference)
+            .to_string();
+        let mut component = Self::new("container", reference.to_string(), name, None);
+        if let Some(hex) = digest.strip_prefix("sha256:") {
+            component.hashes.push(Hash {
+                alg: "SHA-256",
+                content: hex.to_string(),
+            });
+        }
+        component.purl = Some(oci_purl(reference, Some(digest)));
+        component.property("platform", platform);
+        component
+    }
+
+    fn new(kind: &'static str, bom_ref: String, name: String, blake3: Option<String>) -> Self {
+        Self {
+            kind,
+            bom_ref,
+            name,
+            version: None,
+            purl: None,
+            hashes: blake3
+                .map(|content| Hash {
+                    alg: "BLAKE3",
+                    content,
+                })
+                .into_iter()
+                .collect(),
+            external_references: Vec::new(),
+            pedigree: None,
+            properties: Vec::new(),
+            components: Vec::new(),
+        }
+    }
+
+    fn property(&mut self, name: &str, value: &str) {
+        self.properties.push(Property {
+            name: format!("zcash-artifacts:{name}"),
+            value: value.to_string(),
+        });
+    }
+}
+
+/// The package URL of an image, e.g.
+/// `pkg:oci/zebra@sha256%3A…?repository_url=docker.io/zfnd/zebra`.
+fn oci_purl(reference: &str, digest: Option<&str>) -> String {
+    let repository = reference.split('@').next().unwrap_or(reference);
+    // A trailing `:tag`, not a registry's `:port`.
+    let repository = match repository.rsplit_once(':') {
+        Some((repo, tag)) if !tag.contains('/') => repo,
+        _ => repository,
+    };
+    let name = repository.rsplit('/').next().unwrap_or(repository);
+    let digest = digest.or_else(|| reference.split_once('@').map(|(_, d)| d));
+    match digest {
+        Some(digest) => format!(
+            "pkg:oci/{name}@{}?repository_url={repository}",
+            digest.replace(':', "%3A")
+        ),
+        None => format!("pkg:oci/{name}?repository_url={repository}"),
+    }
+}
+
+fn file_url(path: &Path) -> String {
+    url::Url::from_file_path(path)
+        .map(String::from)
+        .unwrap_or_else(|()| PathBuf::from(path).display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundles_without_a_primary_executable_are_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let executables = ["zcash-cli", "zcash-tx"]
+            .map(|name| {
+                let path = dir.path().join(name);
+                std::fs::write(&path, "#!/bin/sh\n").unwrap();
+                (name.to_string(), path)
+            })
+            .into();
+        let mut bundle = ResolvedArtifact::Bundle {
+            executables,
+            provenance: crate::Provenance {
+                source: "local-path",
+                ..Default::default()
+            },
+        };
+        let mut sbom = Sbom::new();
+        let err = sbom.add(&bundle, dir.path()).unwrap_err();
+        assert_eq!(err.kind(), crate::ErrorKind::InvalidInput, "{err}");
+        assert!(sbom.is_empty());
+
+        if let ResolvedArtifact::Bundle { provenance, .. } = &mut bundle {
+            provenance.service = Some(ServiceId::new_static("zcash-cli"));
+        }
+        sbom.add(&bundle, dir.path()).unwrap();
+        assert_eq!(sbom.len(), 1);
+    }
+}
@@ -0,0 +1,283 @@
+//! Signed META for shared caches, behind the `signing` feature.
+//!
+//! A cache on a shared volume, or restored from a CI cache, is only as
+//! trustworthy as everyone who can write to it. With a signing key
+//! configured, every entry a resolver finalizes gets its `meta/META.json`
+//! signed with ed25519, in `meta/META.json.sig`; cache hits then check that
+//! the signature is by a trusted key, that META names the cache key being
+//! looked up, and that the executable and its companions still have the
+//! digests META records:
+//!
+//! ```no_run
+//! # fn main() -> zcash_artifacts::Result<()> {
+//! use zcash_artifacts::{
+//!     ResolverConfig,
+//!     signing::{MetaSigning, PublicKey, SigningKey},
+//! };
+//!
+//! let config = ResolverConfig::builder()
+//!     .cache_root("/mnt/team-cache")
+//!     .meta_signing(MetaSigning {
+//!         key: Some(SigningKey::from_file("/etc/zcash-artifacts/signing.key")?),
+//!         // The other CI runners filling the cache.
+//!         trusted: vec!["3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c"
+//!             .parse::<PublicKey>()?],
+//!         require: true,
+//!     })
+//!     .finish();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Key files hold the key's 32-byte seed as 64 hex digits, e.g. from
+//! `openssl rand -hex 32`; [`SigningKey::public_key`] prints the key to
+//! trust elsewhere. A hit that fails the check is a
+//! `VerifyError::SignatureInvalid` error rather than a miss, so tampering is
+//! noticed instead of papered over. Entries without a signature, like those
+//! made before signing was set up, are misses, downloaded or built again, or
+//! errors with [`MetaSigning::require`] set. Build logs aren't covered; image
+//! entries, which have no executable, only get their META signed.
+
+use std::{fmt, path::Path, str::FromStr};
+
+use ed25519_dalek::Signer;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ResolveContext, cache,
+    error::{FsError, InputError, Result, VerifyError},
+};
+
+/// Signs and checks cache entries; see the [module docs](self). The default
+/// does neither.
+#[derive(Debug, Clone, Default)]
+pub struct MetaSigning {
+    /// Signs the entries this resolver finalizes. Its public key is trusted.
+    pub key: Option<SigningKey>,
+    /// Keys whose signatures cache hits accept, besides [`key`](Self::key)'s.
+    pub trusted: Vec<PublicKey>,
+    /// Reject unsigned entries too, not only badly signed ones, instead of
+    /// replacing them.
+    pub require: bool,
+}
+
+impl MetaSigning {
+    /// Whether cache hits are checked at all.
+    fn checks(&self) -> bool {
+        self.key.is_some() || !self.trusted.is_empty() || self.require
+    }
+
+    fn trusts(&self, key: &PublicKey) -> bool {
+        self.key
+            .as_ref()
+            .is_some_and(|own| own.public_key() == *key)
+            || self.trusted.contains(key)
+    }
+}
+
+/// An ed25519 key signing META; its `Debug` output is redacted.
+#[derive(Clone)]
+pub struct SigningKey(ed25519_dalek::SigningKey);
+
+impl SigningKey {
+    /// The key whose seed is `hex`, 64 hex digits.
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let seed = decode_hex::<32>(hex.trim()).ok_or_else(|| InputError::InvalidConfig {
+            origin: "signing key".into(),
+            reason: "expected 64 hex digits".into(),
+        })?;
+        Ok(Self(ed25519_dalek::SigningKey::from_bytes(&seed)))
+    }
+
+    /// Reads a key file holding the seed in hex.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| FsError::Io {
+            context: format!("read {}", path.display()),
+            source: e,
+        })?;
+        Self::from_hex(&text).map_err(|_| {
+            InputError::InvalidConfig {
+                origin: path.display().to_string(),
+                reason: "expected a signing key of 64 hex digits".into(),
+            }
+            .into()
+        })
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(self.0.verifying_key())
+    }
+}
+
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SigningKey(<redacted>, public {})", self.public_key())
+    }
+}
+
+/// An ed25519 public key; parsed from and displayed as 64 hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicKey(ed25519_dalek::VerifyingKey);
+
+impl FromStr for PublicKey {
+    type Err = crate::ArtifactError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |reason: &str| InputError::InvalidConfig {
+            origin: "public key".into(),
+            reason: format!("{reason}: `{s}`"),
+        };
+        let bytes = decode_hex::<32>(s.trim()).ok_or_else(|| invalid("expected 64 hex digits"))?;
+        ed25519_dalek::VerifyingKey::from_bytes(&bytes)
+            .map(Self)
+            .map_err(|_| invalid("not an ed25519 public key").into())
+    }
+}
+
+impl fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&encode_hex(self.0.as_bytes()))
+    }
+}
+
+/// `meta/META.json.sig`.
+#[derive(Serialize, Deserialize)]
+struct MetaSignature {
+    /// The signer's public key, in hex.
+    key: String,
+    /// The ed25519 signature of `META.json`'s bytes, in hex.
+    signature: String,
+}
+
+/// Signs `json`, just written to `meta_dir/META.json`, with the configured
+/// key; without one, removes any signature left from before, which no longer
+/// matches.
+pub(crate) fn sign(ctx: &ResolveContext<'_>, meta_dir: &Path, json: &[u8]) -> Result<()> {
+    let path = meta_dir.join("META.json.sig");
+    let Some(key) = &ctx.config.meta_signing.key else {
+        return match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(FsError::Io {
+                context: format!("remove {}", path.display()),
+                source: e,
+            }
+            .into()),
+            _ => Ok(()),
+        };
+    };
+    let signature = MetaSignature {
+        key: key.public_key().to_string(),
+        signature: encode_hex(&key.0.sign(json).to_bytes()),
+    };
+    let text = serde_json::to_vec_pretty(&signature).expect("signature serializes");
+    cache::write_atomic(&path, &text)
+}
+
+/// Checks `executable`, found in the entry in `paths`, if signing is
+/// configured; see the [module docs](self). META must be signed for the key
+/// the entry is looked up under, so a signed entry copied to another key's
+/// directory is rejected. `Ok(false)` for an unsigned entry that should be
+/// replaced.
+pub(crate) fn verify_hit(
+    ctx: &ResolveContext<'_>,
+    paths: &cache::CachePaths,
+    executable: &Path,
+) -> Result<bool> {
+    let signing = &ctx.config.meta_signing;
+    if !signing.checks() {
+        return Ok(true);
+    }
+    let (root, meta_dir) = (&paths.root, &paths.meta);
+    let invalid = |reason: String| VerifyError::SignatureInvalid {
+        what: format!("cache entry {}", root.display()),
+        source: reason.into(),
+    };
+    let read = |name: &str| {
+        let path = meta_dir.join(name);
+        std::fs::read(&path).map_err(|e| (e.kind(), path, e))
+    };
+
+    let signature = match read("META.json.sig") {
+        Ok(signature) => signature,
+        Err((std::io::ErrorKind::NotFound, ..)) if !signing.require => return Ok(false),
+        Err((std::io::ErrorKind::NotFound, ..)) => {
+            return Err(invalid("META.json is not signed".into()).into());
+        }
+        Err((_, path, e)) => {
+            return Err(FsError::Io {
+                context: format!("read {}", path.display()),
+                source: e,
+            }
+            .into());
+        }
+    };
+    let signature: MetaSignature = serde_json::from_slice(&signature)
+        .map_err(|e| invalid(format!("malformed META.json.sig: {e}")))?;
+    let key: PublicKey = signature
+        .key
+        .parse()
+        .map_err(|_| invalid(format!("malformed key `{}`", signature.key)))?;
+    if !signing.trusts(&key) {
+        return Err(invalid(format!("signed by untrusted key {key}")).into());
+    }
+    let bytes = decode_hex::<64>(&signature.signature)
+        .ok_or_else(|| invalid("malformed signature".into()))?;
+    let json = read("META.json").map_err(|(_, path, e)| FsError::Io {
+        context: format!("read {}", path.display()),
+        source: e,
+    })?;
+    key.0
+        .verify_strict(&json, &ed25519_dalek::Signature::from_bytes(&bytes))
+        .map_err(|_| invalid("META.json does not match its signature".into()))?;
+
+    let meta: cache::Meta =
+        serde_json::from_slice(&json).map_err(|e| invalid(format!("malformed META.json: {e}")))?;
+    let key = paths.key.to_string();
+    if meta.key != key {
+        return Err(invalid(format!(
+            "META.json is signed for key `{}`, not `{key}`",
+            meta.key
+        ))
+        .into());
+    }
+    let (digest, _) = cache::digest_file(executable)?;
+    if digest != meta.digest {
+        return Err(invalid(format!(
+            "{} has digest {digest}, but META.json records {}",
+            executable.display(),
+            meta.digest
+        ))
+        .into());
+    }
+    let out = executable.parent().unwrap_or(Path::new("."));
+    for (name, recorded) in &meta.companions {
+        let companion = out.join(name);
+        if !companion.is_file() {
+            return Err(invalid(format!("companion {} is missing", companion.display())).into());
+        }
+        let (digest, _) = cache::digest_file(&companion)?;
+        if digest != *recorded {
+            return Err(invalid(format!(
+                "{} has digest {digest}, but META.json records {recorded}",
+                companion.display()
+            ))
+            .into());
+        }
+    }
+    Ok(true)
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    if hex.len() != 2 * N || !hex.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
@@ -0,0 +1,150 @@
+//! Resolving a service together with everything it depends on.
+
+use std::collections::HashMap;
+
+use crate::{
+    ArtifactResolver, ArtifactSource, ResolvedArtifact,
+    error::{InputError, LocateError, Result},
+    registry::{Dependency, ServiceId},
+};
+
+/// A service resolved as part of a stack.
+#[derive(Debug, Clone)]
+pub struct StackService {
+    pub service: ServiceId,
+    pub artifact: ResolvedArtifact,
+    /// Services in the same stack this one talks to.
+    pub depends_on: Vec<ServiceId>,
+}
+
+/// The result of [`ArtifactResolver::resolve_stack`].
+#[derive(Debug, Clone)]
+pub struct ResolvedStack {
+    /// Services in start-up order: every service comes after its dependencies,
+    /// and the root service is last.
+    pub services: Vec<StackService>,
+    /// Non-service resources the stack needs (e.g. `zcash-params`), deduplicated.
+    pub resources: Vec<String>,
+}
+
+impl ArtifactResolver {
+    /// Resolves `root` and its dependency closure in dependency order.
+    ///
+    /// `sources` says how to resolve each service, with the
+    /// [pins](crate::ResolverConfig::pins) filling in for services missing from
+    /// it. For a [`Dependency::OneOf`], the first alternative that has a source
+    /// is used.
+    pub fn resolve_stack(
+        &self,
+        root: &ServiceId,
+        sources: &HashMap<ServiceId, ArtifactSource>,
+    ) -> Result<ResolvedStack> {
+        let mut plan = Plan::default();
+        self.visit(root, sources, &mut Vec::new(), &mut plan)?;
+
+        let mut services = Vec::with_capacity(plan.order.len());
+        for (service, depends_on) in plan.order {
+            let source = self
+                .source_for(&service, sources)
+                .expect("visited services have a source");
+            let artifact = self.resolve(source)?;
+            services.push(StackService {
+                service,
+                artifact,
+                depends_on,
+            });
+        }
+        Ok(ResolvedStack {
+            services,
+            resources: plan.resources,
+        })
+    }
+
+    fn source_for<'a>(
+        &'a self,
+        id: &ServiceId,
+        sources: &'a HashMap<ServiceId, ArtifactSource>,
+    ) -> Option<&'a ArtifactSource> {
+        sources.get(id).or_else(|| self.config.pins.get(id))
+    }
+
+    fn visit(
+        &self,
+        id: &ServiceId,
+        sources: &HashMap<ServiceId, ArtifactSource>,
+        path: &mut Vec<ServiceId>,
+        plan: &mut Plan,
+    ) -> Result<()> {
+        if plan.order.iter().any(|(done, _)| done == id) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|p| p == id) {
+            let cycle = path[start..]
+                .iter()
+                .chain([id])
+                .map(ServiceId::as_str)
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(InputError::DependencyCycle { cycle }.into());
+        }
+        if self.source_for(id, sources).is_none() {
+            return Err(InputError::MissingSource {
+                service: id.clone(),
+            }
+            .into());
+        }
+        let spec = self
+            .registry
+            .get(id)
+            .ok_or_else(|| LocateError::UnknownService {
+                service: id.clone(),
+            })?;
+
+        path.push(id.clone());
+        let mut depends_on = Vec::new();
+        for dep in &spec.dependencies {
+            match dep {
+                Dependency::Service(dep) => {
+                    self.visit(dep, sources, path, plan)?;
+                    depends_on.push(dep.clone());
+                }
+                Dependency::OneOf(alternatives) => {
+                    let Some(dep) = alternatives
+                        .iter()
+                        .find(|alt| self.source_for(alt, sources).is_some())
+                    else {
+                        return Err(InputError::InvalidSource {
+                            service: id.clone(),
+                            reason: format!(
+                                "needs one of [{}], but none has a source",
+                                alternatives
+                                    .iter()
+                                    .map(ServiceId::as_str)
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            ),
+                        }
+                        .into());
+                    };
+                    self.visit(dep, sources, path, plan)?;
+                    depends_on.push(dep.clone());
+                }
+                Dependency::Resource(name) => {
+                    if !plan.resources.contains(name) {
+                        plan.resources.push(name.clone());
+                    }
+                }
+            }
+        }
+        path.pop();
+
+        plan.order.push((id.clone(), depends_on));
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Plan {
+    order: Vec<(ServiceId, Vec<ServiceId>)>,
+    resources: Vec<String>,
+}
@@ -0,0 +1,142 @@
+//! [testcontainers](https://docs.rs/testcontainers) adapters for resolved images.
+//!
+//! [`NodeImage`] turns a [`ResolvedArtifact::OciImage`] into a testcontainers
+//! [`Image`], exposing the service's [`ports`](ToolSpec::ports) (and any other
+//! TCP port the image config exposes) and waiting for its
+//! [`ready_log`](ToolSpec::ready_log) line. For the builtin services:
+//!
+//! | service        | ports                           | ready when the console shows |
+//! |----------------|---------------------------------|------------------------------|
+//! | `zcashd`       | 8232, 8233, 18232, 18233, 18344 | `init message: Done loading` |
+//! | `zebrad`       | 8232, 8233, 18232, 18233        | `Starting zebrad`            |
+//! | `lightwalletd` | 9067, 9068                      | `Starting gRPC server`       |
+//! | `zainod`       | 8137                            | (once started)               |
+//!
+//! Anything else (arguments, environment, port mappings, other ready
+//! conditions) goes through testcontainers' [`ImageExt`], whose
+//! [`ContainerRequest`](::testcontainers::ContainerRequest) took over from
+//! `RunnableImage`:
+//!
+//! ```no_run
+//! # use zcash_artifacts::{ArtifactSource, ArtifactResolver, registry::ZEBRAD};
+//! use testcontainers::{ImageExt, runners::AsyncRunner};
+//! use zcash_artifacts::testcontainers::NodeImage;
+//!
+//! # async fn run(resolver: ArtifactResolver, source: ArtifactSource) -> Result<(), Box<dyn std::error::Error>> {
+//! let image = resolver.resolve(&source)?;
+//! let spec = resolver.registry().get(&ZEBRAD).unwrap();
+//! let zebrad = NodeImage::new(spec, &image)?
+//!     .with_cmd(["zebrad", "start"])
+//!     .start()
+//!     .await?;
+//! let rpc = zebrad.get_host_port_ipv4(18232).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use ::testcontainers::{
+    Image,
+    core::{ContainerPort, WaitFor},
+};
+
+use crate::{
+    ResolvedArtifact,
+    error::{InputError, LocateError, Result},
+    registry::{Registry, ToolSpec},
+    stack::StackService,
+};
+
+pub use ::testcontainers::ImageExt;
+
+/// A resolved image with its service's defaults; see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct NodeImage {
+    name: String,
+    /// The tag, with `@<digest>` appended for pinned references.
+    tag: String,
+    ports: Vec<ContainerPort>,
+    ready: Vec<WaitFor>,
+}
+
+impl NodeImage {
+    /// `artifact`, which must be an [`OciImage`](ResolvedArtifact::OciImage),
+    /// as an image of the service `spec` describes.
+    pub fn new(spec: &ToolSpec, artifact: &ResolvedArtifact) -> Result<Self> {
+        let ResolvedArtifact::OciImage {
+            reference, config, ..
+        } = artifact
+        else {
+            return Err(InputError::InvalidSource {
+                service: spec.id.clone(),
+                reason: "resolved to an executable, not a container image".into(),
+            }
+            .into());
+        };
+        let (name, tag) = split_reference(reference);
+        let mut ports = spec.ports.clone();
+        for port in config.tcp_ports() {
+            if !ports.contains(&port) {
+                ports.push(port);
+            }
+        }
+        Ok(Self {
+            name,
+            tag,
+            ports: ports.into_iter().map(ContainerPort::Tcp).collect(),
+            ready: spec
+                .ready_log
+                .as_deref()
+                .map(WaitFor::message_on_either_std)
+                .into_iter()
+                .collect(),
+        })
+    }
+
+    /// A service of a [resolved stack](crate::stack::ResolvedStack), as
+    /// described by `registry`.
+    pub fn from_stack(registry: &Registry, service: &StackService) -> Result<Self> {
+        let spec = registry
+            .get(&service.service)
+            .ok_or_else(|| LocateError::UnknownService {
+                service: service.service.clone(),
+            })?;
+        Self::new(spec, &service.artifact)
+    }
+}
+
+impl Image for NodeImage {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    fn ready_conditions(&self) -> Vec<WaitFor> {
+        self.ready.clone()
+    }
+
+    fn expose_ports(&self) -> &[ContainerPort] {
+        &self.ports
+    }
+}
+
+/// Splits `name[:tag][@digest]` into the name and the rest, which testcontainers
+/// appends after a `:`; runtimes pull by digest when a reference has one.
+fn split_reference(reference: &str) -> (String, String) {
+    let (name, digest) = match reference.split_once('@') {
+        Some((name, digest)) => (name, Some(digest)),
+        None => (reference, None),
+    };
+    let (name, tag) = match name.rsplit_once(':') {
+        // A `:` before the last `/` is a registry port.
+        Some((name, tag)) if !tag.contains('/') => (name, tag),
+        _ => (name, "latest"),
+    };
+    let tag = match digest {
+        Some(digest) => format!("{tag}@{digest}"),
+        None => tag.to_string(),
+    };
+    (name.to_string(), tag)
+}
@@ -0,0 +1,615 @@
+//! Test doubles for harnesses built on this crate, behind the `testing`
+//! feature.
+//!
+//! A [`MockResolver`] makes an [`ArtifactResolver`] that resolves the sources
+//! it has stubs for to small [`FakeArtifact`] scripts, and nothing else: no
+//! cache lookups, downloads, git or builds. Unit tests of harness logic, like
+//! which sources it asks for or how it starts a node, then run in
+//! milliseconds:
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use zcash_artifacts::{
+//!     ArtifactSource,
+//!     registry::ZEBRAD,
+//!     testing::{FakeArtifact, MockResolver},
+//! };
+//!
+//! let resolver = MockResolver::new()
+//!     .stub(ZEBRAD, FakeArtifact::new().version("zebrad 2.0.0"))
+//!     .finish()?;
+//! let zebrad = resolver.resolve(&ArtifactSource::Release {
+//!     service: ZEBRAD,
+//!     version: "2.0.0".into(),
+//! })?;
+//! let output = std::process::Command::new(zebrad.primary_path().unwrap())
+//!     .arg("--version")
+//!     .output()?;
+//! assert_eq!(output.stdout, b"zebrad 2.0.0\n");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The resolver is otherwise a real one, offline and with its cache in a
+//! directory of its own under the system's temporary directory, so
+//! [`ResolveOptions`](crate::ResolveOptions), observers and hooks all apply.
+//! Sources without a stub fail with `LocateError::Unresolved`. Fakes are
+//! shell scripts, so they only run on Unix.
+//!
+//! Fakes also make fixtures for real resolvers: [`FakeArtifact::write`] puts
+//! one wherever a test needs a binary, and [`FakeArtifact::seed`] puts one in
+//! a resolver's cache, as the entry a release would be downloaded to:
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use zcash_artifacts::{
+//!     ArtifactResolver, ArtifactSource, ResolverConfig, network::NetworkPolicy,
+//!     registry::ZCASHD, testing::FakeArtifact,
+//! };
+//!
+//! let resolver = ArtifactResolver::new(
+//!     ResolverConfig::builder()
+//!         .cache_root("target/test-cache")
+//!         .network(NetworkPolicy::Offline)
+//!         .finish(),
+//! );
+//! let src = ArtifactSource::Release {
+//!     service: ZCASHD,
+//!     version: "v6.2.0".into(),
+//! };
+//! FakeArtifact::new()
+//!     .version("Zcash Daemon version v6.2.0")
+//!     .seed(&resolver, &src)?;
+//! // A cache hit, with a META reporting the fake's version.
+//! let zcashd = resolver.resolve(&src)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! To test the real layers instead, offline, give a resolver a
+//! [`CannedTransport`] (`http` feature) answering its downloads and registry
+//! calls, including slow and failing ones.
+
+#[cfg(feature = "http")]
+use std::{
+    collections::VecDeque,
+    io::Read,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    ArtifactProvider, ArtifactResolver, ArtifactSource, DefaultProvider, Provenance,
+    ResolveContext, ResolvedArtifact, ResolverConfig, VersionProbe, cache,
+    error::{FsError, InputError, Result},
+    network::NetworkPolicy,
+    pipeline,
+    plan::Step,
+    registry::{Registry, ServiceId},
+};
+
+/// A stand-in for a service binary: a script that prints its
+/// [version](Self::version) when run with `--version`, and otherwise runs its
+/// [script](Self::script).
+#[derive(Debug, Clone, Default)]
+pub struct FakeArtifact {
+    version: Option<String>,
+    script: Option<String>,
+    path: Option<PathBuf>,
+}
+
+impl FakeArtifact {
+    /// A fake that exits successfully whatever it is run with.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An existing executable, resolved as it is.
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: Some(path.into()),
+            ..Self::default()
+        }
+    }
+
+    /// The line printed for `--version`, and the resolved artifact's
+    /// [`Provenance::version`].
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Shell commands run for anything but `--version`, e.g. `sleep 3600` for
+    /// a node that stays up, or `exit 1` for one that fails to start.
+    pub fn script(mut self, script: impl Into<String>) -> Self {
+        self.script = Some(script.into());
+        self
+    }
+
+    /// Writes the fake to `path`, an executable script (or a copy of the
+    /// [existing executable](Self::at)), e.g. to stand in for a service in a
+    /// harness's own fixtures.
+    pub fn write(&self, path: impl Into<PathBuf>) -> Result<PathBuf> {
+        let path = path.into();
+        if let Some(existing) = &self.path {
+            cache::copy_atomic(existing, &path)?;
+            cache::chmod_exec(&path)?;
+            return Ok(path);
+        }
+        let mut text = String::from("#!/bin/sh\n");
+        if let Some(version) = &self.version {
+            let quoted = version.replace('\'', r"'\''");
+            text.push_str(&format!(
+                "if [ \"$1\" = --version ]; then echo '{quoted}'; exit 0; fi\n"
+            ));
+        }
+        text.push_str(self.script.as_deref().unwrap_or("exit 0"));
+        text.push('\n');
+        std::fs::write(&path, text).map_err(|e| FsError::Io {
+            context: format!("write {}", path.display()),
+            source: e,
+        })?;
+        cache::chmod_exec(&path)?;
+        Ok(path)
+    }
+
+    /// Puts the fake in `resolver`'s cache as the entry `src` would be
+    /// downloaded to, with a META like a download's, and returns the cached
+    /// executable. The resolver then finds it there, offline.
+    ///
+    /// Only `Release` and `Url` (`http` feature) sources can be seeded: only
+    /// theirs are entries keyed by the source alone. Others are an
+    /// [`InputError::InvalidSource`].
+    pub fn seed(&self, resolver: &ArtifactResolver, src: &ArtifactSource) -> Result<PathBuf> {
+        let platform = resolver.config.platform();
+        let ctx = resolver.context(&platform, None, &resolver.observers);
+        let Some((paths, bin_name, meta)) = pipeline::seedable_entry(src, &ctx)? else {
+            return Err(InputError::InvalidSource {
+                service: src
+                    .service()
+                    .cloned()
+                    .unwrap_or_else(|| ServiceId::new_static(src.kind())),
+                reason: format!("{} sources can't be seeded", src.kind()),
+            }
+            .into());
+        };
+        paths.create_dirs()?;
+        let staged = self.write(paths.root.join(format!(".fake-{}", std::process::id())))?;
+        let probe = FixedVersion(self.version.clone());
+        let cached = cache::finalize(
+            &ctx,
+            &paths,
+            &bin_name,
+            &staged,
+            &[],
+            Some(&probe),
+            false,
+            meta,
+        );
+        let _ = std::fs::remove_file(&staged);
+        cached
+    }
+
+    /// Like [`write`](Self::write), but leaves an existing executable where it is.
+    fn install(&self, path: PathBuf) -> Result<PathBuf> {
+        match &self.path {
+            Some(existing) => Ok(existing.clone()),
+            None => self.write(path),
+        }
+    }
+}
+
+/// Reports a fake's version as a probe of it would.
+struct FixedVersion(Option<String>);
+
+impl VersionProbe for FixedVersion {
+    fn probe(&self, _exe: &std::path::Path) -> Option<String> {
+        self.0.clone()
+    }
+}
+
+/// Which sources a stub answers.
+type Matcher = Box<dyn Fn(&ArtifactSource) -> bool + Send + Sync>;
+
+/// Builds an [`ArtifactResolver`] resolving only stubs; see the
+/// [module docs](self).
+pub struct MockResolver {
+    registry: Registry,
+    platform: Option<String>,
+    stubs: Vec<(Matcher, FakeArtifact)>,
+}
+
+impl Default for MockResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockResolver {
+    /// No stubs, and the built-in services.
+    pub fn new() -> Self {
+        Self {
+            registry: Registry::with_builtins(),
+            platform: None,
+            stubs: Vec::new(),
+        }
+    }
+
+    /// Resolves every source naming `service` to `fake`.
+    pub fn stub(self, service: ServiceId, fake: FakeArtifact) -> Self {
+        self.stub_matching(move |src| src.service() == Some(&service), fake)
+    }
+
+    /// Resolves the sources `matches` accepts to `fake`, e.g. only one
+    /// release of a service. Stubs are tried in the order they were added.
+    pub fn stub_matching(
+        mut self,
+        matches: impl Fn(&ArtifactSource) -> bool + Send + Sync + 'static,
+        fake: FakeArtifact,
+    ) -> Self {
+        self.stubs.push((Box::new(matches), fake));
+        self
+    }
+
+    /// The services sources may name, in place of the built-in ones. Their
+    /// own providers are dropped, so that only stubs resolve.
+    pub fn registry(mut self, registry: Registry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// See [`ResolverConfig::platform_override`].
+    pub fn platform(mut self, platform: impl Into<String>) -> Self {
+        self.platform = Some(platform.into());
+        self
+    }
+
+    /// Writes the fakes and makes the resolver.
+    pub fn finish(mut self) -> Result<ArtifactResolver> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let root = std::env::temp_dir().join(format!(
+            "zcash-artifacts-mock-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let fakes = root.join("fakes");
+        std::fs::create_dir_all(&fakes).map_err(|e| FsError::Io {
+            context: format!("mkdir {}", fakes.display()),
+            source: e,
+        })?;
+        let mut stubs = Vec::new();
+        for (n, (matches, fake)) in self.stubs.into_iter().enumerate() {
+            let path = fake.install(fakes.join(format!("fake-{n}")))?;
+            stubs.push((matches, path, fake.version));
+        }
+
+        let services: Vec<ServiceId> = self.registry.services().cloned().collect();
+        for id in services {
+            self.registry.update(&id, |spec| spec.provider = None)?;
+        }
+        let mut config = ResolverConfig::builder()
+            .cache_root(root.join("cache"))
+            .network(NetworkPolicy::Offline)
+            .log(false);
+        if let Some(platform) = self.platform {
+            config = config.platform_override(platform);
+        }
+        let mut resolver = ArtifactResolver::with_registry(config.finish(), self.registry);
+        let mut provider = DefaultProvider::empty();
+        provider.push(MockLayer(stubs));
+        *resolver.provider_mut() = provider;
+        Ok(resolver)
+    }
+}
+
+/// Resolves sources to the first stub matching them.
+struct MockLayer(Vec<(Matcher, PathBuf, Option<String>)>);
+
+impl MockLayer {
+    fn find(&self, src: &ArtifactSource) -> Option<(&PathBuf, &Option<String>)> {
+        self.0
+            .iter()
+            .find(|(matches, ..)| matches(src))
+            .map(|(_, path, version)| (path, version))
+    }
+}
+
+impl ArtifactProvider for MockLayer {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn resolve(
+        &self,
+        src: &ArtifactSource,
+        _ctx: &ResolveContext<'_>,
+    ) -> Result<Option<ResolvedArtifact>> {
+        Ok(self
+            .find(src)
+            .map(|(path, version)| ResolvedArtifact::Executable {
+                path: path.clone(),
+                provenance: Provenance {
+                    version: version.clone(),
+                    ..Provenance::default()
+                },
+            }))
+    }
+
+    fn plan(&self, src: &ArtifactSource, _ctx: &ResolveContext<'_>) -> Result<Option<Step>> {
+        Ok(self
+            .find(src)
+            .map(|(path, _)| Step::Local { path: path.clone() }))
+    }
+}
+
+/// A [`Transport`](crate::transport::Transport) answering from canned
+/// responses, so that downloads and registry calls run without a network:
+///
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use std::time::Duration;
+///
+/// use zcash_artifacts::{
+///     ArtifactResolver, ArtifactSource, ResolverConfig,
+///     testing::{Canned, CannedTransport},
+/// };
+///
+/// let url = "https://example.com/zebrad";
+/// let transport = CannedTransport::new()
+///     // The first attempt fails; retries get the binary, slowly.
+///     .respond(url, Canned::status(503))
+///     .respond(
+///         url,
+///         Canned::ok(std::fs::read("tests/fixtures/zebrad")?)
+///             .throttle(Duration::from_millis(10)),
+///     );
+/// let resolver = ArtifactResolver::new(
+///     ResolverConfig::builder()
+///         .transport(transport.clone())
+///         .finish(),
+/// );
+/// let src = ArtifactSource::Url {
+///     url: url.parse().unwrap(),
+///     checksum: "<sha256 of tests/fixtures/zebrad>".into(),
+/// };
+/// assert!(resolver.resolve(&src).is_err());
+/// resolver.resolve(&src)?;
+/// assert_eq!(transport.requests(), [format!("GET {url}"), format!("GET {url}")]);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Responses are looked up by the request's exact URL. Several responses for
+/// one URL answer successive requests, the last one repeating; a request
+/// without any fails as if the host refused the connection. Clones share
+/// their responses and the requests they saw.
+#[cfg(feature = "http")]
+#[derive(Clone, Default)]
+pub struct CannedTransport {
+    inner: Arc<Mutex<Canning>>,
+}
+
+#[cfg(feature = "http")]
+#[derive(Default)]
+struct Canning {
+    responses: Vec<(String, VecDeque<Canned>)>,
+    requests: Vec<String>,
+}
+
+#[cfg(feature = "http")]
+impl CannedTransport {
+    /// A transport without responses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers the next request for `url` with `response`, after the ones
+    /// added for it before.
+    pub fn respond(self, url: &str, response: Canned) -> Self {
+        // Compare URLs as the resolver sends them, e.g. with a trailing `/` on a bare host.
+        let url = url::Url::parse(url).map_or_else(|_| url.to_string(), String::from);
+        {
+            let mut canning = self.lock();
+            match canning.responses.iter_mut().find(|(u, _)| *u == url) {
+                Some((_, queue)) => queue.push_back(response),
+                None => canning.responses.push((url, VecDeque::from([response]))),
+            }
+        }
+        self
+    }
+
+    /// The requests sent so far, as `METHOD url`, e.g. `GET https://example.com/a`.
+    pub fn requests(&self) -> Vec<String> {
+        self.lock().requests.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Canning> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(feature = "http")]
+impl crate::transport::Transport for CannedTransport {
+    fn send(
+        &self,
+        request: crate::transport::Request,
+    ) -> std::result::Result<crate::transport::Response, crate::transport::BoxError> {
+        let url = request.url.to_string();
+        let canned = {
+            let mut canning = self.lock();
+            canning.requests.push(format!("{} {url}", request.method));
+            let queue = canning
+                .responses
+                .iter_mut()
+                .find(|(u, _)| *u == url)
+                .map(|(_, queue)| queue);
+            match queue {
+                Some(queue) if queue.len() > 1 => queue.pop_front(),
+                Some(queue) => queue.front().cloned(),
+                None => None,
+            }
+        };
+        let Some(canned) = canned else {
+            return Err(refused(&format!("no canned response for {url}")));
+        };
+        // Send the body, as a server would; a test may check what was pushed.
+        if let Some(body) = request.body {
+            std::io::copy(&mut body.into_reader(), &mut std::io::sink())?;
+        }
+        std::thread::sleep(canned.delay);
+        if canned.unreachable {
+            return Err(refused(&format!("{url} is unreachable")));
+        }
+        let mut headers = canned.headers;
+        if !headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+        {
+            headers.push(("Content-Length".into(), canned.body.len().to_string()));
+        }
+        let body: Box<dyn Read + Send> = match request.method {
+            crate::transport::Method::Head => Box::new(std::io::empty()),
+            _ => Box::new(CannedBody {
+                body: std::io::Cursor::new(canned.body),
+                throttle: canned.throttle,
+                fail_after: canned.fail_after,
+            }),
+        };
+        Ok(crate::transport::Response {
+            status: canned.status,
+            headers,
+            body,
+        })
+    }
+}
+
+#[cfg(feature = "http")]
+fn refused(message: &str) -> crate::transport::BoxError {
+    Box::new(std::io::Error::new(
+        std::io::ErrorKind::ConnectionRefused,
+        message.to_string(),
+    ))
+}
+
+/// A response of a [`CannedTransport`].
+#[cfg(feature = "http")]
+#[derive(Debug, Clone)]
+pub struct Canned {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    delay: Duration,
+    throttle: Duration,
+    fail_after: Option<u64>,
+    unreachable: bool,
+}
+
+#[cfg(feature = "http")]
+impl Canned {
+    /// A `200 OK` with `body`.
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            body: body.into(),
+            ..Self::status(200)
+        }
+    }
+
+    /// An empty response with `status`, e.g. 404 or 503.
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+            delay: Duration::ZERO,
+            throttle: Duration::ZERO,
+            fail_after: None,
+            unreachable: false,
+        }
+    }
+
+    /// No response at all, as from a host refusing connections.
+    pub fn unreachable() -> Self {
+        Self {
+            unreachable: true,
+            ..Self::status(0)
+        }
+    }
+
+    /// A header to answer with; `Content-Length` defaults to the body's length.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Waits `delay` before answering, e.g. to let a timeout expire.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Sends the body a KiB at a time, waiting `per_kib` before each, for
+    /// slow downloads.
+    pub fn throttle(mut self, per_kib: Duration) -> Self {
+        self.throttle = per_kib;
+        self
+    }
+
+    /// Drops the connection after sending `bytes` of the body.
+    pub fn fail_after(mut self, bytes: u64) -> Self {
+        self.fail_after = Some(bytes);
+        self
+    }
+}
+
+/// The body of a [`Canned`] response, as it arrives.
+#[cfg(feature = "http")]
+struct CannedBody {
+    body: std::io::Cursor<Vec<u8>>,
+    throttle: Duration,
+    fail_after: Option<u64>,
+}
+
+#[cfg(feature = "http")]
+impl Read for CannedBody {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let sent = self.body.position();
+        if self.fail_after.is_some_and(|limit| sent >= limit) {
+            return Err(std::io::ErrorKind::ConnectionReset.into());
+        }
+        let mut len = buf.len().min(1024);
+        if let Some(limit) = self.fail_after {
+            len = len.min((limit - sent) as usize);
+        }
+        if !self.throttle.is_zero() && sent < self.body.get_ref().len() as u64 {
+            std::thread::sleep(self.throttle);
+        }
+        self.body.read(&mut buf[..len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_sources_keyed_alone_can_be_seeded() {
+        let resolver = MockResolver::new().finish().unwrap();
+        let err = FakeArtifact::new()
+            .seed(
+                &resolver,
+                &ArtifactSource::LocalPath("/usr/bin/zcashd".into()),
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), crate::ErrorKind::InvalidInput, "{err}");
+        assert!(
+            err.to_string()
+                .contains("local-path sources can't be seeded"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! Instrumentation with [`tracing`](https://docs.rs/tracing), behind the
+//! `tracing` feature; without it, none of this is compiled in.
+//!
+//! The resolver emits spans for the work that takes time, so a harness that
+//! installs a subscriber can see where it goes. Durations are the spans'
+//! own: with `tracing-subscriber`'s `fmt`, for example, set
+//! `.with_span_events(FmtSpan::CLOSE)` to have each span report its
+//! `time.busy` when it closes.
+//!
+//! | span | level | fields |
+//! |------|-------|--------|
+//! | `resolve` | info | `source` (its [kind](crate::ArtifactSource::kind)), `service`, `platform`, `layer` that resolved it |
+//! | `layer` | debug | `layer` |
+//! | `cache_lock` | debug | `entry`; its duration is the time waited for the lock |
+//! | `cache_finalize` | debug | `entry`, `bin`, `bytes` |
+//! | `git` | debug | `repo`, `args`, `bytes` of output |
+//! | `download` | info | `url` (without credentials), `bytes` |
+//! | `blob` | info | `reference`, `digest`, `bytes` |
+//! | `build` | info | `service`, `entry`, `jobs`, `executor` |
+//!
+//! Cache hits are `debug` events with the `entry`, failed resolutions
+//! `error` events on the `resolve` span, and [warnings](crate::warning) `warn`
+//! events with their `kind`.
+
+/// Enters a span until the end of the enclosing block, e.g.
+/// `span!(INFO, "build", service = %id)`.
+macro_rules! span {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::span!(::tracing::Level::$level, $($arg)+).entered();
+    };
+}
+
+/// Records fields declared (as `tracing::field::Empty`) on the current span,
+/// e.g. `record!("bytes" = len)`. The values aren't evaluated without the
+/// feature.
+macro_rules! record {
+    ($($field:literal = $value:expr),+ $(,)?) => {
+        #[cfg(feature = "tracing")]
+        {
+            let span = ::tracing::Span::current();
+            $(span.record($field, $value);)+
+        }
+    };
+}
+
+/// A `debug` event.
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!($($arg)+);
+    };
+}
+
+/// A `warn` event.
+macro_rules! warning {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::warn!($($arg)+);
+    };
+}
+
+pub(crate) use {debug, record, span, warning};
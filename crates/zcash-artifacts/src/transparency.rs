@@ -0,0 +1,374 @@
+//! Cross-checks of release downloads against a transparency log, behind the
+//! `transparency` feature.
+//!
+//! A checksum only shows that a download matches what the release lists
+//! next to it. An endpoint serving some users a different binary, with a
+//! matching checksum list, passes that check. Publishers who log their
+//! release digests in an append-only log, like Sigstore's
+//! [Rekor](https://docs.sigstore.dev/logging/overview/), make that visible:
+//! the binary everyone else got is in the log, and the one served only to
+//! you is not.
+//!
+//! With a [`TransparencyPolicy::log`] set, every release asset a resolver
+//! downloads (`Release` and `Guix` sources) is looked up in the log by its
+//! sha256:
+//!
+//! - the log's index is searched for entries of the digest;
+//! - anyone can log any digest, so only entries signed by one of the
+//!   service's [pinned signers](crate::trust#pinned-signers) count: `rekord`
+//!   entries of a minisign signature by a `minisign` pin. Entries by anyone
+//!   else are passed over, and so are entries signed with a certificate (as
+//!   `cosign sign-blob` logs them), even one naming a `cosign` identity:
+//!   anyone can make a certificate naming any identity, and neither its
+//!   chain to Fulcio nor the log's signed entry timestamp is checked;
+//! - each entry that counts must be a `hashedrekord` or `rekord` entry of
+//!   that digest, and come with an inclusion proof that leads to the tree
+//!   root returned with it. Entries failing either check fail the download
+//!   with `VerifyError::NotLogged`;
+//! - no entry that counts, which is always the case for services without
+//!   `minisign` pins, is a
+//!   [`Warning::NotLogged`](crate::warning::Warning::NotLogged), or an error
+//!   under [`require`](TransparencyPolicy::require).
+//!
+//! ```no_run
+//! use zcash_artifacts::{ResolverConfig, transparency::TransparencyPolicy};
+//!
+//! let config = ResolverConfig::builder()
+//!     .transparency(TransparencyPolicy {
+//!         log: Some(TransparencyPolicy::rekor()),
+//!         require: true,
+//!     })
+//!     .finish();
+//! ```
+//!
+//! The entry checked is recorded in META's `log_entry`. Under `require`,
+//! cache entries without one are downloaded again. Only lookups are made:
+//! entries are submitted by publishers, who sign what they log. Inclusion
+//! proofs are checked against the root the log returns, whose signature is
+//! not checked. This detects a download endpoint serving a different
+//! binary, not a log colluding with it.
+
+use std::path::Path;
+
+use base64::Engine;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{
+    ResolveContext, cache,
+    error::{FetchError, Result, VerifyError},
+    pipeline,
+    registry::ServiceId,
+    transport::{Method, Request},
+    trust::SignerPins,
+    warning::Warning,
+};
+
+/// Which log downloads are cross-checked against; see the
+/// [module docs](self).
+#[derive(Debug, Clone, Default)]
+pub struct TransparencyPolicy {
+    /// A Rekor-compatible log, e.g. [`rekor()`](Self::rekor); `None` checks
+    /// nothing.
+    pub log: Option<Url>,
+    /// Fail downloads whose digest the log has no entry for, rather than
+    /// warning.
+    pub require: bool,
+}
+
+impl TransparencyPolicy {
+    /// Sigstore's public Rekor instance.
+    pub fn rekor() -> Url {
+        Url::parse("https://rekor.sigstore.dev").expect("valid URL")
+    }
+}
+
+/// An entry of `GET /api/v1/log/entries/{uuid}`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LogEntry {
+    /// The entry as logged, base64-encoded JSON.
+    body: String,
+    log_index: u64,
+    verification: Option<Verification>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Verification {
+    inclusion_proof: Option<InclusionProof>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InclusionProof {
+    /// The leaf's index in the tree the proof is for.
+    log_index: u64,
+    tree_size: u64,
+    root_hash: String,
+    hashes: Vec<String>,
+}
+
+/// Looks `sha256` of `service`'s asset downloaded from `url` up in the
+/// configured log; returns the entry to record, `None` if the asset is not
+/// logged by one of the service's pinned signers and that is allowed, or if
+/// no log is configured.
+pub(crate) fn check(
+    ctx: &ResolveContext<'_>,
+    service: &ServiceId,
+    url: &Url,
+    sha256: &str,
+) -> Result<Option<String>> {
+    let policy = &ctx.config.transparency;
+    let Some(log) = &policy.log else {
+        return Ok(None);
+    };
+    let no_pins = SignerPins::default();
+    let pins = ctx.config.signers.get(service).unwrap_or(&no_pins);
+    let digest = sha256.to_ascii_lowercase();
+    let what = crate::credentials::redact(url);
+    let rejected = |reason: String| VerifyError::NotLogged {
+        what: what.clone(),
+        digest: digest.clone(),
+        log: log.to_string(),
+        reason,
+    };
+
+    let uuids: Vec<String> = serde_json::from_str(&search(ctx, log, &digest)?)
+        .map_err(|e| rejected(format!("malformed index response: {e}")))?;
+    let mut first = None;
+    for uuid in &uuids {
+        let url = endpoint(log, &format!("api/v1/log/entries/{uuid}"))?;
+        let entries: std::collections::HashMap<String, LogEntry> =
+            serde_json::from_str(&pipeline::fetch_text(ctx, &url)?)
+                .map_err(|e| rejected(format!("malformed entry {uuid}: {e}")))?;
+        let entry = entries
+            .into_values()
+            .next()
+            .ok_or_else(|| rejected(format!("entry {uuid} not returned")))?;
+        if verify_entry(uuid, &entry, &digest, pins)
+            .map_err(|reason| rejected(format!("entry {uuid}: {reason}")))?
+        {
+            first.get_or_insert(url);
+        }
+    }
+    let Some(first) = first else {
+        let reason = if uuids.is_empty() {
+            "the log has no entry for it".to_string()
+        } else {
+            format!(
+                "no entry for it is by a pinned signer of {}",
+                service.as_str()
+            )
+        };
+        if policy.require {
+            return Err(rejected(reason).into());
+        }
+        ctx.warn(Warning::NotLogged {
+            url: what.clone(),
+            digest: digest.clone(),
+            log: log.to_string(),
+        });
+        return Ok(None);
+    };
+    Ok(Some(first.into()))
+}
+
+/// Whether the cache entry whose META is in `meta_dir` passes the policy:
+/// always, unless the log is required and META records no entry in it.
+pub(crate) fn cached_ok(ctx: &ResolveContext<'_>, meta_dir: &Path) -> bool {
+    let policy = &ctx.config.transparency;
+    if !policy.require || policy.log.is_none() {
+        return true;
+    }
+    std::fs::read(meta_dir.join("META.json"))
+        .ok()
+        .and_then(|json| serde_json::from_slice::<cache::Meta>(&json).ok())
+        .is_some_and(|meta| meta.log_entry.is_some())
+}
+
+/// Searches `log`'s index for `digest`; returns the response, a JSON list
+/// of entry UUIDs.
+fn search(ctx: &ResolveContext<'_>, log: &Url, digest: &str) -> Result<String> {
+    use std::io::Read;
+
+    let url = endpoint(log, "api/v1/index/retrieve")?;
+    ctx.config.network.check(&url)?;
+    let _slot = ctx
+        .limiter
+        .request(url.host_str().unwrap_or_default(), ctx.cancel)?;
+    let query = serde_json::json!({ "hash": format!("sha256:{digest}") });
+    let request = Request::new(Method::Post, url.clone())
+        .header("Content-Type", "application/json")
+        .header("Accept", "application/json")
+        .body(serde_json::to_vec(&query).expect("query serializes").into());
+    let shown = crate::credentials::redact(&url);
+    let mut response = pipeline::send(ctx, request)?
+        .error_for_status()
+        .map_err(|source| FetchError::Http {
+            url: shown.clone(),
+            source,
+        })?;
+    let mut json = String::new();
+    response
+        .read_to_string(&mut json)
+        .map_err(|e| FetchError::Network {
+            url: shown,
+            source: Box::new(e),
+        })?;
+    Ok(json)
+}
+
+fn endpoint(log: &Url, path: &str) -> Result<Url> {
+    let base = format!("{}/", log.as_str().trim_end_matches('/'));
+    Url::parse(&base)
+        .and_then(|base| base.join(path))
+        .map_err(|e| {
+            crate::error::InputError::InvalidConfig {
+                origin: "transparency log".into(),
+                reason: format!("`{log}`: {e}"),
+            }
+            .into()
+        })
+}
+
+/// Checks that `entry` logs `digest` and is included in the log's tree;
+/// `false`, without checking further, if it isn't signed by one of `pins`.
+fn verify_entry(
+    uuid: &str,
+    entry: &LogEntry,
+    digest: &str,
+    pins: &SignerPins,
+) -> std::result::Result<bool, String> {
+    let body = base64::engine::general_purpose::STANDARD
+        .decode(&entry.body)
+        .map_err(|e| format!("malformed body: {e}"))?;
+    let logged: serde_json::Value =
+        serde_json::from_slice(&body).map_err(|e| format!("malformed body: {e}"))?;
+    let kind = logged["kind"].as_str().unwrap_or_default();
+    if !matches!(kind, "hashedrekord" | "rekord") {
+        return Err(format!("a `{kind}` entry, not of a digest"));
+    }
+    let hash = &logged["spec"]["data"]["hash"];
+    let (algorithm, value) = (
+        hash["algorithm"].as_str().unwrap_or_default(),
+        hash["value"].as_str().unwrap_or_default(),
+    );
+    if algorithm != "sha256" || !value.eq_ignore_ascii_case(digest) {
+        return Err(format!("logs {algorithm}:{value}, not this digest"));
+    }
+    if !pinned_signer(&logged["spec"]["signature"], pins) {
+        return Ok(false);
+    }
+
+    let leaf: [u8; 32] = Sha256::new()
+        .chain_update([0])
+        .chain_update(&body)
+        .finalize()
+        .into();
+    // UUIDs are the leaf hash, after a tree ID on sharded logs.
+    if !uuid.to_ascii_lowercase().ends_with(&hex(&leaf)) {
+        return Err("its UUID is not the hash of its body".into());
+    }
+    let proof = entry
+        .verification
+        .as_ref()
+        .and_then(|v| v.inclusion_proof.as_ref())
+        .ok_or("no inclusion proof")?;
+    let path = proof
+        .hashes
+        .iter()
+        .map(|h| unhex(h))
+        .collect::<Option<Vec<_>>>()
+        .ok_or("malformed inclusion proof")?;
+    let root = unhex(&proof.root_hash).ok_or("malformed inclusion proof")?;
+    if !verify_inclusion(leaf, proof.log_index, proof.tree_size, &path, root) {
+        return Err(format!(
+            "inclusion proof of log index {} does not lead to root {}",
+            entry.log_index, proof.root_hash
+        ));
+    }
+    Ok(true)
+}
+
+/// Whether the `signature` of a logged entry is by one of `pins`: a minisign
+/// key among the `minisign` pins. Certificates aren't checked against the
+/// `cosign` pins, as nothing here checks who issued them; other keys and pins
+/// can't be told apart from anyone's.
+fn pinned_signer(signature: &serde_json::Value, pins: &SignerPins) -> bool {
+    if signature["format"].as_str() != Some("minisign") {
+        return false;
+    }
+    let Some(content) = signature["publicKey"]["content"]
+        .as_str()
+        .and_then(|c| base64::engine::general_purpose::STANDARD.decode(c).ok())
+    else {
+        return false;
+    };
+    let content = String::from_utf8_lossy(&content);
+    // A key file's comment line, if any, comes before the key.
+    let key = content
+        .lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty() && !line.starts_with("untrusted comment:"));
+    key.is_some_and(|key| pins.minisign.iter().any(|pin| pin.trim() == key))
+}
+
+/// Whether `path` is the audit path of the leaf hashing to `leaf` at `index`
+/// in a tree of `size` leaves with `root`, as in RFC 9162, section 2.1.3.2.
+/// Leaf hashes are `SHA-256(0x00 || leaf)`; this is what log entries are
+/// checked with, exposed for harnesses checking proofs of their own.
+pub fn verify_inclusion(
+    leaf: [u8; 32],
+    index: u64,
+    size: u64,
+    path: &[[u8; 32]],
+    root: [u8; 32],
+) -> bool {
+    if index >= size {
+        return false;
+    }
+    let node = |left: &[u8; 32], right: &[u8; 32]| -> [u8; 32] {
+        Sha256::new()
+            .chain_update([1])
+            .chain_update(left)
+            .chain_update(right)
+            .finalize()
+            .into()
+    };
+    let (mut index, mut last) = (index, size - 1);
+    let mut r = leaf;
+    for p in path {
+        if last == 0 {
+            return false;
+        }
+        if index & 1 == 1 || index == last {
+            r = node(p, &r);
+            while index & 1 == 0 && index != 0 {
+                index >>= 1;
+                last >>= 1;
+            }
+        } else {
+            r = node(&r, p);
+        }
+        index >>= 1;
+        last >>= 1;
+    }
+    last == 0 && r == root
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut out = [0u8; 32];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(out)
+}
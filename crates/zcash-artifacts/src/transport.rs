@@ -0,0 +1,428 @@
+//! The HTTP client under downloads and registry calls, behind the `http`
+//! feature.
+//!
+//! Every request the resolver makes itself goes through the resolver's
+//! [`Transport`]: release, package and bottle downloads, checksum lists, API
+//! and index lookups, and OCI registry calls (token requests and pushes
+//! included). Unless [configured](crate::ResolverConfig::transport) otherwise
+//! that is [`HttpTransport`], a shared reqwest client, blocking or async by
+//! the crate's `blocking` and `async` features. Replacing it lets tests
+//! run without a network, against canned responses; see
+//! `testing::CannedTransport` (`testing` feature).
+//!
+//! A transport only moves bytes. The [network policy](crate::network),
+//! concurrency limits, credential lookup, checksums and digests are applied
+//! around it, so a replacement sees the requests exactly as they would be
+//! sent, with credentials attached in [`Request::auth`].
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+use std::sync::OnceLock;
+use std::{fmt, io::Read};
+
+use crate::credentials::Credential;
+
+/// Makes HTTP requests for a resolver; see the [module docs](self).
+pub trait Transport: Send + Sync + 'static {
+    /// Sends `request`. Fails only when no response arrived (a refused
+    /// connection, a timeout, a malformed reply); error statuses are
+    /// responses like any other.
+    fn send(&self, request: Request) -> Result<Response, BoxError>;
+}
+
+/// Why a request got no response.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The HTTP methods the resolver uses.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A request to send.
+#[derive(Debug)]
+pub struct Request {
+    pub method: Method,
+    /// Without user-info when [`auth`](Self::auth) is set.
+    pub url: url::Url,
+    /// Headers besides `Authorization`, e.g. `Accept`.
+    pub headers: Vec<(String, String)>,
+    /// Sent as an `Authorization` header.
+    pub auth: Option<Credential>,
+    pub body: Option<Body>,
+}
+
+impl Request {
+    pub fn new(method: Method, url: url::Url) -> Self {
+        Self {
+            method,
+            url,
+            headers: Vec::new(),
+            auth: None,
+            body: None,
+        }
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn auth(mut self, credential: Option<Credential>) -> Self {
+        self.auth = credential;
+        self
+    }
+
+    pub fn body(mut self, body: Body) -> Self {
+        self.body = Some(body);
+        self
+    }
+}
+
+/// A request body, read as it is sent.
+pub struct Body {
+    reader: Box<dyn Read + Send>,
+    len: Option<u64>,
+}
+
+impl Body {
+    /// A body of `len` bytes read from `reader`; without a length, it is sent
+    /// chunked.
+    pub fn new(reader: impl Read + Send + 'static, len: Option<u64>) -> Self {
+        Self {
+            reader: Box::new(reader),
+            len,
+        }
+    }
+
+    pub fn len(&self) -> Option<u64> {
+        self.len
+    }
+
+    /// Whether the body is known to be empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == Some(0)
+    }
+
+    pub fn into_reader(self) -> Box<dyn Read + Send> {
+        self.reader
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Body").field("len", &self.len).finish()
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        let len = bytes.len() as u64;
+        Self::new(std::io::Cursor::new(bytes), Some(len))
+    }
+}
+
+impl From<std::fs::File> for Body {
+    fn from(file: std::fs::File) -> Self {
+        let len = file.metadata().ok().map(|m| m.len());
+        Self::new(file, len)
+    }
+}
+
+/// A response, with its body not yet read.
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Box<dyn Read + Send>,
+}
+
+impl Response {
+    /// An empty response with `status`.
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Box::new(std::io::empty()),
+        }
+    }
+
+    /// The first value of the header `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The announced `Content-Length`; for a `HEAD` request, that of the
+    /// body a `GET` would have.
+    pub fn content_length(&self) -> Option<u64> {
+        self.header("Content-Length")?.trim().parse().ok()
+    }
+
+    /// Whether the status is 2xx.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// The response, or a [`StatusError`] if its status isn't 2xx.
+    pub fn error_for_status(self) -> Result<Self, StatusError> {
+        if self.is_success() {
+            Ok(self)
+        } else {
+            Err(StatusError(self.status))
+        }
+    }
+}
+
+impl Read for Response {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.body.read(buf)
+    }
+}
+
+impl fmt::Debug for Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Response")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An error status a server answered with; it decides the
+/// [`ErrorKind`](crate::ErrorKind) of the failure, and whether it is
+/// [transient](crate::ArtifactError::is_transient).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("HTTP status {0}")]
+pub struct StatusError(pub u16);
+
+/// The default transport: a reqwest client, shared by every resolution of a
+/// resolver so that they reuse its connections.
+///
+/// Which client depends on the crate's features. With `blocking` (a default
+/// feature) it is reqwest's blocking client. With `async` it is reqwest's
+/// async client, driven on the Tokio runtime the resolution runs in, i.e.
+/// through [`AsyncResolver`](crate::nonblocking::AsyncResolver). With both,
+/// the async client serves resolutions inside a runtime and the blocking one
+/// all others. With neither, every request fails; configure a
+/// [transport](crate::ResolverConfig::transport) of your own then.
+#[derive(Default)]
+pub struct HttpTransport {
+    #[cfg(feature = "blocking")]
+    client: OnceLock<reqwest::blocking::Client>,
+    #[cfg(feature = "async")]
+    async_client: OnceLock<reqwest::Client>,
+}
+
+impl Transport for HttpTransport {
+    fn send(&self, request: Request) -> Result<Response, BoxError> {
+        #[cfg(feature = "async")]
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let client = self.async_client.get_or_init(reqwest::Client::new);
+            return send_async(client, runtime, request);
+        }
+        #[cfg(feature = "blocking")]
+        {
+            // Built on first use, inside a resolution: building one on an
+            // async runtime's thread panics.
+            let client = self.client.get_or_init(reqwest::blocking::Client::new);
+            send_blocking(client, request)
+        }
+        #[cfg(not(feature = "blocking"))]
+        {
+            let _ = request;
+            Err(if cfg!(feature = "async") {
+                "the async HTTP client runs on a Tokio runtime; resolve through \
+                 `nonblocking::AsyncResolver`, or enable the `blocking` feature"
+            } else {
+                "no HTTP client is compiled in; enable the `blocking` or `async` \
+                 feature, or configure a transport"
+            }
+            .into())
+        }
+    }
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+fn reqwest_method(method: Method) -> reqwest::Method {
+    match method {
+        Method::Get => reqwest::Method::GET,
+        Method::Head => reqwest::Method::HEAD,
+        Method::Post => reqwest::Method::POST,
+        Method::Put => reqwest::Method::PUT,
+    }
+}
+
+/// The response headers representable as strings.
+#[cfg(any(feature = "blocking", feature = "async"))]
+fn response_headers(headers: &reqwest::header::HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+#[cfg(feature = "blocking")]
+fn send_blocking(
+    client: &reqwest::blocking::Client,
+    request: Request,
+) -> Result<Response, BoxError> {
+    let mut builder = client.request(reqwest_method(request.method), request.url);
+    for (name, value) in request.headers {
+        builder = builder.header(name, value);
+    }
+    builder = match request.auth {
+        Some(Credential::Bearer(token)) => builder.bearer_auth(token),
+        Some(Credential::Basic { username, password }) => {
+            builder.basic_auth(username, Some(password))
+        }
+        None => builder,
+    };
+    if let Some(body) = request.body {
+        builder = builder.body(match body.len {
+            Some(len) => reqwest::blocking::Body::sized(body.reader, len),
+            None => reqwest::blocking::Body::new(body.reader),
+        });
+    }
+    let response = builder.send().map_err(|e| e.without_url())?;
+    Ok(Response {
+        status: response.status().as_u16(),
+        headers: response_headers(response.headers()),
+        body: Box::new(response),
+    })
+}
+
+/// Sends `request` with the async client, blocking on `runtime` until the
+/// response arrives. Resolutions run on the runtime's blocking threads, where
+/// that is allowed; the body is read the same way, chunk by chunk.
+#[cfg(feature = "async")]
+fn send_async(
+    client: &reqwest::Client,
+    runtime: tokio::runtime::Handle,
+    request: Request,
+) -> Result<Response, BoxError> {
+    let mut builder = client.request(reqwest_method(request.method), request.url);
+    for (name, value) in request.headers {
+        builder = builder.header(name, value);
+    }
+    builder = match request.auth {
+        Some(Credential::Bearer(token)) => builder.bearer_auth(token),
+        Some(Credential::Basic { username, password }) => {
+            builder.basic_auth(username, Some(password))
+        }
+        None => builder,
+    };
+    if let Some(body) = request.body {
+        if let Some(len) = body.len {
+            builder = builder.header(reqwest::header::CONTENT_LENGTH, len);
+        }
+        builder = builder.body(reqwest::Body::wrap_stream(ReaderStream::spawn(body.reader)));
+    }
+    let response = runtime
+        .block_on(builder.send())
+        .map_err(|e| e.without_url())?;
+    Ok(Response {
+        status: response.status().as_u16(),
+        headers: response_headers(response.headers()),
+        body: Box::new(AsyncBody {
+            runtime,
+            response,
+            chunk: Vec::new(),
+            read: 0,
+        }),
+    })
+}
+
+/// A request body read on a thread of its own, so the runtime's workers never
+/// block on the reader.
+#[cfg(feature = "async")]
+struct ReaderStream(tokio::sync::mpsc::Receiver<std::io::Result<Vec<u8>>>);
+
+#[cfg(feature = "async")]
+impl ReaderStream {
+    fn spawn(mut reader: Box<dyn Read + Send>) -> Self {
+        let (tx, rx) = tokio::sync::mpsc::channel(4);
+        std::thread::spawn(move || {
+            loop {
+                let mut chunk = vec![0; 64 * 1024];
+                let chunk = match reader.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        chunk.truncate(n);
+                        Ok(chunk)
+                    }
+                    Err(e) => Err(e),
+                };
+                let failed = chunk.is_err();
+                // Gone once the request was abandoned.
+                if tx.blocking_send(chunk).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Self(rx)
+    }
+}
+
+#[cfg(feature = "async")]
+impl futures_core::Stream for ReaderStream {
+    type Item = std::io::Result<Vec<u8>>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx)
+    }
+}
+
+/// The body of a response from the async client, read by blocking on the
+/// runtime for one chunk at a time.
+#[cfg(feature = "async")]
+struct AsyncBody {
+    runtime: tokio::runtime::Handle,
+    response: reqwest::Response,
+    chunk: Vec<u8>,
+    read: usize,
+}
+
+#[cfg(feature = "async")]
+impl Read for AsyncBody {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.read == self.chunk.len() {
+            match self.runtime.block_on(self.response.chunk()) {
+                Ok(Some(chunk)) => {
+                    self.chunk = chunk.to_vec();
+                    self.read = 0;
+                }
+                Ok(None) => return Ok(0),
+                Err(e) => return Err(std::io::Error::other(e.without_url())),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.read);
+        buf[..n].copy_from_slice(&self.chunk[self.read..self.read + n]);
+        self.read += n;
+        Ok(n)
+    }
+}
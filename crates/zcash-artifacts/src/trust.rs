@@ -0,0 +1,545 @@
+//! Release signing keys the resolver trusts, behind the `trust-store`
+//! feature.
+//!
+//! A [`TrustStore`] holds OpenPGP public keys, each with who it belongs to,
+//! what it signs, and when it is valid. Signatures are checked against the
+//! keys valid at the time: apt repositories' `InRelease` files (see
+//! `crate::deb`, `deb` feature) whose [`AptRepository`](crate::deb::AptRepository)
+//! has no keyring of its own are checked against the keys scoped to their
+//! host.
+//!
+//! [`TrustStore::bundled`], the default, holds the keys listed in the crate's
+//! `trust/keys.toml`. That list is still empty: the Electric Coin Company's
+//! and the Zcash Foundation's release keys are only to be added once their
+//! fingerprints are checked against the owners' published ones. Until then,
+//! `InRelease` files of hosts the store has no keys for are not checked, so
+//! operators add the keyrings they trust, or replace the store:
+//!
+//! ```no_run
+//! # fn main() -> zcash_artifacts::Result<()> {
+//! use zcash_artifacts::{ResolverConfig, trust::TrustStore};
+//!
+//! let config = ResolverConfig::builder()
+//!     // Our mirror re-signs with its own key.
+//!     .trust_store(TrustStore::bundled().keyring("/etc/apt/keyrings/mirror.gpg")?)
+//!     .finish();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Store files list keys as `[[key]]` tables:
+//!
+//! ```toml
+//! [[key]]
+//! owner = "Electric Coin Company"
+//! fingerprint = "<40 hex digits>"
+//! scopes = ["apt.z.cash"]            # hosts it signs for; "*" for any
+//! file = "ecc-apt-2024.asc"          # armored or binary, next to the store
+//! not_before = "2024-01-01T00:00:00Z"
+//! expires = "2027-01-01T00:00:00Z"
+//! superseded_by = "<fingerprint of its successor>"
+//! ```
+//!
+//! A key is used from `not_before` until `expires`, both optional; a key
+//! rotated out keeps verifying until it expires, so that releases signed
+//! during the handover still do. When every key for a host is out of its
+//! window, its signatures fail to verify rather than going unchecked.
+//! `superseded_by` is informational. Keys added with [`TrustStore::keyring`]
+//! have no metadata, and are trusted for every scope. Signatures are checked
+//! with `gpgv`, which must be installed.
+//!
+//! # Pinned signers
+//!
+//! A valid signature only shows that some trusted key made it. To accept a
+//! service's artifacts from particular signers only, pin them in
+//! [`ResolverConfig::signers`](crate::ResolverConfig::signers), or in a
+//! [configuration file](crate::config):
+//!
+//! ```toml
+//! [signers.zcashd]
+//! gpg = ["<fingerprint of the repository's signing key>"]
+//!
+//! [signers.zebrad]
+//! minisign = ["RWQ…"]
+//! cosign = [{ identity = "https://github.com/ZcashFoundation/zebra/.github/workflows/release.yml@refs/heads/main", issuer = "https://token.actions.githubusercontent.com" }]
+//! ```
+//!
+//! Each kind of pin applies to the artifacts signed that way, and artifacts
+//! of the service without a valid signature by one of the pinned signers are
+//! rejected with `VerifyError::SignatureInvalid`:
+//!
+//! - `gpg`: the `InRelease` of the service's apt repository (`Deb` sources)
+//!   must be signed by one of these keys, primary or subkey, by fingerprint;
+//! - `minisign`: release assets (`Release` and `Guix` sources) must have a
+//!   valid `<asset URL>.minisig` by one of these public keys;
+//! - `cosign`: images naming the service (`OciImage` sources, `oci` feature)
+//!   must pass `cosign verify` for one of these keyless identities. `cosign`
+//!   must be installed, and reaches the registry and the transparency log.
+//!
+//! The signer is recorded in META's `signer`, and cache entries made before
+//! the pin, or under a different one, are fetched again. Images resolved as
+//! images have no entry of their own, and are checked on every resolution.
+
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use serde::Deserialize;
+use url::Url;
+
+use crate::{
+    ResolveContext, cache,
+    error::{FsError, InputError, Result, VerifyError},
+    registry::ServiceId,
+};
+
+/// The store file bundled with the crate, and the key files it names.
+// TODO: add the ECC (apt.z.cash) and Zcash Foundation release keys, with
+// their validity windows and rotations, once their fingerprints are verified.
+const BUNDLED: &str = include_str!("../trust/keys.toml");
+const BUNDLED_FILES: &[(&str, &[u8])] = &[];
+
+/// Keys to check release signatures with; see the [module docs](self).
+#[derive(Debug, Clone, Default)]
+pub struct TrustStore {
+    keys: Vec<TrustedKey>,
+}
+
+/// A public key in a [`TrustStore`].
+#[derive(Debug, Clone)]
+pub struct TrustedKey {
+    /// Who the key belongs to, e.g. `Electric Coin Company`.
+    pub owner: String,
+    /// The primary key's fingerprint; empty for keys from keyrings.
+    pub fingerprint: String,
+    /// Hosts the key signs for; `*` matches any.
+    pub scopes: Vec<String>,
+    /// Start of the key's validity, `YYYY-MM-DDTHH:MM:SSZ`.
+    pub not_before: Option<String>,
+    /// End of the key's validity, `YYYY-MM-DDTHH:MM:SSZ`.
+    pub expires: Option<String>,
+    /// The key that replaced this one.
+    pub superseded_by: Option<String>,
+    /// The key itself, as OpenPGP packets.
+    packets: Vec<u8>,
+}
+
+/// A `[[key]]` table of a store file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyEntry {
+    owner: String,
+    fingerprint: String,
+    scopes: Vec<String>,
+    file: String,
+    not_before: Option<String>,
+    expires: Option<String>,
+    superseded_by: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StoreFile {
+    #[serde(default)]
+    key: Vec<KeyEntry>,
+}
+
+impl TrustStore {
+    /// The keys bundled with the crate; see the [module docs](self).
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED, "bundled trust store", |file| {
+            BUNDLED_FILES
+                .iter()
+                .find(|(name, _)| *name == file)
+                .map(|(_, bytes)| bytes.to_vec())
+                .ok_or_else(|| format!("no bundled key file `{file}`"))
+        })
+        .expect("the bundled trust store is valid")
+    }
+
+    /// A store without keys.
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// Reads a store file, with key files relative to it.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| FsError::Io {
+            context: format!("read {}", path.display()),
+            source: e,
+        })?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Self::parse(&text, &path.display().to_string(), |file| {
+            std::fs::read(dir.join(file)).map_err(|e| format!("can't read `{file}`: {e}"))
+        })
+    }
+
+    fn parse(
+        text: &str,
+        origin: &str,
+        read: impl Fn(&str) -> std::result::Result<Vec<u8>, String>,
+    ) -> Result<Self> {
+        let invalid = |reason: String| InputError::InvalidConfig {
+            origin: origin.to_string(),
+            reason,
+        };
+        let file: StoreFile = toml::from_str(text).map_err(|e| invalid(e.to_string()))?;
+        let mut keys = Vec::with_capacity(file.key.len());
+        for entry in file.key {
+            let bytes = read(&entry.file).map_err(invalid)?;
+            let packets = dearmor(&bytes)
+                .ok_or_else(|| invalid(format!("`{}` is not an OpenPGP key", entry.file)))?;
+            keys.push(TrustedKey {
+                owner: entry.owner,
+                fingerprint: entry.fingerprint.replace(' ', "").to_ascii_uppercase(),
+                scopes: entry.scopes,
+                not_before: entry.not_before,
+                expires: entry.expires,
+                superseded_by: entry.superseded_by,
+                packets,
+            });
+        }
+        Ok(Self { keys })
+    }
+
+    /// Adds the keys of the keyring at `path`, armored or binary (like those
+    /// apt's `signed-by` names), trusted for every scope.
+    pub fn keyring(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| FsError::Io {
+            context: format!("read {}", path.display()),
+            source: e,
+        })?;
+        let packets = dearmor(&bytes).ok_or_else(|| InputError::InvalidConfig {
+            origin: path.display().to_string(),
+            reason: "not an OpenPGP keyring".into(),
+        })?;
+        self.keys.push(TrustedKey {
+            owner: path.display().to_string(),
+            fingerprint: String::new(),
+            scopes: vec!["*".into()],
+            not_before: None,
+            expires: None,
+            superseded_by: None,
+            packets,
+        });
+        Ok(self)
+    }
+
+    pub fn keys(&self) -> &[TrustedKey] {
+        &self.keys
+    }
+
+    /// Writes the keys valid now for `scope` into a keyring for `gpgv` under
+    /// `cache_root`; `None` if the store has no keys for it. If it only has
+    /// expired or not yet valid ones, checking `what` fails.
+    pub(crate) fn keyring_for(
+        &self,
+        scope: &str,
+        cache_root: &Path,
+        what: &str,
+    ) -> Result<Option<PathBuf>> {
+        let now = cache::timestamp();
+        let scoped: Vec<&TrustedKey> = self.keys.iter().filter(|key| key.covers(scope)).collect();
+        if scoped.is_empty() {
+            return Ok(None);
+        }
+        let packets: Vec<u8> = scoped
+            .iter()
+            .filter(|key| key.valid_at(&now))
+            .flat_map(|key| key.packets.iter().copied())
+            .collect();
+        if packets.is_empty() {
+            return Err(VerifyError::SignatureInvalid {
+                what: what.to_string(),
+                source: format!("no key trusted for {scope} is valid at {now}").into(),
+            }
+            .into());
+        }
+        let path = cache_root.join("trust").join(format!("{scope}.gpg"));
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| FsError::Io {
+                context: format!("create {}", dir.display()),
+                source: e,
+            })?;
+        }
+        cache::write_atomic(&path, &packets)?;
+        Ok(Some(path))
+    }
+}
+
+impl TrustedKey {
+    fn covers(&self, scope: &str) -> bool {
+        self.scopes
+            .iter()
+            .any(|s| s == "*" || s.eq_ignore_ascii_case(scope))
+    }
+
+    /// Whether the key is valid at `now`; timestamps compare as strings.
+    pub fn valid_at(&self, now: &str) -> bool {
+        self.not_before.as_deref().is_none_or(|from| from <= now)
+            && self.expires.as_deref().is_none_or(|until| now < until)
+    }
+}
+
+/// The OpenPGP packets of an armored key block, or `bytes` if they aren't
+/// armored.
+fn dearmor(bytes: &[u8]) -> Option<Vec<u8>> {
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return Some(bytes.to_vec());
+    };
+    if !text.contains("-----BEGIN PGP PUBLIC KEY BLOCK-----") {
+        // Binary packets start with a tag byte, which is never ASCII text.
+        return bytes
+            .first()
+            .is_some_and(|b| b & 0x80 != 0)
+            .then(|| bytes.to_vec());
+    }
+    let mut packets = Vec::new();
+    let mut lines = text.lines().map(str::trim);
+    while lines.any(|line| line == "-----BEGIN PGP PUBLIC KEY BLOCK-----") {
+        // Armor headers end with a blank line; the checksum line starts with `=`.
+        let body: String = lines
+            .by_ref()
+            .skip_while(|line| line.contains(':'))
+            .take_while(|line| !line.starts_with("-----END"))
+            .filter(|line| !line.is_empty() && !line.starts_with('='))
+            .collect();
+        packets.extend(
+            base64::engine::general_purpose::STANDARD
+                .decode(body)
+                .ok()?,
+        );
+    }
+    Some(packets)
+}
+
+/// Signers a service's artifacts must come from; see
+/// [pinned signers](self#pinned-signers). Empty lists pin nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignerPins {
+    /// Fingerprints of the OpenPGP keys that may sign the service's apt
+    /// repository, primary keys or subkeys.
+    #[serde(default)]
+    pub gpg: Vec<String>,
+    /// minisign public keys, in base64, that may sign release assets.
+    #[serde(default)]
+    pub minisign: Vec<String>,
+    /// Keyless cosign identities that may sign images of the service.
+    #[serde(default)]
+    pub cosign: Vec<CosignIdentity>,
+}
+
+/// A certificate identity of keyless cosign signatures, e.g. a CI workflow.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CosignIdentity {
+    /// The certificate's subject, e.g. the workflow's URL at a ref.
+    pub identity: String,
+    /// The OIDC issuer, e.g. `https://token.actions.githubusercontent.com`.
+    pub issuer: String,
+}
+
+/// The kinds of [`SignerPins`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Signer {
+    Gpg,
+    Minisign,
+    #[cfg(feature = "oci")]
+    Cosign,
+}
+
+/// The pins of `kind` for `service`, as META's `signer` records them.
+fn pinned(ctx: &ResolveContext<'_>, service: &ServiceId, kind: Signer) -> Vec<String> {
+    let Some(pins) = ctx.config.signers.get(service) else {
+        return Vec::new();
+    };
+    match kind {
+        Signer::Gpg => pins
+            .gpg
+            .iter()
+            .map(|fpr| format!("gpg:{}", fingerprint(fpr)))
+            .collect(),
+        Signer::Minisign => pins
+            .minisign
+            .iter()
+            .map(|key| format!("minisign:{}", key.trim()))
+            .collect(),
+        #[cfg(feature = "oci")]
+        Signer::Cosign => pins
+            .cosign
+            .iter()
+            .map(|id| format!("cosign:{} ({})", id.identity, id.issuer))
+            .collect(),
+    }
+}
+
+fn fingerprint(fpr: &str) -> String {
+    fpr.replace(' ', "").to_ascii_uppercase()
+}
+
+/// Whether the cache entry whose META is in `meta_dir` was checked against
+/// one of `service`'s current pins of `kind`; always if there are none.
+pub(crate) fn cached_ok(
+    ctx: &ResolveContext<'_>,
+    service: &ServiceId,
+    kind: Signer,
+    meta_dir: &Path,
+) -> bool {
+    let pins = pinned(ctx, service, kind);
+    if pins.is_empty() {
+        return true;
+    }
+    std::fs::read(meta_dir.join("META.json"))
+        .ok()
+        .and_then(|json| serde_json::from_slice::<cache::Meta>(&json).ok())
+        .and_then(|meta| meta.signer)
+        .is_some_and(|signer| pins.contains(&signer))
+}
+
+fn unpinned(what: &str, reason: String) -> VerifyError {
+    VerifyError::SignatureInvalid {
+        what: what.to_string(),
+        source: reason.into(),
+    }
+}
+
+/// Checks that `fingerprints`, the keys `gpgv` found valid signatures of on
+/// `what`, include one of `service`'s pins; returns the signer to record.
+pub(crate) fn check_gpg(
+    ctx: &ResolveContext<'_>,
+    service: &ServiceId,
+    fingerprints: &[String],
+    what: &Url,
+) -> Result<Option<String>> {
+    let pins = pinned(ctx, service, Signer::Gpg);
+    if pins.is_empty() {
+        return Ok(None);
+    }
+    if let Some(pin) = fingerprints
+        .iter()
+        .map(|fpr| format!("gpg:{}", fingerprint(fpr)))
+        .find(|signer| pins.contains(signer))
+    {
+        return Ok(Some(pin));
+    }
+    let reason = match fingerprints.first() {
+        Some(fpr) => format!(
+            "signed by {fpr}, which is not a pinned signer of {}",
+            service.as_str()
+        ),
+        None => format!(
+            "{} has pinned signers, but there is no keyring to check with",
+            service.as_str()
+        ),
+    };
+    Err(unpinned(what.as_str(), reason).into())
+}
+
+/// Checks `file`, downloaded from `url`, against `<url>.minisig` and
+/// `service`'s minisign pins; returns the signer to record.
+pub(crate) fn check_minisign(
+    ctx: &ResolveContext<'_>,
+    service: &ServiceId,
+    url: &Url,
+    file: &Path,
+) -> Result<Option<String>> {
+    use std::io::Read;
+
+    use minisign_verify::{PublicKey, Signature};
+
+    let pins = pinned(ctx, service, Signer::Minisign);
+    if pins.is_empty() {
+        return Ok(None);
+    }
+    let what = crate::credentials::redact(url);
+    let sig_url = Url::parse(&format!("{url}.minisig"))
+        .map_err(|e| unpinned(&what, format!("bad signature URL: {e}")))?;
+    let signature = crate::pipeline::fetch_text(ctx, &sig_url)?;
+    let signature = Signature::decode(&signature)
+        .map_err(|e| unpinned(&what, format!("malformed {sig_url}: {e}")))?;
+    for pin in pins {
+        let key = PublicKey::from_base64(&pin["minisign:".len()..])
+            .map_err(|e| unpinned(&what, format!("bad pinned minisign key `{pin}`: {e}")))?;
+        // Signatures by other keys fail here, on their key ID.
+        let Ok(mut verifier) = key.verify_stream(&signature) else {
+            continue;
+        };
+        let io = |e| FsError::Io {
+            context: format!("read {}", file.display()),
+            source: e,
+        };
+        let mut reader = std::fs::File::open(file).map_err(io)?;
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = reader.read(&mut buf).map_err(io)?;
+            if n == 0 {
+                break;
+            }
+            verifier.update(&buf[..n]);
+        }
+        return match verifier.finalize() {
+            Ok(()) => Ok(Some(pin)),
+            Err(e) => Err(unpinned(&what, e.to_string()).into()),
+        };
+    }
+    Err(unpinned(
+        &what,
+        format!(
+            "{sig_url} is not by a pinned signer of {}",
+            service.as_str()
+        ),
+    )
+    .into())
+}
+
+/// Runs `cosign verify` on the image `reference`, pinned to its digest, for
+/// each of `service`'s cosign pins until one passes; returns the signer to
+/// record.
+#[cfg(feature = "oci")]
+pub(crate) fn check_cosign(
+    ctx: &ResolveContext<'_>,
+    service: &ServiceId,
+    reference: &crate::oci::Reference,
+) -> Result<Option<String>> {
+    let Some(pins) = ctx
+        .config
+        .signers
+        .get(service)
+        .filter(|p| !p.cosign.is_empty())
+    else {
+        return Ok(None);
+    };
+    let what = reference.to_string();
+    ctx.config
+        .network
+        .check_host(&reference.registry, || what.clone())?;
+    let mut failures = Vec::new();
+    for pin in &pins.cosign {
+        let output = std::process::Command::new("cosign")
+            .arg("verify")
+            .args(["--certificate-identity", &pin.identity])
+            .args(["--certificate-oidc-issuer", &pin.issuer])
+            .arg(&what)
+            .output()
+            .map_err(|e| unpinned(&what, format!("could not run cosign: {e}")))?;
+        if output.status.success() {
+            return Ok(Some(format!("cosign:{} ({})", pin.identity, pin.issuer)));
+        }
+        failures.push(format!(
+            "{}: {}",
+            pin.identity,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Err(unpinned(
+        &what,
+        format!(
+            "not signed by a pinned signer of {}; {}",
+            service.as_str(),
+            failures.join("; ")
+        ),
+    )
+    .into())
+}
@@ -0,0 +1,632 @@
+//! Release assets from a [TUF](https://theupdateframework.io) repository,
+//! behind the `tuf` feature.
+//!
+//! A checksum table only says what the index's author saw. [`TufReleaseIndex`]
+//! takes assets from a repository publishing TUF metadata instead (an
+//! upstream's, or an internal mirror's) and runs the client workflow of the
+//! [specification](https://theupdateframework.github.io/specification/latest/#detailed-client-workflow)
+//! before anything is downloaded:
+//!
+//! 1. `root.json` is updated, one version at a time, each new root signed by
+//!    the threshold of keys of both the previous one and itself;
+//! 2. `timestamp.json`, `snapshot.json` and `targets.json` are fetched and
+//!    checked against the keys and thresholds the root sets for them, and
+//!    against each other's versions and hashes;
+//! 3. every role must be unexpired (no freeze attack), and no version older
+//!    than one seen before (no rollback attack).
+//!
+//! The asset is then looked up in the targets by a path rendered like
+//! [`TemplateReleaseIndex`](crate::release::TemplateReleaseIndex) URLs, and
+//! downloaded from the targets URL and checked against its sha256 as usual.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use zcash_artifacts::{registry::ToolSpec, tuf::TufReleaseIndex};
+//!
+//! let index = TufReleaseIndex::new(
+//!     "https://tuf.example.com/metadata/".parse()?,
+//!     "https://tuf.example.com/targets/".parse()?,
+//!     // Shipped with the program, or distributed out of band.
+//!     std::fs::read("trusted-root.json")?,
+//!     "zcashd/{version}/zcash-{version}-{platform}.tar.gz",
+//! );
+//! let spec = ToolSpec::builder(zcash_artifacts::registry::ZCASHD)
+//!     .release_index(index)
+//!     .finish();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The metadata last trusted is kept in `tuf/<hash of the metadata URL>/`
+//! under the cache root, which is what rollback protection compares against.
+//! So are the roots the given one was rotated to, as `<version>.root.json`;
+//! they spare refetching the rotations, but are checked to chain from the
+//! given root like fetched ones, so the cache can't swap in a root of its
+//! own. Only ed25519
+//! keys are supported, and only the top-level targets role is searched, not
+//! delegated ones. Failures are `VerifyError::TufRejected` errors.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{
+    ReleaseIndex, ResolveContext, cache,
+    error::{FsError, Result, VerifyError},
+    pipeline::fetch_text,
+};
+
+/// Root versions fetched in one update at most, so that a malicious
+/// repository can't keep the client updating forever.
+const MAX_ROOT_ROTATIONS: u64 = 32;
+
+/// A [`ReleaseIndex`] backed by TUF metadata; see the [module docs](self).
+pub struct TufReleaseIndex {
+    metadata_url: Url,
+    targets_url: Url,
+    root: Vec<u8>,
+    target_template: String,
+    /// The targets of the last update, reused until an asset is missing
+    /// from them.
+    trusted: Mutex<Option<Trusted>>,
+}
+
+struct Trusted {
+    consistent_snapshot: bool,
+    targets: Targets,
+}
+
+impl TufReleaseIndex {
+    /// An index over the repository whose metadata is under `metadata_url`
+    /// and targets under `targets_url`, trusting `root`, the contents of a
+    /// `root.json`, to start with.
+    ///
+    /// `target_template` renders the target path of an asset; it may use
+    /// `{version}`, `{platform}`, `{os}`, `{arch}` and `{asset}`, as
+    /// [`TemplateReleaseIndex`](crate::release::TemplateReleaseIndex) URL
+    /// templates do.
+    pub fn new(
+        metadata_url: Url,
+        targets_url: Url,
+        root: impl Into<Vec<u8>>,
+        target_template: impl Into<String>,
+    ) -> Self {
+        Self {
+            metadata_url: as_directory(metadata_url),
+            targets_url: as_directory(targets_url),
+            root: root.into(),
+            target_template: target_template.into(),
+            trusted: Mutex::new(None),
+        }
+    }
+
+    fn rejected(&self, role: &str, reason: impl Into<String>) -> crate::ArtifactError {
+        VerifyError::TufRejected {
+            repository: crate::credentials::redact(&self.metadata_url),
+            role: role.into(),
+            reason: reason.into(),
+        }
+        .into()
+    }
+
+    /// Where the metadata last trusted is kept.
+    fn state_dir(&self, ctx: &ResolveContext<'_>) -> PathBuf {
+        let hash = blake3::hash(self.metadata_url.as_str().as_bytes()).to_hex();
+        ctx.config.cache_root.join("tuf").join(&hash[..16])
+    }
+
+    /// Runs the client workflow, and returns the targets it ends with.
+    fn update(&self, ctx: &ResolveContext<'_>) -> Result<Trusted> {
+        let dir = self.state_dir(ctx);
+        std::fs::create_dir_all(&dir).map_err(|e| FsError::Io {
+            context: format!("create {}", dir.display()),
+            source: e,
+        })?;
+        let now = cache::timestamp();
+
+        // The given root, then the rotations from it stored by earlier updates,
+        // up to the first that doesn't chain from the root before it.
+        let mut root = self.verify::<Root>("root", &self.root, None)?;
+        while let Some(bytes) = read(&dir, &format!("{}.root.json", root.version + 1))? {
+            match self.next_root(&root, &bytes) {
+                Ok(next) => root = next,
+                Err(_) => break,
+            }
+        }
+        let mut rotated = false;
+        for _ in 0..MAX_ROOT_ROTATIONS {
+            let name = format!("{}.root.json", root.version + 1);
+            let Some(bytes) = self.fetch_optional(ctx, &name)? else {
+                break;
+            };
+            let next = self.next_root(&root, &bytes)?;
+            rotated |= ["timestamp", "snapshot"]
+                .iter()
+                .any(|role| root.role_keys(role) != next.role_keys(role));
+            write(&dir, &name, &bytes)?;
+            root = next;
+        }
+        self.unexpired("root", &root.expires, &now)?;
+        if rotated {
+            // New keys invalidate what the old ones vouched for.
+            for name in ["timestamp.json", "snapshot.json"] {
+                let _ = std::fs::remove_file(dir.join(name));
+            }
+        }
+
+        let (bytes, timestamp) =
+            self.fetch_role::<Timestamp>(ctx, "timestamp", "timestamp.json", &root)?;
+        let snapshot_meta = timestamp
+            .meta
+            .get("snapshot.json")
+            .ok_or_else(|| self.rejected("timestamp", "no snapshot.json in it"))?
+            .clone();
+        if let Some(old) = self.stored::<Timestamp>(&dir, "timestamp", &root) {
+            if timestamp.version < old.version {
+                return Err(self.rejected(
+                    "timestamp",
+                    format!(
+                        "version {} is older than {}",
+                        timestamp.version, old.version
+                    ),
+                ));
+            }
+            if let Some(old_snapshot) = old.meta.get("snapshot.json")
+                && snapshot_meta.version < old_snapshot.version
+            {
+                return Err(self.rejected(
+                    "timestamp",
+                    format!(
+                        "snapshot version {} is older than {}",
+                        snapshot_meta.version, old_snapshot.version
+                    ),
+                ));
+            }
+        }
+        self.unexpired("timestamp", &timestamp.expires, &now)?;
+        write(&dir, "timestamp.json", &bytes)?;
+
+        let name = versioned(
+            root.consistent_snapshot,
+            snapshot_meta.version,
+            "snapshot.json",
+        );
+        let (bytes, snapshot) = self.fetch_role::<Snapshot>(ctx, "snapshot", &name, &root)?;
+        self.matches("snapshot", &bytes, &snapshot_meta, snapshot.version)?;
+        if let Some(old) = self.stored::<Snapshot>(&dir, "snapshot", &root) {
+            for (name, old_meta) in &old.meta {
+                match snapshot.meta.get(name) {
+                    Some(meta) if meta.version >= old_meta.version => {}
+                    Some(meta) => {
+                        return Err(self.rejected(
+                            "snapshot",
+                            format!(
+                                "{name} version {} is older than {}",
+                                meta.version, old_meta.version
+                            ),
+                        ));
+                    }
+                    None => return Err(self.rejected("snapshot", format!("{name} is missing"))),
+                }
+            }
+        }
+        self.unexpired("snapshot", &snapshot.expires, &now)?;
+        write(&dir, "snapshot.json", &bytes)?;
+
+        let targets_meta = snapshot
+            .meta
+            .get("targets.json")
+            .ok_or_else(|| self.rejected("snapshot", "no targets.json in it"))?;
+        let name = versioned(
+            root.consistent_snapshot,
+            targets_meta.version,
+            "targets.json",
+        );
+        let (bytes, targets) = self.fetch_role::<Targets>(ctx, "targets", &name, &root)?;
+        self.matches("targets", &bytes, targets_meta, targets.version)?;
+        self.unexpired("targets", &targets.expires, &now)?;
+        write(&dir, "targets.json", &bytes)?;
+
+        Ok(Trusted {
+            consistent_snapshot: root.consistent_snapshot,
+            targets,
+        })
+    }
+
+    /// `bytes`, checked to be the root following `root`: its next version,
+    /// signed by the threshold of `root`'s keys and of its own.
+    fn next_root(&self, root: &Root, bytes: &[u8]) -> Result<Root> {
+        self.verify::<Value>("root", bytes, Some(root))?;
+        let next = self.verify::<Root>("root", bytes, None)?;
+        if next.version != root.version + 1 {
+            return Err(self.rejected(
+                "root",
+                format!(
+                    "{}.root.json has version {}",
+                    root.version + 1,
+                    next.version
+                ),
+            ));
+        }
+        Ok(next)
+    }
+
+    /// Fetches and verifies the metadata file `name` of `role`.
+    fn fetch_role<T: DeserializeOwned>(
+        &self,
+        ctx: &ResolveContext<'_>,
+        role: &str,
+        name: &str,
+        root: &Root,
+    ) -> Result<(Vec<u8>, T)> {
+        let url = self.join(&self.metadata_url, name)?;
+        let bytes = fetch_text(ctx, &url)?.into_bytes();
+        let signed = self.verify::<T>(role, &bytes, Some(root))?;
+        Ok((bytes, signed))
+    }
+
+    /// The metadata file `name`; `None` if the repository doesn't have it.
+    fn fetch_optional(&self, ctx: &ResolveContext<'_>, name: &str) -> Result<Option<Vec<u8>>> {
+        let url = self.join(&self.metadata_url, name)?;
+        match fetch_text(ctx, &url) {
+            Ok(text) => Ok(Some(text.into_bytes())),
+            Err(e) if e.kind() == crate::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The stored metadata of `role`, if it is still signed by `root`'s keys.
+    fn stored<T: DeserializeOwned>(&self, dir: &Path, role: &str, root: &Root) -> Option<T> {
+        let bytes = read(dir, &format!("{role}.json")).ok()??;
+        self.verify::<T>(role, &bytes, Some(root)).ok()
+    }
+
+    /// Checks `bytes`' signatures against the keys and threshold `root`
+    /// (or, for a root, its own) sets for `role`, and its `_type`.
+    fn verify<T: DeserializeOwned>(
+        &self,
+        role: &str,
+        bytes: &[u8],
+        root: Option<&Root>,
+    ) -> Result<T> {
+        #[derive(Deserialize)]
+        struct Envelope {
+            signed: Value,
+            signatures: Vec<Signature>,
+        }
+        #[derive(Deserialize)]
+        struct Signature {
+            keyid: String,
+            sig: String,
+        }
+
+        let envelope: Envelope = serde_json::from_slice(bytes)
+            .map_err(|e| self.rejected(role, format!("malformed metadata: {e}")))?;
+        if envelope.signed.get("_type").and_then(Value::as_str) != Some(role) {
+            return Err(self.rejected(role, format!("not {role} metadata")));
+        }
+        let own;
+        let root = match root {
+            Some(root) => root,
+            None => {
+                own = serde_json::from_value::<Root>(envelope.signed.clone())
+                    .map_err(|e| self.rejected(role, format!("malformed metadata: {e}")))?;
+                &own
+            }
+        };
+        let keys = root
+            .roles
+            .get(role)
+            .ok_or_else(|| self.rejected(role, "the root has no keys for it"))?;
+        let message = canonical(&envelope.signed);
+        let mut valid = Vec::new();
+        for signature in &envelope.signatures {
+            if valid.contains(&&signature.keyid) || !keys.keyids.contains(&signature.keyid) {
+                continue;
+            }
+            if root
+                .keys
+                .get(&signature.keyid)
+                .is_some_and(|key| key.verifies(&message, &signature.sig))
+            {
+                valid.push(&signature.keyid);
+            }
+        }
+        if keys.threshold == 0 || (valid.len() as u64) < keys.threshold {
+            return Err(self.rejected(
+                role,
+                format!(
+                    "signed by {} of the {} keys required",
+                    valid.len(),
+                    keys.threshold
+                ),
+            ));
+        }
+        serde_json::from_value(envelope.signed)
+            .map_err(|e| self.rejected(role, format!("malformed metadata: {e}")))
+    }
+
+    /// Checks metadata against what the role above it says of it.
+    fn matches(&self, role: &str, bytes: &[u8], meta: &MetaFile, version: u64) -> Result<()> {
+        if version != meta.version {
+            return Err(self.rejected(
+                role,
+                format!("version {version}, expected {}", meta.version),
+            ));
+        }
+        if let Some(length) = meta.length
+            && bytes.len() as u64 != length
+        {
+            return Err(self.rejected(role, format!("{} bytes, expected {length}", bytes.len())));
+        }
+        if let Some(expected) = meta.hashes.get("sha256")
+            && hex(&Sha256::digest(bytes)) != expected.to_ascii_lowercase()
+        {
+            return Err(self.rejected(role, "sha256 mismatch"));
+        }
+        Ok(())
+    }
+
+    fn unexpired(&self, role: &str, expires: &str, now: &str) -> Result<()> {
+        // Both are `YYYY-MM-DDTHH:MM:SSZ`, which compare as strings.
+        match expires.get(..19) {
+            Some(at) if at > &now[..19] => Ok(()),
+            Some(_) => Err(self.rejected(role, format!("expired at {expires}"))),
+            None => Err(self.rejected(role, format!("malformed expiry `{expires}`"))),
+        }
+    }
+
+    fn join(&self, base: &Url, path: &str) -> Result<Url> {
+        base.join(path).map_err(|e| {
+            crate::error::InputError::InvalidConfig {
+                origin: "TUF repository".into(),
+                reason: format!("can't join `{path}` to {base}: {e}"),
+            }
+            .into()
+        })
+    }
+
+    /// The URL and sha256 of `path` in `trusted`'s targets.
+    fn target(&self, trusted: &Trusted, path: &str) -> Result<Option<(Url, String)>> {
+        let Some(target) = trusted.targets.targets.get(path) else {
+            return Ok(None);
+        };
+        let sha256 = target
+            .hashes
+            .get("sha256")
+            .ok_or_else(|| self.rejected("targets", format!("no sha256 for {path}")))?
+            .to_ascii_lowercase();
+        // Consistent snapshots prefix target file names with their hash.
+        let file = match (trusted.consistent_snapshot, path.rsplit_once('/')) {
+            (false, _) => path.to_string(),
+            (true, Some((dir, name))) => format!("{dir}/{sha256}.{name}"),
+            (true, None) => format!("{sha256}.{path}"),
+        };
+        Ok(Some((self.join(&self.targets_url, &file)?, sha256)))
+    }
+}
+
+impl ReleaseIndex for TufReleaseIndex {
+    fn lookup(
+        &self,
+        version: &str,
+        platform: &str,
+        asset_name: Option<&str>,
+        ctx: &ResolveContext<'_>,
+    ) -> Result<Option<(Url, String)>> {
+        let platform = &crate::platform::normalize(platform);
+        let Some(path) =
+            crate::release::render(&self.target_template, version, platform, asset_name)
+        else {
+            return Ok(None);
+        };
+        let mut trusted = self.trusted.lock().unwrap_or_else(|e| e.into_inner());
+        // The last update's targets, while they are fresh and have the asset;
+        // a new release needs an update anyway.
+        if let Some(last) = trusted.as_ref()
+            && last.targets.targets.contains_key(&path)
+            && self
+                .unexpired("targets", &last.targets.expires, &cache::timestamp())
+                .is_ok()
+        {
+            return self.target(last, &path);
+        }
+        let updated = trusted.insert(self.update(ctx)?);
+        self.target(updated, &path)
+    }
+}
+
+#[derive(Deserialize)]
+struct Root {
+    version: u64,
+    expires: String,
+    #[serde(default)]
+    consistent_snapshot: bool,
+    keys: HashMap<String, Key>,
+    roles: HashMap<String, RoleKeys>,
+}
+
+impl Root {
+    fn role_keys(&self, role: &str) -> Option<(&[String], u64)> {
+        self.roles
+            .get(role)
+            .map(|keys| (keys.keyids.as_slice(), keys.threshold))
+    }
+}
+
+#[derive(Deserialize)]
+struct RoleKeys {
+    keyids: Vec<String>,
+    threshold: u64,
+}
+
+#[derive(Deserialize)]
+struct Key {
+    keytype: String,
+    scheme: String,
+    keyval: KeyValue,
+}
+
+#[derive(Deserialize)]
+struct KeyValue {
+    public: String,
+}
+
+impl Key {
+    /// Whether `sig`, in hex, is this key's signature of `message`. Keys of
+    /// other types never verify.
+    fn verifies(&self, message: &[u8], sig: &str) -> bool {
+        if self.keytype != "ed25519" || self.scheme != "ed25519" {
+            return false;
+        }
+        let (Some(public), Some(sig)) =
+            (decode_hex::<32>(&self.keyval.public), decode_hex::<64>(sig))
+        else {
+            return false;
+        };
+        ed25519_dalek::VerifyingKey::from_bytes(&public).is_ok_and(|key| {
+            key.verify_strict(message, &ed25519_dalek::Signature::from_bytes(&sig))
+                .is_ok()
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct Timestamp {
+    version: u64,
+    expires: String,
+    meta: HashMap<String, MetaFile>,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    version: u64,
+    expires: String,
+    meta: HashMap<String, MetaFile>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct MetaFile {
+    version: u64,
+    #[serde(default)]
+    length: Option<u64>,
+    #[serde(default)]
+    hashes: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct Targets {
+    version: u64,
+    expires: String,
+    targets: HashMap<String, Target>,
+}
+
+#[derive(Deserialize)]
+struct Target {
+    hashes: HashMap<String, String>,
+}
+
+/// `name` as consistent snapshots publish it, prefixed with its version.
+fn versioned(consistent_snapshot: bool, version: u64, name: &str) -> String {
+    match consistent_snapshot {
+        true => format!("{version}.{name}"),
+        false => name.to_string(),
+    }
+}
+
+/// `url` with a trailing slash, so that joining keeps its last segment.
+fn as_directory(mut url: Url) -> Url {
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    url
+}
+
+fn read(dir: &Path, name: &str) -> Result<Option<Vec<u8>>> {
+    let path = dir.join(name);
+    match std::fs::read(&path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(FsError::Io {
+            context: format!("read {}", path.display()),
+            source: e,
+        }
+        .into()),
+    }
+}
+
+fn write(dir: &Path, name: &str, bytes: &[u8]) -> Result<()> {
+    cache::write_atomic(&dir.join(name), bytes)
+}
+
+/// The canonical JSON TUF signs: object keys sorted, no whitespace, and only
+/// `"` and `\` escaped in strings.
+fn canonical(value: &Value) -> Vec<u8> {
+    fn write(value: &Value, out: &mut Vec<u8>) {
+        match value {
+            Value::Null | Value::Bool(_) | Value::Number(_) => {
+                out.extend_from_slice(value.to_string().as_bytes());
+            }
+            Value::String(s) => {
+                out.push(b'"');
+                for c in s.chars() {
+                    if c == '"' || c == '\\' {
+                        out.push(b'\\');
+                    }
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                out.push(b'"');
+            }
+            Value::Array(items) => {
+                out.push(b'[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(b',');
+                    }
+                    write(item, out);
+                }
+                out.push(b']');
+            }
+            Value::Object(map) => {
+                let sorted: BTreeMap<_, _> = map.iter().collect();
+                out.push(b'{');
+                for (i, (key, item)) in sorted.into_iter().enumerate() {
+                    if i > 0 {
+                        out.push(b',');
+                    }
+                    write(&Value::String(key.clone()), out);
+                    out.push(b':');
+                    write(item, out);
+                }
+                out.push(b'}');
+            }
+        }
+    }
+    let mut out = Vec::new();
+    write(value, &mut out);
+    out
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    if hex.len() != 2 * N || !hex.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
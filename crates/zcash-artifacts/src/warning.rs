@@ -0,0 +1,121 @@
+//! Advisory findings of resolutions.
+//!
+//! Some things a resolution does are allowed, but worth knowing about when
+//! auditing a test run: a build that included uncommitted changes, a binary
+//! whose quarantine attribute was removed. Rather than failing, the resolver
+//! reports each as a [`Warning`]: observers get an
+//! [`Event::Warning`](crate::observe::Event::Warning), and
+//! [`ArtifactResolver::resolve_traced`](crate::ArtifactResolver::resolve_traced)
+//! collects them into [`ResolutionTrace::warnings`](crate::observe::ResolutionTrace::warnings):
+//!
+//! ```no_run
+//! # fn demo(resolver: &zcash_artifacts::ArtifactResolver, src: &zcash_artifacts::ArtifactSource) {
+//! use zcash_artifacts::{ResolveOptions, warning::Warning};
+//!
+//! let (result, trace) = resolver.resolve_traced(src, &ResolveOptions::default());
+//! for warning in &trace.warnings {
+//!     if let Warning::DirtyBuild { repo, .. } = warning {
+//!         eprintln!("{} had uncommitted changes", repo.display());
+//!     }
+//! }
+//! # }
+//! ```
+//!
+//! Warnings about how an entry was made are also kept in its META's
+//! `warnings`, but are only reported by the resolution that made it; with the
+//! `tracing` feature, they are `warn` events too.
+
+use std::{fmt, path::PathBuf};
+
+use serde::Serialize;
+
+/// Something allowed that a resolution did. More kinds may be added.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Warning {
+    /// A build of `repo` includes its uncommitted changes, as
+    /// [`GitPolicy::AllowDirty`](crate::git::GitPolicy::AllowDirty) allows;
+    /// reported whether it is built or found in the cache.
+    DirtyBuild {
+        repo: PathBuf,
+        /// The hash keying the build's cache entry; see the
+        /// [cache docs](crate::cache).
+        worktree_hash: String,
+    },
+    /// macOS's `com.apple.quarantine` attribute was removed from `path`; see
+    /// [`ResolverConfig::keep_quarantine`](crate::ResolverConfig::keep_quarantine).
+    QuarantineStripped { path: PathBuf },
+    /// No asset was published for `requested`, so the one for `used` was
+    /// downloaded, e.g. an x86_64 asset under Rosetta.
+    PlatformFallback {
+        version: String,
+        requested: String,
+        used: String,
+    },
+    /// An image or artifact was pulled over plain HTTP from `host`, one of the
+    /// `insecure_registries` of `oci::OciConfig` (`oci` feature).
+    InsecureRegistry { host: String },
+    /// Credentials for `host`, an insecure registry, may be sent over plain
+    /// HTTP, as `oci::OciConfig::insecure_credentials` allows (`oci` feature).
+    InsecureCredentials { host: String },
+    /// The release asset downloaded from `url` has no entry in the
+    /// transparency `log`; see `crate::transparency` (`transparency` feature).
+    NotLogged {
+        url: String,
+        digest: String,
+        log: String,
+    },
+}
+
+impl Warning {
+    /// The kind of warning in kebab-case, e.g. `dirty-build`, as in its JSON.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Warning::DirtyBuild { .. } => "dirty-build",
+            Warning::QuarantineStripped { .. } => "quarantine-stripped",
+            Warning::PlatformFallback { .. } => "platform-fallback",
+            Warning::InsecureRegistry { .. } => "insecure-registry",
+            Warning::InsecureCredentials { .. } => "insecure-credentials",
+            Warning::NotLogged { .. } => "not-logged",
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::DirtyBuild {
+                repo,
+                worktree_hash,
+            } => write!(
+                f,
+                "using uncommitted changes in {} (worktree {worktree_hash})",
+                repo.display()
+            ),
+            Warning::QuarantineStripped { path } => {
+                write!(f, "removed the quarantine attribute of {}", path.display())
+            }
+            Warning::PlatformFallback {
+                version,
+                requested,
+                used,
+            } => write!(f, "no {requested} asset for {version}; using {used}"),
+            Warning::InsecureRegistry { host } => {
+                write!(
+                    f,
+                    "pulled over plain HTTP from {host}, an insecure registry"
+                )
+            }
+            Warning::InsecureCredentials { host } => {
+                write!(f, "credentials for {host} may be sent over plain HTTP")
+            }
+            Warning::NotLogged { url, digest, log } => {
+                write!(
+                    f,
+                    "{url} (sha256 {digest}) has no entry in transparency log {log}"
+                )
+            }
+        }
+    }
+}
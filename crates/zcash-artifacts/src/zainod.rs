@@ -0,0 +1,12 @@
+use crate::registry::{Dependency, ToolSpec, ZAINOD, ZCASHD, ZEBRAD};
+
+pub fn spec_zainod() -> ToolSpec {
+    let builder = ToolSpec::builder(ZAINOD)
+        .dependency(Dependency::OneOf(vec![ZEBRAD, ZCASHD]))
+        .binary_names(["zainod"])
+        .expected_output("target/release/zainod")
+        .ports([8137]);
+    #[cfg(feature = "local-build")]
+    let builder = builder.build_recipe(crate::recipe::CargoRecipe::new("zainod"));
+    builder.finish()
+}
@@ -1,46 +1,77 @@
+use crate::registry::{Dependency, PlatformRequirements, ToolSpec, ZCASHD};
 #[cfg(feature = "local-build")]
-use crate::BuildRecipe;
-use crate::registry::{ToolSpec, ZCASHD};
+use crate::{BuildInvocation, BuildRecipe};
 
 #[cfg(feature = "local-build")]
 struct ZcashdBuild;
 
 #[cfg(feature = "local-build")]
 impl BuildRecipe for ZcashdBuild {
-    fn build(
-        &self,
-        repo: &std::path::Path,
-        jobs: usize,
-        log: &std::path::Path,
-    ) -> crate::error::Result<std::path::PathBuf> {
-        // ./zcutil/build.sh -j{jobs}, logs to `log`,
-        // returns PathBuf::from("src/zcashd") on success.
-        unimplemented!()
+    /// Runs `./zcutil/build.sh -j{jobs} [extra_args]`, logging stdout/stderr to `log`.
+    ///
+    /// The depends system needs GNU make, which FreeBSD installs as `gmake`.
+    /// Cross builds pass the target to `build.sh` as `HOST`, in the GNU spelling
+    /// the depends system expects (`aarch64-linux-gnu`).
+    fn build(&self, inv: &BuildInvocation<'_>) -> crate::error::Result<std::path::PathBuf> {
+        let mut cmd = std::process::Command::new("./zcutil/build.sh");
+        cmd.arg(format!("-j{}", inv.jobs));
+        if cfg!(target_os = "freebsd") && std::env::var_os("MAKE").is_none() {
+            cmd.env("MAKE", "gmake");
+        }
+        if let Some(triple) = inv.target {
+            cmd.env("HOST", triple.replacen("-unknown-", "-", 1));
+        }
+        crate::recipe::run_logged(cmd, inv)?;
+        Ok(std::path::PathBuf::from("src/zcashd"))
     }
 }
 
 pub fn spec_zcashd() -> ToolSpec {
-    fn names(platform: &str) -> &'static [&'static str] {
-        match platform {
-            "linux-x86_64" | "linux-aarch64" | "macos-x86_64" | "macos-arm64" => &["zcashd"],
-            _ => &["zcashd"],
-        }
-    }
-
+    let builder = ToolSpec::builder(ZCASHD)
+        .dependency(Dependency::Resource("zcash-params".into()))
+        .binary_names(["zcashd"])
+        .expected_output("src/zcashd")
+        .archive_layout(["zcash-*/bin/zcashd", "bin/zcashd"])
+        .companion("zcash-cli", "zcash-cli")
+        .companion("zcash-tx", "zcash-tx")
+        .capability_probe(crate::probe::HelpCapabilityProbe::help())
+        // RPC and P2P ports of mainnet and testnet, and regtest P2P.
+        .ports([8232, 8233, 18232, 18233, 18344])
+        .ready_log("init message: Done loading")
+        // No 32-bit targets: zcashd only builds for 64-bit hosts.
+        .requirements(PlatformRequirements {
+            platforms: [
+                "linux-x86_64",
+                "linux-aarch64",
+                "macos-x86_64",
+                "macos-arm64",
+                "freebsd-x86_64",
+            ]
+            .map(String::from)
+            .to_vec(),
+            min_glibc: None,
+        });
+    // Official releases are built with Guix, with the tarballs listed in a
+    // `SHA256SUMS` next to them.
+    #[cfg(feature = "http")]
+    let builder = builder.guix_releases(crate::guix::GuixReleases::new(
+        "https://download.z.cash/downloads/zcash-{version}-{triple}.tar.gz",
+        "https://download.z.cash/downloads/zcash-{version}-SHA256SUMS",
+    ));
+    // The packages Electric Coin Co. publishes for Debian.
+    #[cfg(feature = "deb")]
+    let builder = builder.apt_repository(crate::deb::AptRepository::new(
+        "https://apt.z.cash/",
+        "bookworm",
+        "zcash",
+    ));
     #[cfg(feature = "local-build")]
-    static ZCASHD_BUILD: ZcashdBuild = ZcashdBuild;
-
-    ToolSpec {
-        id: ZCASHD,
-        binary_names: names,
-        default_expected_output: "src/zcashd".into(),
-        #[cfg(feature = "local-build")]
-        build: Some(&ZCASHD_BUILD),     // runs ./zcutil/build.sh -jN
-        #[cfg(not(feature = "local-build"))]
-        // when the feature is off, the field doesn't exist
-        version_probe: Some(&DEFAULT_PROBE),
-        #[cfg(feature = "http")]
-        releases: todo!(),
-        version_probe: todo!(),    // optional; see below
-    }
+    let builder = builder
+        .build_recipe(ZcashdBuild) // runs ./zcutil/build.sh -jN
+        .build_defaults(crate::registry::BuildDefaults {
+            // The depends tree and objects of a full build.
+            disk_estimate: Some(15 << 30),
+            ..Default::default()
+        });
+    builder.finish()
 }
@@ -0,0 +1,15 @@
+use crate::registry::{ToolSpec, ZEBRAD};
+
+pub fn spec_zebrad() -> ToolSpec {
+    let builder = ToolSpec::builder(ZEBRAD)
+        .binary_names(["zebrad"])
+        .expected_output("target/release/zebrad")
+        .archive_layout(["zebrad-*/zebrad", "zebrad"])
+        .capability_probe(crate::probe::HelpCapabilityProbe::help())
+        // RPC and P2P ports of mainnet and testnet.
+        .ports([8232, 8233, 18232, 18233])
+        .ready_log("Starting zebrad");
+    #[cfg(feature = "local-build")]
+    let builder = builder.build_recipe(crate::recipe::CargoRecipe::new("zebrad"));
+    builder.finish()
+}
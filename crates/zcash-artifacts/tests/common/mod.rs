@@ -0,0 +1,109 @@
+//! Fixtures shared by the integration tests.
+
+#![allow(dead_code)]
+
+use sha2::{Digest, Sha256};
+use zcash_artifacts::{
+    ArtifactResolver, ReleaseIndex, ResolverConfig,
+    registry::{Registry, ServiceId, ToolSpec},
+    testing::CannedTransport,
+};
+
+/// The service the tests resolve releases of.
+pub const DEMO: ServiceId = ServiceId::new_static("demo");
+
+/// Where [`DEMO`] releases are downloaded from.
+pub const ASSET_URL: &str = "https://releases.example.com/demo";
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// minisign's own test vector: [`SIGNATURE`](minisign::SIGNATURE), by
+/// [`SIGNER`](minisign::SIGNER), of [`FILE`](minisign::FILE).
+pub mod minisign {
+    pub const SIGNER: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+    pub const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==
+";
+    pub const FILE: &[u8] = b"test";
+
+    /// Another minisign public key.
+    pub fn other_signer() -> String {
+        use base64::Engine;
+
+        let key = ed25519_dalek::SigningKey::from_bytes(&[9; 32]);
+        let mut bytes = b"Ed".to_vec();
+        bytes.extend([7; 8]);
+        bytes.extend(key.verifying_key().as_bytes());
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    }
+}
+
+/// A script standing in for a downloaded binary.
+pub fn script(body: &str) -> Vec<u8> {
+    format!("#!/bin/sh\n{body}\n").into_bytes()
+}
+
+/// Lists [`ASSET_URL`] for every release, with `sha256`.
+pub struct FixedIndex(pub String);
+
+impl ReleaseIndex for FixedIndex {
+    fn asset_for(
+        &self,
+        _version: &str,
+        _platform: &str,
+        _asset_name: Option<&str>,
+    ) -> Option<(url::Url, String)> {
+        Some((ASSET_URL.parse().unwrap(), self.0.clone()))
+    }
+}
+
+/// The built-in services and [`DEMO`], whose releases `index` lists.
+pub fn registry(index: impl ReleaseIndex) -> Registry {
+    Registry::with_builtins()
+        .register(
+            ToolSpec::builder(DEMO)
+                .binary_names(["demo"])
+                .release_index(index)
+                .finish(),
+        )
+        .unwrap()
+}
+
+/// A config caching under `root` and answering requests from `transport`.
+pub fn config(root: &std::path::Path, transport: &CannedTransport) -> ResolverConfig {
+    ResolverConfig::builder()
+        .cache_root(root)
+        .transport(transport.clone())
+        .log(false)
+        .finish()
+}
+
+/// `resolver`'s error resolving release `version` of [`DEMO`], as text.
+pub fn release_error(resolver: &ArtifactResolver, version: &str) -> String {
+    let src = zcash_artifacts::ArtifactSource::Release {
+        service: DEMO,
+        version: version.into(),
+    };
+    match resolver.resolve(&src) {
+        Ok(resolved) => panic!("resolved to {resolved:?}"),
+        Err(e) => chain(&e),
+    }
+}
+
+/// `err` and its sources, as text.
+pub fn chain(err: &(dyn std::error::Error + 'static)) -> String {
+    let mut text = err.to_string();
+    let mut next = err.source();
+    while let Some(source) = next {
+        text.push_str(&format!(": {source}"));
+        next = source.source();
+    }
+    text
+}
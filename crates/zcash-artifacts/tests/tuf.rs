@@ -0,0 +1,227 @@
+//! Releases from a TUF repository: rolled back, expired and forged metadata
+//! is rejected before anything is downloaded.
+
+mod common;
+
+use common::{DEMO, sha256_hex};
+use ed25519_dalek::{Signer, SigningKey};
+use serde_json::{Value, json};
+use zcash_artifacts::{
+    ArtifactResolver, ArtifactSource, ErrorKind,
+    testing::{Canned, CannedTransport},
+    tuf::TufReleaseIndex,
+};
+
+const METADATA: &str = "https://tuf.example.com/metadata/";
+const TARGETS: &str = "https://tuf.example.com/targets/";
+const FRESH: &str = "2100-01-01T00:00:00Z";
+
+/// A key, and its ID in the repository's roots.
+struct Key(SigningKey);
+
+impl Key {
+    fn new(seed: u8) -> Self {
+        Self(SigningKey::from_bytes(&[seed; 32]))
+    }
+
+    fn public(&self) -> String {
+        hex(self.0.verifying_key().as_bytes())
+    }
+
+    fn id(&self) -> String {
+        sha256_hex(self.public().as_bytes())
+    }
+
+    /// Metadata with `signed`, signed by this key. Value serializes
+    /// sorted and compact, which is TUF's canonical JSON for ASCII strings.
+    fn sign(&self, signed: Value) -> String {
+        let sig = self.0.sign(&serde_json::to_vec(&signed).unwrap());
+        json!({
+            "signed": signed,
+            "signatures": [{ "keyid": self.id(), "sig": hex(&sig.to_bytes()) }],
+        })
+        .to_string()
+    }
+
+    /// Root metadata `version` making this key the only one of every role.
+    fn root(&self, version: u64) -> String {
+        let role = json!({ "keyids": [self.id()], "threshold": 1 });
+        self.sign(json!({
+            "_type": "root",
+            "spec_version": "1.0.31",
+            "version": version,
+            "expires": FRESH,
+            "consistent_snapshot": false,
+            "keys": { self.id(): {
+                "keytype": "ed25519",
+                "scheme": "ed25519",
+                "keyval": { "public": self.public() },
+            }},
+            "roles": {
+                "root": role,
+                "timestamp": role,
+                "snapshot": role,
+                "targets": role,
+            },
+        }))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// What the repository serves.
+struct Repo {
+    key: Key,
+    timestamp_version: u64,
+    timestamp_expires: &'static str,
+}
+
+impl Repo {
+    fn new(key: Key) -> Self {
+        Self {
+            key,
+            timestamp_version: 1,
+            timestamp_expires: FRESH,
+        }
+    }
+
+    /// A transport serving the repository's metadata, releases 1.0.0 and
+    /// 1.0.1 of [`DEMO`], and no root rotation.
+    fn transport(&self) -> CannedTransport {
+        let asset = common::script("echo demo");
+        let targets = self.key.sign(json!({
+            "_type": "targets",
+            "version": 1,
+            "expires": FRESH,
+            "targets": {
+                "demo/1.0.0/demo": { "length": asset.len(), "hashes": { "sha256": sha256_hex(&asset) } },
+                "demo/1.0.1/demo": { "length": asset.len(), "hashes": { "sha256": sha256_hex(&asset) } },
+            },
+        }));
+        let snapshot = self.key.sign(json!({
+            "_type": "snapshot",
+            "version": 1,
+            "expires": FRESH,
+            "meta": { "targets.json": { "version": 1 } },
+        }));
+        let timestamp = self.key.sign(json!({
+            "_type": "timestamp",
+            "version": self.timestamp_version,
+            "expires": self.timestamp_expires,
+            "meta": { "snapshot.json": { "version": 1 } },
+        }));
+        let metadata = |name: &str| format!("{METADATA}{name}");
+        CannedTransport::new()
+            .respond(&metadata("2.root.json"), Canned::status(404))
+            .respond(&metadata("timestamp.json"), Canned::ok(timestamp))
+            .respond(&metadata("snapshot.json"), Canned::ok(snapshot))
+            .respond(&metadata("targets.json"), Canned::ok(targets))
+            .respond(
+                &format!("{TARGETS}demo/1.0.0/demo"),
+                Canned::ok(asset.clone()),
+            )
+            .respond(&format!("{TARGETS}demo/1.0.1/demo"), Canned::ok(asset))
+    }
+}
+
+/// A resolver caching under `root`, trusting `trusted` as the initial root.
+fn resolver(
+    root: &std::path::Path,
+    trusted: &Key,
+    transport: &CannedTransport,
+) -> ArtifactResolver {
+    let index = TufReleaseIndex::new(
+        METADATA.parse().unwrap(),
+        TARGETS.parse().unwrap(),
+        trusted.root(1),
+        "demo/{version}/demo",
+    );
+    ArtifactResolver::with_registry(common::config(root, transport), common::registry(index))
+}
+
+fn resolve(resolver: &ArtifactResolver, version: &str) -> zcash_artifacts::Result<()> {
+    resolver
+        .resolve(&ArtifactSource::Release {
+            service: DEMO,
+            version: version.into(),
+        })
+        .map(drop)
+}
+
+fn rejected(resolver: &ArtifactResolver, version: &str) -> String {
+    let err = resolve(resolver, version).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Verification, "{err}");
+    common::chain(&err)
+}
+
+#[test]
+fn valid_metadata_resolves() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = Repo::new(Key::new(1));
+    resolve(&resolver(dir.path(), &repo.key, &repo.transport()), "1.0.0").unwrap();
+}
+
+#[test]
+fn timestamp_rollback_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut repo = Repo::new(Key::new(1));
+    repo.timestamp_version = 2;
+    resolve(&resolver(dir.path(), &repo.key, &repo.transport()), "1.0.0").unwrap();
+
+    // A mirror replaying the older, validly signed timestamp.
+    repo.timestamp_version = 1;
+    let transport = repo.transport();
+    let err = rejected(&resolver(dir.path(), &repo.key, &transport), "1.0.1");
+    assert!(err.contains("version 1 is older than 2"), "{err}");
+    assert!(
+        !transport.requests().iter().any(|r| r.contains(TARGETS)),
+        "downloaded from a rolled back repository: {:?}",
+        transport.requests()
+    );
+}
+
+#[test]
+fn expired_timestamp_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut repo = Repo::new(Key::new(1));
+    repo.timestamp_expires = "2000-01-01T00:00:00Z";
+    let err = rejected(&resolver(dir.path(), &repo.key, &repo.transport()), "1.0.0");
+    assert!(err.contains("expired at 2000-01-01T00:00:00Z"), "{err}");
+}
+
+#[test]
+fn metadata_by_other_keys_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = Repo::new(Key::new(2));
+    let err = rejected(
+        &resolver(dir.path(), &Key::new(1), &repo.transport()),
+        "1.0.0",
+    );
+    assert!(err.contains("signed by 0 of the 1 keys required"), "{err}");
+}
+
+#[test]
+fn stored_root_not_chaining_from_the_given_one_is_ignored() {
+    let dir = tempfile::tempdir().unwrap();
+    let trusted = Key::new(1);
+    let repo = Repo::new(Key::new(1));
+    resolve(&resolver(dir.path(), &trusted, &repo.transport()), "1.0.0").unwrap();
+
+    // Someone with write access to the cache plants a rotation to their own
+    // key, and serves metadata signed with it.
+    let state = std::fs::read_dir(dir.path().join("tuf"))
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let forger = Repo::new(Key::new(3));
+    std::fs::write(state.join("2.root.json"), forger.key.root(2)).unwrap();
+    let err = rejected(
+        &resolver(dir.path(), &trusted, &forger.transport()),
+        "1.0.1",
+    );
+    assert!(err.contains("signed by 0 of the 1 keys required"), "{err}");
+}